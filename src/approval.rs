@@ -0,0 +1,176 @@
+//! Approval requests codex makes under `approval_bridge`: forwarded to the MCP
+//! client as elicitations, with the decision written back to codex's stdin.
+//!
+//! Anything short of an explicit approval denies the request: the client
+//! declining or cancelling, not answering within `approval_timeout_secs`, not
+//! supporting elicitation, or the run having no client to ask (background and
+//! scheduled runs).
+
+use std::time::Duration;
+
+use rmcp::model::{CreateElicitationRequestParam, ElicitationAction, ElicitationSchema};
+use rmcp::service::{Peer, RoleServer};
+use rmcp::ServiceError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Property of the elicitation form the client sets to approve.
+const APPROVE_PROPERTY: &str = "approve";
+
+/// What codex asked for approval of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApprovalKind {
+    Command,
+    FileChange,
+}
+
+/// An approval request event codex is waiting on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalRequest {
+    kind: ApprovalKind,
+    /// ID codex expects back with the decision.
+    id: String,
+    /// What codex wants to do, e.g. "run `cargo publish`".
+    action: String,
+}
+
+impl ApprovalRequest {
+    /// Parse an `exec_approval_request` or `apply_patch_approval_request` event.
+    pub fn from_event(event: &serde_json::Value) -> Self {
+        let command = match event.get("command") {
+            Some(serde_json::Value::Array(parts)) => Some(
+                parts
+                    .iter()
+                    .filter_map(|p| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Some(serde_json::Value::String(command)) => Some(command.clone()),
+            _ => None,
+        };
+        let id = ["call_id", "id"]
+            .into_iter()
+            .find_map(|key| event.get(key).and_then(|v| v.as_str()))
+            .unwrap_or_default()
+            .to_string();
+        match command {
+            Some(command) => Self {
+                kind: ApprovalKind::Command,
+                id,
+                action: format!("run `{command}`"),
+            },
+            None => Self {
+                kind: ApprovalKind::FileChange,
+                id,
+                action: "apply a file change".to_string(),
+            },
+        }
+    }
+
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    /// The line to write to codex's stdin to answer the request.
+    pub fn answer(&self, approved: bool) -> String {
+        let op = match self.kind {
+            ApprovalKind::Command => "exec_approval",
+            ApprovalKind::FileChange => "patch_approval",
+        };
+        let decision = if approved { "approved" } else { "denied" };
+        let answer = serde_json::json!({
+            "id": self.id,
+            "op": {"type": op, "id": self.id, "decision": decision},
+        });
+        format!("{answer}\n")
+    }
+}
+
+/// Who, or what, decided an approval request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DecidedBy {
+    /// The client answered the elicitation.
+    Client,
+    /// The client did not answer within `approval_timeout_secs`.
+    Timeout,
+    /// The client could not be asked: it does not support elicitation, the
+    /// run has no client, or the request failed.
+    Unavailable,
+}
+
+/// How one approval request of a run was decided.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ApprovalDecision {
+    /// What codex asked to do, e.g. "run `cargo publish`".
+    pub action: String,
+    pub approved: bool,
+    pub decided_by: DecidedBy,
+}
+
+/// Ask `client` whether codex may go ahead with `request`, denying it after
+/// `timeout`.
+pub async fn ask(client: Option<&Peer<RoleServer>>, request: &ApprovalRequest, timeout: Duration) -> ApprovalDecision {
+    let decision = |approved, decided_by| ApprovalDecision {
+        action: request.action.clone(),
+        approved,
+        decided_by,
+    };
+    let Some(client) = client.filter(|client| client.supports_elicitation()) else {
+        return decision(false, DecidedBy::Unavailable);
+    };
+    let schema = ElicitationSchema::builder()
+        .required_bool_with(APPROVE_PROPERTY, |b| b.description("Allow codex to go ahead"))
+        .build()
+        .expect("the required property is defined");
+    let params = CreateElicitationRequestParam {
+        message: format!("codex asks to {}. Allow it?", request.action),
+        requested_schema: schema,
+    };
+    match client.create_elicitation_with_timeout(params, Some(timeout)).await {
+        Ok(result) => {
+            let approved = result.action == ElicitationAction::Accept
+                && result
+                    .content
+                    .as_ref()
+                    .and_then(|content| content.get(APPROVE_PROPERTY))
+                    .and_then(|approve| approve.as_bool())
+                    == Some(true);
+            decision(approved, DecidedBy::Client)
+        }
+        Err(ServiceError::Timeout { .. }) => decision(false, DecidedBy::Timeout),
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to ask the client for approval; denying");
+            decision(false, DecidedBy::Unavailable)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_requests_and_answers_them() {
+        let event =
+            serde_json::json!({"type": "exec_approval_request", "call_id": "c1", "command": ["cargo", "publish"]});
+        let request = ApprovalRequest::from_event(&event);
+        assert_eq!(request.action(), "run `cargo publish`");
+        let answer: serde_json::Value = serde_json::from_str(&request.answer(true)).unwrap();
+        assert_eq!(answer["op"], serde_json::json!({"type": "exec_approval", "id": "c1", "decision": "approved"}));
+
+        let event = serde_json::json!({"type": "apply_patch_approval_request", "id": "p1"});
+        let request = ApprovalRequest::from_event(&event);
+        assert_eq!(request.action(), "apply a file change");
+        let answer: serde_json::Value = serde_json::from_str(&request.answer(false)).unwrap();
+        assert_eq!(answer["op"], serde_json::json!({"type": "patch_approval", "id": "p1", "decision": "denied"}));
+    }
+
+    #[tokio::test]
+    async fn test_denies_without_a_client() {
+        let request = ApprovalRequest::from_event(&serde_json::json!({"command": "rm -rf target"}));
+        let decision = ask(None, &request, Duration::from_secs(1)).await;
+        assert!(!decision.approved);
+        assert_eq!(decision.decided_by, DecidedBy::Unavailable);
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-run artifact directories, so CI systems can archive everything a run produced.
+//!
+//! Each run gets `<artifacts_dir>/<unix millis>-<seq>/` containing:
+//! - `transcript.jsonl`: raw codex stdout
+//! - `stderr.log`: raw codex stderr
+//! - `diff.patch`: working-tree diff against `HEAD` after a write-enabled run
+//! - `run.json`: the `CodexResult` returned to the client
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::secrets;
+use crate::state::unix_now_millis;
+
+/// Distinguishes runs started within the same millisecond.
+static RUN_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Artifact files of one run, written while the run progresses.
+#[derive(Debug)]
+pub struct RunArtifacts {
+    dir: PathBuf,
+    transcript: BufWriter<File>,
+    stderr: BufWriter<File>,
+}
+
+impl RunArtifacts {
+    /// Create a fresh artifacts directory for a run under `root`.
+    pub fn create(root: &Path) -> Result<Self> {
+        let seq = RUN_SEQ.fetch_add(1, Ordering::Relaxed);
+        let dir = root.join(format!("{}-{seq}", unix_now_millis()));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let open = |name: &str| -> Result<BufWriter<File>> {
+            let path = dir.join(name);
+            let file = File::create(&path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            Ok(BufWriter::new(file))
+        };
+        Ok(Self {
+            transcript: open("transcript.jsonl")?,
+            stderr: open("stderr.log")?,
+            dir,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Append a raw stdout line. Write errors surface when the run finishes.
+    pub fn record_stdout(&mut self, line: &str) {
+        let _ = writeln!(self.transcript, "{}", secrets::redact(line));
+    }
+
+    /// Append a raw stderr line. Write errors surface when the run finishes.
+    pub fn record_stderr(&mut self, line: &str) {
+        let _ = writeln!(self.stderr, "{}", secrets::redact(line));
+    }
+
+    /// Flush the logs and write the diff and `run.json` summary.
+    pub fn finish(mut self, summary: &impl Serialize, diff: Option<&str>) -> Result<()> {
+        self.transcript.flush().context("failed to write transcript.jsonl")?;
+        self.stderr.flush().context("failed to write stderr.log")?;
+        if let Some(diff) = diff {
+            std::fs::write(self.dir.join("diff.patch"), secrets::redact(diff).as_bytes()).context("failed to write diff.patch")?;
+        }
+        let json = serde_json::to_string_pretty(summary)?;
+        std::fs::write(self.dir.join("run.json"), secrets::redact(&json).as_bytes()).context("failed to write run.json")?;
+        Ok(())
+    }
+}
+
+/// Run directories under `root`, with the Unix millis they were created at.
+fn run_dirs(root: &Path) -> Result<Vec<(PathBuf, u128)>> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to list {}", root.display())),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        // Only `<millis>-<seq>` directories are ours to delete.
+        let created = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.split_once('-'))
+            .filter(|(_, seq)| seq.parse::<u64>().is_ok())
+            .and_then(|(millis, _)| millis.parse::<u128>().ok());
+        if let Some(created) = created
+            && entry.file_type()?.is_dir()
+        {
+            dirs.push((entry.path(), created));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Delete the run directories under `root` created before `cutoff_millis`
+/// (Unix millis), returning how many.
+pub fn remove_older_than(root: &Path, cutoff_millis: u128) -> Result<usize> {
+    let mut removed = 0;
+    for (dir, created) in run_dirs(root)? {
+        if created < cutoff_millis {
+            std::fs::remove_dir_all(&dir).with_context(|| format!("failed to delete {}", dir.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Delete the run directories under `root` whose `run.json` names
+/// `session_id`, returning how many.
+pub fn remove_session(root: &Path, session_id: &str) -> Result<usize> {
+    let mut removed = 0;
+    for (dir, _) in run_dirs(root)? {
+        let Ok(text) = std::fs::read_to_string(dir.join("run.json")) else {
+            continue;
+        };
+        let summary: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        if summary["SESSION_ID"] == session_id {
+            std::fs::remove_dir_all(&dir).with_context(|| format!("failed to delete {}", dir.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_artifacts_layout() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-artifacts-{}", std::process::id()));
+        let mut artifacts = RunArtifacts::create(&root).unwrap();
+        let dir = artifacts.dir().to_path_buf();
+        artifacts.record_stdout(r#"{"type":"thread.started"}"#);
+        artifacts.record_stderr("warning: something");
+        artifacts
+            .finish(&serde_json::json!({"success": true}), Some("diff --git a/x b/x\n"))
+            .unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("transcript.jsonl"), "{\"type\":\"thread.started\"}\n");
+        assert_eq!(read("stderr.log"), "warning: something\n");
+        assert_eq!(read("diff.patch"), "diff --git a/x b/x\n");
+        assert!(read("run.json").contains("\"success\": true"));
+        assert_ne!(RunArtifacts::create(&root).unwrap().dir(), dir);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_remove_runs_by_session_and_age() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-artifacts-purge-{}", std::process::id()));
+        for session in ["s1", "s1", "s2"] {
+            let artifacts = RunArtifacts::create(&root).unwrap();
+            artifacts.finish(&serde_json::json!({"SESSION_ID": session}), None).unwrap();
+        }
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+
+        assert_eq!(remove_session(&root, "s1").unwrap(), 2);
+        assert_eq!(run_dirs(&root).unwrap().len(), 1);
+        assert_eq!(remove_older_than(&root, 0).unwrap(), 0);
+        assert_eq!(remove_older_than(&root, unix_now_millis() + 1).unwrap(), 1);
+        assert!(root.join("notes").exists(), "only run directories are deleted");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
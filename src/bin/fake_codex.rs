@@ -0,0 +1,120 @@
+//! Scripted stand-in for the codex CLI, used by the integration tests.
+//!
+//! Replays the JSONL script named by `FAKE_CODEX_SCRIPT` to stdout, one line
+//! at a time. Lines of the form `{"fake": {...}}` are directives instead of
+//! events:
+//!
+//! - `{"fake": {"sleep_ms": 100}}` pauses before the next line.
+//! - `{"fake": {"stderr": "text"}}` writes a line to stderr.
+//! - `{"fake": {"raw": "text"}}` writes `text` to stdout verbatim (e.g. non-JSON noise).
+//! - `{"fake": {"exit": 3}}` exits immediately with the given status code.
+//! - `{"fake": {"signal": 9}}` kills the process with the given signal (Unix only).
+//! - `{"fake": {"write": "dist/app.js", "content": "text"}}` writes a file,
+//!   relative to the `--cd` directory, as a command codex runs would.
+//! - `{"fake": {"read_stdin": true}}` waits for a line on stdin, e.g. the
+//!   answer to an approval request, and appends it to the file named by
+//!   `FAKE_CODEX_STDIN_OUT`.
+//!
+//! `--version` prints `codex-cli 0.0.0-fake` and exits without replaying;
+//! `exec --help` prints the file named by `FAKE_CODEX_HELP` if it exists, or
+//! a help text listing every option the server probes for.
+//!
+//! When `FAKE_CODEX_ARGS_OUT` is set, the received arguments are written there
+//! as a JSON array so tests can assert on the CLI invocation, and when
+//! `FAKE_CODEX_ENV_OUT` is set, its environment is written there as a JSON
+//! object.
+
+use std::io::Write;
+use std::time::Duration;
+
+/// `codex exec --help` of a codex supporting everything the server uses.
+const EXEC_HELP: &str = "\
+Usage: codex exec [OPTIONS] [PROMPT]
+
+Commands:
+  resume  Resume a previous session
+
+Options:
+  -i, --image <FILE>...    Images to attach to the prompt
+  -p, --profile <PROFILE>  Configuration profile
+      --oss                Use a local open source model provider
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args == ["--version"] {
+        println!("codex-cli 0.0.0-fake");
+        return;
+    }
+    if args == ["exec", "--help"] {
+        let help = std::env::var_os("FAKE_CODEX_HELP").and_then(|path| std::fs::read_to_string(path).ok());
+        print!("{}", help.as_deref().unwrap_or(EXEC_HELP));
+        return;
+    }
+    if let Some(path) = std::env::var_os("FAKE_CODEX_ARGS_OUT") {
+        let json = serde_json::to_string(&args).expect("serialize args");
+        std::fs::write(path, json).expect("write FAKE_CODEX_ARGS_OUT");
+    }
+    if let Some(path) = std::env::var_os("FAKE_CODEX_ENV_OUT") {
+        let env: serde_json::Map<String, serde_json::Value> = std::env::vars().map(|(k, v)| (k, v.into())).collect();
+        std::fs::write(path, serde_json::to_string(&env).expect("serialize env")).expect("write FAKE_CODEX_ENV_OUT");
+    }
+
+    let Some(script) = std::env::var_os("FAKE_CODEX_SCRIPT") else {
+        eprintln!("fake-codex: FAKE_CODEX_SCRIPT is not set");
+        std::process::exit(2);
+    };
+    let script = std::fs::read_to_string(script).expect("read FAKE_CODEX_SCRIPT");
+
+    let mut stdout = std::io::stdout().lock();
+    for line in script.lines().filter(|l| !l.trim().is_empty()) {
+        let directive = serde_json::from_str::<serde_json::Value>(line)
+            .ok()
+            .and_then(|v| v.get("fake").cloned());
+        let Some(directive) = directive else {
+            writeln!(stdout, "{line}").expect("write event");
+            stdout.flush().expect("flush stdout");
+            continue;
+        };
+
+        if let Some(ms) = directive.get("sleep_ms").and_then(|v| v.as_u64()) {
+            std::thread::sleep(Duration::from_millis(ms));
+        }
+        if let Some(text) = directive.get("stderr").and_then(|v| v.as_str()) {
+            eprintln!("{text}");
+        }
+        if let Some(text) = directive.get("raw").and_then(|v| v.as_str()) {
+            writeln!(stdout, "{text}").expect("write raw line");
+            stdout.flush().expect("flush stdout");
+        }
+        if let Some(path) = directive.get("write").and_then(|v| v.as_str()) {
+            let cd = args.iter().position(|arg| arg == "--cd").and_then(|i| args.get(i + 1));
+            let path = std::path::Path::new(cd.map_or(".", String::as_str)).join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).expect("create parent directory");
+            }
+            let content = directive.get("content").and_then(|v| v.as_str()).unwrap_or_default();
+            std::fs::write(path, content).expect("write file");
+        }
+        if directive.get("read_stdin").is_some_and(|v| v.as_bool() == Some(true)) {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).expect("read stdin");
+            if let Some(path) = std::env::var_os("FAKE_CODEX_STDIN_OUT") {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .expect("open FAKE_CODEX_STDIN_OUT");
+                file.write_all(line.as_bytes()).expect("write FAKE_CODEX_STDIN_OUT");
+            }
+        }
+        if let Some(code) = directive.get("exit").and_then(|v| v.as_i64()) {
+            std::process::exit(code as i32);
+        }
+        #[cfg(unix)]
+        if let Some(signal) = directive.get("signal").and_then(|v| v.as_i64()) {
+            // SAFETY: signalling our own process has no memory-safety preconditions.
+            unsafe { libc::kill(libc::getpid(), signal as i32) };
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! What the installed codex CLI supports, detected from `codex exec --help`,
+//! so clients are only offered the tools and parameters that will work with
+//! it. The binary is re-probed when it changes, e.g. after an upgrade, and
+//! connected clients are told their tool list changed.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use rmcp::{Peer, RoleServer};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Longest `codex exec --help` may take before the probe is abandoned.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the codex binary is checked for changes.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Optional codex features the server's tools and parameters rely on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Capabilities {
+    /// `codex exec --image`, behind the `image` parameter and `codex_with_screenshot`.
+    pub image: bool,
+    /// `codex exec --profile`, behind the `profile` parameter.
+    pub profile: bool,
+    /// `codex exec resume`, behind `SESSION_ID`, `resume_last`, and `summarize_session`.
+    pub resume: bool,
+    /// `codex exec --oss`, behind the `oss` parameter.
+    pub oss: bool,
+}
+
+impl Default for Capabilities {
+    /// Everything, assumed until codex has been probed or when probing fails.
+    fn default() -> Self {
+        Self {
+            image: true,
+            profile: true,
+            resume: true,
+            oss: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Capabilities advertised by the output of `codex exec --help`.
+    pub fn from_help(help: &str) -> Self {
+        Self {
+            image: has_flag(help, "--image"),
+            profile: has_flag(help, "--profile"),
+            resume: has_subcommand(help, "resume"),
+            oss: has_flag(help, "--oss"),
+        }
+    }
+
+    /// Tool parameters the installed codex cannot serve.
+    pub fn unsupported_params(&self) -> Vec<&'static str> {
+        let mut params = Vec::new();
+        if !self.image {
+            params.push("image");
+        }
+        if !self.profile {
+            params.push("profile");
+        }
+        if !self.resume {
+            params.extend(["SESSION_ID", "resume_last", "summarize_session"]);
+        }
+        if !self.oss {
+            params.push("oss");
+        }
+        params
+    }
+
+    /// Tools the installed codex cannot serve.
+    pub fn unsupported_tools(&self) -> Vec<&'static str> {
+        if self.image { Vec::new() } else { vec!["codex_with_screenshot"] }
+    }
+}
+
+/// Whether `help` lists `flag`, e.g. `-i, --image <FILE>...`.
+fn has_flag(help: &str, flag: &str) -> bool {
+    help.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).any(|word| word == flag)
+}
+
+/// Whether the `Commands:` section of `help` lists `name`.
+fn has_subcommand(help: &str, name: &str) -> bool {
+    help.lines()
+        .skip_while(|line| line.trim() != "Commands:")
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        .any(|line| line.split_whitespace().next() == Some(name))
+}
+
+/// Run `codex exec --help`, or `None` if it fails.
+async fn probe(codex_path: &Path) -> Option<Capabilities> {
+    let output = Command::new(codex_path)
+        .args(["exec", "--help"])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(PROBE_TIMEOUT, output).await.ok()?.ok()?;
+    output
+        .status
+        .success()
+        .then(|| Capabilities::from_help(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The binary last probed, identified by its path and modification time.
+#[derive(Debug, PartialEq)]
+struct Binary {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Capabilities of the installed codex, and the clients to notify when they change.
+#[derive(Debug, Default)]
+pub struct CapabilityTracker {
+    state: Mutex<(Option<Binary>, Capabilities)>,
+    peers: Mutex<Vec<Peer<RoleServer>>>,
+}
+
+impl CapabilityTracker {
+    pub fn current(&self) -> Capabilities {
+        self.state.lock().unwrap().1
+    }
+
+    /// Probe `codex_path` unless it is the binary probed last. Returns
+    /// whether the capabilities changed.
+    pub async fn refresh(&self, codex_path: &Path) -> bool {
+        let binary = Binary {
+            path: codex_path.to_path_buf(),
+            modified: std::fs::metadata(codex_path).and_then(|m| m.modified()).ok(),
+        };
+        if self.state.lock().unwrap().0.as_ref() == Some(&binary) {
+            return false;
+        }
+        let capabilities = probe(codex_path).await.unwrap_or_else(|| {
+            tracing::warn!(codex = %codex_path.display(), "Failed to probe codex capabilities; assuming all are supported");
+            Capabilities::default()
+        });
+        let mut state = self.state.lock().unwrap();
+        let changed = state.1 != capabilities;
+        if changed {
+            tracing::info!(?capabilities, "Detected codex capabilities");
+        }
+        *state = (Some(binary), capabilities);
+        changed
+    }
+
+    /// Notify `peer` when the tool list changes.
+    pub fn add_peer(&self, peer: Peer<RoleServer>) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|peer| !peer.is_transport_closed());
+        peers.push(peer);
+    }
+
+    /// Tell every connected client that the tool list changed.
+    pub async fn notify_tool_list_changed(&self) {
+        let peers = {
+            let mut peers = self.peers.lock().unwrap();
+            peers.retain(|peer| !peer.is_transport_closed());
+            peers.clone()
+        };
+        for peer in peers {
+            if let Err(e) = peer.notify_tool_list_changed().await {
+                tracing::warn!(error = %e, "Failed to send tools/list_changed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELP: &str = "\
+Run Codex non-interactively
+
+Usage: codex exec [OPTIONS] [PROMPT]
+       codex exec [OPTIONS] <COMMAND> [ARGS]
+
+Commands:
+  resume  Resume a previous session by id or pick the most recent with --last
+  help    Print this message or the help of the given subcommand(s)
+
+Options:
+  -i, --image <FILE>...  Optional image(s) to attach to the initial prompt
+  -m, --model <MODEL>    Model the agent should use
+";
+
+    #[test]
+    fn test_capabilities_from_help() {
+        let capabilities = Capabilities::from_help(HELP);
+        assert_eq!(
+            capabilities,
+            Capabilities {
+                image: true,
+                profile: false,
+                resume: true,
+                oss: false,
+            }
+        );
+        assert_eq!(capabilities.unsupported_params(), ["profile", "oss"]);
+        assert!(capabilities.unsupported_tools().is_empty());
+
+        // "resume" in a description is not a subcommand.
+        let old = Capabilities::from_help("Usage: codex exec [OPTIONS] [PROMPT]\n\nOptions:\n  --json  Print events; resume with care\n");
+        assert!(!old.resume && !old.image);
+        assert_eq!(old.unsupported_tools(), ["codex_with_screenshot"]);
+    }
+}
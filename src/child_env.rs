@@ -0,0 +1,127 @@
+//! `scrub_child_env`: spawn codex with only an allowlisted part of the
+//! server's environment and a minimal `PATH`, so credentials the server
+//! happens to hold (cloud keys, SSH agents, tokens) never reach a run, least
+//! of all a `danger-full-access` one.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use tokio::process::Command;
+
+use crate::config::ServerConfig;
+
+/// Variables codex needs to find its home directory and credentials, reach
+/// the model provider (through a proxy if configured), and behave like a
+/// normal process. A trailing `*` matches any suffix.
+const DEFAULT_ALLOWLIST: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_*",
+    "TERM",
+    "TMPDIR",
+    "TZ",
+    "CODEX_HOME",
+    "OPENAI_API_KEY",
+    "OPENAI_BASE_URL",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    // Windows
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "PATHEXT",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "TEMP",
+    "TMP",
+];
+
+/// `PATH` of scrubbed runs unless `child_path` is set.
+#[cfg(unix)]
+pub const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+#[cfg(windows)]
+pub const DEFAULT_PATH: &str = r"C:\Windows\System32;C:\Windows";
+
+fn matches(pattern: &str, name: &OsStr) -> bool {
+    let Some(name) = name.to_str() else {
+        return false;
+    };
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None if cfg!(windows) => name.eq_ignore_ascii_case(pattern),
+        None => name == pattern,
+    }
+}
+
+/// The variables of `vars` kept by the default allowlist plus `extra`.
+fn allowed(vars: impl IntoIterator<Item = (OsString, OsString)>, extra: &[String]) -> Vec<(OsString, OsString)> {
+    vars.into_iter()
+        .filter(|(name, _)| {
+            DEFAULT_ALLOWLIST.iter().any(|pattern| matches(pattern, name))
+                || extra.iter().any(|pattern| matches(pattern, name))
+        })
+        .collect()
+}
+
+/// `PATH` for a scrubbed run of `codex_path`: its own directory first, so
+/// the interpreter installed next to it (e.g. `node` for the npm package) is
+/// found, then `path`.
+fn path_for(codex_path: &Path, path: &str) -> OsString {
+    let mut dirs: Vec<_> = codex_path.parent().map(Path::to_path_buf).into_iter().collect();
+    dirs.extend(std::env::split_paths(path));
+    std::env::join_paths(dirs).unwrap_or_else(|_| path.into())
+}
+
+/// Restrict the environment `cmd` runs `codex_path` with, if the server is
+/// configured to.
+pub fn apply(cmd: &mut Command, codex_path: &Path, config: &ServerConfig) {
+    if !config.scrub_child_env {
+        return;
+    }
+    cmd.env_clear();
+    cmd.envs(allowed(std::env::vars_os(), &config.child_env_allowlist));
+    cmd.env("PATH", path_for(codex_path, config.child_path.as_deref().unwrap_or(DEFAULT_PATH)));
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_drops_credentials() {
+        let vars = [
+            ("HOME", "/home/dev"),
+            ("LC_ALL", "C.UTF-8"),
+            ("OPENAI_API_KEY", "sk-test"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("SSH_AUTH_SOCK", "/tmp/agent.sock"),
+            ("GITHUB_TOKEN", "ghp_test"),
+            ("NPM_CONFIG_PREFIX", "/opt/npm"),
+            ("PATH", "/home/dev/.cargo/bin:/usr/bin"),
+        ]
+        .map(|(name, value)| (OsString::from(name), OsString::from(value)));
+
+        let kept = allowed(vars.clone(), &[]);
+        let names: Vec<_> = kept.iter().map(|(name, _)| name.to_str().unwrap()).collect();
+        assert_eq!(names, ["HOME", "LC_ALL", "OPENAI_API_KEY"]);
+
+        let kept = allowed(vars, &["NPM_*".to_string()]);
+        assert!(kept.iter().any(|(name, _)| name == "NPM_CONFIG_PREFIX"));
+
+        assert_eq!(
+            path_for(Path::new("/opt/codex/bin/codex"), DEFAULT_PATH),
+            "/opt/codex/bin:/usr/local/bin:/usr/bin:/bin"
+        );
+    }
+}
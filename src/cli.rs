@@ -0,0 +1,397 @@
+//! Command-line arguments of the `codex-mcp` binary.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+pub const USAGE: &str = "\
+Usage: codex-mcp [OPTIONS]
+       codex-mcp exec [EXEC OPTIONS] [PROMPT]
+       codex-mcp completions <bash|zsh|fish>
+       codex-mcp man
+
+Options:
+  --transport <stdio|unix>  Transport to serve MCP over [default: stdio]
+  --socket <PATH>           Socket path for the unix transport
+  --socket-mode <MODE>      Octal permissions of the socket file [default: 600]
+  --self-test               Check that codex can run before serving, and exit if not
+  -h, --help                Print this help
+
+Exec runs codex directly, without an MCP client, and prints each result as a
+line of JSON. Without PROMPT, every non-empty line of stdin is run as a prompt.
+
+Exec options:
+  --cd <DIR>                Workspace directory or configured workspace name [default: .]
+  --sandbox <POLICY>        read-only, workspace-write, or danger-full-access
+  --model <MODEL>           Model to use
+  --session-id <ID>         Session to resume
+  --same-session            Run each stdin prompt in the session of the previous one
+
+Completions prints a completion script for the given shell, and man prints a
+man page in roff format.";
+
+/// Value a command-line flag takes, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagValue {
+    None,
+    /// Free text, named by its placeholder.
+    Text(&'static str),
+    /// A file or directory path, named by its placeholder.
+    Path(&'static str),
+    OneOf(&'static [&'static str]),
+}
+
+/// A command-line flag, as listed in shell completions and the man page.
+#[derive(Debug, Clone, Copy)]
+pub struct Flag {
+    pub long: &'static str,
+    pub short: Option<char>,
+    pub value: FlagValue,
+    pub help: &'static str,
+}
+
+const HELP_FLAG: Flag = Flag {
+    long: "help",
+    short: Some('h'),
+    value: FlagValue::None,
+    help: "Print help",
+};
+
+/// Flags of the server itself.
+pub const SERVE_FLAGS: &[Flag] = &[
+    Flag {
+        long: "transport",
+        short: None,
+        value: FlagValue::OneOf(&["stdio", "unix"]),
+        help: "Transport to serve MCP over (default: stdio)",
+    },
+    Flag {
+        long: "socket",
+        short: None,
+        value: FlagValue::Path("PATH"),
+        help: "Socket path for the unix transport",
+    },
+    Flag {
+        long: "socket-mode",
+        short: None,
+        value: FlagValue::Text("MODE"),
+        help: "Octal permissions of the socket file (default: 600)",
+    },
+    Flag {
+        long: "self-test",
+        short: None,
+        value: FlagValue::None,
+        help: "Check that codex can run before serving, and exit if not",
+    },
+    HELP_FLAG,
+];
+
+/// Flags of `codex-mcp exec`.
+pub const EXEC_FLAGS: &[Flag] = &[
+    Flag {
+        long: "cd",
+        short: None,
+        value: FlagValue::Path("DIR"),
+        help: "Workspace directory or configured workspace name (default: .)",
+    },
+    Flag {
+        long: "sandbox",
+        short: None,
+        value: FlagValue::OneOf(&["read-only", "workspace-write", "danger-full-access"]),
+        help: "Sandbox policy",
+    },
+    Flag {
+        long: "model",
+        short: None,
+        value: FlagValue::Text("MODEL"),
+        help: "Model to use",
+    },
+    Flag {
+        long: "session-id",
+        short: None,
+        value: FlagValue::Text("ID"),
+        help: "Session to resume",
+    },
+    Flag {
+        long: "same-session",
+        short: None,
+        value: FlagValue::None,
+        help: "Run each stdin prompt in the session of the previous one",
+    },
+    HELP_FLAG,
+];
+
+/// Subcommands, with a one-line description each.
+pub const SUBCOMMANDS: &[(&str, &str)] = &[
+    ("exec", "Run codex directly, without an MCP client"),
+    ("completions", "Print a shell completion script"),
+    ("man", "Print the man page"),
+];
+
+/// Shell to print a completion script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub const NAMES: &[&str] = &["bash", "zsh", "fish"];
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            other => bail!("unknown shell {other:?}, expected `bash`, `zsh`, or `fish`"),
+        }
+    }
+}
+
+/// Default permissions of the Unix socket: only the owning user may connect.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// How the server talks to its MCP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Stdio,
+    Unix { socket: PathBuf, mode: u32 },
+}
+
+/// What the binary was asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Serve MCP over a transport.
+    Serve(Transport),
+    /// Run codex directly from the command line.
+    Exec(ExecArgs),
+    /// Print a shell completion script.
+    Completions(Shell),
+    /// Print the man page.
+    Man,
+}
+
+/// Arguments of `codex-mcp exec`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecArgs {
+    /// Prompt to run; read line by line from stdin when absent.
+    pub prompt: Option<String>,
+    pub cd: Option<String>,
+    pub sandbox: Option<String>,
+    pub model: Option<String>,
+    pub session_id: Option<String>,
+    /// Resume the previous stdin prompt's session for the next one.
+    pub same_session: bool,
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub command: Command,
+    pub help: bool,
+    /// Run the startup self-test before serving.
+    pub self_test: bool,
+}
+
+impl Cli {
+    /// Parse the process arguments.
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter().peekable();
+        if args.peek().is_some_and(|arg| arg == "exec") {
+            args.next();
+            return parse_exec(args);
+        }
+        if args.peek().is_some_and(|arg| arg == "completions" || arg == "man") {
+            return parse_docs(args);
+        }
+
+        let mut transport = None;
+        let mut socket = None;
+        let mut mode = None;
+        let mut help = false;
+        let mut self_test = false;
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow!("{flag} requires a value\n\n{USAGE}"))
+            };
+            match flag.as_str() {
+                "--transport" => transport = Some(value()?),
+                "--socket" => socket = Some(PathBuf::from(value()?)),
+                "--socket-mode" => {
+                    let text = value()?;
+                    mode = Some(
+                        u32::from_str_radix(&text, 8)
+                            .ok()
+                            .filter(|m| *m <= 0o777)
+                            .with_context(|| format!("invalid --socket-mode {text:?}, expected octal like 660"))?,
+                    );
+                }
+                "--self-test" => self_test = true,
+                "-h" | "--help" => help = true,
+                _ => bail!("unexpected argument {flag:?}\n\n{USAGE}"),
+            }
+        }
+
+        let transport = match transport.as_deref().unwrap_or("stdio") {
+            "stdio" => {
+                if socket.is_some() || mode.is_some() {
+                    bail!("--socket and --socket-mode require --transport unix");
+                }
+                Transport::Stdio
+            }
+            "unix" => Transport::Unix {
+                socket: socket.context("--transport unix requires --socket <PATH>")?,
+                mode: mode.unwrap_or(DEFAULT_SOCKET_MODE),
+            },
+            other => bail!("unknown transport {other:?}, expected `stdio` or `unix`"),
+        };
+
+        Ok(Self {
+            command: Command::Serve(transport),
+            help,
+            self_test,
+        })
+    }
+}
+
+fn parse_exec(mut args: impl Iterator<Item = String>) -> Result<Cli> {
+    let mut exec = ExecArgs::default();
+    let mut help = false;
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if exec.prompt.is_some() {
+                bail!("unexpected argument {arg:?}: pass the prompt as a single argument\n\n{USAGE}");
+            }
+            exec.prompt = Some(arg);
+            continue;
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| anyhow!("{flag} requires a value\n\n{USAGE}"))
+        };
+        match flag.as_str() {
+            "--cd" => exec.cd = Some(value()?),
+            "--sandbox" => exec.sandbox = Some(value()?),
+            "--model" => exec.model = Some(value()?),
+            "--session-id" => exec.session_id = Some(value()?),
+            "--same-session" => exec.same_session = true,
+            "-h" | "--help" => help = true,
+            _ => bail!("unexpected argument {flag:?}\n\n{USAGE}"),
+        }
+    }
+    if exec.same_session && exec.prompt.is_some() {
+        bail!("--same-session only applies to prompts read from stdin");
+    }
+    Ok(Cli {
+        command: Command::Exec(exec),
+        help,
+        self_test: false,
+    })
+}
+
+/// Parse `completions <SHELL>` or `man`.
+fn parse_docs(mut args: impl Iterator<Item = String>) -> Result<Cli> {
+    let subcommand = args.next().unwrap_or_default();
+    let mut operands = Vec::new();
+    let mut help = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => help = true,
+            _ if arg.starts_with('-') => bail!("unexpected argument {arg:?}\n\n{USAGE}"),
+            _ => operands.push(arg),
+        }
+    }
+    let command = match (subcommand.as_str(), operands.as_slice()) {
+        ("man", []) => Command::Man,
+        ("completions", [shell]) => Command::Completions(Shell::parse(shell)?),
+        ("completions", _) => bail!("usage: codex-mcp completions <bash|zsh|fish>"),
+        _ => bail!("unexpected argument {:?}\n\n{USAGE}", operands[0]),
+    };
+    Ok(Cli {
+        command,
+        help,
+        self_test: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli> {
+        Cli::parse_from(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_defaults_to_stdio() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Serve(Transport::Stdio));
+        assert!(!parse(&[]).unwrap().self_test);
+        assert!(parse(&["--self-test"]).unwrap().self_test);
+    }
+
+    #[test]
+    fn test_parses_unix_transport() {
+        let cli = parse(&["--transport", "unix", "--socket=/run/codex.sock", "--socket-mode", "660"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Serve(Transport::Unix {
+                socket: PathBuf::from("/run/codex.sock"),
+                mode: 0o660,
+            })
+        );
+
+        assert!(parse(&["--transport", "unix"]).is_err());
+        assert!(parse(&["--socket", "/run/codex.sock"]).is_err());
+        assert!(parse(&["--transport", "unix", "--socket", "s", "--socket-mode", "9"]).is_err());
+    }
+
+    #[test]
+    fn test_parses_exec() {
+        let cli = parse(&["exec", "--cd=backend", "--sandbox", "workspace-write", "fix the build"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Exec(ExecArgs {
+                prompt: Some("fix the build".to_string()),
+                cd: Some("backend".to_string()),
+                sandbox: Some("workspace-write".to_string()),
+                ..ExecArgs::default()
+            })
+        );
+
+        assert!(parse(&["exec", "one", "two"]).is_err());
+        assert!(parse(&["exec", "--same-session", "one"]).is_err());
+    }
+
+    #[test]
+    fn test_parses_completions_and_man() {
+        assert_eq!(parse(&["completions", "zsh"]).unwrap().command, Command::Completions(Shell::Zsh));
+        assert_eq!(parse(&["man"]).unwrap().command, Command::Man);
+        assert!(parse(&["completions"]).is_err());
+        assert!(parse(&["completions", "tcsh"]).is_err());
+        assert!(parse(&["man", "extra"]).is_err());
+
+        // The flag tables behind completions and the man page match the help.
+        for flag in SERVE_FLAGS.iter().chain(EXEC_FLAGS) {
+            assert!(USAGE.contains(&format!("--{} ", flag.long)), "--{} is not in USAGE", flag.long);
+        }
+    }
+}
@@ -3,20 +3,97 @@
 use std::fmt;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use anyhow::Context as _;
 use rmcp::{
     handler::server::{tool::ToolRouter, wrapper::Parameters},
-    model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
-    tool, tool_handler, tool_router, ErrorData as McpError,
+    model::{
+        AnnotateAble, CallToolResult, Content, ListResourcesResult, PaginatedRequestParam,
+        RawResource, ReadResourceRequestParam, ReadResourceResult, ResourceContents,
+        ServerCapabilities, ServerInfo,
+    },
+    service::{NotificationContext, Peer, RequestContext},
+    tool, tool_handler, tool_router, ErrorData as McpError, RoleServer,
 };
 use schemars::JsonSchema;
 use serde::de::{self, Deserializer, Visitor};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::OnceCell;
 use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
+use crate::approval::{self, ApprovalDecision};
+use crate::artifacts::{self, RunArtifacts};
+use crate::capabilities::{self, CapabilityTracker};
+use crate::child_env;
+use crate::codex_home;
+use crate::compression;
+use crate::config::{GitRepoCheckPolicy, ServerConfig};
+use crate::correlation;
+use crate::cost::{CostLimit, ModelPrice};
+use crate::environment::{self, RunEnvironment};
 use crate::error::CodexError;
+use crate::exit::ProcessExit;
+use crate::exclusions::PathExclusions;
+use crate::events::{EventStream, Flow, PlanStep, RunLimits, RunObserver, SandboxDenial, StopOn};
+use crate::explain::{self, ExplainCache};
+use crate::file_changes::{self, FileChange, Snapshot};
+use crate::formatting::{self, FormatReport};
+use crate::git;
+use crate::i18n::{self, Locale};
+use crate::idempotency::{self, Claim, IdempotencyStore};
+use crate::memory;
+use crate::jobs::{Job, JobStatusReport, JobTable};
+use crate::model_stats::{self, AutoModelChoice, ModelStats};
+use crate::orphans::{self, PidFile};
+use crate::os_sandbox::{self, Wrapper};
+use crate::output_schema;
+use crate::policy;
+use crate::post_process;
+use crate::preflight;
+use crate::prompt::{self, PromptContext};
+use crate::registry::{RunRecord, SessionFilter, SessionRecord, SessionRegistry};
+use crate::run_as;
+use crate::state::{self, unix_now_millis};
+use crate::resources::{self, ResourceKind, ResourceStore, StoredResource};
+use crate::review::{self, SelfReviewParams, SelfReviewResult};
+use crate::rules::{PolicyRules, PolicyViolation, RuleInput};
+use crate::search::{self, SessionHit};
+use crate::self_test;
+use crate::server_info::{CodexCli, VersionReport};
+use crate::shutdown;
+use crate::secrets;
+use crate::status::{ServerLimits, StatusTracker};
+use crate::tee::StreamTee;
+use crate::throttle::Throttle;
+use crate::timings::{RunTimings, TimingRecorder};
+use crate::tuning::{self, ReasoningEffort, TextVerbosity};
+use crate::uploads::{UploadParams, UploadStore};
+use crate::usage::{TokenUsage, UsageTracker};
+use crate::workspace::WorkspaceLocks;
+use crate::workspace_files::{
+    self, WorkspaceListParams, WorkspaceListResult, WorkspaceReadParams, WorkspaceReadResult,
+};
+
+/// Codex config key used to replace the built-in base instructions.
+const INSTRUCTIONS_FILE_KEY: &str = "experimental_instructions_file";
+
+/// Codex config key listing extra writable directories for `workspace-write`.
+const WRITABLE_ROOTS_KEY: &str = "sandbox_workspace_write.writable_roots";
+
+/// Codex config key toggling outbound network access for `workspace-write`.
+const NETWORK_ACCESS_KEY: &str = "sandbox_workspace_write.network_access";
+
+/// Codex config key setting when codex asks for approval.
+const APPROVAL_POLICY_KEY: &str = "approval_policy";
+
+/// Approval policy under `approval_bridge`: codex asks when it sees fit.
+const ON_REQUEST: &str = "on-request";
 
 /// Sandbox policy for model-generated commands.
 #[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
@@ -33,25 +110,59 @@ pub enum SandboxPolicy {
 }
 
 impl SandboxPolicy {
-    fn as_str(&self) -> &'static str {
+    pub(crate) fn as_str(&self) -> &'static str {
         match self {
             SandboxPolicy::ReadOnly => "read-only",
             SandboxPolicy::WorkspaceWrite => "workspace-write",
             SandboxPolicy::DangerFullAccess => "danger-full-access",
         }
     }
+
+    /// How permissive the policy is, for comparing against a maximum.
+    pub(crate) fn rank(&self) -> u8 {
+        match self {
+            SandboxPolicy::ReadOnly => 0,
+            SandboxPolicy::WorkspaceWrite => 1,
+            SandboxPolicy::DangerFullAccess => 2,
+        }
+    }
+}
+
+/// Shape of a run result in the tool response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// The whole result as one JSON text block (default).
+    #[default]
+    Json,
+    /// The final agent message as a plain text block for display, and the
+    /// rest of the result as structured content (also as a JSON text block,
+    /// for clients that do not read structured content).
+    Text,
 }
 
 /// Parameters for the codex tool.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CodexParams {
-    /// Instruction for the task to send to codex.
+    /// Instruction for the task to send to codex, or `resource:` followed by
+    /// a resource URI of this server or a file path inside `cd` to read it
+    /// from, for prompts too large to pass inline.
     #[serde(rename = "PROMPT")]
     pub prompt: String,
 
-    /// Set the workspace root for codex before executing the task.
+    /// Set the workspace root for codex before executing the task. Either a
+    /// path or the name of a workspace configured on the server.
     pub cd: PathBuf,
 
+    /// Create `cd` (an absolute path) if it does not exist, for one-off
+    /// generation tasks. Must be enabled by the server.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub create_cd_if_missing: bool,
+
+    /// Run `git init` in a directory created by `create_cd_if_missing`.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub init_git: bool,
+
     /// Sandbox policy for model-generated commands. Defaults to `read-only`.
     #[serde(default)]
     pub sandbox: SandboxPolicy,
@@ -61,26 +172,70 @@ pub struct CodexParams {
     pub session_id: Option<String>,
 
     /// Allow codex running outside a Git repository (useful for one-off directories).
+    /// Defaults to the server setting, which may also override the request.
     #[serde(
-        default = "default_true",
-        deserialize_with = "deserialize_bool_from_string_or_bool"
+        default,
+        deserialize_with = "deserialize_option_bool_from_string_or_bool",
+        skip_serializing_if = "Option::is_none"
     )]
-    pub skip_git_repo_check: bool,
+    pub skip_git_repo_check: Option<bool>,
 
     /// Return all messages (e.g. reasoning, tool calls, etc.) from the codex session.
     /// Set to `false` by default, only the agent's final reply message is returned.
     #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
     pub return_all_messages: bool,
 
-    /// Attach one or more image files to the initial prompt.
+    /// Only include these event types in `all_messages`, matched against an
+    /// event's `type` (e.g. `turn.completed`, `stderr`) or the `type` of its
+    /// item (e.g. `command_execution`, `file_change`). Implies
+    /// `return_all_messages`; empty includes every event.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub event_filter: Vec<String>,
+
+    /// Index of the first event of `all_messages` to return, for clients
+    /// that cannot take the whole list in one message.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub messages_offset: usize,
+
+    /// Most events of `all_messages` to return. The full list is stored (with
+    /// a state directory) so the rest can be read with `codex_read_transcript`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages_limit: Option<usize>,
+
+    /// Return `all_messages` gzip-compressed and base64-encoded, as
+    /// `all_messages_compressed` with its `encoding`, for clients on
+    /// bandwidth-constrained links.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub compress: bool,
+
+    /// Attach one or more image files (PNG, JPEG, GIF, or WebP) to the initial
+    /// prompt. Relative paths are resolved against `cd`.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub image: Vec<PathBuf>,
 
+    /// Files codex is told to read as context for the task, e.g. specs or
+    /// logs. Each must exist inside `cd` or have been uploaded with
+    /// `codex_upload_context`; relative paths are resolved against `cd`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_files: Vec<PathBuf>,
+
     /// The model to use for the codex session.
     /// This parameter is strictly prohibited unless explicitly specified by the user.
+    /// `auto` picks the model with the best track record in this workspace.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
 
+    /// Reasoning effort of the model: `minimal`, `low`, `medium`, or `high`.
+    /// Ignored by models without adjustable reasoning; `minimal` is GPT-5 only
+    /// and becomes `low` on o-series models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+
+    /// Length and detail of the model's answers: `low`, `medium`, or `high`.
+    /// Only GPT-5 models support it; ignored for others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_verbosity: Option<TextVerbosity>,
+
     /// Run every command without approvals or sandboxing.
     /// Only use when `sandbox` couldn't be applied.
     #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
@@ -90,10 +245,518 @@ pub struct CodexParams {
     /// This parameter is strictly prohibited unless explicitly specified by the user.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub profile: Option<String>,
+
+    /// Path to a file whose contents replace Codex's built-in base instructions,
+    /// e.g. to give the agent a task-specific persona. Relative paths are resolved
+    /// against `cd`. The file must live in a directory allowlisted by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_instructions_file: Option<PathBuf>,
+
+    /// Extra directories (e.g. a shared build cache) that codex may write to in
+    /// addition to `cd`. Only valid with the `workspace-write` sandbox; relative
+    /// paths are resolved against `cd`. Each must be allowlisted by the server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writable_roots: Vec<PathBuf>,
+
+    /// Allow commands to access the network (e.g. to fetch dependencies) under the
+    /// `workspace-write` sandbox. Defaults to the server setting; the server may
+    /// refuse to enable it. Ignored for other sandbox policies.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_option_bool_from_string_or_bool",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allow_network: Option<bool>,
+
+    /// Run on a local open-source model (codex's `--oss` mode, e.g. through
+    /// Ollama) instead of a cloud model, needing no cloud credentials.
+    /// Defaults to the server setting.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_option_bool_from_string_or_bool",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub oss: Option<bool>,
+
+    /// Human-readable label stored with the session in the server registry,
+    /// e.g. the name of the workstream. Replaces any previous label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Tags stored with the session in the server registry, used to find it
+    /// later via `codex_list_sessions`. Accumulate across runs of a session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Return the model's reasoning summaries as a separate `reasoning_summary` field.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub include_reasoning: bool,
+
+    /// Resume the most recent codex CLI session instead of a specific `SESSION_ID`.
+    /// The resolved session ID is returned in the result. Cannot be combined with `SESSION_ID`.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub resume_last: bool,
+
+    /// Instead of resuming `SESSION_ID` directly, have codex summarize it in a
+    /// read-only run and continue in a fresh session seeded with the summary,
+    /// keeping the context of long sessions from growing without bound. The
+    /// result carries the new `SESSION_ID` and the old `previous_session_id`.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub summarize_session: bool,
+
+    /// Files or directories the task is scoped to. Each must exist inside `cd`;
+    /// relative paths are resolved against `cd`. The server prepends an explicit
+    /// instruction telling codex to confine its work to these paths.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub focus_paths: Vec<PathBuf>,
+
+    /// Leave the workspace's project memory (see `codex_memory_get`) out of
+    /// the prompt.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub skip_memory: bool,
+
+    /// Leave the server's `prompt_postamble` (output instructions appended
+    /// to every prompt) off this run's prompt.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub skip_postamble: bool,
+
+    /// Language codex should respond in, as a name or BCP 47 tag, e.g.
+    /// `Japanese`, `ja`, or `pt-BR`. The server adds the instruction to the
+    /// prompt and records the language with the session and in
+    /// `effective_options`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// Maximum number of characters of `agent_messages` to return. Longer
+    /// messages keep their beginning and end around a truncation marker, and
+    /// the full text is available from `agent_messages_uri`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_chars: Option<usize>,
+
+    /// JSON Schema the final agent message must match. Codex is told to
+    /// answer with JSON only; the answer is validated, codex is asked once to
+    /// correct a mismatch, and the parsed value is returned as
+    /// `structured_answer`. A run whose answer still does not match fails with
+    /// `output_schema_mismatch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_output_schema: Option<serde_json::Value>,
+
+    /// Stop the run once its cost, computed from token usage and the server's
+    /// `model_prices`, exceeds this many USD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+
+    /// Record how long spawning codex, its first event, its first agent
+    /// message, and the whole run took, returned as `timings` and aggregated
+    /// in `codex_usage`. For diagnosing slow runs.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub collect_timings: bool,
+
+    /// File or FIFO to copy every raw JSONL event line to as it arrives, for
+    /// tailing the run from outside. Must lie within the server's
+    /// `allowed_stream_dirs`; a FIFO must already have a reader.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_to: Option<PathBuf>,
+
+    /// When to stop reading codex's events: `first_turn` (default) at the
+    /// first completed turn, or `process_exit` to collect every turn of flows
+    /// that run several per invocation, with their messages joined.
+    #[serde(default)]
+    pub stop_on: StopOn,
+
+    /// How the result is returned: `json` (default), one JSON text block;
+    /// or `text`, the final agent message as plain text followed by the
+    /// session ID, usage, changed files, and the rest as structured content.
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+
+    /// Stop the run once codex starts more than this many turns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<u32>,
+
+    /// Stop the run once codex starts more than this many shell commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_commands: Option<u32>,
+
+    /// Commit the files codex changed on a branch after a successful
+    /// write-enabled run. The commit SHA is returned as `commit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitOptions>,
+
+    /// After a successful write-enabled run, run the server's formatters on
+    /// the files it changed. What they changed is returned as `formatting`
+    /// and included in `patch` and `commit`.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub post_format: bool,
+
+    /// Client-chosen key identifying this request. If a run with the same key
+    /// already succeeded, its stored result is returned (with
+    /// `idempotent_replay` set) instead of running codex again, so retrying a
+    /// timed-out call does not spend tokens twice. Reusing a key for a
+    /// different request is an error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+
+    /// Files an earlier attempt of this run changed before codex died,
+    /// included in the resumed attempt's `patch` and `commit`.
+    #[serde(skip)]
+    pub(crate) changed_before_resume: Vec<String>,
+}
+
+/// How to commit a run's changes.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CommitOptions {
+    /// Branch to commit on; created from the current `HEAD` if it does not exist.
+    pub branch: String,
+
+    /// Commit message. `{summary}` is replaced with the first line of the
+    /// agent's final message and `{session_id}` with the session ID; a
+    /// `Codex-Session` trailer is appended if the template does not use it.
+    #[serde(default = "default_commit_message_template")]
+    pub message_template: String,
+
+    /// Push the branch and open a pull request (merge request on GitLab) for
+    /// it on the server's `vcs_provider`. The URL is returned as
+    /// `pull_request_url`. Requires the `vcs` build feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<PullRequestOptions>,
+}
+
+/// How to open a pull request for a committed run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PullRequestOptions {
+    /// Pull request title. Defaults to the first line of the commit message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Branch to merge into. Defaults to the repository's default branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+
+    /// Open the pull request as a draft.
+    #[serde(default)]
+    pub draft: bool,
+}
+
+fn default_commit_message_template() -> String {
+    "{summary}".to_string()
+}
+
+impl CommitOptions {
+    fn message(&self, session_id: &str, agent_messages: &str) -> String {
+        let summary = agent_messages
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .unwrap_or("Apply codex changes");
+        let mut message = self
+            .message_template
+            .replace("{summary}", summary)
+            .replace("{session_id}", session_id);
+        if !self.message_template.contains("{session_id}") {
+            message.push_str(&format!("\n\nCodex-Session: {session_id}"));
+        }
+        message
+    }
+}
+
+/// Pull request description: the agent's final message and the session it came from.
+#[cfg_attr(not(feature = "vcs"), allow(dead_code))]
+fn pull_request_body(session_id: &str, agent_messages: &str) -> String {
+    format!("{}\n\n---\nCodex session: `{session_id}`", agent_messages.trim())
+}
+
+/// A commit created for a run's changes.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CommitInfo {
+    pub branch: String,
+    pub sha: String,
+}
+
+/// Parameters for the codex_read_transcript tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReadTranscriptParams {
+    /// Session whose transcript to read.
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Index of the first event to return.
+    #[serde(default)]
+    pub offset: usize,
+
+    /// Maximum number of events to return (default 100, at most 500).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+
+    /// Return the page gzip-compressed and base64-encoded, as
+    /// `events_compressed` with its `encoding`, instead of as `events`.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub compress: bool,
+}
+
+/// Result returned by the codex_read_transcript tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReadTranscriptResult {
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Where the events came from: `server` (transcripts stored by this
+    /// server, one per run, oldest first) or `codex_rollout` (the codex CLI's
+    /// own session log).
+    pub source: String,
+
+    /// Total number of events in the transcript.
+    pub total: usize,
+
+    /// Index of the first returned event.
+    pub offset: usize,
+
+    /// The requested page of events; empty when `compress` was set.
+    pub events: Vec<serde_json::Value>,
+
+    /// The page as a JSON array, encoded as declared in `encoding`, when
+    /// `compress` was set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events_compressed: Option<String>,
+
+    /// Encoding of `events_compressed`: `gzip+base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+
+    /// Offset of the next page, absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+}
+
+/// Parameters for the codex_explain_repo tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExplainRepoParams {
+    /// Repository to explain. Either a path or the name of a workspace
+    /// configured on the server.
+    pub cd: PathBuf,
+
+    /// Produce a fresh overview even if one is cached for the current commit.
+    #[serde(default, deserialize_with = "deserialize_bool_from_string_or_bool")]
+    pub refresh: bool,
+}
+
+/// Result returned by the codex_explain_repo tool.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ExplainRepoResult {
+    pub success: bool,
+
+    /// Whether the overview came from the cache rather than a new codex run.
+    pub cached: bool,
+
+    /// Commit the overview describes. Absent outside a git repository, where
+    /// overviews are not cached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+
+    /// The repository overview.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+
+    /// Codex session that produced the overview; resume it to ask follow-up questions.
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// Unix timestamp of when the overview was produced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+/// Parameters for the codex_memory_get tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryGetParams {
+    /// Workspace whose notes to return. Either the name of a workspace
+    /// configured on the server or a path inside one.
+    pub cd: PathBuf,
+}
+
+/// Parameters for the codex_memory_append tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryAppendParams {
+    /// Workspace to record the note for. Either the name of a workspace
+    /// configured on the server or a path inside one.
+    pub cd: PathBuf,
+
+    /// Convention, decision, or fact later runs should know about. Recorded
+    /// as one Markdown list item.
+    pub note: String,
+}
+
+/// Result returned by the codex_memory_get and codex_memory_append tools.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryResult {
+    /// Resolved workspace directory.
+    pub cd: PathBuf,
+
+    /// The workspace's notes file.
+    pub path: PathBuf,
+
+    /// Contents of the notes file; empty when nothing was recorded yet.
+    pub notes: String,
+
+    /// Whether the notes exceed the server's `memory_max_bytes`, so the
+    /// oldest ones are left out of prompts.
+    pub truncated_in_prompts: bool,
+}
+
+/// Parameters for the codex_compare tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CompareParams {
+    /// Models to run the prompt against (at most 8). Defaults to the
+    /// server's `auto_models`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// The run to compare, as for the `codex` tool. It always runs read-only
+    /// in a fresh session; `model` is ignored in favor of `models`.
+    #[serde(flatten)]
+    pub codex: CodexParams,
+}
+
+/// One model's run in a codex_compare result.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ComparedRun {
+    pub model: String,
+
+    /// Wall-clock duration of the run.
+    pub duration_ms: u64,
+
+    pub result: CodexResult,
+}
+
+/// Result returned by the codex_compare tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CompareResult {
+    /// One entry per model, in the order the models were given.
+    pub runs: Vec<ComparedRun>,
+}
+
+/// Parameters for the codex_poll and codex_result tools.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunIdParams {
+    /// Run ID returned by `codex_start`.
+    pub run_id: String,
+}
+
+/// Prompt sent when resuming a background run interrupted by a server restart.
+const INTERRUPTED_RUN_PROMPT: &str = "Your previous turn was interrupted because the server running you restarted. \
+Check the current state of the workspace and continue the original task from where you left off.";
+
+/// Prefix of a `PROMPT` to be read from a resource or workspace file.
+const PROMPT_RESOURCE_PREFIX: &str = "resource:";
+
+/// Prompt sent when resuming a run whose codex process died mid-turn.
+const CRASHED_RUN_PROMPT: &str = "Your previous turn was interrupted because the codex process exited unexpectedly. \
+Check the current state of the workspace and continue the original task from where you left off.";
+
+/// Default and maximum page sizes of codex_read_transcript.
+const TRANSCRIPT_PAGE_DEFAULT: usize = 100;
+const TRANSCRIPT_PAGE_MAX: usize = 500;
+
+/// Most models `codex_compare` runs at once.
+const MAX_COMPARE_MODELS: usize = 8;
+
+/// How often stored run output is checked against `retention_days`.
+const RETENTION_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Default and maximum numbers of sessions returned by codex_search_sessions.
+const SEARCH_RESULTS_DEFAULT: usize = 20;
+const SEARCH_RESULTS_MAX: usize = 100;
+
+/// Parameters for the codex_search_sessions tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SearchSessionsParams {
+    /// Text to look for (case-insensitive), e.g. a topic or a file path.
+    pub query: String,
+
+    /// Maximum number of sessions to return (default 20, at most 100).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Result returned by the codex_search_sessions tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SearchSessionsResult {
+    /// Matching sessions, most recently used first.
+    pub sessions: Vec<SessionHit>,
+
+    /// Whether more sessions matched than `limit`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Parameters for the codex_purge_session tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PurgeSessionParams {
+    /// Session whose stored data to delete.
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Also delete the codex CLI's own log of the session under `~/.codex/sessions`,
+    /// after which it can no longer be resumed.
+    #[serde(default)]
+    pub include_codex_rollout: bool,
+}
+
+/// Result returned by the codex_purge_session tool: what was deleted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PurgeSessionResult {
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Whether the session's registry entry (label, tags, etc.) was deleted.
+    pub registry_entry: bool,
+
+    /// Stored transcripts and full agent messages.
+    pub stored_outputs: usize,
+
+    /// Run artifact directories under `artifacts_dir`.
+    pub artifact_dirs: usize,
+
+    /// Finished background runs, with their parameters and results.
+    pub background_runs: usize,
+
+    /// Idempotency keys whose saved result came from the session.
+    pub idempotency_keys: usize,
+
+    /// Cached `codex_explain_repo` overviews produced in the session.
+    pub repo_overviews: usize,
+
+    /// Whether the codex CLI's session log was deleted.
+    pub codex_rollout: bool,
+}
+
+/// Parameters for the codex_list_sessions tool.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ListSessionsParams {
+    /// Only return sessions carrying all of these tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Only return sessions whose label contains this text (case-insensitive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Result returned by the codex_list_sessions tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListSessionsResult {
+    /// Matching sessions, most recently used first.
+    pub sessions: Vec<SessionRecord>,
 }
 
-fn default_true() -> bool {
-    true
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 fn deserialize_bool_from_string_or_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
@@ -140,8 +803,17 @@ where
     deserializer.deserialize_any(BoolVisitor)
 }
 
+fn deserialize_option_bool_from_string_or_bool<'de, D>(
+    deserializer: D,
+) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_bool_from_string_or_bool(deserializer).map(Some)
+}
+
 /// Result returned by the codex tool.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct CodexResult {
     /// Whether the execution was successful.
     pub success: bool,
@@ -154,26 +826,662 @@ pub struct CodexResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agent_messages: Option<String>,
 
+    /// Whether codex finished the run without a final message, in which case
+    /// `agent_messages` is absent but the run still succeeded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_final_message: bool,
+
+    /// Commands the sandbox blocked, so callers can re-run with a more
+    /// permissive `sandbox` instead of parsing the agent's prose.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sandbox_denials: Vec<SandboxDenial>,
+
+    /// Approval requests codex made under `approval_bridge`, and how each
+    /// was decided.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<ApprovalDecision>,
+
+    /// Files the run created, modified, deleted, or renamed, relative to
+    /// `cd`: those codex edited, plus, for write-enabled runs, those found by
+    /// comparing the workspace before and after (e.g. generated files).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_changed: Vec<FileChange>,
+
+    /// Whether `agent_messages` was shortened to `max_output_chars`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+
+    /// Resource URI of the untruncated `agent_messages`, when it was truncated
+    /// and the server has a state directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_messages_uri: Option<String>,
+
+    /// Reasoning summaries emitted by the model, in order (only included when
+    /// include_reasoning is true).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_summary: Option<Vec<String>>,
+
     /// Error message if execution failed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 
+    /// Machine-readable error code (e.g. `workspace_busy`, `approval_required`)
+    /// when the failure has a known cause.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+
+    /// How the codex process ended, when the run failed and codex exited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit: Option<ProcessExit>,
+
     /// All messages from the session (only included when return_all_messages is true).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub all_messages: Option<Vec<serde_json::Value>>,
-}
 
-/// The Codex MCP Server.
-#[derive(Clone)]
-pub struct CodexServer {
-    tool_router: ToolRouter<Self>,
+    /// Resource URI of the stored transcript when `all_messages` was too large
+    /// to return inline. Read it with `resources/read`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_messages_uri: Option<String>,
+
+    /// Number of events in the transcript referenced by `all_messages_uri`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_messages_count: Option<usize>,
+
+    /// `all_messages` when `compress` was set: the JSON array, encoded as
+    /// declared in `encoding`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_messages_compressed: Option<String>,
+
+    /// Encoding of the compressed payloads in this result: `gzip+base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+
+    /// Number of events the run produced, when `messages_offset` or
+    /// `messages_limit` made `all_messages` a page of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_events: Option<usize>,
+
+    /// `offset` to pass to `codex_read_transcript` for the events after this
+    /// page, absent on the last page or when the events were not stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_transcript_offset: Option<usize>,
+
+    /// Unified diff of the files codex changed, against `HEAD`, capped at the
+    /// server's `patch_max_bytes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+
+    /// Whether `patch` was cut off at the size cap.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub patch_truncated: bool,
+
+    /// What the formatters changed, with `post_format`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatting: Option<FormatReport>,
+
+    /// Commit created for this run's changes when `commit` was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitInfo>,
+
+    /// Why the requested commit could not be created. The run itself succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_error: Option<String>,
+
+    /// URL of the pull request opened when `commit.pull_request` was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request_url: Option<String>,
+
+    /// Why the requested pull request could not be opened. The commit still exists locally.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request_error: Option<String>,
+
+    /// Policy rules the request violated, when `error_code` is `policy_denied`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policy_violations: Vec<PolicyViolation>,
+
+    /// Token usage reported by codex for this run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+
+    /// Non-JSON lines codex printed among its events (e.g. log output),
+    /// which do not fail the run as long as JSON events follow them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unparsed_lines: Vec<String>,
+
+    /// Error messages codex reported that the server's `noise_filters`
+    /// classify as warnings rather than failures.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+
+    /// The model's plan as of its last update, in order, for showing progress
+    /// or resuming with e.g. "continue from step 3".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plan: Vec<PlanStep>,
+
+    /// Number of turns codex completed, with `stop_on: process_exit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turns_completed: Option<u32>,
+
+    /// Seconds the model provider asked to wait, when `error_code` is `rate_limited`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+
+    /// Cost of `usage` in USD, when the server has a price for the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+
+    /// Timing breakdown of the run, with `collect_timings`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<RunTimings>,
+
+    /// Options actually used for the run after server policy was applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effective_options: Option<EffectiveOptions>,
+
+    /// Codex version, model, OS, and git remote the run executed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<RunEnvironment>,
+
+    /// Directory holding this run's transcript, stderr log, diff, and
+    /// `run.json`, when the server is configured with `artifacts_dir`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts_dir: Option<PathBuf>,
+
+    /// Fingerprint of the prompt, for spotting duplicate requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_fingerprint: Option<String>,
+
+    /// Fingerprint of `agent_messages`, for spotting identical answers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_fingerprint: Option<String>,
+
+    /// ID of this run in the server's log lines, to quote when reporting a problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// Whether this is the stored result of an earlier run with the same
+    /// `idempotency_key` rather than a new run.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub idempotent_replay: bool,
+
+    /// The final agent message parsed as JSON, with `expected_output_schema`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_answer: Option<serde_json::Value>,
+
+    /// Session that was summarized into this one, with `summarize_session`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_session_id: Option<String>,
+
+    /// Summary of `previous_session_id` the new session was seeded with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_summary: Option<String>,
+
+    /// Whether codex died mid-turn and the run was resumed in its session
+    /// (`resume_crashed_runs`); usage and events cover both attempts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub resumed_after_crash: bool,
+
+    /// The result rendered as Markdown, with the `markdown` post-processor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<String>,
+
+    /// Whether codex exited unsuccessfully before its turn completed.
+    #[serde(skip)]
+    pub(crate) crashed_mid_turn: bool,
+
+    /// Files codex reported changing.
+    #[serde(skip)]
+    pub(crate) changed_files: Vec<String>,
+}
+
+/// The codex invocation actually used for a run, after server policy was applied.
+///
+/// Compare against the request to see whether the server downgraded or
+/// overrode any requested option.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EffectiveOptions {
+    /// Sandbox policy passed to codex.
+    pub sandbox: SandboxPolicy,
+
+    /// Approval policy in effect: `never` for `codex exec`, `on-request` under
+    /// `approval_bridge`, or `bypass` with `yolo`.
+    pub approval_policy: String,
+
+    /// Workspace root passed to codex.
+    pub cd: PathBuf,
+
+    /// Model passed to codex, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Configuration profile passed to codex, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Reasoning effort passed to codex, after adapting the request to the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+
+    /// Text verbosity passed to codex, after adapting the request to the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_verbosity: Option<TextVerbosity>,
+
+    /// Whether the run uses a local model server (`--oss`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oss: bool,
+
+    /// Session being resumed, if any.
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// Whether the most recent CLI session was resumed.
+    pub resume_last: bool,
+
+    /// Attached image files.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<PathBuf>,
+
+    /// Resolved base instructions file, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_instructions_file: Option<PathBuf>,
+
+    /// Resolved extra writable roots.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub writable_roots: Vec<PathBuf>,
+
+    /// Language codex was instructed to respond in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// Focus paths injected into the prompt, relative to `cd`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub focus_paths: Vec<PathBuf>,
+
+    /// Resolved context files named in the prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_files: Vec<PathBuf>,
+
+    /// Resolved `stream_to` target, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_to: Option<PathBuf>,
+
+    /// Network access for `workspace-write` runs; absent for other sandboxes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_access: Option<bool>,
+
+    /// Whether approvals and sandboxing were bypassed.
+    pub yolo: bool,
+
+    /// OS-level sandbox codex was wrapped in (`os_sandbox`), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_sandbox: Option<Wrapper>,
+
+    /// Whether the Git repository check was skipped.
+    pub skip_git_repo_check: bool,
+
+    /// Full CLI arguments passed to codex, excluding the prompt.
+    pub args: Vec<String>,
+
+    /// How the model was chosen when `auto` was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_model: Option<AutoModelChoice>,
+}
+
+impl EffectiveOptions {
+    /// Whether codex may modify files in the workspace.
+    fn is_write_enabled(&self) -> bool {
+        self.yolo || !matches!(self.sandbox, SandboxPolicy::ReadOnly)
+    }
+
+    /// Whether codex asks for approval, with requests forwarded to the client.
+    fn bridges_approvals(&self) -> bool {
+        self.approval_policy == ON_REQUEST
+    }
+
+    /// Build the `codex` CLI arguments for these options, excluding the prompt.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            "exec".to_string(),
+            "--sandbox".to_string(),
+            self.sandbox.as_str().to_string(),
+            "--cd".to_string(),
+            self.cd.display().to_string(),
+            "--json".to_string(),
+        ];
+
+        // One flag per image: a comma-joined list breaks on paths containing commas.
+        for image in &self.images {
+            args.push("--image".to_string());
+            args.push(image.display().to_string());
+        }
+
+        if self.oss {
+            args.push("--oss".to_string());
+        }
+
+        if let Some(ref model) = self.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+
+        if let Some(ref profile) = self.profile {
+            args.push("--profile".to_string());
+            args.push(profile.clone());
+        }
+
+        if let Some(effort) = self.reasoning_effort {
+            args.push("-c".to_string());
+            args.push(config_override(tuning::REASONING_EFFORT_KEY, &toml_string(effort.as_str())));
+        }
+
+        if let Some(verbosity) = self.text_verbosity {
+            args.push("-c".to_string());
+            args.push(config_override(tuning::VERBOSITY_KEY, &toml_string(verbosity.as_str())));
+        }
+
+        if let Some(ref file) = self.base_instructions_file {
+            args.push("-c".to_string());
+            args.push(config_override(
+                INSTRUCTIONS_FILE_KEY,
+                &toml_string(&file.display().to_string()),
+            ));
+        }
+
+        if !self.writable_roots.is_empty() {
+            let roots: Vec<String> = self
+                .writable_roots
+                .iter()
+                .map(|p| toml_string(&p.display().to_string()))
+                .collect();
+            args.push("-c".to_string());
+            args.push(config_override(
+                WRITABLE_ROOTS_KEY,
+                &format!("[{}]", roots.join(", ")),
+            ));
+        }
+
+        if let Some(enabled) = self.network_access {
+            args.push("-c".to_string());
+            args.push(config_override(NETWORK_ACCESS_KEY, &enabled.to_string()));
+        }
+
+        if self.bridges_approvals() {
+            args.push("-c".to_string());
+            args.push(config_override(APPROVAL_POLICY_KEY, &toml_string(ON_REQUEST)));
+        }
+
+        if self.yolo {
+            args.push("--yolo".to_string());
+        }
+
+        if self.skip_git_repo_check {
+            args.push("--skip-git-repo-check".to_string());
+        }
+
+        // Handle session resumption
+        if let Some(ref session_id) = self.session_id {
+            args.push("resume".to_string());
+            args.push(session_id.clone());
+        } else if self.resume_last {
+            args.push("resume".to_string());
+            args.push("--last".to_string());
+        }
+
+        args
+    }
+}
+
+/// Where a run was requested from, and who watches it as it progresses.
+#[derive(Clone, Copy)]
+pub(crate) struct RunContext<'a> {
+    /// Tool (or `scheduler`) the run was requested through, matched by policy rules.
+    pub tool: &'a str,
+    pub observer: Option<&'a dyn RunObserver>,
+    /// ID of the background run, for `codex_server_status`.
+    pub run_id: Option<&'a str>,
+    /// Correlation ID already given to the run; a new one is generated otherwise.
+    pub correlation_id: Option<&'a str>,
+    /// Client asked to decide codex's approval requests under `approval_bridge`.
+    pub peer: Option<&'a Peer<RoleServer>>,
+}
+
+impl<'a> RunContext<'a> {
+    pub fn tool(tool: &'a str) -> Self {
+        Self {
+            tool,
+            observer: None,
+            run_id: None,
+            correlation_id: None,
+            peer: None,
+        }
+    }
+}
+
+/// The Codex MCP Server.
+#[derive(Clone)]
+pub struct CodexServer {
+    tool_router: ToolRouter<Self>,
+    /// Settings that change when the config file is reloaded.
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+    registry: Arc<SessionRegistry>,
+    workspace_locks: Arc<WorkspaceLocks>,
+    usage: Arc<UsageTracker>,
+    resources: ResourceStore,
+    uploads: Arc<UploadStore>,
+    explain_cache: Arc<ExplainCache>,
+    jobs: Arc<JobTable>,
+    rules: Arc<RwLock<Arc<PolicyRules>>>,
+    idempotency: Arc<IdempotencyStore>,
+    model_stats: Arc<ModelStats>,
+    throttle: Arc<Throttle>,
+    status: Arc<StatusTracker>,
+    capabilities: Arc<CapabilityTracker>,
+    /// Cancelled when the server shuts down, stopping the runs in progress.
+    shutdown: CancellationToken,
+    /// `codex --version`, probed on the first run.
+    codex_version: Arc<OnceCell<Option<String>>>,
+    /// Identity of the connected client on multi-client transports.
+    client: Option<String>,
 }
 
 #[tool_router]
 impl CodexServer {
-    pub fn new() -> Self {
+    /// Create the server, loading any persisted state from `config.state_dir`.
+    pub fn new(config: ServerConfig) -> anyhow::Result<Self> {
+        let state_dir = config.state_dir.as_deref();
+        let store = state::open_store(&config)?;
+        let registry = SessionRegistry::open(store.clone())?;
+        let usage = UsageTracker::open(store.clone().filter(|_| config.persist_usage))?;
+        let resources = ResourceStore::new(state_dir);
+        let uploads = UploadStore::new(state_dir);
+        let explain_cache = ExplainCache::open(store.clone())?;
+        let jobs = JobTable::open(store.clone())?;
+        let idempotency = IdempotencyStore::open(store.clone())?;
+        let model_stats = ModelStats::open(store.clone())?;
+        let throttle = Throttle::new(store);
+        let rules = match config.policy_file {
+            Some(ref path) => PolicyRules::load(path)?,
+            None => PolicyRules::default(),
+        };
+        let mut tool_router = Self::tool_router();
+        #[cfg(feature = "screenshot")]
+        {
+            tool_router += Self::screenshot_router();
+        }
+        filter_tools(&mut tool_router, &config)?;
+        if let Some(ref prefix) = config.tool_name_prefix {
+            prefix_tools(&mut tool_router, prefix)?;
+        }
+        Ok(Self {
+            tool_router,
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            registry: Arc::new(registry),
+            workspace_locks: Arc::new(WorkspaceLocks::default()),
+            usage: Arc::new(usage),
+            resources,
+            uploads: Arc::new(uploads),
+            explain_cache: Arc::new(explain_cache),
+            jobs: Arc::new(jobs),
+            rules: Arc::new(RwLock::new(Arc::new(rules))),
+            idempotency: Arc::new(idempotency),
+            model_stats: Arc::new(model_stats),
+            throttle: Arc::new(throttle),
+            status: Arc::new(StatusTracker::default()),
+            capabilities: Arc::new(CapabilityTracker::default()),
+            shutdown: CancellationToken::new(),
+            codex_version: Arc::new(OnceCell::new()),
+            client: None,
+        })
+    }
+
+    /// The current server config.
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Locale of error messages for a request in `language`, if any.
+    pub(crate) fn locale(&self, language: Option<&str>) -> Locale {
+        Locale::resolve(self.config().locale, language)
+    }
+
+    /// `e` as a tool call error, with its localized message and `error_code`.
+    fn tool_error(&self, e: &CodexError) -> McpError {
+        McpError::invalid_params(
+            i18n::message(e, self.locale(None)),
+            Some(serde_json::json!({"error_code": e.code()})),
+        )
+    }
+
+    fn rules(&self) -> Arc<PolicyRules> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Apply a reloaded `config` to this server and every client sharing it,
+    /// reloading its policy rules too. Settings only read at startup keep
+    /// their current values; the names of those that changed are returned.
+    pub fn reload_config(&self, mut config: ServerConfig) -> anyhow::Result<Vec<&'static str>> {
+        let rules = match config.policy_file {
+            Some(ref path) => PolicyRules::load(path)?,
+            None => PolicyRules::default(),
+        };
+        let mut current = self.config.write().unwrap();
+        let kept = config.keep_restart_only(&current);
+        *self.rules.write().unwrap() = Arc::new(rules);
+        *current = Arc::new(config);
+        Ok(kept)
+    }
+
+    /// Probe the installed codex if it changed since it was last probed,
+    /// telling connected clients when that changes the tools offered.
+    pub async fn refresh_capabilities(&self) {
+        let Ok(codex_path) = self.codex_path() else {
+            return;
+        };
+        if self.capabilities.refresh(&codex_path).await {
+            self.capabilities.notify_tool_list_changed().await;
+        }
+    }
+
+    /// Run the startup self-test against the configured codex, failing with
+    /// a diagnosis if it does not reply.
+    pub async fn self_test(&self) -> anyhow::Result<()> {
+        let codex_path = self.codex_path()?;
+        tracing::info!(codex = %codex_path.display(), "Running codex self-test");
+        let reply = self_test::run(&codex_path, &self.config()).await?;
+        tracing::info!(%reply, "Codex self-test passed");
+        Ok(())
+    }
+
+    /// Re-probe codex in the background whenever its binary changes.
+    pub fn watch_capabilities(&self) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(capabilities::CHECK_INTERVAL).await;
+                server.refresh_capabilities().await;
+            }
+        });
+    }
+
+    /// Delete run output past `retention_days` now and then hourly, reading
+    /// the setting anew each time so reloads apply.
+    pub fn enforce_retention(&self) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                server.remove_expired_output();
+                tokio::time::sleep(RETENTION_SWEEP_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Delete the stored outputs and artifact directories older than `retention_days`.
+    fn remove_expired_output(&self) {
+        let config = self.config();
+        let Some(days) = config.retention_days.filter(|days| *days > 0) else {
+            return;
+        };
+        let max_age = Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+        let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+        match self.resources.remove_older_than(cutoff) {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!(removed, retention_days = days, "Deleted stored run outputs past retention"),
+            Err(e) => tracing::warn!(error = %e, "Failed to delete stored run outputs past retention"),
+        }
+        if let Some(ref root) = config.artifacts_dir {
+            let cutoff_millis = cutoff.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+            match artifacts::remove_older_than(root, cutoff_millis) {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(removed, retention_days = days, "Deleted run artifacts past retention"),
+                Err(e) => tracing::warn!(error = %e, "Failed to delete run artifacts past retention"),
+            }
+        }
+    }
+
+    /// The tools offered to clients: those enabled in the config that the
+    /// installed codex supports, without the parameters it does not.
+    fn tools(&self) -> ToolRouter<Self> {
+        let capabilities = self.capabilities.current();
+        let prefix = self.config().tool_name_prefix.clone().unwrap_or_default();
+        let mut router = self.tool_router.clone();
+        for name in capabilities.unsupported_tools() {
+            router.remove_route(&format!("{prefix}{name}"));
+        }
+        let params = capabilities.unsupported_params();
+        if !params.is_empty() {
+            for route in router.map.values_mut() {
+                let schema = Arc::make_mut(&mut route.attr.input_schema);
+                if let Some(serde_json::Value::Object(properties)) = schema.get_mut("properties") {
+                    properties.retain(|name, _| !params.contains(&name.as_str()));
+                }
+            }
+        }
+        router
+    }
+
+    /// Reject parameters the installed codex does not support.
+    fn check_capabilities(&self, params: &CodexParams) -> Result<(), CodexError> {
+        let used = [
+            ("image", !params.image.is_empty()),
+            ("profile", params.profile.is_some()),
+            ("SESSION_ID", params.session_id.is_some()),
+            ("resume_last", params.resume_last),
+            ("summarize_session", params.summarize_session),
+            ("oss", params.oss == Some(true)),
+        ];
+        let unsupported = self.capabilities.current().unsupported_params();
+        match used.into_iter().find(|(name, used)| *used && unsupported.contains(name)) {
+            Some((name, _)) => Err(CodexError::UnsupportedByCodex(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// A handle for one client of a multi-client transport, sharing all state
+    /// but scoping sessions and resources to `client` unless `shared_sessions` is set.
+    pub fn for_client(&self, client: String) -> Self {
         Self {
-            tool_router: Self::tool_router(),
+            client: Some(client),
+            ..self.clone()
         }
     }
 
@@ -198,270 +1506,2266 @@ It supports resuming ongoing sessions for continuity and enforces sandbox polici
 **Edge Cases & Best Practices:**
     - Ensure `cd` exists and is accessible; tool fails silently on invalid paths.
     - For most repos, prefer "read-only" to avoid accidental changes.
-    - If needed, set `return_all_messages` to `True` to parse "all_messages" for detailed tracing (e.g., reasoning, tool calls, etc.)."#
+    - If needed, set `return_all_messages` to `True` to parse "all_messages" for detailed tracing (e.g., reasoning, tool calls, etc.), and `event_filter` (e.g. `["command_execution", "file_change"]`) to keep only the event types you need."#
     )]
     pub async fn codex(
         &self,
         params: Parameters<CodexParams>,
+        request: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
-        let result = match self.execute_codex(params.0).await {
-            Ok(r) => r,
-            Err(e) => CodexResult {
-                success: false,
-                session_id: None,
-                agent_messages: None,
-                error: Some(e.to_string()),
-                all_messages: None,
-            },
+        let format = params.0.response_format;
+        let context = RunContext {
+            peer: Some(&request.peer),
+            ..RunContext::tool("codex")
         };
+        let result = self.run_codex(params.0, context).await;
+        Ok(tool_result(&result, format))
+    }
 
-        let json_str = serde_json::to_string_pretty(&result)
-            .unwrap_or_else(|_| format!("{:?}", result));
+    /// Starts a codex run in the background and returns its run ID immediately.
+    #[tool(
+        name = "codex_start",
+        description = r#"Starts a codex run in the background and returns a `run_id` immediately, for clients whose tool calls time out before long runs finish.
+Takes the same parameters as the `codex` tool. Check progress with `codex_poll` and fetch the final result with `codex_result` once `status` is `completed`."#
+    )]
+    pub async fn codex_start(
+        &self,
+        params: Parameters<CodexParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let job = self.jobs.create(self.client.clone(), params.0.clone());
+        self.spawn_job(job.clone(), params.0);
+
+        let json_str = serde_json::to_string_pretty(&job.status())
+            .unwrap_or_else(|_| format!("{:?}", job.status()));
 
         Ok(CallToolResult::success(vec![Content::text(json_str)]))
     }
-}
 
-impl CodexServer {
-    /// Execute the codex CLI command and process its output.
-    async fn execute_codex(&self, params: CodexParams) -> Result<CodexResult, CodexError> {
-        // Find the codex executable
-        let codex_path = which::which("codex").map_err(|_| CodexError::ExecutableNotFound)?;
+    /// Reports the status and progress of a background run.
+    #[tool(
+        name = "codex_poll",
+        description = r#"Reports the status (`running` or `completed`) and progress of a run started with `codex_start`: elapsed time, number of codex events so far, the latest event type, and the `SESSION_ID` once known."#
+    )]
+    pub async fn codex_poll(
+        &self,
+        params: Parameters<RunIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let job = self.job(&params.0.run_id)?;
+        let result: JobStatusReport = job.status();
 
-        // Fail fast with a clearer error than whatever the CLI might emit.
-        if !params.cd.is_dir() {
-            return Err(CodexError::InvalidWorkingDirectory(params.cd));
-        }
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
 
-        // Build command arguments
-        let mut cmd = Command::new(&codex_path);
-        cmd.kill_on_drop(true); // Ensure process is killed when dropped
-        cmd.arg("exec")
-            .arg("--sandbox")
-            .arg(params.sandbox.as_str())
-            .arg("--cd")
-            .arg(&params.cd)
-            .arg("--json");
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
 
-        // Add optional arguments
-        if !params.image.is_empty() {
-            let images: Vec<String> = params.image.iter().map(|p| p.display().to_string()).collect();
-            cmd.arg("--image").arg(images.join(","));
-        }
+    /// Returns the result of a finished background run.
+    #[tool(
+        name = "codex_result",
+        description = r#"Returns the final result of a run started with `codex_start`, in the same form as the `codex` tool. Fails with `run_in_progress` while the run is still going; use `codex_poll` to wait for it."#
+    )]
+    pub async fn codex_result(
+        &self,
+        params: Parameters<RunIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let job = self.job(&params.0.run_id)?;
+        let Some(result) = job.result() else {
+            let e = CodexError::RunInProgress(job.id());
+            return Err(self.tool_error(&e));
+        };
+        Ok(tool_result(&result, job.params().response_format))
+    }
 
-        if let Some(ref model) = params.model {
-            if !model.is_empty() {
-                cmd.arg("--model").arg(model);
-            }
-        }
+    /// Lists codex sessions started through this server, optionally filtered by tags or label.
+    #[tool(
+        name = "codex_list_sessions",
+        description = r#"Lists codex sessions started through this server, most recently used first.
+Each entry includes the `SESSION_ID` (pass it to the `codex` tool to resume), workspace, label, tags, and run statistics.
+Filter by `tags` (sessions must carry all of them) and/or `label` (case-insensitive substring) to find the right workstream among many concurrent ones."#
+    )]
+    pub async fn codex_list_sessions(
+        &self,
+        params: Parameters<ListSessionsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let filter = SessionFilter {
+            tags: params.0.tags,
+            label: params.0.label.filter(|l| !l.is_empty()),
+            owner: self.isolated_client().map(str::to_string),
+            ..SessionFilter::default()
+        };
+        let result = ListSessionsResult {
+            sessions: self.registry.list(&filter),
+        };
 
-        if let Some(ref profile) = params.profile {
-            if !profile.is_empty() {
-                cmd.arg("--profile").arg(profile);
-            }
-        }
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
 
-        if params.yolo {
-            cmd.arg("--yolo");
-        }
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
 
-        if params.skip_git_repo_check {
-            cmd.arg("--skip-git-repo-check");
-        }
+    /// Searches past sessions' metadata and transcripts for a query.
+    #[tool(
+        name = "codex_search_sessions",
+        description = r#"Searches the sessions started through this server for `query` (case-insensitive): their label, tags, and workspace, and every message, command, and changed file path in their transcripts.
+Returns matching sessions, most recently used first, each with up to three context snippets and its `SESSION_ID` for `codex_read_transcript` or resuming. Pass `limit` to return more than 20 (max 100)."#
+    )]
+    pub async fn codex_search_sessions(
+        &self,
+        params: Parameters<SearchSessionsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.search_sessions(params.0).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Returns a page of the stored transcript of a past session.
+    #[tool(
+        name = "codex_read_transcript",
+        description = r#"Returns the event transcript of a past codex session by `SESSION_ID`, for reviewing what a previous run actually did (commands, file changes, messages).
+Events are paged: pass `offset` and `limit` (default 100, max 500) and follow `next_offset` until it is absent. Uses transcripts stored by this server when available, otherwise the codex CLI's own session log."#
+    )]
+    pub async fn codex_read_transcript(
+        &self,
+        params: Parameters<ReadTranscriptParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let (source, events) = self.load_transcript(&params.session_id).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let limit = params
+            .limit
+            .unwrap_or(TRANSCRIPT_PAGE_DEFAULT)
+            .clamp(1, TRANSCRIPT_PAGE_MAX);
+        let total = events.len();
+        let end = params.offset.saturating_add(limit).min(total);
+        let mut result = ReadTranscriptResult {
+            session_id: params.session_id,
+            source: source.to_string(),
+            total,
+            offset: params.offset,
+            events: events
+                .into_iter()
+                .skip(params.offset)
+                .take(limit)
+                .collect(),
+            events_compressed: None,
+            encoding: None,
+            next_offset: (end < total).then_some(end),
+        };
+        if params.compress {
+            result.events_compressed = Some(compression::encode_json(&std::mem::take(&mut result.events)));
+            result.encoding = Some(compression::ENCODING.to_string());
+        }
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Deletes everything the server stored about a session.
+    #[tool(
+        name = "codex_purge_session",
+        description = r#"Deletes the data this server stored about a codex session by `SESSION_ID`, e.g. to honor a data deletion request: its registry entry, stored transcripts and agent messages, run artifact directories, finished background runs, saved idempotent results, and cached repository overviews.
+Pass `include_codex_rollout` to also delete the codex CLI's own session log, after which the session cannot be resumed. Returns what was deleted."#
+    )]
+    pub async fn codex_purge_session(
+        &self,
+        params: Parameters<PurgeSessionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.purge_session(params.0).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Returns an overview of a repository, cached per commit.
+    #[tool(
+        name = "codex_explain_repo",
+        description = r#"Returns an overview of a repository (purpose, layout, architecture, tooling, conventions, pitfalls) for bootstrapping context before working on it.
+Runs a built-in analysis prompt in a read-only sandbox, so no prompt needs to be written. The overview is cached by the repository's `HEAD` commit and returned instantly until the commit changes; pass `refresh` to regenerate it."#
+    )]
+    pub async fn codex_explain_repo(
+        &self,
+        params: Parameters<ExplainRepoParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = match self.explain_repo(params.0).await {
+            Ok(r) => r,
+            Err(e) => ExplainRepoResult {
+                success: false,
+                error: Some(i18n::message(&e, self.locale(None))),
+                error_code: Some(e.code().to_string()),
+                ..Default::default()
+            },
+        };
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Returns the project memory of a workspace.
+    #[tool(
+        name = "codex_memory_get",
+        description = r#"Returns the project memory of a workspace: durable notes (conventions, decisions, pitfalls) that the server injects into the prompt of every codex run there, so they carry over across sessions.
+Add notes with `codex_memory_append`; runs can opt out with `skip_memory`."#
+    )]
+    pub async fn codex_memory_get(
+        &self,
+        params: Parameters<MemoryGetParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.memory(&params.0.cd, None).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Appends a note to the project memory of a workspace.
+    #[tool(
+        name = "codex_memory_append",
+        description = r#"Records a note in the project memory of a workspace, a Markdown file in the workspace that the server injects into the prompt of every later codex run there. Use it for conventions and decisions that should outlive the current session (e.g. "Use `thiserror` for new error types").
+Returns the updated memory, as `codex_memory_get` does."#
+    )]
+    pub async fn codex_memory_append(
+        &self,
+        params: Parameters<MemoryAppendParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let result = self.memory(&params.cd, Some(&params.note)).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Runs the same read-only prompt against several models concurrently.
+    #[tool(
+        name = "codex_compare",
+        description = r#"Runs the same prompt against several models concurrently and returns their results side by side, each with its duration, token usage, and cost, for evaluating which model to use by default.
+Takes the same parameters as the `codex` tool plus `models` (defaults to the server's `auto_models`). Runs are always read-only and start fresh sessions; each result's `SESSION_ID` can be resumed with the `codex` tool."#
+    )]
+    pub async fn codex_compare(
+        &self,
+        params: Parameters<CompareParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.compare(params.0).await.map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Reviews the changes of an earlier session in a read-only run.
+    #[tool(
+        name = "codex_self_review",
+        description = r#"Reviews what an earlier session changed: extracts the diff of the files it changed in its workspace and runs codex read-only (optionally with a different `model`) to critique it, for plan → edit → review pipelines.
+Returns a `verdict` (`approve` or `request_changes`), a `summary`, and structured `comments` (each with `path`, optional `line`, `severity` of `critical`, `major`, `minor`, or `nit`, and `comment`), most severe first. `focus` adds what to pay particular attention to; `review_session_id` can be resumed to discuss the review."#
+    )]
+    pub async fn codex_self_review(
+        &self,
+        params: Parameters<SelfReviewParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.self_review(params.0).await.map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Reports the server's current load, limits, and versions.
+    #[tool(
+        name = "codex_server_status",
+        description = r#"Reports the current state of this server, for orchestrators deciding whether to submit more work here or route it elsewhere: counts of `running` and `queued` runs (waiting for a workspace lock or a provider rate limit), each active run with its `run_id`, tool, workspace, model, and elapsed time, uptime, configured limits with today's spend, any rate-limit `throttle`, and the server and codex versions."#
+    )]
+    pub async fn codex_server_status(&self) -> Result<CallToolResult, McpError> {
+        let limits = ServerLimits {
+            workspace_lock: self.config().workspace_lock.as_str().to_string(),
+            rate_limit_max_defer_secs: self.config().rate_limit_max_defer_secs,
+            daily_budget_usd: self.config().daily_budget_usd,
+            spent_today_usd: self.usage.spent_today(),
+        };
+        let mut result = self.status.status(self.isolated_client(), limits);
+        result.throttle = self.throttle.state();
+        if let Ok(codex_path) = self.codex_path() {
+            result.codex_version = self.codex_version(&codex_path).await;
+        }
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Reports cumulative token usage and run statistics.
+    #[tool(
+        name = "codex_usage",
+        description = r#"Reports cumulative token usage, run counts, and success rates for codex runs executed through this server, overall and broken down by model.
+Totals cover the period since `since` (a Unix timestamp): server start, or the first recorded run when the server persists usage across restarts."#
+    )]
+    pub async fn codex_usage(&self) -> Result<CallToolResult, McpError> {
+        let mut result = self.usage.report();
+        result.throttle = self.throttle.state();
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Lists files in a workspace.
+    #[tool(
+        name = "codex_workspace_list",
+        description = r#"Lists the files and directories of a workspace (or of `path` inside it), with each file's size, so the results of a run can be inspected without another codex turn. `cd` must be a workspace configured on the server or lie inside one. Set `recursive` to include subdirectories; `.git` is skipped. Returns at most `limit` entries (default 200), with `truncated` set if there were more."#
+    )]
+    pub async fn codex_workspace_list(
+        &self,
+        params: Parameters<WorkspaceListParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.workspace_list(&params.0).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Reads a file in a workspace.
+    #[tool(
+        name = "codex_workspace_read",
+        description = r#"Reads a text file in a workspace, e.g. to check a file a run changed without another codex turn. `cd` must be a workspace configured on the server or lie inside one, and configured secrets are redacted from the content. Returns at most the server's `workspace_read_max_bytes` (or `max_bytes`) from `offset`; when `truncated` is set, call again with `offset` set to `next_offset` for the rest. Binary files are reported with `binary` set and no content."#
+    )]
+    pub async fn codex_workspace_read(
+        &self,
+        params: Parameters<WorkspaceReadParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self.workspace_read(&params.0).map_err(|e| {
+            self.tool_error(&e)
+        })?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Receives one base64 chunk of a file to use as run context.
+    #[tool(
+        name = "codex_upload_context",
+        description = r#"Uploads a file in base64 chunks to the server, for clients that do not share a filesystem with it (e.g. remote clients), so it can be used as context of a run.
+Omit `upload_id` on the first chunk (giving `file_name`), then pass the returned `upload_id` with each further chunk and set `done` on the last one. The returned `path` is a handle to pass in `context_files` (files codex reads for the task) or `image` of the `codex` tool; only the client that uploaded it can use it. Uploads are held to the server's `max_upload_bytes`; at most 8 can be in progress at once, one without a chunk for an hour is dropped, and assembled files are kept for a day."#
+    )]
+    pub async fn codex_upload_context(
+        &self,
+        params: Parameters<UploadParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self
+            .uploads
+            .append(self.client.as_deref(), &params.0, self.config().max_upload_bytes)
+            .map_err(|e| self.tool_error(&e))?;
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+
+    /// Reports what this deployment supports.
+    #[tool(
+        name = "codex_mcp_version",
+        description = r#"Reports what this deployment supports, for orchestration layers adapting their behavior to it: the server version, supported transports and compiled-in features (`vcs`, `screenshot`, `redis`), the configured state backend and integrations, the tools offered, workspace names, configured limits, and the path, version, and detected capabilities of the installed codex CLI."#
+    )]
+    pub async fn codex_mcp_version(&self) -> Result<CallToolResult, McpError> {
+        let tools = self.tools().list_all().into_iter().map(|tool| tool.name.to_string()).collect();
+        let path = self.codex_path().ok();
+        let version = match path {
+            Some(ref path) => self.codex_version(path).await,
+            None => None,
+        };
+        let codex = CodexCli {
+            path,
+            version,
+            capabilities: self.capabilities.current(),
+        };
+        let result = VersionReport::new(&self.config(), tools, codex);
+
+        let json_str = serde_json::to_string_pretty(&result)
+            .unwrap_or_else(|_| format!("{:?}", result));
+
+        Ok(CallToolResult::success(vec![Content::text(json_str)]))
+    }
+}
+
+impl CodexServer {
+    /// Run codex, reporting errors that prevented the run as a failed result.
+    ///
+    /// Everything logged while the run is going carries its correlation ID.
+    pub(crate) async fn run_codex(&self, params: CodexParams, context: RunContext<'_>) -> CodexResult {
+        let correlation_id = context.correlation_id.map_or_else(correlation::new_id, str::to_string);
+        let span = tracing::info_span!("run", correlation_id = %correlation_id, tool = %context.tool);
+        let prompt_fingerprint = idempotency::fingerprint(&params.prompt);
+        let mut result = async {
+            tracing::debug!("Run requested");
+            match params.idempotency_key.clone() {
+                Some(key) => self.run_idempotent(&key, params, context).await,
+                None => self.execute(params, context).await,
+            }
+        }
+        .instrument(span)
+        .await;
+        result.prompt_fingerprint = Some(prompt_fingerprint);
+        result.result_fingerprint = result.agent_messages.as_deref().map(idempotency::fingerprint);
+        result.correlation_id = Some(correlation_id);
+        result
+    }
+
+    /// Run codex, with configured secrets redacted from the result and the
+    /// configured post-processors applied to it.
+    async fn execute(&self, mut params: CodexParams, context: RunContext<'_>) -> CodexResult {
+        let locale = self.locale(params.language.as_deref());
+        let compress = params.compress;
+        let result = if let Err(e) = self.check_capabilities(&params) {
+            Err(e)
+        } else if let Err(e) = self.resolve_prompt(&mut params) {
+            Err(e)
+        } else if params.summarize_session {
+            self.execute_summarized(params, context).await
+        } else {
+            self.execute_structured(params, context).await
+        };
+        let mut result = result.unwrap_or_else(|e| error_result(e, locale));
+        redact_result(&mut result);
+        post_process::Pipeline::new(&self.config().post_processors).apply(&mut result, locale);
+        if compress && let Some(messages) = result.all_messages.take() {
+            result.all_messages_compressed = Some(compression::encode_json(&messages));
+            result.encoding = Some(compression::ENCODING.to_string());
+        }
+        result
+    }
+
+    /// Summarize the session being resumed in a read-only run, then run the
+    /// prompt in a fresh session seeded with the summary.
+    async fn execute_summarized(
+        &self,
+        mut params: CodexParams,
+        context: RunContext<'_>,
+    ) -> Result<CodexResult, CodexError> {
+        let Some(previous) = params.session_id.take() else {
+            return Err(CodexError::SummarizeRequiresSession);
+        };
+        let summary_params = CodexParams {
+            prompt: prompt::SUMMARY_PROMPT.to_string(),
+            session_id: Some(previous.clone()),
+            sandbox: SandboxPolicy::ReadOnly,
+            yolo: false,
+            writable_roots: Vec::new(),
+            image: Vec::new(),
+            context_files: Vec::new(),
+            focus_paths: Vec::new(),
+            skip_memory: true,
+            skip_postamble: true,
+            return_all_messages: false,
+            event_filter: Vec::new(),
+            max_output_chars: None,
+            expected_output_schema: None,
+            commit: None,
+            ..params.clone()
+        };
+        let summary = self.execute_codex(summary_params, RunContext::tool(context.tool)).await?;
+        let text = summary.agent_messages.filter(|text| summary.success && !text.trim().is_empty());
+        let Some(text) = text else {
+            return Err(CodexError::SessionSummaryFailed {
+                session_id: previous,
+                reason: summary.error.unwrap_or_else(|| "codex returned no summary".to_string()),
+            });
+        };
+        tracing::info!(session_id = %previous, summary_chars = text.len(), "Continuing summarized session in a new one");
+
+        params.prompt = prompt::with_summary(&text, &params.prompt);
+        let mut result = self.execute_structured(params, context).await?;
+        // The result accounts for everything the call spent, summary included.
+        if let Some(usage) = summary.usage {
+            *result.usage.get_or_insert_default() += usage;
+        }
+        if let Some(cost) = summary.cost_usd {
+            *result.cost_usd.get_or_insert_default() += cost;
+        }
+        result.previous_session_id = Some(previous);
+        result.session_summary = Some(text);
+        Ok(result)
+    }
+
+    /// Run codex, parsing its final message with `expected_output_schema` and
+    /// resuming the session once to have codex correct an answer that does
+    /// not match. The answer is parsed from the unredacted message; `execute`
+    /// redacts `structured_answer` with the rest of the result.
+    async fn execute_structured(
+        &self,
+        params: CodexParams,
+        context: RunContext<'_>,
+    ) -> Result<CodexResult, CodexError> {
+        let Some(schema) = params.expected_output_schema.clone() else {
+            return self.execute_resuming(params, context).await;
+        };
+        output_schema::check(&schema).map_err(CodexError::InvalidOutputSchema)?;
+        let locale = self.locale(params.language.as_deref());
+        // Truncating the answer would break its JSON, so it is truncated
+        // only once parsed.
+        let max_output_chars = params.max_output_chars;
+        let params = CodexParams {
+            max_output_chars: None,
+            ..params
+        };
+
+        let mut result = self.execute_resuming(params.clone(), context).await?;
+        if !result.success {
+            return Ok(result);
+        }
+        let mut answer = output_schema::parse_answer(&schema, result.agent_messages.as_deref().unwrap_or_default());
+        if let Err(ref problem) = answer
+            && let Some(ref session_id) = result.session_id
+            && self.capabilities.current().resume
+        {
+            tracing::info!(session_id = %session_id, problem = %problem, "Answer does not match expected_output_schema, asking codex to correct it");
+            let retry_params = CodexParams {
+                prompt: output_schema::retry_prompt(problem),
+                session_id: Some(session_id.clone()),
+                resume_last: false,
+                sandbox: SandboxPolicy::ReadOnly,
+                yolo: false,
+                writable_roots: Vec::new(),
+                image: Vec::new(),
+                create_cd_if_missing: false,
+                commit: None,
+                ..params
+            };
+            let retry = self.execute_codex(retry_params, RunContext::tool(context.tool)).await?;
+            if let Some(usage) = retry.usage {
+                *result.usage.get_or_insert_default() += usage;
+            }
+            if let Some(cost) = retry.cost_usd {
+                *result.cost_usd.get_or_insert_default() += cost;
+            }
+            if retry.success {
+                answer = output_schema::parse_answer(&schema, retry.agent_messages.as_deref().unwrap_or_default());
+                result.agent_messages = retry.agent_messages;
+            }
+        }
+        match answer {
+            Ok(answer) => result.structured_answer = Some(answer),
+            Err(problem) => {
+                let e = CodexError::OutputSchemaMismatch(problem);
+                result.success = false;
+                result.error = Some(i18n::message(&e, locale));
+                result.error_code = Some(e.code().to_string());
+            }
+        }
+        Ok(match max_output_chars {
+            Some(max_chars) => self.truncate_agent_messages(result, max_chars),
+            None => result,
+        })
+    }
+
+    /// Run codex, resuming it once in its session if it dies mid-turn and
+    /// `resume_crashed_runs` is set.
+    async fn execute_resuming(&self, params: CodexParams, context: RunContext<'_>) -> Result<CodexResult, CodexError> {
+        let first = self.execute_codex(params.clone(), context).await?;
+        let resumable = first.crashed_mid_turn && self.config().resume_crashed_runs && self.capabilities.current().resume;
+        let Some(session_id) = first.session_id.clone().filter(|_| resumable) else {
+            return Ok(first);
+        };
+        let exit = first.exit.as_ref().map_or_else(String::new, ToString::to_string);
+        tracing::warn!(session_id = %session_id, exit = %exit, "Codex died mid-turn, resuming its session");
+        let resume_params = CodexParams {
+            prompt: CRASHED_RUN_PROMPT.to_string(),
+            session_id: Some(session_id),
+            resume_last: false,
+            summarize_session: false,
+            image: Vec::new(),
+            create_cd_if_missing: false,
+            changed_before_resume: first.changed_files.clone(),
+            ..params
+        };
+        let mut result = self.execute_codex(resume_params, context).await?;
+        merge_crashed_attempt(first, &mut result);
+        Ok(result)
+    }
+
+    /// Run codex unless a run with `key` already succeeded for the same request.
+    async fn run_idempotent(&self, key: &str, params: CodexParams, context: RunContext<'_>) -> CodexResult {
+        // Keys are per client, and identify the request minus the key itself.
+        let scoped_key = format!("{}/{key}", self.isolated_client().unwrap_or(""));
+        let request = idempotency::fingerprint(
+            &serde_json::to_string(&CodexParams {
+                idempotency_key: None,
+                ..params.clone()
+            })
+            .unwrap_or_default(),
+        );
+        match self.idempotency.claim(&scoped_key, &request) {
+            Claim::Completed(mut result) => {
+                tracing::info!(idempotency_key = %key, "Returning stored result for idempotency key");
+                result.idempotent_replay = true;
+                *result
+            }
+            Claim::InProgress => error_result(
+                CodexError::IdempotencyKeyInProgress(key.to_string()),
+                self.locale(params.language.as_deref()),
+            ),
+            Claim::Mismatch => error_result(
+                CodexError::IdempotencyKeyReused(key.to_string()),
+                self.locale(params.language.as_deref()),
+            ),
+            Claim::New => {
+                let result = self.execute(params, context).await;
+                if result.success {
+                    self.idempotency.complete(&scoped_key, &result);
+                } else {
+                    self.idempotency.release(&scoped_key);
+                }
+                result
+            }
+        }
+    }
+
+    /// Run `params` in the background, storing the result in `job`.
+    fn spawn_job(&self, job: Arc<Job>, params: CodexParams) {
+        let server = match job.owner() {
+            Some(owner) => self.for_client(owner),
+            None => self.clone(),
+        };
+        tokio::spawn(async move {
+            let run_id = job.id();
+            let correlation_id = job.correlation_id();
+            let context = RunContext {
+                observer: Some(&*job),
+                run_id: Some(&run_id),
+                correlation_id: Some(&correlation_id),
+                ..RunContext::tool("codex_start")
+            };
+            let result = server.run_codex(params, context).await;
+            // Left in progress, for the next start to report or resume.
+            if server.shutdown.is_cancelled() {
+                return;
+            }
+            job.finish(result);
+        });
+    }
+
+    /// Stop every run in progress and wait, up to `shutdown::GRACE`, for
+    /// their codex processes to exit.
+    pub async fn shut_down(&self) {
+        self.shutdown.cancel();
+        let deadline = tokio::time::Instant::now() + shutdown::GRACE;
+        while self.status.running_count() > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Kill or adopt the codex processes of previous instances, per `orphaned_processes`.
+    pub async fn clean_up_orphans(&self) {
+        let config = self.config();
+        if let Some(ref state_dir) = config.state_dir {
+            let orphans = orphans::clean_up(state_dir, config.orphaned_processes).await;
+            if orphans > 0 {
+                tracing::info!(orphans, "Handled codex processes left by a previous server instance");
+            }
+        }
+    }
+
+    /// Deal with background runs the previous server process left unfinished:
+    /// resume them in their codex session if `resume_interrupted_runs` is set
+    /// and the session is known, otherwise mark them failed.
+    pub fn recover_interrupted_runs(&self) {
+        for job in self.jobs.take_interrupted() {
+            match job.session_id() {
+                Some(session_id) if self.config().resume_interrupted_runs => {
+                    tracing::info!(run_id = %job.id(), session_id = %session_id, "Resuming interrupted background run");
+                    let mut params = job.params();
+                    params.prompt = INTERRUPTED_RUN_PROMPT.to_string();
+                    params.session_id = Some(session_id);
+                    params.resume_last = false;
+                    self.spawn_job(job, params);
+                }
+                _ => {
+                    tracing::warn!(run_id = %job.id(), "Background run was interrupted by a server restart");
+                    let locale = self.locale(job.params().language.as_deref());
+                    job.finish(error_result(CodexError::RunInterrupted, locale));
+                }
+            }
+        }
+    }
+
+    /// Execute the codex CLI command and process its output.
+    async fn execute_codex(
+        &self,
+        mut params: CodexParams,
+        context: RunContext<'_>,
+    ) -> Result<CodexResult, CodexError> {
+        let codex_path = self.codex_path()?;
+        let run_as = run_as::resolve(&self.config())?;
+        params.cd = self.resolve_workspace(&params.cd)?;
+        let create_cd = params.create_cd_if_missing && !params.cd.exists();
+
+        // Fail fast with a clearer error than whatever the CLI might emit.
+        if !create_cd && !params.cd.is_dir() {
+            return Err(CodexError::InvalidWorkingDirectory(params.cd));
+        }
+
+        let workspace_key = std::fs::canonicalize(&params.cd).unwrap_or_else(|_| params.cd.clone());
+        let auto_model = if params.model.as_deref() == Some(model_stats::AUTO_MODEL) {
+            let choice = self.model_stats.choose(&workspace_key, &self.config().auto_models);
+            params.model.clone_from(&choice.model);
+            Some(choice)
+        } else {
+            None
+        };
+
+        let mut options = self.effective_options(&params)?;
+        options.auto_model = auto_model;
+        // Before the run is registered or anything is created for it.
+        let violations = self.rules().evaluate(&RuleInput {
+            tool: context.tool,
+            client: self.client.as_deref(),
+            options: &options,
+        });
+        if !violations.is_empty() {
+            return Err(CodexError::PolicyDenied(violations));
+        }
+        let active_run = self
+            .status
+            .register(context.run_id, context.tool, self.client.as_deref(), options.cd.clone());
+        // Only once the run is allowed, so a refused run creates nothing.
+        if create_cd {
+            create_workspace_dir(&options.cd, params.init_git, self.config().allow_create_cd).await?;
+            if let Some(ref run_as) = run_as {
+                run_as.take_ownership(&options.cd);
+            }
+        }
+        if let Some(ref session_id) = options.session_id {
+            self.validate_session_id(session_id, &options.cd)?;
+        }
+        if options.oss {
+            check_oss_server(&self.config().oss_server).await?;
+        }
+        if options.is_write_enabled() {
+            preflight::check(&options.cd, &self.config()).await?;
+        }
+        let price = self.config().model_price(options.model.as_deref());
+        let cost_limit = self.cost_limit(params.max_cost_usd, price, options.model.as_deref())?;
+        self.throttle
+            .wait(Duration::from_secs(self.config().rate_limit_max_defer_secs))
+            .await?;
+
+        // Held until the run finishes so concurrent write runs don't share a checkout.
+        let _workspace_guard = if options.is_write_enabled() {
+            self.workspace_locks
+                .acquire(&options.cd, self.config().workspace_lock)
+                .await?
+        } else {
+            None
+        };
+
+        // Build command arguments
+        let writable = os_sandbox::writable_paths(&options.sandbox, &options.cd, &options.writable_roots);
+        let mut cmd = os_sandbox::command(&codex_path, options.os_sandbox, &writable)?;
+        cmd.kill_on_drop(true); // Ensure process is killed when dropped
+        cmd.args(options.args());
+        child_env::apply(&mut cmd, &codex_path, &self.config());
+        if let Some(ref run_as) = run_as {
+            run_as.apply(&mut cmd);
+        }
+
+        // Add the prompt (with Windows escaping if needed)
+        let notes = if params.skip_memory {
+            String::new()
+        } else {
+            memory::read(&options.cd, &self.config().memory_file).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Failed to read project memory");
+                String::new()
+            })
+        };
+        let prompt = prompt::compose(
+            &params.prompt,
+            &PromptContext {
+                context_files: options.context_files.clone(),
+                focus_paths: options.focus_paths.clone(),
+                memory: memory::for_prompt(&notes, self.config().memory_max_bytes).map(str::to_string),
+                language: options.language.clone(),
+                postamble: if params.skip_postamble { None } else { self.config().prompt_postamble.clone() },
+                output_format: params.expected_output_schema.as_ref().map(output_schema::instruction),
+            },
+        );
+        let prompt = if cfg!(windows) {
+            windows_escape(&prompt)
+        } else {
+            prompt
+        };
+        cmd.arg("--").arg(&prompt);
+
+        // Configure process I/O; stdin carries the answers to approval requests.
+        cmd.stdin(if options.bridges_approvals() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // Avoid logging the full command line because it includes the prompt content.
+        tracing::debug!(
+            sandbox = options.sandbox.as_str(),
+            cd = %options.cd.display(),
+            has_session_id = options.session_id.is_some(),
+            resume_last = options.resume_last,
+            yolo = options.yolo,
+            return_all_messages = params.return_all_messages,
+            image_count = options.images.len(),
+            has_base_instructions = options.base_instructions_file.is_some(),
+            writable_root_count = options.writable_roots.len(),
+            network_access = ?options.network_access,
+            os_sandbox = ?options.os_sandbox,
+            "Executing codex"
+        );
+
+        let mut artifacts = match self.config().artifacts_dir {
+            Some(ref root) => RunArtifacts::create(root)
+                .inspect_err(|e| tracing::warn!(error = %e, "Failed to create run artifacts directory"))
+                .ok(),
+            None => None,
+        };
+
+        let tee = match options.stream_to {
+            Some(ref path) => Some(StreamTee::open(path).map_err(|e| CodexError::StreamToFailed {
+                path: path.clone(),
+                reason: e.to_string(),
+            })?),
+            None => None,
+        };
+
+        // Compared after the run to find the files codex's commands changed.
+        let snapshot = if options.is_write_enabled() {
+            Snapshot::capture(&options.cd).await
+        } else {
+            None
+        };
+
+        // Spawn the process
+        let started = std::time::Instant::now();
+        let mut timings = params.collect_timings.then(TimingRecorder::start);
+        let mut child = cmd.spawn()?;
+        // Kept until the run ends, so the next instance finds codex if this one dies first.
+        let _pid_file = match (self.config().state_dir.as_deref(), child.id()) {
+            (Some(state_dir), Some(pid)) => PidFile::create(state_dir, pid)
+                .inspect_err(|e| tracing::warn!(error = %e, "Failed to write codex PID file"))
+                .ok(),
+            _ => None,
+        };
+        // Dropped when the run ends, killing any processes codex left behind.
+        #[cfg(windows)]
+        let _job = crate::job_object::JobObject::assign(&child)
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to assign codex to a job object"))
+            .ok();
+        if let Some(ref mut timings) = timings {
+            timings.spawned();
+        }
+        active_run.running(options.model.as_deref());
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or(CodexError::StdoutCaptureFailed)?;
+        let mut reader = BufReader::new(stdout).lines();
+        // stderr is read alongside stdout so a full pipe never blocks codex.
+        let mut stderr_lines = child.stderr.take().map(|s| BufReader::new(s).lines());
+        let mut codex_stdin = child.stdin.take();
+
+        // Process output - only collect all_messages if needed
+        let collect_all_messages = params.return_all_messages || !params.event_filter.is_empty();
+        let mut stream = EventStream::new(collect_all_messages, params.include_reasoning)
+            .with_event_filter(params.event_filter.clone())
+            .with_noise_filters(self.config().noise_filters.clone())
+            .with_limits(RunLimits {
+                max_turns: params.max_turns,
+                max_commands: params.max_commands,
+                cost: cost_limit,
+            })
+            .with_stop_on(params.stop_on)
+            .with_approval_bridge(options.bridges_approvals());
+        let mut stdout_closed = false;
+        let mut shut_down = false;
+        loop {
+            tokio::select! {
+                line = reader.next_line() => {
+                    let Some(line) = line? else {
+                        stdout_closed = true;
+                        break;
+                    };
+                    if let Some(ref mut artifacts) = artifacts {
+                        artifacts.record_stdout(&line);
+                    }
+                    if let Some(observer) = context.observer {
+                        observer.observe_line(&line);
+                    }
+                    if let Some(ref tee) = tee {
+                        tee.write_line(&line);
+                    }
+                    let flow = stream.push_line(&line);
+                    if let Some(ref mut timings) = timings {
+                        timings.event(stream.has_agent_message());
+                    }
+                    if let Some(request) = stream.take_approval_request() {
+                        let timeout = Duration::from_secs(self.config().approval_timeout_secs);
+                        let decision = tokio::select! {
+                            decision = approval::ask(context.peer, &request, timeout) => decision,
+                            _ = self.shutdown.cancelled() => {
+                                shut_down = true;
+                                break;
+                            }
+                        };
+                        tracing::info!(
+                            action = request.action(),
+                            approved = decision.approved,
+                            decided_by = ?decision.decided_by,
+                            "Answered codex approval request"
+                        );
+                        if let Some(ref mut stdin) = codex_stdin {
+                            let answer = request.answer(decision.approved);
+                            if let Err(e) = stdin.write_all(answer.as_bytes()).await {
+                                tracing::warn!(error = %e, "Failed to send the approval decision to codex");
+                            }
+                        }
+                        stream.record_approval(decision);
+                    }
+                    if flow == Flow::Stop {
+                        break;
+                    }
+                }
+                line = next_stderr_line(&mut stderr_lines), if stderr_lines.is_some() => {
+                    match line {
+                        Some(line) => {
+                            log_stderr_line(&line);
+                            if let Some(ref mut artifacts) = artifacts {
+                                artifacts.record_stderr(&line);
+                            }
+                            stream.push_stderr(&line);
+                        }
+                        None => stderr_lines = None,
+                    }
+                }
+                _ = self.shutdown.cancelled() => {
+                    shut_down = true;
+                    break;
+                }
+            }
+        }
+
+        if shut_down {
+            stream.record_failure("server shutdown", "the server shut down before codex finished, e.g. because its MCP client disconnected");
+            shutdown::terminate(&mut child);
+        }
+        let killed_by_server = stream.aborted() || shut_down;
+        if stream.aborted() {
+            let _ = child.kill().await;
+        }
+
+        let wait_timeout = Duration::from_secs(5);
+        if let Some(mut lines) = stderr_lines {
+            if stdout_closed {
+                // codex is exiting; collect what it printed last (often the
+                // reason it failed) before reporting.
+                let drain = async {
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        log_stderr_line(&line);
+                        if let Some(ref mut artifacts) = artifacts {
+                            artifacts.record_stderr(&line);
+                        }
+                        stream.push_stderr(&line);
+                    }
+                };
+                let _ = tokio::time::timeout(wait_timeout, drain).await;
+            } else {
+                // codex may keep running briefly after an early stop; keep
+                // draining so it never blocks on a full pipe.
+                tokio::spawn(
+                    async move {
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            log_stderr_line(&line);
+                        }
+                    }
+                    .in_current_span(),
+                );
+            }
+        }
+
+        // Wait for process to finish with proper error handling
+        let mut exit = None;
+        match tokio::time::timeout(wait_timeout, child.wait()).await {
+            Ok(Ok(status)) => {
+                let process_exit = ProcessExit::from_status(status);
+                if !status.success() {
+                    let mut detail = process_exit.to_string();
+                    if !killed_by_server && let Some(hint) = process_exit.hint() {
+                        detail.push_str(". ");
+                        detail.push_str(hint);
+                    }
+                    stream.record_failure("codex exit", &detail);
+                }
+                exit = Some(process_exit);
+            }
+            Ok(Err(e)) => {
+                stream.record_failure("codex wait error", &e.to_string());
+            }
+            Err(_) => {
+                stream.record_failure("codex wait timeout", &format!("{wait_timeout:?}"));
+                let _ = child.kill().await;
+                let _ = child.wait().await;
+            }
+        }
+
+        // Validate results
+        let timings = timings.map(TimingRecorder::finish);
+        if let Some(ref timings) = timings {
+            self.usage.record_timings(timings);
+        }
+        let run = stream.finish();
+        let success = run.success;
+        let crashed_mid_turn = !killed_by_server
+            && run.turn_interrupted
+            && run.rate_limit.is_none()
+            && exit.as_ref().is_some_and(|exit| exit.code != Some(0));
+        let mut changed_files = params.changed_before_resume.clone();
+        for path in &run.changed_files {
+            if !changed_files.contains(path) {
+                changed_files.push(path.clone());
+            }
+        }
+        if let Some(retry_after) = run.rate_limit {
+            self.throttle.defer(retry_after);
+        }
+
+        let cost_usd = price.zip(run.usage).map(|(price, usage)| price.cost(&usage));
+        self.usage
+            .record(options.model.as_deref(), success, run.usage.unwrap_or_default(), cost_usd);
+        if let Some(ref model) = options.model {
+            self.model_stats.record(&workspace_key, model, success, started.elapsed());
+        }
+
+        if let Some(ref session_id) = run.thread_id {
+            self.registry.record_run(RunRecord {
+                session_id,
+                cd: &options.cd,
+                label: params.label.as_deref(),
+                tags: &params.tags,
+                model: options.model.as_deref(),
+                language: options.language.as_deref(),
+                success,
+                owner: self.client.as_deref(),
+            });
+        }
+
+        let codex_version = self.codex_version(&codex_path).await;
+        let environment = RunEnvironment::capture(
+            &options.cd,
+            &self.config().git_remote,
+            codex_version,
+            run.model.clone().or_else(|| options.model.clone()),
+        )
+        .await;
+
+        let exclusions = self.exclusions(&options.cd);
+        let patch_files: Vec<String> = changed_files
+            .iter()
+            .filter(|file| {
+                let path = std::path::Path::new(file.as_str());
+                !exclusions.is_excluded(path.strip_prefix(&options.cd).unwrap_or(path), false)
+            })
+            .cloned()
+            .collect();
+        let detected = match snapshot {
+            Some(ref snapshot) => snapshot.changes(&options.cd).await,
+            None => Vec::new(),
+        };
+        let files_changed = file_changes::merge(&options.cd, &run.file_changes, detected, &exclusions);
+        // Before the patch is taken, so that it includes the formatting.
+        let formatting = if params.post_format && success && options.is_write_enabled() {
+            Some(formatting::format_files(&options.cd, &files_changed, &self.config().formatters).await)
+        } else {
+            None
+        };
+        let patch = if patch_files.is_empty() || self.config().patch_max_bytes == 0 {
+            None
+        } else {
+            git::diff_paths(&options.cd, &patch_files).await
+        };
+
+        // Build result
+        let mut result = if success {
+            CodexResult {
+                success: true,
+                session_id: run.thread_id,
+                agent_messages: (!run.no_final_message).then_some(run.agent_messages),
+                no_final_message: run.no_final_message,
+                sandbox_denials: run.sandbox_denials,
+                reasoning_summary: run.reasoning,
+                all_messages: run.all_messages,
+                usage: run.usage,
+                cost_usd,
+                timings,
+                effective_options: Some(options),
+                environment: Some(environment),
+                ..Default::default()
+            }
+        } else {
+            CodexResult {
+                success: false,
+                session_id: run.thread_id,
+                agent_messages: if run.agent_messages.is_empty() {
+                    None
+                } else {
+                    Some(run.agent_messages)
+                },
+                sandbox_denials: run.sandbox_denials,
+                reasoning_summary: run.reasoning,
+                error: Some(run.error),
+                error_code: if run.approval_requested {
+                    Some("approval_required".to_string())
+                } else if run.limit_exceeded {
+                    Some("limit_exceeded".to_string())
+                } else if run.budget_exceeded {
+                    Some("budget_exceeded".to_string())
+                } else if shut_down {
+                    Some("server_shutdown".to_string())
+                } else if run.rate_limit.is_some() {
+                    Some("rate_limited".to_string())
+                } else {
+                    exit.as_ref().and_then(ProcessExit::error_code).map(str::to_string)
+                },
+                exit,
+                all_messages: run.all_messages,
+                usage: run.usage,
+                retry_after_secs: run.rate_limit.map(|d| d.as_secs().max(1)),
+                cost_usd,
+                timings,
+                effective_options: Some(options),
+                environment: Some(environment),
+                ..Default::default()
+            }
+        };
+
+        result.unparsed_lines = run.unparsed_lines;
+        result.approvals = run.approvals;
+        result.warnings = run.warnings;
+        result.plan = run.plan;
+        result.crashed_mid_turn = crashed_mid_turn;
+        result.changed_files.clone_from(&changed_files);
+        result.files_changed = files_changed;
+        result.formatting = formatting;
+        if params.stop_on == StopOn::ProcessExit {
+            result.turns_completed = Some(run.turns_completed);
+        }
+
+        if let Some(patch) = patch.filter(|p| !p.is_empty()) {
+            let capped = cap_bytes(&patch, self.config().patch_max_bytes);
+            result.patch_truncated = capped.len() < patch.len();
+            result.patch = Some(capped.to_string());
+        }
 
-        // Handle session resumption
-        if let Some(ref session_id) = params.session_id {
-            if !session_id.is_empty() {
-                cmd.arg("resume").arg(session_id);
+        if let Some(ref commit) = params.commit
+            && result.success
+            && let Some(ref session_id) = result.session_id
+        {
+            let message = commit.message(session_id, result.agent_messages.as_deref().unwrap_or(""));
+            match git::commit_changes(&params.cd, &commit.branch, &changed_files, &message).await {
+                Ok(sha) => {
+                    if let Some(ref run_as) = run_as {
+                        run_as.take_ownership(&params.cd.join(".git"));
+                    }
+                    result.commit = Some(CommitInfo {
+                        branch: commit.branch.clone(),
+                        sha,
+                    })
+                }
+                Err(e) => result.commit_error = Some(format!("{e:#}")),
+            }
+            #[cfg(feature = "vcs")]
+            if let Some(ref pull_request) = commit.pull_request
+                && result.commit.is_some()
+            {
+                let body = pull_request_body(session_id, result.agent_messages.as_deref().unwrap_or(""));
+                let title = pull_request
+                    .title
+                    .as_deref()
+                    .unwrap_or_else(|| message.lines().next().unwrap_or_default());
+                match crate::vcs::open_change_request(&params.cd, &self.config(), &commit.branch, title, &body, pull_request)
+                    .await
+                {
+                    Ok(url) => result.pull_request_url = Some(url),
+                    Err(e) => result.pull_request_error = Some(format!("{e:#}")),
+                }
             }
         }
 
-        // Add the prompt (with Windows escaping if needed)
-        let prompt = if cfg!(windows) {
-            windows_escape(&params.prompt)
+        // Before anything is cut or stored, so no part of a secret survives.
+        redact_result(&mut result);
+        let result = if params.messages_offset > 0 || params.messages_limit.is_some() {
+            self.page_all_messages(result, params.messages_offset, params.messages_limit)
         } else {
-            params.prompt.clone()
+            result
+        };
+        let result = self.offload_all_messages(result);
+        let mut result = match params.max_output_chars {
+            Some(max_chars) => self.truncate_agent_messages(result, max_chars),
+            None => result,
         };
-        cmd.arg("--").arg(&prompt);
 
-        // Configure process I/O
-        // Use inherit for stderr to avoid buffer blocking issues
-        cmd.stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit());
+        if let Some(artifacts) = artifacts {
+            let diff = match result.effective_options {
+                Some(ref options) if options.is_write_enabled() => {
+                    git::workspace_diff(&options.cd).await
+                }
+                _ => None,
+            };
+            result.artifacts_dir = Some(artifacts.dir().to_path_buf());
+            // The events are already in transcript.jsonl.
+            let summary = CodexResult {
+                all_messages: None,
+                ..result.clone()
+            };
+            if let Err(e) = artifacts.finish(&summary, diff.as_deref()) {
+                tracing::warn!(error = %e, "Failed to write run artifacts");
+            }
+        }
+        Ok(result)
+    }
+}
 
-        // Avoid logging the full command line because it includes the prompt content.
-        tracing::debug!(
-            sandbox = params.sandbox.as_str(),
-            cd = %params.cd.display(),
-            has_session_id = params.session_id.is_some(),
-            yolo = params.yolo,
-            return_all_messages = params.return_all_messages,
-            image_count = params.image.len(),
-            "Executing codex"
-        );
+impl CodexServer {
+    /// Serve a cached overview of `params.cd` or run codex to produce one.
+    async fn explain_repo(&self, params: ExplainRepoParams) -> Result<ExplainRepoResult, CodexError> {
+        let cd = self.resolve_workspace(&params.cd)?;
+        let key = std::fs::canonicalize(&cd).unwrap_or_else(|_| cd.clone());
+        let head = git::head_sha(&cd).await;
 
-        // Spawn the process
-        let mut child = cmd.spawn()?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or(CodexError::StdoutCaptureFailed)?;
-        let mut reader = BufReader::new(stdout).lines();
+        if !params.refresh
+            && let Some(ref head) = head
+            && let Some(entry) = self.explain_cache.get(self.isolated_client(), &key, head)
+        {
+            return Ok(ExplainRepoResult {
+                success: true,
+                cached: true,
+                head: Some(entry.head),
+                explanation: Some(entry.explanation),
+                session_id: entry.session_id,
+                created_at: Some(entry.created_at),
+                ..Default::default()
+            });
+        }
 
-        // Process output - only collect all_messages if needed
-        let mut all_messages: Option<Vec<serde_json::Value>> =
-            params.return_all_messages.then_some(Vec::new());
-        let mut agent_messages = String::new();
-        let mut thread_id: Option<String> = None;
-        let mut err_message = String::new();
-        let mut success = true;
-
-        while let Some(line) = reader.next_line().await? {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-
-            match serde_json::from_str::<serde_json::Value>(line) {
-                Ok(line_dict) => {
-                    if let Some(all) = all_messages.as_mut() {
-                        all.push(line_dict.clone());
-                    }
+        let run_params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": explain::EXPLAIN_PROMPT,
+            "cd": cd,
+            "sandbox": "read-only",
+        }))
+        .expect("explain_repo parameters are valid");
+        // Through `run_codex` like every other tool, so the cached explanation
+        // is already redacted and post-processed.
+        let run = self.run_codex(run_params, RunContext::tool("codex_explain_repo")).await;
+        let mut result = ExplainRepoResult {
+            success: run.success,
+            head: head.clone(),
+            session_id: run.session_id.clone(),
+            error: run.error,
+            error_code: run.error_code,
+            ..Default::default()
+        };
+        if let Some(explanation) = run.agent_messages.filter(|_| run.success) {
+            result.explanation = Some(explanation.clone());
+            if let Some(ref head) = head {
+                let owner = self.isolated_client();
+                let entry = self.explain_cache.insert(owner, &key, head, explanation, run.session_id);
+                result.created_at = Some(entry.created_at);
+            }
+        }
+        Ok(result)
+    }
 
-                    // Extract agent messages
-                    if let Some(item) = line_dict.get("item") {
-                        if let Some(item_type) = item.get("type").and_then(|t| t.as_str()) {
-                            if item_type == "agent_message" {
-                                if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                                    agent_messages.push_str(text);
-                                }
-                            }
-                        }
-                    }
+    /// Run `params.codex` read-only once per model, concurrently.
+    async fn compare(&self, params: CompareParams) -> Result<CompareResult, CodexError> {
+        let models = if params.models.is_empty() {
+            self.config().auto_models.clone()
+        } else {
+            params.models
+        };
+        if models.is_empty() {
+            return Err(CodexError::InvalidCompareModels(
+                "pass `models` or configure `auto_models` on the server".to_string(),
+            ));
+        }
+        if models.len() > MAX_COMPARE_MODELS {
+            return Err(CodexError::InvalidCompareModels(format!("at most {MAX_COMPARE_MODELS} models can be compared")));
+        }
+        if let Some(model) = models.iter().enumerate().find_map(|(i, m)| models[..i].contains(m).then_some(m)) {
+            return Err(CodexError::InvalidCompareModels(format!("{model:?} is listed twice")));
+        }
+        let base = params.codex;
+        if base.session_id.is_some() || base.resume_last {
+            return Err(CodexError::CompareSessionUnsupported);
+        }
 
-                    // Extract thread_id
-                    if let Some(tid) = line_dict.get("thread_id").and_then(|t| t.as_str()) {
-                        thread_id = Some(tid.to_string());
-                    }
+        let mut runs = tokio::task::JoinSet::new();
+        for (index, model) in models.into_iter().enumerate() {
+            let run = CodexParams {
+                model: Some(model.clone()),
+                sandbox: SandboxPolicy::ReadOnly,
+                yolo: false,
+                writable_roots: Vec::new(),
+                commit: None,
+                summarize_session: false,
+                // One key per model, so the runs don't claim each other's key.
+                idempotency_key: base.idempotency_key.as_ref().map(|key| format!("{key}/{model}")),
+                ..base.clone()
+            };
+            let server = self.clone();
+            runs.spawn(async move {
+                let started = std::time::Instant::now();
+                let result = server.run_codex(run, RunContext::tool("codex_compare")).await;
+                let duration_ms = started.elapsed().as_millis() as u64;
+                (index, ComparedRun { model, duration_ms, result })
+            });
+        }
+        let mut runs: Vec<(usize, ComparedRun)> = runs.join_all().await;
+        runs.sort_by_key(|(index, _)| *index);
+        Ok(CompareResult {
+            runs: runs.into_iter().map(|(_, run)| run).collect(),
+        })
+    }
 
-                    // Check for failures
-                    if let Some(msg_type) = line_dict.get("type").and_then(|t| t.as_str()) {
-                        if msg_type.contains("fail") {
-                            success = false;
-                            if let Some(error) = line_dict.get("error") {
-                                if let Some(error_msg) = error.get("message").and_then(|m| m.as_str())
-                                {
-                                    err_message.push_str("\n\n[codex error] ");
-                                    err_message.push_str(error_msg);
-                                }
-                            }
-                        }
+    /// Review the uncommitted changes of a session in a read-only run.
+    async fn self_review(&self, params: SelfReviewParams) -> Result<SelfReviewResult, CodexError> {
+        if !codex_home::is_valid_session_id(&params.session_id) {
+            return Err(CodexError::InvalidSessionId(params.session_id));
+        }
+        let record = self
+            .registry
+            .get(&params.session_id)
+            .filter(|record| self.can_access(record))
+            .ok_or_else(|| CodexError::SessionNotFound {
+                session_id: params.session_id.clone(),
+                alternatives: Vec::new(),
+            })?;
+        let cd = record.cd;
 
-                        if msg_type.contains("error") {
-                            if let Some(error_msg) = line_dict.get("message").and_then(|m| m.as_str())
-                            {
-                                // Ignore "Reconnecting..." noise
-                                if error_msg.starts_with("Reconnecting...") {
-                                    continue;
-                                }
-
-                                success = false;
-                                err_message.push_str("\n\n[codex error] ");
-                                err_message.push_str(error_msg);
-                            }
-                        }
+        // The files the session's own transcripts report, if stored; else
+        // everything uncommitted.
+        let reported = match self.load_transcript(&params.session_id) {
+            Ok(("server", events)) => review::changed_paths(&events),
+            _ => Vec::new(),
+        };
+        let candidates = if reported.is_empty() {
+            git::changed_paths(&cd).await.unwrap_or_default()
+        } else {
+            reported
+        };
+        let exclusions = self.exclusions(&cd);
+        let files: Vec<String> = candidates
+            .into_iter()
+            .map(|file| {
+                let path = std::path::Path::new(&file);
+                path.strip_prefix(&cd).unwrap_or(path).to_string_lossy().into_owned()
+            })
+            .filter(|file| !exclusions.is_excluded(std::path::Path::new(file), false))
+            .collect();
+        let diff = if files.is_empty() { None } else { git::diff_paths(&cd, &files).await };
+        let Some(diff) = diff.filter(|diff| !diff.trim().is_empty()) else {
+            return Err(CodexError::NothingToReview(params.session_id));
+        };
 
-                        // Check for turn completion
-                        if msg_type == "turn.completed" {
-                            break;
-                        }
-                    }
+        let inlined = cap_bytes(&diff, review::MAX_DIFF_BYTES);
+        let diff_truncated = inlined.len() < diff.len();
+        let run_params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": review::prompt(inlined, &files, diff_truncated, params.focus.as_deref()),
+            "cd": cd,
+            "sandbox": "read-only",
+            "model": params.model,
+            "expected_output_schema": review::answer_schema(),
+        }))
+        .expect("self_review parameters are valid");
+        let run = self.run_codex(run_params, RunContext::tool("codex_self_review")).await;
+        let mut result = SelfReviewResult {
+            success: run.success,
+            session_id: params.session_id,
+            cd,
+            files,
+            diff_truncated,
+            review_session_id: run.session_id,
+            usage: run.usage,
+            cost_usd: run.cost_usd,
+            error: run.error,
+            error_code: run.error_code,
+            ..Default::default()
+        };
+        if let Some(answer) = run.structured_answer.filter(|_| run.success) {
+            match serde_json::from_value(answer) {
+                Ok(answer) => result.set_answer(answer),
+                Err(e) => {
+                    let e = CodexError::OutputSchemaMismatch(e.to_string());
+                    result.success = false;
+                    result.error = Some(i18n::message(&e, self.locale(None)));
+                    result.error_code = Some(e.code().to_string());
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// The codex executable to run.
+    fn codex_path(&self) -> Result<PathBuf, CodexError> {
+        match self.config().codex_path {
+            Some(ref path) => which::which(path),
+            None => which::which("codex"),
+        }
+        .map_err(|_| CodexError::ExecutableNotFound)
+    }
+
+    /// Version of the codex at `codex_path`, queried once.
+    async fn codex_version(&self, codex_path: &std::path::Path) -> Option<String> {
+        self.codex_version
+            .get_or_init(|| environment::codex_version(codex_path))
+            .await
+            .clone()
+    }
+
+    /// The project memory of `cd`, after appending `note` if given.
+    fn memory(&self, cd: &std::path::Path, note: Option<&str>) -> Result<MemoryResult, CodexError> {
+        let cd = self.resolve_configured_workspace(cd)?;
+        if !cd.is_dir() {
+            return Err(CodexError::InvalidWorkingDirectory(cd));
+        }
+        if let Some(note) = note {
+            if note.trim().is_empty() {
+                return Err(CodexError::EmptyMemoryNote);
+            }
+            let written = memory::append(&cd, &self.config().memory_file, note)?;
+            // Only the file and the directories created for it, e.g.
+            // `.codex-mcp/`, not a directory that already held it.
+            if let Some(ref run_as) = run_as::resolve(&self.config())? {
+                written.iter().for_each(|path| run_as.take_ownership(path));
+            }
+        }
+        let notes = memory::read(&cd, &self.config().memory_file)?;
+        Ok(MemoryResult {
+            path: memory::path(&cd, &self.config().memory_file)?,
+            truncated_in_prompts: notes.trim().len() > self.config().memory_max_bytes,
+            notes,
+            cd,
+        })
+    }
+
+    /// Paths of workspace `cd` kept out of prompts and results.
+    fn exclusions(&self, cd: &std::path::Path) -> PathExclusions {
+        let config = self.config();
+        PathExclusions::load(cd, &config.ignore_file, &config.excluded_paths)
+    }
+
+    /// The workspace `cd` and `path` inside it, resolved for the workspace
+    /// file tools.
+    fn workspace_path(&self, cd: &std::path::Path, path: &std::path::Path) -> Result<(PathBuf, PathBuf), CodexError> {
+        let cd = self.resolve_configured_workspace(cd)?;
+        if !cd.is_dir() {
+            return Err(CodexError::InvalidWorkingDirectory(cd));
+        }
+        let root = cd.canonicalize()?;
+        let resolved = policy::resolve_within(&cd.join(path), std::slice::from_ref(&root))
+            .ok_or_else(|| CodexError::WorkspacePathNotAllowed(path.to_path_buf()))?;
+        Ok((root, resolved))
+    }
+
+    fn workspace_list(&self, params: &WorkspaceListParams) -> Result<WorkspaceListResult, CodexError> {
+        let path = params.path.clone().unwrap_or_else(|| PathBuf::from("."));
+        let (root, dir) = self.workspace_path(&params.cd, &path)?;
+        if !dir.is_dir() {
+            return Err(CodexError::WorkspacePathNotAllowed(path));
+        }
+        let limit = params
+            .limit
+            .unwrap_or(workspace_files::LIST_LIMIT_DEFAULT)
+            .clamp(1, workspace_files::LIST_LIMIT_MAX);
+        let exclusions = self.exclusions(&root);
+        if exclusions.is_excluded(dir.strip_prefix(&root).unwrap_or(&dir), true) {
+            return Err(CodexError::PathExcluded(path));
+        }
+        let (entries, truncated) = workspace_files::list(&root, &dir, params.recursive, limit, &exclusions)?;
+        Ok(WorkspaceListResult {
+            cd: root,
+            entries,
+            truncated,
+        })
+    }
+
+    fn workspace_read(&self, params: &WorkspaceReadParams) -> Result<WorkspaceReadResult, CodexError> {
+        let (root, file) = self.workspace_path(&params.cd, &params.path)?;
+        if !file.is_file() {
+            return Err(CodexError::WorkspacePathNotAllowed(params.path.clone()));
+        }
+        if self.exclusions(&root).is_excluded(file.strip_prefix(&root).unwrap_or(&file), false) {
+            return Err(CodexError::PathExcluded(params.path.clone()));
+        }
+        let max_server = self.config().workspace_read_max_bytes;
+        let max_bytes = params.max_bytes.map_or(max_server, |max| max.min(max_server));
+        let (size, mut content, end) = workspace_files::read(&file, params.offset, max_bytes)?;
+        secrets::redact_option(&mut content);
+        let more = content.is_some() && end < size;
+        Ok(WorkspaceReadResult {
+            path: file.strip_prefix(&root).unwrap_or(&file).to_path_buf(),
+            cd: root,
+            size,
+            offset: params.offset.min(size),
+            binary: content.is_none(),
+            content,
+            truncated: more,
+            next_offset: more.then_some(end),
+        })
+    }
+
+    /// The background run `run_id`, if it exists and belongs to this client.
+    fn job(&self, run_id: &str) -> Result<Arc<Job>, McpError> {
+        self.jobs
+            .get(run_id)
+            .filter(|job| self.isolated_client().is_none_or(|client| job.owner().as_deref() == Some(client)))
+            .ok_or_else(|| {
+                let e = CodexError::RunNotFound(run_id.to_string());
+                self.tool_error(&e)
+            })
+    }
+
+    /// Keep only the requested page of `all_messages`, storing the full list
+    /// so the events after it can be read with `codex_read_transcript`.
+    fn page_all_messages(&self, mut result: CodexResult, offset: usize, limit: Option<usize>) -> CodexResult {
+        let Some(messages) = result.all_messages.take() else {
+            return result;
+        };
+        let total = messages.len();
+        let end = limit.map_or(total, |limit| offset.saturating_add(limit)).min(total);
+
+        let mut transcript_start = None;
+        if self.resources.is_enabled()
+            && let Some(ref session_id) = result.session_id
+        {
+            // The session's transcript lists this run's events after those stored before.
+            let stored_before = self.stored_transcript_len(session_id);
+            match self.resources.save_transcript(&resource_name(&result), &messages) {
+                Ok(stored) => {
+                    result.all_messages_count = Some(total);
+                    result.all_messages_uri = Some(stored.uri());
+                    transcript_start = Some(stored_before);
                 }
                 Err(e) => {
-                    success = false;
-                    err_message.push_str("\n\n[json decode error] ");
-                    err_message.push_str(&e.to_string());
-                    err_message.push_str(": ");
-                    err_message.push_str(line);
+                    tracing::warn!(error = %e, "Failed to store transcript, returning only the requested page");
                 }
             }
         }
 
-        // Wait for process to finish with proper error handling
-        let wait_timeout = Duration::from_secs(5);
-        match tokio::time::timeout(wait_timeout, child.wait()).await {
-            Ok(Ok(status)) => {
-                if !status.success() {
-                    success = false;
-                    err_message.push_str("\n\n[codex exit] ");
-                    err_message.push_str(&format!("{status:?}"));
+        result.total_events = Some(total);
+        result.next_transcript_offset = transcript_start.filter(|_| end < total).map(|start| start + end);
+        result.all_messages = Some(messages.into_iter().take(end).skip(offset).collect());
+        result
+    }
+
+    /// Number of events in the transcripts stored for `session_id`.
+    fn stored_transcript_len(&self, session_id: &str) -> usize {
+        self.resources
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.kind == ResourceKind::Transcript && r.session_id() == Some(session_id))
+            .filter_map(|r| self.resources.read(&r.uri()).ok().flatten())
+            .map(|(_, text)| parse_jsonl(&text).count())
+            .sum()
+    }
+
+    /// Move an oversized `all_messages` into the transcript store, replacing it
+    /// with a resource URI. Falls back to inline messages if storage fails.
+    fn offload_all_messages(&self, mut result: CodexResult) -> CodexResult {
+        let Some(ref messages) = result.all_messages else {
+            return result;
+        };
+        // A page whose full list is already stored.
+        if !self.resources.is_enabled() || result.all_messages_uri.is_some() {
+            return result;
+        }
+        let size = serde_json::to_vec(messages).map(|v| v.len()).unwrap_or(0);
+        if size <= self.config().inline_messages_max_bytes {
+            return result;
+        }
+
+        match self.resources.save_transcript(&resource_name(&result), messages) {
+            Ok(stored) => {
+                result.all_messages_count = Some(messages.len());
+                result.all_messages_uri = Some(stored.uri());
+                result.all_messages = None;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to store transcript, returning all_messages inline");
+            }
+        }
+        result
+    }
+
+    /// Shorten `agent_messages` to `max_chars`, storing the full text as a
+    /// resource when a state directory is configured.
+    fn truncate_agent_messages(&self, mut result: CodexResult, max_chars: usize) -> CodexResult {
+        let Some(ref messages) = result.agent_messages else {
+            return result;
+        };
+        let Some(truncated) = truncate_middle(messages, max_chars) else {
+            return result;
+        };
+
+        if self.resources.is_enabled() {
+            match self
+                .resources
+                .save(ResourceKind::AgentMessages, &resource_name(&result), messages)
+            {
+                Ok(stored) => result.agent_messages_uri = Some(stored.uri()),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to store full agent_messages");
                 }
             }
-            Ok(Err(e)) => {
-                success = false;
-                err_message.push_str("\n\n[codex wait error] ");
-                err_message.push_str(&e.to_string());
+        }
+        result.agent_messages = Some(truncated);
+        result.truncated = true;
+        result
+    }
+
+    /// Events of a session's transcript and where they came from, with
+    /// secrets redacted.
+    fn load_transcript(
+        &self,
+        session_id: &str,
+    ) -> Result<(&'static str, Vec<serde_json::Value>), CodexError> {
+        if !codex_home::is_valid_session_id(session_id) {
+            return Err(CodexError::InvalidSessionId(session_id.to_string()));
+        }
+        let not_found = || CodexError::SessionNotFound {
+            session_id: session_id.to_string(),
+            alternatives: Vec::new(),
+        };
+        let record = self.registry.get(session_id);
+        if record.as_ref().is_some_and(|r| !self.can_access(r))
+            || (record.is_none() && self.isolated_client().is_some())
+        {
+            return Err(not_found());
+        }
+
+        let runs: Vec<StoredResource> = self
+            .resources
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.kind == ResourceKind::Transcript && r.session_id() == Some(session_id))
+            .collect();
+        let (source, mut events) = if !runs.is_empty() {
+            let mut events = Vec::new();
+            for run in runs {
+                if let Some((_, text)) = self.resources.read(&run.uri()).map_err(std::io::Error::other)? {
+                    events.extend(parse_jsonl(&text));
+                }
             }
-            Err(_) => {
-                success = false;
-                err_message.push_str("\n\n[codex wait timeout] ");
-                err_message.push_str(&format!("{wait_timeout:?}"));
-                let _ = child.kill().await;
-                let _ = child.wait().await;
+            ("server", events)
+        } else {
+            // Codex's own rollout holds whatever the run printed, resolved
+            // credentials included.
+            let rollout = codex_home::sessions_dir()
+                .and_then(|dir| codex_home::find_rollout(&dir, session_id))
+                .ok_or_else(not_found)?;
+            let text = std::fs::read_to_string(rollout)?;
+            ("codex_rollout", parse_jsonl(&text).collect())
+        };
+        events.iter_mut().for_each(secrets::redact_json);
+        Ok((source, events))
+    }
+
+    fn search_sessions(&self, params: SearchSessionsParams) -> Result<SearchSessionsResult, CodexError> {
+        let query = params.query.trim();
+        if query.is_empty() {
+            return Err(CodexError::EmptySearchQuery);
+        }
+        let limit = params
+            .limit
+            .unwrap_or(SEARCH_RESULTS_DEFAULT)
+            .clamp(1, SEARCH_RESULTS_MAX);
+        let filter = SessionFilter {
+            owner: self.isolated_client().map(str::to_string),
+            ..SessionFilter::default()
+        };
+
+        let mut sessions = Vec::new();
+        let mut truncated = false;
+        for record in self.registry.list(&filter) {
+            let events = self
+                .load_transcript(&record.session_id)
+                .map(|(_, events)| events)
+                .unwrap_or_default();
+            if let Some(hit) = search::search_session(record, &events, query) {
+                if sessions.len() == limit {
+                    truncated = true;
+                    break;
+                }
+                sessions.push(hit);
+            }
+        }
+        Ok(SearchSessionsResult { sessions, truncated })
+    }
+
+    fn purge_session(&self, params: PurgeSessionParams) -> Result<PurgeSessionResult, CodexError> {
+        let session_id = params.session_id;
+        if !codex_home::is_valid_session_id(&session_id) {
+            return Err(CodexError::InvalidSessionId(session_id));
+        }
+        let record = self.registry.get(&session_id);
+        if record.as_ref().is_some_and(|r| !self.can_access(r))
+            || (record.is_none() && self.isolated_client().is_some())
+        {
+            return Err(CodexError::SessionNotFound {
+                session_id,
+                alternatives: Vec::new(),
+            });
+        }
+
+        let mut result = PurgeSessionResult {
+            session_id: session_id.clone(),
+            ..PurgeSessionResult::default()
+        };
+        let purge = |result: &mut PurgeSessionResult| -> anyhow::Result<()> {
+            for resource in self.resources.list()? {
+                if resource.session_id() == Some(session_id.as_str()) {
+                    self.resources.remove(&resource)?;
+                    result.stored_outputs += 1;
+                }
+            }
+            if let Some(ref root) = self.config().artifacts_dir {
+                result.artifact_dirs = artifacts::remove_session(root, &session_id)?;
+            }
+            result.background_runs = self.jobs.remove_session(&session_id)?;
+            result.idempotency_keys = self.idempotency.remove_session(&session_id)?;
+            result.repo_overviews = self.explain_cache.remove_session(&session_id)?;
+            if params.include_codex_rollout
+                && let Some(rollout) = codex_home::sessions_dir().and_then(|dir| codex_home::find_rollout(&dir, &session_id))
+            {
+                std::fs::remove_file(&rollout).with_context(|| format!("failed to delete {}", rollout.display()))?;
+                result.codex_rollout = true;
+            }
+            // Last, so a failed purge can be retried by the same client.
+            result.registry_entry = self.registry.remove(&session_id)?;
+            Ok(())
+        };
+        purge(&mut result).map_err(|e| CodexError::PurgeFailed {
+            session_id: session_id.clone(),
+            reason: format!("{e:#}"),
+        })?;
+        tracing::info!(session_id = %session_id, ?result, "Purged session data");
+        Ok(result)
+    }
+
+    /// Resolve `cd`, which may name a configured workspace, to a directory path.
+    fn resolve_workspace(&self, cd: &std::path::Path) -> Result<PathBuf, CodexError> {
+        self.resolve_workspace_in(cd, self.config().restrict_to_workspaces)
+    }
+
+    /// Like `resolve_workspace`, but `cd` must always lie in a configured
+    /// workspace, for the tools that read or write its files themselves.
+    fn resolve_configured_workspace(&self, cd: &std::path::Path) -> Result<PathBuf, CodexError> {
+        self.resolve_workspace_in(cd, true)
+    }
+
+    fn resolve_workspace_in(&self, cd: &std::path::Path, restrict: bool) -> Result<PathBuf, CodexError> {
+        let workspaces = &self.config().workspaces;
+        let name = cd.to_str().filter(|s| is_workspace_name(s));
+        if let Some(path) = name.and_then(|name| workspaces.get(name)) {
+            return Ok(path.clone());
+        }
+        let unknown = |name: &str| CodexError::UnknownWorkspace {
+            name: name.to_string(),
+            known: workspaces.keys().cloned().collect(),
+        };
+
+        if restrict {
+            if let Some(name) = name {
+                return Err(unknown(name));
+            }
+            let roots: Vec<PathBuf> = workspaces.values().cloned().collect();
+            let resolved = if cd.exists() {
+                policy::resolve_within(cd, &roots)
+            } else {
+                // May still be created by `create_cd_if_missing`.
+                policy::resolve_new_within(cd, &roots)
+            };
+            return resolved.ok_or_else(|| CodexError::WorkspaceNotAllowed(cd.to_path_buf()));
+        }
+        // A bare name that is neither a workspace nor a directory is most
+        // likely a mistyped workspace name.
+        if let Some(name) = name
+            && !workspaces.is_empty()
+            && !cd.is_dir()
+        {
+            return Err(unknown(name));
+        }
+        Ok(cd.to_path_buf())
+    }
+
+    /// Check a requested base instructions file against the server allowlist.
+    /// Paths outside the allowlist are refused alike whether or not they
+    /// exist, so the error reveals nothing about the rest of the host.
+    fn resolve_instructions_file(&self, file: &std::path::Path) -> Result<PathBuf, CodexError> {
+        let resolved = policy::resolve_new_within(file, &self.config().instructions_dirs)
+            .ok_or_else(|| CodexError::InstructionsFileNotAllowed(file.to_path_buf()))?;
+        if !resolved.is_file() {
+            return Err(CodexError::InstructionsFileNotFound(file.to_path_buf()));
+        }
+        Ok(resolved)
+    }
+
+    /// Apply server policy to the request, producing the options codex will run with.
+    fn effective_options(&self, params: &CodexParams) -> Result<EffectiveOptions, CodexError> {
+        let base_instructions_file = match params.base_instructions_file {
+            Some(ref file) => Some(self.resolve_instructions_file(&params.cd.join(file))?),
+            None => None,
+        };
+        let writable_roots = self.resolve_writable_roots(params)?;
+        let stream_to = match params.stream_to {
+            Some(ref path) => {
+                let path = params.cd.join(path);
+                Some(
+                    policy::resolve_new_within(&path, &self.config().allowed_stream_dirs)
+                        .ok_or(CodexError::StreamToNotAllowed(path))?,
+                )
+            }
+            None => None,
+        };
+        let focus_paths = resolve_focus_paths(&params.cd, &params.focus_paths)?;
+        let images = resolve_images(&params.cd, &params.image, self.config().max_image_bytes)?;
+        // Another client's upload is reported as missing, like one that never existed.
+        if let Some(i) = images.iter().position(|path| !self.uploads.can_use(self.client.as_deref(), path)) {
+            return Err(CodexError::ImageNotFound(params.image[i].clone()));
+        }
+        let context_files = resolve_context_files(
+            &params.cd,
+            &params.context_files,
+            &self.uploads,
+            self.client.as_deref(),
+            &self.exclusions(&params.cd),
+        )?;
+        let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+        let session_id = non_empty(&params.session_id);
+        if params.resume_last && session_id.is_some() {
+            return Err(CodexError::ConflictingResumeOptions);
+        }
+        if params.resume_last && self.isolated_client().is_some() {
+            return Err(CodexError::ResumeLastUnavailable);
+        }
+
+        let model = non_empty(&params.model);
+        let (reasoning_effort, text_verbosity) =
+            tuning::for_model(model.as_deref(), params.reasoning_effort, params.text_verbosity);
+        if (reasoning_effort, text_verbosity) != (params.reasoning_effort, params.text_verbosity) {
+            tracing::info!(model = ?model, "Adapted reasoning_effort/text_verbosity to what the model supports");
+        }
+
+        let mut options = EffectiveOptions {
+            sandbox: params.sandbox.clone(),
+            approval_policy: if params.yolo {
+                "bypass"
+            } else if self.config().approval_bridge {
+                ON_REQUEST
+            } else {
+                "never"
+            }
+            .to_string(),
+            cd: params.cd.clone(),
+            model,
+            profile: non_empty(&params.profile),
+            reasoning_effort,
+            text_verbosity,
+            oss: params.oss.unwrap_or(self.config().oss_default),
+            session_id,
+            resume_last: params.resume_last,
+            images,
+            base_instructions_file,
+            writable_roots,
+            language: resolve_language(params.language.as_deref())?,
+            focus_paths,
+            context_files,
+            stream_to,
+            network_access: self.effective_network_access(params),
+            yolo: params.yolo,
+            os_sandbox: os_sandbox::resolve(self.config().os_sandbox, &params.sandbox, params.yolo)?,
+            skip_git_repo_check: self.effective_skip_git_repo_check(params),
+            args: Vec::new(),
+            auto_model: None,
+        };
+        options.args = options.args();
+        if params.commit.is_some() && !options.is_write_enabled() {
+            return Err(CodexError::CommitRequiresWriteAccess);
+        }
+        if params.post_format && self.config().formatters.is_empty() {
+            return Err(CodexError::PostFormatUnavailable);
+        }
+        if !cfg!(feature = "vcs") && params.commit.as_ref().is_some_and(|c| c.pull_request.is_some()) {
+            return Err(CodexError::PullRequestsUnsupported);
+        }
+        Ok(options)
+    }
+
+    /// The cost cap of a run: the lower of `max_cost_usd` and what is left of
+    /// the daily budget. Fails if the daily budget is used up, or if a cap
+    /// applies but the model has no price to enforce it with.
+    fn cost_limit(
+        &self,
+        max_cost_usd: Option<f64>,
+        price: Option<ModelPrice>,
+        model: Option<&str>,
+    ) -> Result<Option<CostLimit>, CodexError> {
+        let daily_remaining = self.config().daily_budget_usd.map(|budget| budget - self.usage.spent_today());
+        if let Some(remaining) = daily_remaining
+            && remaining <= 0.0
+        {
+            return Err(CodexError::BudgetExceeded(format!(
+                "the daily budget of ${:.2} is used up",
+                self.config().daily_budget_usd.unwrap_or_default()
+            )));
+        }
+        let max_usd = match (max_cost_usd, daily_remaining) {
+            (Some(run), Some(day)) => run.min(day),
+            (Some(cap), None) | (None, Some(cap)) => cap,
+            (None, None) => return Ok(None),
+        };
+        let price = price.ok_or_else(|| CodexError::ModelPriceUnknown(model.unwrap_or("(default)").to_string()))?;
+        Ok(Some(CostLimit { price, max_usd }))
+    }
+
+    /// Reject malformed or unknown session IDs before spawning codex.
+    ///
+    /// A session is known if it is in the registry or has a rollout under the
+    /// codex sessions directory. If that directory is unavailable, only the
+    /// format is checked.
+    fn validate_session_id(&self, session_id: &str, cd: &std::path::Path) -> Result<(), CodexError> {
+        if !codex_home::is_valid_session_id(session_id) {
+            return Err(CodexError::InvalidSessionId(session_id.to_string()));
+        }
+        match self.registry.get(session_id) {
+            Some(record) if self.can_access(&record) => return Ok(()),
+            // Isolated clients may only resume sessions they started; other
+            // sessions are reported as missing so their IDs are not confirmed.
+            Some(_) => return Err(self.session_not_found(session_id, cd)),
+            None if self.isolated_client().is_some() => {
+                return Err(self.session_not_found(session_id, cd));
             }
+            None => {}
+        }
+        let exists = codex_home::sessions_dir()
+            .and_then(|dir| codex_home::rollout_exists(&dir, session_id));
+        if exists != Some(false) {
+            return Ok(());
+        }
+        Err(self.session_not_found(session_id, cd))
+    }
+
+    fn session_not_found(&self, session_id: &str, cd: &std::path::Path) -> CodexError {
+        let recent = SessionFilter {
+            cd: Some(cd.to_path_buf()),
+            owner: self.isolated_client().map(str::to_string),
+            ..SessionFilter::default()
+        };
+        CodexError::SessionNotFound {
+            session_id: session_id.to_string(),
+            alternatives: self
+                .registry
+                .list(&recent)
+                .into_iter()
+                .take(5)
+                .map(|r| r.session_id)
+                .collect(),
+        }
+    }
+
+    /// The client whose view is restricted to its own sessions, if any.
+    fn isolated_client(&self) -> Option<&str> {
+        self.client.as_deref().filter(|_| !self.config().shared_sessions)
+    }
+
+    fn can_access(&self, record: &SessionRecord) -> bool {
+        self.isolated_client()
+            .is_none_or(|client| record.owner.as_deref() == Some(client))
+    }
+
+    /// Replace a `resource:` prompt with the text it refers to: a resource
+    /// of this server the client may read, or a file inside the workspace.
+    fn resolve_prompt(&self, params: &mut CodexParams) -> Result<(), CodexError> {
+        let Some(reference) = params.prompt.strip_prefix(PROMPT_RESOURCE_PREFIX).map(str::trim) else {
+            return Ok(());
+        };
+        let unavailable = |reason: String| CodexError::PromptResourceUnavailable {
+            reference: reference.to_string(),
+            reason,
+        };
+        let text = if reference.starts_with(resources::URI_SCHEME) {
+            self.resources
+                .read(reference)
+                .map_err(|e| unavailable(e.to_string()))?
+                .filter(|(resource, _)| self.can_access_resource(resource))
+                .ok_or_else(|| unavailable("no such resource".to_string()))?
+                .1
+        } else {
+            let cd = self.resolve_workspace(&params.cd)?;
+            let path = reference.strip_prefix("file://").unwrap_or(reference);
+            read_prompt_file(&cd, std::path::Path::new(path), &self.exclusions(&cd)).map_err(unavailable)?
+        };
+        tracing::debug!(reference, chars = text.len(), "Read prompt from resource");
+        params.prompt = text;
+        Ok(())
+    }
+
+    /// Whether a stored run output belongs to a session this client can access.
+    fn can_access_resource(&self, resource: &StoredResource) -> bool {
+        if self.isolated_client().is_none() {
+            return true;
+        }
+        resource
+            .session_id()
+            .and_then(|session_id| self.registry.get(session_id))
+            .is_some_and(|record| self.can_access(&record))
+    }
+
+    /// Whether to pass `--skip-git-repo-check`, after applying the server policy.
+    fn effective_skip_git_repo_check(&self, params: &CodexParams) -> bool {
+        match self.config().git_repo_check {
+            GitRepoCheckPolicy::AlwaysSkip => true,
+            GitRepoCheckPolicy::NeverSkip => false,
+            GitRepoCheckPolicy::ClientChoice => params
+                .skip_git_repo_check
+                .unwrap_or(self.config().skip_git_repo_check_default),
+        }
+    }
+
+    /// Network access to configure for this run, after applying the server default
+    /// and ceiling. `None` when the sandbox policy has no network toggle.
+    fn effective_network_access(&self, params: &CodexParams) -> Option<bool> {
+        if !matches!(params.sandbox, SandboxPolicy::WorkspaceWrite) {
+            return None;
+        }
+        let requested = params
+            .allow_network
+            .unwrap_or(self.config().network_access_default);
+        if requested && !self.config().network_access_permitted {
+            tracing::info!("Network access requested but not permitted by server config");
+            return Some(false);
+        }
+        Some(requested)
+    }
+
+    /// Check requested extra writable roots against the sandbox and server allowlist.
+    fn resolve_writable_roots(&self, params: &CodexParams) -> Result<Vec<PathBuf>, CodexError> {
+        if params.writable_roots.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !matches!(params.sandbox, SandboxPolicy::WorkspaceWrite) {
+            return Err(CodexError::WritableRootsRequireWorkspaceWrite);
+        }
+        params
+            .writable_roots
+            .iter()
+            .map(|root| {
+                let root = params.cd.join(root);
+                policy::resolve_within(&root, &self.config().allowed_writable_roots)
+                    .filter(|p| p.is_dir())
+                    .ok_or(CodexError::WritableRootNotAllowed(root))
+            })
+            .collect()
+    }
+}
+
+/// Remove the tools the server config does not expose, rejecting unknown
+/// tool names so a typo does not silently expose a tool.
+fn filter_tools(router: &mut ToolRouter<CodexServer>, config: &ServerConfig) -> anyhow::Result<()> {
+    let known: Vec<String> = router.list_all().into_iter().map(|tool| tool.name.to_string()).collect();
+    for (key, names) in [("enabled_tools", &config.enabled_tools), ("disabled_tools", &config.disabled_tools)] {
+        if let Some(name) = names.iter().find(|name| !known.contains(name)) {
+            anyhow::bail!("unknown tool {name:?} in `{key}`; available tools: {}", known.join(", "));
+        }
+    }
+    for name in &known {
+        let enabled = config.enabled_tools.is_empty() || config.enabled_tools.contains(name);
+        if !enabled || config.disabled_tools.contains(name) {
+            router.remove_route(name);
         }
+    }
+    Ok(())
+}
+
+/// Register every tool under its name with `prefix` prepended.
+fn prefix_tools(router: &mut ToolRouter<CodexServer>, prefix: &str) -> anyhow::Result<()> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    if prefix.is_empty() || !prefix.chars().all(valid) {
+        anyhow::bail!(
+            "invalid `tool_name_prefix` {prefix:?}: use letters, digits, `_`, `-`, and `.` only"
+        );
+    }
+    router.map = std::mem::take(&mut router.map)
+        .into_values()
+        .map(|mut route| {
+            route.attr.name = format!("{prefix}{}", route.attr.name).into();
+            (route.attr.name.clone(), route)
+        })
+        .collect();
+    Ok(())
+}
+
+/// Fold the attempt of a run whose codex died into the result of resuming
+/// it, so usage, cost, and events cover the whole run.
+fn merge_crashed_attempt(first: CodexResult, result: &mut CodexResult) {
+    if let Some(usage) = first.usage {
+        *result.usage.get_or_insert_default() += usage;
+    }
+    if let Some(cost) = first.cost_usd {
+        *result.cost_usd.get_or_insert_default() += cost;
+    }
+    if let (Some(earlier), Some(later)) = (first.all_messages, result.all_messages.as_mut()) {
+        later.splice(0..0, earlier);
+    }
+    result.sandbox_denials.splice(0..0, first.sandbox_denials);
+    result.approvals.splice(0..0, first.approvals);
+    result.warnings.splice(0..0, first.warnings);
+    result.unparsed_lines.splice(0..0, first.unparsed_lines);
+    let earlier: Vec<FileChange> = first
+        .files_changed
+        .into_iter()
+        .filter(|change| !result.files_changed.iter().any(|c| c.path == change.path))
+        .collect();
+    result.files_changed.splice(0..0, earlier);
+    if result.plan.is_empty() {
+        result.plan = first.plan;
+    }
+    result.resumed_after_crash = true;
+}
+
+/// A failed result for an error that prevented the run.
+pub(crate) fn error_result(e: CodexError, locale: Locale) -> CodexResult {
+    CodexResult {
+        success: false,
+        error: Some(i18n::message(&e, locale)),
+        error_code: Some(e.code().to_string()),
+        retry_after_secs: match e {
+            CodexError::RateLimited(secs) => Some(secs),
+            _ => None,
+        },
+        policy_violations: match e {
+            CodexError::PolicyDenied(violations) => violations,
+            _ => Vec::new(),
+        },
+        ..Default::default()
+    }
+}
+
+/// Replace configured secrets in the text of `result`, e.g. a token echoed
+/// by a failing `gh` command or printed by codex.
+fn redact_result(result: &mut CodexResult) {
+    let texts = [
+        &mut result.agent_messages,
+        &mut result.error,
+        &mut result.patch,
+        &mut result.commit_error,
+        &mut result.pull_request_error,
+        &mut result.session_summary,
+        &mut result.markdown,
+    ];
+    texts.into_iter().for_each(secrets::redact_option);
+    let lines = result.reasoning_summary.iter_mut().flatten();
+    lines.chain(&mut result.unparsed_lines).chain(&mut result.warnings).for_each(secrets::redact_in_place);
+    for message in result.all_messages.iter_mut().flatten().chain(result.structured_answer.as_mut()) {
+        secrets::redact_json(message);
+    }
+    for denial in &mut result.sandbox_denials {
+        secrets::redact_in_place(&mut denial.command);
+        secrets::redact_in_place(&mut denial.reason);
+    }
+    for step in &mut result.plan {
+        secrets::redact_in_place(&mut step.step);
+    }
+    for violation in &mut result.policy_violations {
+        secrets::redact_in_place(&mut violation.reason);
+    }
+    if let Some(ref mut formatting) = result.formatting {
+        secrets::redact_option(&mut formatting.diff);
+        formatting.errors.iter_mut().for_each(|error| secrets::redact_in_place(&mut error.message));
+    }
+}
 
-        // Validate results
-        if thread_id.is_none() {
-            success = false;
-            err_message = format!(
-                "Failed to get `SESSION_ID` from the codex session.\n\n{}",
-                err_message
-            );
+/// Tool response for a run result in `format`, with links to its stored outputs.
+pub(crate) fn tool_result(result: &CodexResult, format: ResponseFormat) -> CallToolResult {
+    let (mut content, structured) = match format {
+        ResponseFormat::Json => {
+            let json_str = serde_json::to_string_pretty(result)
+                .unwrap_or_else(|_| format!("{:?}", result));
+            (vec![Content::text(json_str)], None)
         }
-
-        if agent_messages.is_empty() {
-            success = false;
-            err_message = format!(
-                "Failed to get `agent_messages` from the codex session.\n\nYou can try to set `return_all_messages` to `True` to get the full reasoning information. {}",
-                err_message
-            );
+        ResponseFormat::Text => {
+            let mut metadata = serde_json::to_value(result).unwrap_or_default();
+            let message = match metadata.as_object_mut().and_then(|m| m.remove("agent_messages")) {
+                Some(serde_json::Value::String(message)) => message,
+                _ => result.error.clone().unwrap_or_default(),
+            };
+            let json_str = serde_json::to_string_pretty(&metadata).unwrap_or_default();
+            (vec![Content::text(message), Content::text(json_str)], Some(metadata))
         }
-
-        // Build result
-        let result = if success {
-            CodexResult {
-                success: true,
-                session_id: thread_id,
-                agent_messages: Some(agent_messages),
-                error: None,
-                all_messages,
-            }
-        } else {
-            CodexResult {
-                success: false,
-                session_id: thread_id,
-                agent_messages: if agent_messages.is_empty() {
-                    None
-                } else {
-                    Some(agent_messages)
-                },
-                error: Some(err_message),
-                all_messages,
-            }
-        };
-
-        Ok(result)
+    };
+    if let Some(ref uri) = result.all_messages_uri {
+        let mut link = RawResource::new(uri.clone(), "all_messages");
+        link.description = Some("Full codex event transcript for this run".to_string());
+        link.mime_type = Some(ResourceKind::Transcript.mime_type().to_string());
+        content.push(Content::resource_link(link));
+    }
+    if let Some(ref uri) = result.agent_messages_uri {
+        let mut link = RawResource::new(uri.clone(), "agent_messages");
+        link.description = Some("Untruncated codex agent message for this run".to_string());
+        link.mime_type = Some(ResourceKind::AgentMessages.mime_type().to_string());
+        content.push(Content::resource_link(link));
     }
+    let mut tool_result = CallToolResult::success(content);
+    tool_result.structured_content = structured;
+    tool_result
 }
 
-#[tool_handler]
+#[tool_handler(router = self.tools())]
 impl rmcp::ServerHandler for CodexServer {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: Default::default(),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_tool_list_changed()
+                .enable_resources()
+                .build(),
             server_info: rmcp::model::Implementation {
                 name: "Codex MCP Server".into(),
                 version: env!("CARGO_PKG_VERSION").into(),
@@ -474,6 +3778,310 @@ impl rmcp::ServerHandler for CodexServer {
             ),
         }
     }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.capabilities.add_peer(context.peer);
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let resources = self
+            .resources
+            .list()
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        Ok(ListResourcesResult::with_all_items(
+            resources
+                .iter()
+                .filter(|r| self.can_access_resource(r))
+                .map(|r| r.to_resource().no_annotation())
+                .collect(),
+        ))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let (resource, text) = self
+            .resources
+            .read(&request.uri)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?
+            .filter(|(resource, _)| self.can_access_resource(resource))
+            .ok_or_else(|| {
+                McpError::resource_not_found(format!("Resource not found: {}", request.uri), None)
+            })?;
+        // Stored outputs hold what codex printed, which may include secrets.
+        let text = secrets::redact(&text).into_owned();
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: request.uri,
+                mime_type: Some(resource.kind.mime_type().to_string()),
+                text,
+                meta: None,
+            }],
+        })
+    }
+}
+
+/// Fail unless the local model server at `addr` accepts connections, since
+/// codex would otherwise only fail after trying for a while.
+async fn check_oss_server(addr: &str) -> Result<(), CodexError> {
+    let target = addr.to_string();
+    let connected = tokio::task::spawn_blocking(move || {
+        let mut last_error = std::io::Error::new(std::io::ErrorKind::NotFound, "no address found");
+        for addr in std::net::ToSocketAddrs::to_socket_addrs(&target)? {
+            match std::net::TcpStream::connect_timeout(&addr, Duration::from_secs(2)) {
+                Ok(_) => return Ok(()),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    })
+    .await
+    .map_err(std::io::Error::other)?;
+    connected.map_err(|e| CodexError::OssServerUnreachable {
+        addr: addr.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Create a missing workspace directory, optionally as a fresh git repository.
+async fn create_workspace_dir(cd: &std::path::Path, init_git: bool, allowed: bool) -> Result<(), CodexError> {
+    if !allowed {
+        return Err(CodexError::CreateCdNotAllowed);
+    }
+    // A relative path would be created relative to the server's own directory.
+    if !cd.is_absolute() {
+        return Err(CodexError::InvalidWorkingDirectory(cd.to_path_buf()));
+    }
+    std::fs::create_dir_all(cd)?;
+    tracing::info!(cd = %cd.display(), init_git, "Created workspace directory");
+
+    if init_git {
+        let output = Command::new("git")
+            .arg("init")
+            .arg("--quiet")
+            .current_dir(cd)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(CodexError::Io(std::io::Error::other(format!(
+                "git init failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `cd` is a bare name (no path separators) that may refer to a workspace.
+fn is_workspace_name(cd: &str) -> bool {
+    !cd.is_empty() && cd != "." && cd != ".." && !cd.contains(['/', '\\'])
+}
+
+/// Parse JSONL text, skipping lines that are not valid JSON.
+fn parse_jsonl(text: &str) -> impl Iterator<Item = serde_json::Value> + '_ {
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+}
+
+type StderrLines = tokio::io::Lines<BufReader<tokio::process::ChildStderr>>;
+
+/// Next stderr line, or `None` once stderr is closed or unreadable.
+async fn next_stderr_line(lines: &mut Option<StderrLines>) -> Option<String> {
+    match lines {
+        Some(lines) => lines.next_line().await.ok().flatten(),
+        None => None,
+    }
+}
+
+/// Forward codex stderr to the server log, where it went before it was captured.
+fn log_stderr_line(line: &str) {
+    tracing::info!(target: "codex_stderr", "{line}");
+}
+
+/// Name under which a run's outputs are stored: `<SESSION_ID>-<millis>`.
+fn resource_name(result: &CodexResult) -> String {
+    format!(
+        "{}-{}",
+        result.session_id.as_deref().unwrap_or("run"),
+        unix_now_millis()
+    )
+}
+
+/// Longest prefix of `text` of at most `max_bytes` bytes, cut at a char boundary.
+fn cap_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Keep the head and tail of `text` within `max_chars`, joined by a marker
+/// stating how much was cut. Returns `None` if `text` already fits.
+pub(crate) fn truncate_middle(text: &str, max_chars: usize) -> Option<String> {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return None;
+    }
+
+    let head = max_chars.div_ceil(2);
+    let tail = max_chars - head;
+    let head_end = text.char_indices().nth(head).map_or(text.len(), |(i, _)| i);
+    let tail_start = text
+        .char_indices()
+        .nth(total - tail)
+        .map_or(text.len(), |(i, _)| i);
+    Some(format!(
+        "{}\n\n[... {} characters truncated ...]\n\n{}",
+        &text[..head_end],
+        total - max_chars,
+        &text[tail_start..]
+    ))
+}
+
+/// Longest `language` accepted; real names and tags are far shorter.
+const MAX_LANGUAGE_CHARS: usize = 64;
+
+/// Validate `language`, which is spliced into the prompt: one short line.
+/// Blank means none.
+fn resolve_language(language: Option<&str>) -> Result<Option<String>, CodexError> {
+    let Some(language) = language.map(str::trim).filter(|l| !l.is_empty()) else {
+        return Ok(None);
+    };
+    if language.chars().count() > MAX_LANGUAGE_CHARS || language.chars().any(char::is_control) {
+        return Err(CodexError::InvalidLanguage(language.to_string()));
+    }
+    Ok(Some(language.to_string()))
+}
+
+/// Text of the prompt file `path`, which must be inside the workspace `cd`
+/// and not excluded.
+fn read_prompt_file(
+    cd: &std::path::Path,
+    path: &std::path::Path,
+    exclusions: &PathExclusions,
+) -> Result<String, String> {
+    let root = cd.canonicalize().map_err(|e| e.to_string())?;
+    let resolved = policy::resolve_within(&cd.join(path), std::slice::from_ref(&root))
+        .ok_or_else(|| "not a file inside the working directory".to_string())?;
+    if !resolved.is_file() {
+        return Err("not a file inside the working directory".to_string());
+    }
+    if exclusions.is_excluded(resolved.strip_prefix(&root).unwrap_or(&resolved), false) {
+        return Err("the file is excluded by the workspace's ignore file or the server's `excluded_paths`".to_string());
+    }
+    std::fs::read_to_string(&resolved).map_err(|e| e.to_string())
+}
+
+/// Validate focus paths against the workspace, returning them relative to `cd`.
+fn resolve_focus_paths(cd: &std::path::Path, paths: &[PathBuf]) -> Result<Vec<PathBuf>, CodexError> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let root = cd.canonicalize()?;
+    paths
+        .iter()
+        .map(|path| {
+            let joined = cd.join(path);
+            if !joined.exists() {
+                return Err(CodexError::FocusPathNotFound(path.clone()));
+            }
+            let resolved = policy::resolve_within(&joined, std::slice::from_ref(&root))
+                .ok_or_else(|| CodexError::FocusPathOutsideWorkspace(path.clone()))?;
+            let relative = resolved.strip_prefix(&root).unwrap_or(&resolved);
+            if relative.as_os_str().is_empty() {
+                Ok(PathBuf::from("."))
+            } else {
+                Ok(relative.to_path_buf())
+            }
+        })
+        .collect()
+}
+
+/// Resolve context files against `cd`, checking that each is a file inside
+/// `cd` or one of the uploads of `uploader`, and not excluded if inside `cd`.
+fn resolve_context_files(
+    cd: &std::path::Path,
+    files: &[PathBuf],
+    uploads: &UploadStore,
+    uploader: Option<&str>,
+    exclusions: &PathExclusions,
+) -> Result<Vec<PathBuf>, CodexError> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let root = cd.canonicalize()?;
+    let roots = [root.clone(), uploads.dir().to_path_buf()];
+    files
+        .iter()
+        .map(|file| {
+            let resolved = policy::resolve_within(&cd.join(file), &roots)
+                .filter(|resolved| resolved.is_file() && uploads.can_use(uploader, resolved))
+                .ok_or_else(|| CodexError::ContextFileNotAllowed(file.clone()))?;
+            if let Ok(relative) = resolved.strip_prefix(&root)
+                && exclusions.is_excluded(relative, false)
+            {
+                return Err(CodexError::PathExcluded(file.clone()));
+            }
+            Ok(resolved)
+        })
+        .collect()
+}
+
+/// Resolve attached images against `cd`, checking that each exists, is a
+/// supported format (judged by its contents), and is at most `max_bytes`.
+fn resolve_images(cd: &std::path::Path, images: &[PathBuf], max_bytes: u64) -> Result<Vec<PathBuf>, CodexError> {
+    images
+        .iter()
+        .map(|image| {
+            let path = cd.join(image);
+            let metadata = std::fs::metadata(&path)
+                .ok()
+                .filter(|m| m.is_file())
+                .ok_or_else(|| CodexError::ImageNotFound(image.clone()))?;
+            if metadata.len() > max_bytes {
+                return Err(CodexError::ImageTooLarge {
+                    path: image.clone(),
+                    size: metadata.len(),
+                    max: max_bytes,
+                });
+            }
+            let mut header = [0u8; 12];
+            let read = std::io::Read::read(&mut std::fs::File::open(&path)?, &mut header)?;
+            if !is_supported_image(&header[..read]) {
+                return Err(CodexError::UnsupportedImageFormat(image.clone()));
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Whether `header` starts like a PNG, JPEG, GIF, or WebP file.
+fn is_supported_image(header: &[u8]) -> bool {
+    header.starts_with(b"\x89PNG\r\n\x1a\n")
+        || header.starts_with(&[0xff, 0xd8, 0xff])
+        || header.starts_with(b"GIF87a")
+        || header.starts_with(b"GIF89a")
+        || (header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP"))
+}
+
+/// Format a `-c key=value` override for the codex CLI. `value` must be valid TOML.
+fn config_override(key: &str, value: &str) -> String {
+    format!("{key}={value}")
+}
+
+/// Quote a string as a TOML basic string (JSON string escapes are valid TOML).
+fn toml_string(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
 }
 
 /// Escape special characters for Windows command line.
@@ -501,6 +4109,60 @@ mod tests {
         assert_eq!(windows_escape("say \"hello\""), "say \\\"hello\\\"");
     }
 
+    #[test]
+    fn test_resolve_focus_paths_stays_inside_workspace() {
+        let cd = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let resolved = resolve_focus_paths(cd, &[PathBuf::from("src/./main.rs")]).unwrap();
+        assert_eq!(resolved, [PathBuf::from("src/main.rs")]);
+
+        assert!(matches!(
+            resolve_focus_paths(cd, &[PathBuf::from("src/missing.rs")]),
+            Err(CodexError::FocusPathNotFound(_))
+        ));
+        assert!(matches!(
+            resolve_focus_paths(&cd.join("src"), &[PathBuf::from("../Cargo.toml")]),
+            Err(CodexError::FocusPathOutsideWorkspace(_))
+        ));
+    }
+
+    #[test]
+    fn test_images_are_validated_and_passed_one_per_flag() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-images-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a,b.png"), b"\x89PNG\r\n\x1a\n....").unwrap();
+        std::fs::write(dir.join("c.webp"), b"RIFF\0\0\0\0WEBPVP8 ").unwrap();
+        std::fs::write(dir.join("notes.png"), b"not an image").unwrap();
+
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "describe",
+            "cd": dir,
+            "image": ["a,b.png", dir.join("c.webp")]
+        }))
+        .unwrap();
+        let args = server.effective_options(&params).unwrap().args;
+        let images: Vec<_> = args.windows(2).filter(|w| w[0] == "--image").map(|w| w[1].clone()).collect();
+        assert_eq!(
+            images,
+            [dir.join("a,b.png").display().to_string(), dir.join("c.webp").display().to_string()]
+        );
+
+        let check = |image: &str, max_bytes| resolve_images(&dir, &[PathBuf::from(image)], max_bytes);
+        assert!(matches!(check("missing.png", 1024), Err(CodexError::ImageNotFound(_))));
+        assert!(matches!(check("notes.png", 1024), Err(CodexError::UnsupportedImageFormat(_))));
+        assert!(matches!(check("a,b.png", 4), Err(CodexError::ImageTooLarge { size: 12, max: 4, .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_toml_string_override() {
+        assert_eq!(
+            config_override(INSTRUCTIONS_FILE_KEY, &toml_string(r#"C:\a "b".md"#)),
+            r#"experimental_instructions_file="C:\\a \"b\".md""#
+        );
+    }
+
     #[test]
     fn test_sandbox_policy_as_str() {
         assert_eq!(SandboxPolicy::ReadOnly.as_str(), "read-only");
@@ -522,11 +4184,677 @@ mod tests {
         });
 
         let params: CodexParams = serde_json::from_value(json).unwrap();
-        assert!(!params.skip_git_repo_check);
+        assert_eq!(params.skip_git_repo_check, Some(false));
         assert!(params.return_all_messages);
         assert!(params.yolo);
     }
 
+    #[test]
+    fn test_git_repo_check_policy() {
+        let params = |skip: Option<bool>| CodexParams {
+            skip_git_repo_check: skip,
+            ..serde_json::from_value(serde_json::json!({"PROMPT": "p", "cd": "/w"})).unwrap()
+        };
+        let server = |policy| {
+            CodexServer::new(ServerConfig {
+                git_repo_check: policy,
+                ..ServerConfig::default()
+            })
+            .unwrap()
+        };
+
+        let choice = server(GitRepoCheckPolicy::ClientChoice);
+        assert!(choice.effective_skip_git_repo_check(&params(None)));
+        assert!(!choice.effective_skip_git_repo_check(&params(Some(false))));
+        assert!(!server(GitRepoCheckPolicy::NeverSkip).effective_skip_git_repo_check(&params(Some(true))));
+        assert!(server(GitRepoCheckPolicy::AlwaysSkip).effective_skip_git_repo_check(&params(Some(false))));
+    }
+
+    #[test]
+    fn test_writable_roots_require_workspace_write() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "hello",
+            "cd": ".",
+            "writable_roots": ["target"]
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            server.resolve_writable_roots(&params),
+            Err(CodexError::WritableRootsRequireWorkspaceWrite)
+        ));
+    }
+
+    #[test]
+    fn test_network_access_respects_server_ceiling() {
+        let params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "hello",
+            "cd": ".",
+            "sandbox": "workspace-write",
+            "allow_network": "true"
+        }))
+        .unwrap();
+
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        assert_eq!(server.effective_network_access(&params), Some(true));
+
+        let server = CodexServer::new(ServerConfig {
+            network_access_permitted: false,
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        assert_eq!(server.effective_network_access(&params), Some(false));
+
+        let read_only = CodexParams {
+            sandbox: SandboxPolicy::ReadOnly,
+            ..params
+        };
+        assert_eq!(server.effective_network_access(&read_only), None);
+    }
+
+    #[test]
+    fn test_effective_options_args_exclude_prompt() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "secret prompt",
+            "cd": "/work",
+            "sandbox": "workspace-write",
+            "SESSION_ID": "abc",
+            "model": "",
+            "profile": "fast"
+        }))
+        .unwrap();
+
+        let options = server.effective_options(&params).unwrap();
+        assert_eq!(options.model, None);
+        assert_eq!(
+            options.args,
+            [
+                "exec",
+                "--sandbox",
+                "workspace-write",
+                "--cd",
+                "/work",
+                "--json",
+                "--profile",
+                "fast",
+                "-c",
+                "sandbox_workspace_write.network_access=false",
+                "--skip-git-repo-check",
+                "resume",
+                "abc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tuning_becomes_config_overrides_for_the_model() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let params = |model: &str| -> CodexParams {
+            serde_json::from_value(serde_json::json!({
+                "PROMPT": "go",
+                "cd": "/work",
+                "model": model,
+                "reasoning_effort": "minimal",
+                "text_verbosity": "low"
+            }))
+            .unwrap()
+        };
+        let overrides = |model: &str| -> Vec<String> {
+            let args = server.effective_options(&params(model)).unwrap().args;
+            args.windows(2).filter(|w| w[0] == "-c" && w[1].starts_with("model_")).map(|w| w[1].clone()).collect()
+        };
+
+        assert_eq!(overrides("gpt-5"), [r#"model_reasoning_effort="minimal""#, r#"model_verbosity="low""#]);
+        assert_eq!(overrides("o3"), [r#"model_reasoning_effort="low""#]);
+        assert!(overrides("gpt-4.1").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_oss_runs_pass_oss_and_require_a_reachable_server() {
+        let server = CodexServer::new(ServerConfig {
+            oss_default: true,
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let params: CodexParams = serde_json::from_value(serde_json::json!({"PROMPT": "go", "cd": "/work"})).unwrap();
+        assert!(server.effective_options(&params).unwrap().args.contains(&"--oss".to_string()));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        assert!(check_oss_server(&addr).await.is_ok());
+        drop(listener);
+        assert!(matches!(
+            check_oss_server(&addr).await,
+            Err(CodexError::OssServerUnreachable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_offload_all_messages_above_threshold() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-offload-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            inline_messages_max_bytes: 8,
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let result = CodexResult {
+            success: true,
+            session_id: Some("abc".to_string()),
+            all_messages: Some(vec![serde_json::json!({"type": "turn.completed"})]),
+            ..Default::default()
+        };
+
+        let result = server.offload_all_messages(result);
+        assert!(result.all_messages.is_none());
+        assert_eq!(result.all_messages_count, Some(1));
+        let uri = result.all_messages_uri.unwrap();
+        assert!(uri.starts_with("codex-mcp://transcripts/abc-"));
+        assert!(server.resources.read(&uri).unwrap().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_page_all_messages_continues_in_transcript() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-page-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let session_id = "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61";
+        let event = |n: usize| serde_json::json!({"type": "item.completed", "n": n});
+        // An earlier run of the session whose transcript was stored.
+        server
+            .resources
+            .save_transcript(&format!("{session_id}-1"), &[event(100), event(101)])
+            .unwrap();
+
+        let result = CodexResult {
+            success: true,
+            session_id: Some(session_id.to_string()),
+            all_messages: Some((0..5).map(event).collect()),
+            ..Default::default()
+        };
+        let result = server.page_all_messages(result, 1, Some(2));
+        assert_eq!(result.all_messages, Some(vec![event(1), event(2)]));
+        assert_eq!(result.total_events, Some(5));
+        assert_eq!(result.next_transcript_offset, Some(5));
+        let (_, events) = server.load_transcript(session_id).unwrap();
+        assert_eq!(events[5], event(3));
+
+        let last = CodexResult {
+            session_id: Some(session_id.to_string()),
+            all_messages: Some((0..3).map(event).collect()),
+            ..Default::default()
+        };
+        let last = server.page_all_messages(last, 2, None);
+        assert_eq!(last.all_messages, Some(vec![event(2)]));
+        assert_eq!(last.next_transcript_offset, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncate_middle_keeps_head_and_tail() {
+        assert_eq!(truncate_middle("short", 5), None);
+        assert_eq!(
+            truncate_middle("abcdéfghij", 5).unwrap(),
+            "abc\n\n[... 5 characters truncated ...]\n\nij"
+        );
+    }
+
+    #[test]
+    fn test_commit_message_template() {
+        let commit = CommitOptions {
+            branch: "codex/fix".to_string(),
+            message_template: default_commit_message_template(),
+            pull_request: None,
+        };
+        assert_eq!(
+            commit.message("abc", "\nFixed the parser.\nDetails follow."),
+            "Fixed the parser.\n\nCodex-Session: abc"
+        );
+
+        let commit = CommitOptions {
+            message_template: "fix: {summary} ({session_id})".to_string(),
+            ..commit
+        };
+        assert_eq!(commit.message("abc", "Done"), "fix: Done (abc)");
+        assert_eq!(
+            pull_request_body("abc", "Fixed it.\n"),
+            "Fixed it.\n\n---\nCodex session: `abc`"
+        );
+    }
+
+    #[test]
+    fn test_cap_bytes_respects_char_boundaries() {
+        assert_eq!(cap_bytes("abc", 10), "abc");
+        assert_eq!(cap_bytes("aé", 2), "a");
+        assert_eq!(cap_bytes("abcdef", 3), "abc");
+    }
+
+    #[test]
+    fn test_redact_result_covers_every_text_field() {
+        use crate::events::PlanStatus;
+        use crate::formatting::{FormatError, FormatReport};
+
+        let secret = "sk_redact_result_secret";
+        secrets::register(secret);
+        let text = format!("token {secret}");
+        let mut result = CodexResult {
+            agent_messages: Some(text.clone()),
+            error: Some(text.clone()),
+            patch: Some(text.clone()),
+            commit_error: Some(text.clone()),
+            pull_request_error: Some(text.clone()),
+            session_summary: Some(text.clone()),
+            markdown: Some(text.clone()),
+            reasoning_summary: Some(vec![text.clone()]),
+            unparsed_lines: vec![text.clone()],
+            warnings: vec![text.clone()],
+            all_messages: Some(vec![serde_json::json!({"text": text})]),
+            structured_answer: Some(serde_json::json!({"nested": [text]})),
+            sandbox_denials: vec![SandboxDenial {
+                command: text.clone(),
+                reason: text.clone(),
+            }],
+            plan: vec![PlanStep {
+                step: text.clone(),
+                status: PlanStatus::Pending,
+            }],
+            policy_violations: vec![PolicyViolation {
+                rule: "r".to_string(),
+                parameter: "p".to_string(),
+                reason: text.clone(),
+            }],
+            formatting: Some(FormatReport {
+                formatted: Vec::new(),
+                diff: Some(text.clone()),
+                errors: vec![FormatError {
+                    path: "a.rs".to_string(),
+                    message: text.clone(),
+                }],
+            }),
+            ..CodexResult::default()
+        };
+        redact_result(&mut result);
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(!json.contains(secret), "{json}");
+        assert_eq!(json.matches("token [REDACTED]").count(), 18, "{json}");
+    }
+
+    #[test]
+    fn test_truncated_agent_messages_are_stored_as_resource() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-truncate-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let result = CodexResult {
+            success: true,
+            session_id: Some("abc".to_string()),
+            agent_messages: Some("x".repeat(100)),
+            ..Default::default()
+        };
+
+        let result = server.truncate_agent_messages(result, 10);
+        assert!(result.truncated);
+        assert!(result.agent_messages.unwrap().contains("90 characters truncated"));
+        let uri = result.agent_messages_uri.unwrap();
+        assert!(uri.starts_with("codex-mcp://agent_messages/abc-"));
+        let (_, full) = server.resources.read(&uri).unwrap().unwrap();
+        assert_eq!(full.len(), 100);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume_last_conflicts_with_session_id() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let mut params: CodexParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "continue",
+            "cd": "/work",
+            "resume_last": true
+        }))
+        .unwrap();
+
+        let options = server.effective_options(&params).unwrap();
+        assert!(options.args.ends_with(&["resume".to_string(), "--last".to_string()]));
+
+        params.session_id = Some("abc".to_string());
+        assert!(matches!(
+            server.effective_options(&params),
+            Err(CodexError::ConflictingResumeOptions)
+        ));
+    }
+
+    #[test]
+    fn test_uploads_are_usable_only_by_their_client() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-upload-owner-{}", std::process::id()));
+        let cd = dir.join("workspace");
+        std::fs::create_dir_all(&cd).unwrap();
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let alice = server.for_client("uid:1000".to_string());
+        let bob = server.for_client("uid:1001".to_string());
+        let upload: UploadParams =
+            serde_json::from_value(serde_json::json!({"file_name": "spec.md", "data": "aGk=", "done": true})).unwrap();
+        let path = alice.uploads.append(Some("uid:1000"), &upload, 16).unwrap().path.unwrap();
+
+        let params: CodexParams =
+            serde_json::from_value(serde_json::json!({"PROMPT": "go", "cd": cd, "context_files": [path]})).unwrap();
+        assert!(alice.effective_options(&params).is_ok());
+        assert!(matches!(bob.effective_options(&params), Err(CodexError::ContextFileNotAllowed(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_session_id_rejects_malformed_ids() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        assert!(matches!(
+            server.validate_session_id("not-a-uuid", std::path::Path::new("/work")),
+            Err(CodexError::InvalidSessionId(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_workspace_aliases() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-workspaces-{}", std::process::id()));
+        let backend = root.join("backend");
+        std::fs::create_dir_all(&backend).unwrap();
+        let mut config = ServerConfig::default();
+        config.workspaces.insert("backend".to_string(), backend.clone());
+        let server = CodexServer::new(config.clone()).unwrap();
+
+        let resolve = |server: &CodexServer, cd: &str| server.resolve_workspace(std::path::Path::new(cd));
+        assert_eq!(resolve(&server, "backend").unwrap(), backend);
+        assert!(matches!(
+            resolve(&server, "backnd"),
+            Err(CodexError::UnknownWorkspace { ref known, .. }) if known == &["backend"]
+        ));
+        assert_eq!(resolve(&server, "/tmp").unwrap(), PathBuf::from("/tmp"));
+
+        config.restrict_to_workspaces = true;
+        let restricted = CodexServer::new(config).unwrap();
+        assert!(resolve(&restricted, backend.join(".").to_str().unwrap()).is_ok());
+        assert!(matches!(
+            resolve(&restricted, "/tmp"),
+            Err(CodexError::WorkspaceNotAllowed(_))
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_instructions_outside_allowlist_are_refused_alike() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-instructions-{}", std::process::id()));
+        let allowed = root.join("personas");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::write(allowed.join("reviewer.md"), "Review carefully.").unwrap();
+        std::fs::write(root.join("other.md"), "x").unwrap();
+        let server = CodexServer::new(ServerConfig {
+            instructions_dirs: vec![allowed.clone()],
+            ..ServerConfig::default()
+        })
+        .unwrap();
+
+        let resolve = |path: PathBuf| server.resolve_instructions_file(&path);
+        assert!(resolve(allowed.join("reviewer.md")).is_ok());
+        assert!(matches!(resolve(allowed.join("missing.md")), Err(CodexError::InstructionsFileNotFound(_))));
+        for outside in [root.join("other.md"), root.join("missing.md"), allowed.join("../other.md")] {
+            assert!(matches!(resolve(outside), Err(CodexError::InstructionsFileNotAllowed(_))));
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_workspace_dir_requires_server_opt_in() {
+        let dir = std::env::temp_dir()
+            .join(format!("codex-mcp-create-cd-{}", std::process::id()))
+            .join("scratch");
+        assert!(matches!(
+            create_workspace_dir(&dir, false, false).await,
+            Err(CodexError::CreateCdNotAllowed)
+        ));
+        assert!(!dir.exists());
+        assert!(matches!(
+            create_workspace_dir(std::path::Path::new("scratch"), false, true).await,
+            Err(CodexError::InvalidWorkingDirectory(_))
+        ));
+
+        create_workspace_dir(&dir, true, true).await.unwrap();
+        assert!(dir.join(".git").is_dir());
+
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_load_transcript_concatenates_stored_runs() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-read-transcript-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let session_id = "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61";
+        let event = |n: u64| serde_json::json!({"type": "item.completed", "n": n});
+        server
+            .resources
+            .save_transcript(&format!("{session_id}-1000"), &[event(1), event(2)])
+            .unwrap();
+        server
+            .resources
+            .save_transcript(&format!("{session_id}-2000"), &[event(3)])
+            .unwrap();
+        secrets::register("sk_transcript_secret");
+        let secret_event = serde_json::json!({"n": 4, "text": "sk_transcript_secret"});
+        server
+            .resources
+            .save_transcript(&format!("{session_id}-3000"), &[secret_event])
+            .unwrap();
+
+        let (source, events) = server.load_transcript(session_id).unwrap();
+        assert_eq!(source, "server");
+        let order: Vec<u64> = events.iter().map(|e| e["n"].as_u64().unwrap()).collect();
+        assert_eq!(order, [1, 2, 3, 4]);
+        assert_eq!(events[3]["text"], "[REDACTED]");
+        assert!(matches!(
+            server.load_transcript("nope"),
+            Err(CodexError::InvalidSessionId(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_sessions_finds_transcript_text() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-search-sessions-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        for (session_id, label, text) in [
+            ("019bc4ce-610d-7f50-bd2a-fb5b8ac83b61", "deps", "Bumped tokio."),
+            ("019bc4ce-610d-7f50-bd2a-fb5b8ac83b62", "cleanup", "Split the auth module into login and session."),
+        ] {
+            server.registry.record_run(RunRecord {
+                session_id,
+                cd: &dir,
+                label: Some(label),
+                tags: &[],
+                model: None,
+            language: None,
+                success: true,
+                owner: None,
+            });
+            let event = serde_json::json!({"type": "item.completed", "item": {"type": "agent_message", "text": text}});
+            server.resources.save_transcript(&format!("{session_id}-1000"), &[event]).unwrap();
+        }
+
+        let search = |query: &str| server.search_sessions(SearchSessionsParams { query: query.to_string(), limit: None });
+        let result = search("Auth Module").unwrap();
+        assert_eq!(result.sessions.len(), 1);
+        assert_eq!(result.sessions[0].session.label.as_deref(), Some("cleanup"));
+        assert_eq!(result.sessions[0].matches[0].field, "transcript");
+        assert_eq!(search("codex-mcp-search").unwrap().sessions.len(), 2, "cd matches");
+        assert!(matches!(search(" "), Err(CodexError::EmptySearchQuery)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_purge_session_deletes_its_stored_data() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-purge-session-{}", std::process::id()));
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.join("state")),
+            artifacts_dir: Some(dir.join("artifacts")),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let (purged, kept) = ("019bc4ce-610d-7f50-bd2a-fb5b8ac83b61", "019bc4ce-610d-7f50-bd2a-fb5b8ac83b62");
+        for session_id in [purged, kept] {
+            server.registry.record_run(RunRecord {
+                session_id,
+                cd: &dir,
+                label: None,
+                tags: &[],
+                model: None,
+            language: None,
+                success: true,
+                owner: None,
+            });
+            server.resources.save_transcript(&format!("{session_id}-1000"), &[]).unwrap();
+            let result = CodexResult {
+                success: true,
+                session_id: Some(session_id.to_string()),
+                ..Default::default()
+            };
+            RunArtifacts::create(&dir.join("artifacts")).unwrap().finish(&result, None).unwrap();
+            server.idempotency.claim(session_id, "req");
+            server.idempotency.complete(session_id, &result);
+        }
+
+        let purge = |session_id: &str| {
+            server.purge_session(PurgeSessionParams {
+                session_id: session_id.to_string(),
+                include_codex_rollout: false,
+            })
+        };
+        let result = purge(purged).unwrap();
+        assert!(result.registry_entry);
+        assert_eq!((result.stored_outputs, result.artifact_dirs, result.idempotency_keys), (1, 1, 1));
+        assert!(server.registry.get(purged).is_none() && server.registry.get(kept).is_some());
+        assert!(server.load_transcript(kept).is_ok());
+        assert!(matches!(server.idempotency.claim(purged, "req"), Claim::New));
+        assert!(matches!(server.idempotency.claim(kept, "req"), Claim::Completed(_)));
+        assert_eq!(std::fs::read_dir(dir.join("artifacts")).unwrap().count(), 1);
+
+        let again = purge(purged).unwrap();
+        assert!(!again.registry_entry && again.stored_outputs == 0);
+        assert!(matches!(purge("nope"), Err(CodexError::InvalidSessionId(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tools_can_be_enabled_and_disabled() {
+        let tools = |enabled: &[&str], disabled: &[&str]| {
+            let server = CodexServer::new(ServerConfig {
+                enabled_tools: enabled.iter().map(|t| t.to_string()).collect(),
+                disabled_tools: disabled.iter().map(|t| t.to_string()).collect(),
+                ..ServerConfig::default()
+            })?;
+            let mut names: Vec<String> = server.tool_router.list_all().into_iter().map(|t| t.name.to_string()).collect();
+            names.sort();
+            anyhow::Ok(names)
+        };
+
+        assert!(tools(&[], &[]).unwrap().len() > 1);
+        assert_eq!(tools(&["codex"], &[]).unwrap(), ["codex"]);
+        assert!(!tools(&[], &["codex_usage"]).unwrap().contains(&"codex_usage".to_string()));
+        assert!(tools(&["codx"], &[]).is_err());
+
+        let prefixed = CodexServer::new(ServerConfig {
+            enabled_tools: vec!["codex".to_string(), "codex_poll".to_string()],
+            tool_name_prefix: Some("acme_".to_string()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let mut names: Vec<String> = prefixed.tool_router.list_all().into_iter().map(|t| t.name.to_string()).collect();
+        names.sort();
+        assert_eq!(names, ["acme_codex", "acme_codex_poll"]);
+        let invalid = CodexServer::new(ServerConfig {
+            tool_name_prefix: Some("acme tools".to_string()),
+            ..ServerConfig::default()
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_interrupted_background_runs_are_marked_failed() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-recover-{}", std::process::id()));
+        let params: CodexParams = serde_json::from_value(serde_json::json!({"PROMPT": "p", "cd": "/w"})).unwrap();
+        let run_id = JobTable::open(Some(Arc::new(state::FileStore::new(&dir)))).unwrap().create(None, params).id();
+
+        let server = CodexServer::new(ServerConfig {
+            state_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        assert_eq!(server.jobs.get(&run_id).unwrap().status().status, crate::jobs::JobStatus::Running);
+        server.recover_interrupted_runs();
+        let result = server.jobs.get(&run_id).unwrap().result().unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error_code.as_deref(), Some("run_interrupted"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_isolated_clients_cannot_resume_each_others_sessions() {
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let session_id = "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61";
+        server.registry.record_run(RunRecord {
+            session_id,
+            cd: std::path::Path::new("/work"),
+            label: None,
+            tags: &[],
+            model: None,
+            language: None,
+            success: true,
+            owner: Some("uid:1000"),
+        });
+        let cd = std::path::Path::new("/work");
+
+        let owner = server.for_client("uid:1000".to_string());
+        assert!(owner.validate_session_id(session_id, cd).is_ok());
+        let other = server.for_client("uid:1001".to_string());
+        assert!(matches!(
+            other.validate_session_id(session_id, cd),
+            Err(CodexError::SessionNotFound { .. })
+        ));
+
+        let shared = CodexServer {
+            config: Arc::new(RwLock::new(Arc::new(ServerConfig {
+                shared_sessions: true,
+                ..ServerConfig::default()
+            }))),
+            ..other
+        };
+        assert!(shared.validate_session_id(session_id, cd).is_ok());
+    }
+
     #[test]
     fn test_codex_params_bool_fields_reject_invalid_string() {
         let json = serde_json::json!({
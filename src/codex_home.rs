@@ -0,0 +1,93 @@
+//! Access to the codex CLI's own state directory (`$CODEX_HOME`, default `~/.codex`).
+
+use std::path::{Path, PathBuf};
+
+/// Environment variable the codex CLI uses to override its home directory.
+const CODEX_HOME_ENV: &str = "CODEX_HOME";
+
+/// Resolve the codex home directory, if it can be determined.
+pub fn codex_home() -> Option<PathBuf> {
+    match std::env::var_os(CODEX_HOME_ENV) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::home_dir().map(|home| home.join(".codex")),
+    }
+}
+
+/// Directory where the codex CLI stores session rollouts.
+pub fn sessions_dir() -> Option<PathBuf> {
+    codex_home().map(|home| home.join("sessions"))
+}
+
+/// Whether `session_id` has the UUID shape codex uses for session IDs.
+pub fn is_valid_session_id(session_id: &str) -> bool {
+    let groups: Vec<&str> = session_id.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Look for a rollout file for `session_id` under `dir`.
+///
+/// Returns `None` when `dir` does not exist, meaning existence cannot be
+/// determined (e.g. the CLI runs as a different user), rather than `Some(false)`.
+pub fn rollout_exists(dir: &Path, session_id: &str) -> Option<bool> {
+    if !dir.is_dir() {
+        return None;
+    }
+    Some(find_rollout(dir, session_id).is_some())
+}
+
+/// Path of the rollout file for `session_id` under `dir`, if there is one.
+pub fn find_rollout(dir: &Path, session_id: &str) -> Option<PathBuf> {
+    let suffix = format!("{}.jsonl", session_id.to_ascii_lowercase());
+    find_file_with_suffix(dir, &suffix)
+}
+
+/// Recursively search `dir` for a file whose name ends with `suffix`.
+fn find_file_with_suffix(dir: &Path, suffix: &str) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    entries.flatten().find_map(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            find_file_with_suffix(&path, suffix)
+        } else {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.to_ascii_lowercase().ends_with(suffix))
+                .then_some(path)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_session_id() {
+        assert!(is_valid_session_id("019bc4ce-610d-7f50-bd2a-fb5b8ac83b61"));
+        assert!(!is_valid_session_id("019bc4ce-610d-7f50-bd2a"));
+        assert!(!is_valid_session_id("019bc4ce-610d-7f50-bd2a-fb5b8ac83b6z"));
+        assert!(!is_valid_session_id("last"));
+    }
+
+    #[test]
+    fn test_rollout_exists_searches_nested_dirs() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-home-{}", std::process::id()));
+        let day = dir.join("2025/01/02");
+        std::fs::create_dir_all(&day).unwrap();
+        let id = "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61";
+        std::fs::write(day.join(format!("rollout-2025-01-02T10-00-00-{id}.jsonl")), "").unwrap();
+
+        assert_eq!(rollout_exists(&dir, id), Some(true));
+        assert_eq!(
+            rollout_exists(&dir, "00000000-0000-0000-0000-000000000000"),
+            Some(false)
+        );
+        assert_eq!(rollout_exists(&dir.join("missing"), id), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+//! Shell completion scripts and the man page (`codex-mcp completions <shell>`,
+//! `codex-mcp man`), generated from the flag tables in `cli` so they list
+//! exactly what the parser accepts.
+
+use std::fmt::Write;
+
+use crate::cli::{EXEC_FLAGS, Flag, FlagValue, SERVE_FLAGS, SUBCOMMANDS, Shell};
+
+/// Completion script for `shell`.
+pub fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+/// `--long` and `-s` spellings of `flag`.
+fn spellings(flag: &Flag) -> Vec<String> {
+    let mut names = vec![format!("--{}", flag.long)];
+    names.extend(flag.short.map(|short| format!("-{short}")));
+    names
+}
+
+fn words(flags: &[Flag]) -> String {
+    flags.iter().flat_map(spellings).collect::<Vec<_>>().join(" ")
+}
+
+fn bash() -> String {
+    let mut out = String::from(
+        "_codex_mcp() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n\n    case \"$prev\" in\n",
+    );
+    for flag in SERVE_FLAGS.iter().chain(EXEC_FLAGS) {
+        let reply = match flag.value {
+            FlagValue::None => continue,
+            FlagValue::Text(_) => String::new(),
+            FlagValue::Path(_) => "COMPREPLY=($(compgen -f -- \"$cur\")); ".to_string(),
+            FlagValue::OneOf(values) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); ", values.join(" ")),
+        };
+        let _ = writeln!(out, "        {}) {reply}return ;;", spellings(flag).join("|"));
+    }
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|(name, _)| *name).collect();
+    let _ = write!(
+        out,
+        "    esac
+
+    local words=\"\"
+    case \"${{COMP_WORDS[1]}}\" in
+        exec) words=\"{exec}\" ;;
+        completions) [[ $COMP_CWORD -eq 2 ]] && words=\"{shells}\" ;;
+        man) ;;
+        *)
+            words=\"{serve}\"
+            [[ $COMP_CWORD -eq 1 ]] && words=\"{subcommands} $words\"
+            ;;
+    esac
+    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))
+}}
+
+complete -F _codex_mcp codex-mcp
+",
+        exec = words(EXEC_FLAGS),
+        shells = Shell::NAMES.join(" "),
+        serve = words(SERVE_FLAGS),
+        subcommands = subcommands.join(" "),
+    );
+    out
+}
+
+/// `_arguments` spec of `flag`, single-quoted.
+fn zsh_spec(flag: &Flag) -> String {
+    let help = flag.help.replace('\'', r"'\''").replace('[', r"\[").replace(']', r"\]");
+    let value = match flag.value {
+        FlagValue::None => String::new(),
+        FlagValue::Text(name) => format!(":{name}:"),
+        FlagValue::Path(name) => format!(":{name}:_files"),
+        FlagValue::OneOf(values) => format!(":value:({})", values.join(" ")),
+    };
+    match flag.short {
+        Some(short) => format!("'(-{short} --{long})'{{-{short},--{long}}}'[{help}]{value}'", long = flag.long),
+        None => format!("'--{}[{help}]{value}'", flag.long),
+    }
+}
+
+fn zsh() -> String {
+    let specs = |flags: &[Flag], indent: &str| {
+        flags.iter().map(|flag| format!("{indent}{} \\\n", zsh_spec(flag))).collect::<String>()
+    };
+    let subcommands: Vec<String> = SUBCOMMANDS
+        .iter()
+        .map(|(name, about)| format!("'{name}:{}'", about.replace('\'', r"'\''")))
+        .collect();
+    format!(
+        "#compdef codex-mcp
+
+_codex_mcp() {{
+    local line state
+
+    _arguments -C \\
+{serve}        '1: :->command' \\
+        '*:: :->args'
+
+    case $state in
+        command)
+            local -a commands=({subcommands})
+            _describe -t commands command commands
+            ;;
+        args)
+            case $line[1] in
+                exec)
+                    _arguments \\
+{exec}                        '1::prompt:'
+                    ;;
+                completions) _arguments '1:shell:({shells})' ;;
+            esac
+            ;;
+    esac
+}}
+
+_codex_mcp \"$@\"
+",
+        serve = specs(SERVE_FLAGS, "        "),
+        exec = specs(EXEC_FLAGS, "                        "),
+        subcommands = subcommands.join(" "),
+        shells = Shell::NAMES.join(" "),
+    )
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn fish() -> String {
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|(name, _)| *name).collect();
+    let mut out = String::from("complete -c codex-mcp -f\n");
+    for (name, about) in SUBCOMMANDS {
+        let _ = writeln!(out, "complete -c codex-mcp -n __fish_use_subcommand -a {name} -d {}", fish_quote(about));
+    }
+    let serve_condition = format!("'not __fish_seen_subcommand_from {}'", subcommands.join(" "));
+    for (condition, flags) in [(serve_condition.as_str(), SERVE_FLAGS), ("'__fish_seen_subcommand_from exec'", EXEC_FLAGS)] {
+        for flag in flags {
+            let _ = write!(out, "complete -c codex-mcp -n {condition} -l {}", flag.long);
+            if let Some(short) = flag.short {
+                let _ = write!(out, " -s {short}");
+            }
+            match flag.value {
+                FlagValue::None => {}
+                FlagValue::Text(_) => out.push_str(" -x"),
+                FlagValue::Path(_) => out.push_str(" -r -F"),
+                FlagValue::OneOf(values) => {
+                    let _ = write!(out, " -x -a {}", fish_quote(&values.join(" ")));
+                }
+            }
+            let _ = writeln!(out, " -d {}", fish_quote(flag.help));
+        }
+    }
+    let _ = writeln!(
+        out,
+        "complete -c codex-mcp -n '__fish_seen_subcommand_from completions' -a {}",
+        fish_quote(&Shell::NAMES.join(" "))
+    );
+    out
+}
+
+/// `text` with the characters roff treats specially escaped.
+fn roff(text: &str) -> String {
+    let text = text.replace('\\', r"\e").replace('-', r"\-");
+    if text.starts_with(['.', '\'']) { format!(r"\&{text}") } else { text }
+}
+
+fn man_flags(out: &mut String, flags: &[Flag]) {
+    for flag in flags {
+        let names: Vec<String> = spellings(flag).iter().rev().map(|name| format!(r"\fB{}\fR", roff(name))).collect();
+        let value = match flag.value {
+            FlagValue::None => String::new(),
+            FlagValue::Text(name) | FlagValue::Path(name) => format!(r" \fI{name}\fR"),
+            FlagValue::OneOf(values) => {
+                let values: Vec<String> = values.iter().map(|v| format!(r"\fI{}\fR", roff(v))).collect();
+                format!(" {}", values.join("|"))
+            }
+        };
+        let _ = write!(out, ".TP\n{}{value}\n{}\n", names.join(", "), roff(flag.help));
+    }
+}
+
+/// The man page, in roff format.
+pub fn man_page() -> String {
+    let mut out = format!(
+        r#".TH CODEX\-MCP 1 "" "codex\-mcp {version}" "User Commands"
+.SH NAME
+codex\-mcp \- {description}
+.SH SYNOPSIS
+.B codex\-mcp
+[\fIOPTIONS\fR]
+.br
+.B codex\-mcp exec
+[\fIEXEC OPTIONS\fR] [\fIPROMPT\fR]
+.br
+.B codex\-mcp completions
+\fIbash\fR|\fIzsh\fR|\fIfish\fR
+.br
+.B codex\-mcp man
+.SH DESCRIPTION
+Serves the Model Context Protocol (MCP) over stdio, or a Unix socket, with
+tools that run the Codex CLI on behalf of the connected client.
+.SH OPTIONS
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+        description = roff(env!("CARGO_PKG_DESCRIPTION")),
+    );
+    man_flags(&mut out, SERVE_FLAGS);
+    out.push_str(".SH COMMANDS\n");
+    for (name, about) in SUBCOMMANDS {
+        let _ = write!(out, ".TP\n.B {name}\n{}.\n", roff(about));
+    }
+    out.push_str(
+        r".PP
+\fBexec\fR prints each result as a line of JSON. Without \fIPROMPT\fR, every
+non\-empty line of stdin is run as a prompt. It exits with status 1 when any
+run failed.
+.SH EXEC OPTIONS
+",
+    );
+    man_flags(&mut out, EXEC_FLAGS);
+    out.push_str(
+        r".SH ENVIRONMENT
+.TP
+.B CODEX_MCP_CONFIG
+Path of the server configuration file (TOML); defaults are used when unset.
+.TP
+.B RUST_LOG
+Log filter, e.g. \fBdebug\fR or \fBwarn\fR (default: \fBinfo\fR). Logs are
+written to stderr.
+",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_list_every_flag() {
+        let outputs = [bash(), zsh(), fish(), man_page()];
+        for flag in SERVE_FLAGS.iter().chain(EXEC_FLAGS) {
+            assert!(outputs[0].contains(&format!("--{}", flag.long)), "bash lacks --{}", flag.long);
+            assert!(outputs[1].contains(&format!("--{}", flag.long)), "zsh lacks --{}", flag.long);
+            assert!(outputs[2].contains(&format!("-l {} ", flag.long)), "fish lacks --{}", flag.long);
+            assert!(outputs[3].contains(&format!(r"\fB{}\fR", roff(&format!("--{}", flag.long)))), "man lacks --{}", flag.long);
+        }
+        assert!(outputs[0].contains("--sandbox) COMPREPLY=($(compgen -W \"read-only workspace-write danger-full-access\""));
+        assert!(outputs[1].contains(r"'(-h --help)'{-h,--help}'[Print help]'"));
+    }
+
+    #[test]
+    fn test_bash_script_parses() {
+        let Ok(output) = std::process::Command::new("bash").args(["-n", "-c", &bash()]).output() else {
+            return;
+        };
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -0,0 +1,276 @@
+//! `compress`: gzip+base64 encoding of large result payloads (`all_messages`,
+//! transcript pages), for clients on bandwidth-constrained links.
+//!
+//! The encoder is a small DEFLATE implementation (LZ77 over a 32 KiB window
+//! with fixed Huffman codes, RFC 1951) wrapped in a gzip member (RFC 1952).
+//! It trades some ratio for simplicity, which matters little for the highly
+//! repetitive JSON it is used on; any gzip decoder reads its output.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
+
+/// Encoding declared in results carrying compressed payloads.
+pub const ENCODING: &str = "gzip+base64";
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// Most earlier positions tried per match, bounding the time spent on
+/// inputs with many repeats.
+const MAX_CHAIN: usize = 64;
+
+/// Base lengths of length codes 257..=285 and their extra bits.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// Base distances of distance codes 0..=29 and their extra bits.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// `value` as JSON, gzip-compressed and base64-encoded.
+pub fn encode_json<T: Serialize + ?Sized>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    BASE64.encode(gzip(&json))
+}
+
+/// `data` as a gzip member.
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // Magic, deflate, no flags, no mtime, no extra flags, unknown OS.
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Writes bits least significant first, as DEFLATE requires.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which is defined most significant bit first.
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// A literal/length symbol in the fixed Huffman code.
+fn literal(writer: &mut BitWriter, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn copy(writer: &mut BitWriter, len: usize, dist: usize) {
+    let code = LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= len).expect("len >= 3");
+    literal(writer, 257 + code as u16);
+    writer.bits((len - usize::from(LENGTH_BASE[code])) as u32, u32::from(LENGTH_EXTRA[code]));
+    let code = DIST_BASE.iter().rposition(|&base| usize::from(base) <= dist).expect("dist >= 1");
+    writer.code(code as u32, 5);
+    writer.bits((dist - usize::from(DIST_BASE[code])) as u32, u32::from(DIST_EXTRA[code]));
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let key = (u32::from(data[pos]) << 16) | (u32::from(data[pos + 1]) << 8) | u32::from(data[pos + 2]);
+    (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// `data` as one final fixed-Huffman DEFLATE block.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes).
+    writer.bits(0b011, 3);
+
+    // Most recent position of each hash, and the previous position with the
+    // same hash of each position in the window.
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut [usize], prev: &mut [usize]| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(data, pos);
+            prev[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let len = data[candidate..].iter().zip(&data[pos..pos + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                // Older entries of the ring were overwritten by newer positions.
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+        if best.0 >= MIN_MATCH {
+            copy(&mut writer, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(p, &mut head, &mut prev);
+            }
+            pos += best.0;
+        } else {
+            literal(&mut writer, u16::from(data[pos]));
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    literal(&mut writer, 256);
+    writer.finish()
+}
+
+/// CRC-32 (IEEE) of `data`, as gzip stores it.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decoder for the fixed-Huffman blocks `deflate` writes.
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut bit = 0;
+        let mut read = |count: u32| -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                value |= u32::from((data[bit / 8] >> (bit % 8)) & 1) << i;
+                bit += 1;
+            }
+            value
+        };
+        assert_eq!(read(3), 0b011, "one final fixed-Huffman block");
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let mut code = 0;
+            let mut len = 0;
+            let symbol = loop {
+                code = (code << 1) | read(1);
+                len += 1;
+                match (len, code) {
+                    (7, 0..=0x17) => break code + 256,
+                    (8, 0x30..=0xbf) => break code - 0x30,
+                    (8, 0xc0..=0xc7) => break code - 0xc0 + 280,
+                    (9, 0x190..=0x1ff) => break code - 0x190 + 144,
+                    _ => {}
+                }
+            };
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => return out,
+                _ => {
+                    let i = (symbol - 257) as usize;
+                    let len = usize::from(LENGTH_BASE[i]) + read(u32::from(LENGTH_EXTRA[i])) as usize;
+                    let d = (0..5).fold(0, |code, _| (code << 1) | read(1)) as usize;
+                    let dist = usize::from(DIST_BASE[d]) + read(u32::from(DIST_EXTRA[d])) as usize;
+                    for _ in 0..len {
+                        out.push(out[out.len() - dist]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gzip_round_trips_and_compresses() {
+        let events: Vec<serde_json::Value> = (0..200)
+            .map(|i| serde_json::json!({"type": "item.completed", "item": {"id": format!("item_{i}"), "type": "reasoning"}}))
+            .collect();
+        let json = serde_json::to_vec(&events).unwrap();
+        let compressed = gzip(&json);
+        assert!(compressed.len() * 5 < json.len(), "{} of {} bytes", compressed.len(), json.len());
+        assert_eq!(&compressed[..3], &[0x1f, 0x8b, 8]);
+        let body = &compressed[10..compressed.len() - 8];
+        assert_eq!(inflate(body), json);
+        assert_eq!(&compressed[compressed.len() - 8..compressed.len() - 4], &crc32(&json).to_le_bytes());
+
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let binary: Vec<u8> = (0..=255).cycle().take(70_000).collect();
+        assert_eq!(inflate(&deflate(&binary)), binary);
+        assert_eq!(inflate(&deflate(b"")), b"");
+        assert_eq!(BASE64.decode(encode_json(&events)).unwrap(), compressed);
+    }
+
+    #[test]
+    fn test_gzip_agrees_with_reference_output() {
+        // `printf %s <text> | gzip -n`, which chooses fixed Huffman codes for
+        // these inputs too. Decoding them checks `inflate` and `crc32`
+        // against an independent encoder, so the round trip above cannot
+        // pass on a mistake the two sides share.
+        let vectors = [
+            ("a", "1f8b08000000000000034b040043beb7e801000000"),
+            ("hello hello hello hello", "1f8b0800000000000003cb48cdc9c957c8402701e3513d8d17000000"),
+            (
+                r#"{"type":"item.completed","type":"item.completed"}"#,
+                "1f8b0800000000000003ab562aa92c4855b252ca2c49cdd54bcecf2dc8492d494d51d2c1215e0b00b03d0faa31000000",
+            ),
+        ];
+        let hex = |text: &str| -> Vec<u8> {
+            (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+        };
+        for (text, reference) in vectors {
+            let reference = hex(reference);
+            let (body, trailer) = reference[10..].split_at(reference.len() - 18);
+            assert_eq!(inflate(body), text.as_bytes(), "{text}");
+            let ours = gzip(text.as_bytes());
+            assert_eq!(&ours[ours.len() - 8..], trailer, "{text}");
+            // The same header, except the OS: 3 (Unix) there, 255 (unknown) here.
+            assert_eq!((&ours[..9], ours[9]), (&reference[..9], 0xff));
+        }
+        // A single literal leaves the LZ77 parse no choice, so the whole
+        // output matches.
+        assert_eq!(gzip(b"a")[10..], hex(vectors[0].1)[10..]);
+    }
+}
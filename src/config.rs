@@ -0,0 +1,568 @@
+//! Server-side configuration for the Codex MCP server.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::codex::CodexParams;
+use crate::cost::{self, ModelPrice};
+use crate::i18n::Locale;
+use crate::log_format::LogFormat;
+use crate::memory;
+use crate::noise::NoiseFilter;
+use crate::post_process::PostProcessorConfig;
+use crate::orphans::OrphanPolicy;
+use crate::os_sandbox::OsSandbox;
+use crate::secrets;
+use crate::state::StateBackend;
+use crate::workspace::WorkspaceLockMode;
+
+/// Environment variable pointing to the server configuration file (TOML).
+pub const CONFIG_ENV: &str = "CODEX_MCP_CONFIG";
+
+/// Whether codex may run outside a git repository (`--skip-git-repo-check`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitRepoCheckPolicy {
+    /// Honor the client's `skip_git_repo_check`, defaulting to
+    /// `skip_git_repo_check_default` when omitted (default).
+    #[default]
+    ClientChoice,
+    /// Always skip the check, regardless of the request.
+    AlwaysSkip,
+    /// Never skip the check, so codex refuses to run outside version control.
+    NeverSkip,
+}
+
+/// Hosting service that `commit.pull_request` opens pull/merge requests on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VcsProviderKind {
+    /// GitHub, through the `gh` CLI (default).
+    #[default]
+    Github,
+    /// GitLab, through the `glab` CLI.
+    Gitlab,
+    /// Bitbucket Cloud, through its REST API with `curl`.
+    Bitbucket,
+}
+
+/// Configuration controlled by whoever deploys the server, as opposed to
+/// the per-request parameters supplied by MCP clients.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Path to the codex executable. When unset, `codex` is looked up on `PATH`.
+    pub codex_path: Option<PathBuf>,
+
+    /// Directory for persistent server state (session registry, etc.).
+    /// When unset, state is kept in memory and lost on restart.
+    pub state_dir: Option<PathBuf>,
+
+    /// How state is kept: `files` (one JSON file each in `state_dir`),
+    /// `sqlite` (one database there, written in transactions), or `redis`
+    /// (shared by replicas, see `redis_url`).
+    pub state_backend: StateBackend,
+
+    /// Redis server of the `redis` backend, e.g. `redis://:password@host:6379/0`,
+    /// or a `keychain:`/`file:` reference to one.
+    pub redis_url: Option<String>,
+
+    /// Prefix of every key the `redis` backend writes, so deployments can
+    /// share a Redis database.
+    pub redis_key_prefix: String,
+
+    /// Persist `codex_usage` totals in `state_dir` so they survive restarts.
+    pub persist_usage: bool,
+
+    /// Reserved for encrypting stored outputs and artifacts at rest. Not yet
+    /// supported: setting it is an error, rather than storing plaintext the
+    /// operator asked to have encrypted.
+    pub encryption_key: Option<String>,
+
+    /// Size (bytes of serialized JSON) above which `all_messages` is written to
+    /// `state_dir` and returned as a resource link instead of inline.
+    pub inline_messages_max_bytes: usize,
+
+    /// Size cap (bytes) of the `patch` returned for runs that changed files.
+    /// `0` disables the field.
+    pub patch_max_bytes: usize,
+
+    /// Formatter commands by file extension (e.g. `rs = ["rustfmt"]`), run
+    /// with the file's path appended on files changed by runs with
+    /// `post_format`.
+    pub formatters: BTreeMap<String, Vec<String>>,
+
+    /// Project memory notes file, relative to each workspace root and
+    /// without `..` components.
+    pub memory_file: PathBuf,
+
+    /// Size cap (bytes) of the project memory injected into prompts; older
+    /// notes beyond it are left out. `0` disables injection.
+    pub memory_max_bytes: usize,
+
+    /// Instructions appended to every prompt, e.g. to always end with a
+    /// summary of the changes so `agent_messages` is never empty. Runs can
+    /// opt out with `skip_postamble`.
+    pub prompt_postamble: Option<String>,
+
+    /// Size cap (bytes) of each image attached with `image`.
+    pub max_image_bytes: u64,
+
+    /// Size cap (bytes) of each file uploaded with `codex_upload_context`.
+    pub max_upload_bytes: u64,
+
+    /// Most bytes of a file `codex_workspace_read` returns per call.
+    pub workspace_read_max_bytes: usize,
+
+    /// File in each workspace root listing paths, in `.gitignore` syntax,
+    /// that never go into prompts or results (see `exclusions`).
+    pub ignore_file: PathBuf,
+
+    /// Patterns excluded in every workspace, in `.gitignore` syntax, on top
+    /// of those in its `ignore_file`, e.g. `[".env", "secrets/"]`.
+    pub excluded_paths: Vec<String>,
+
+    /// Root under which each run gets a directory with its raw transcript,
+    /// stderr log, resulting diff, and `run.json` summary. Disabled when unset.
+    pub artifacts_dir: Option<PathBuf>,
+
+    /// Delete stored transcripts and agent messages and run artifact
+    /// directories once they are this many days old. Unset or 0 keeps them.
+    pub retention_days: Option<u64>,
+
+    /// Named workspaces clients can pass as `cd` instead of a host path,
+    /// e.g. `backend = "/srv/repos/backend"`.
+    pub workspaces: BTreeMap<String, PathBuf>,
+
+    /// Only allow `cd` values that are workspace names or paths inside a
+    /// configured workspace.
+    pub restrict_to_workspaces: bool,
+
+    /// Allow clients to have a missing `cd` created with `create_cd_if_missing`.
+    pub allow_create_cd: bool,
+
+    /// Git remote that branches are pushed to for `pull_request`.
+    pub git_remote: String,
+
+    /// Where `pull_request` opens pull/merge requests.
+    pub vcs_provider: VcsProviderKind,
+
+    /// Token for opening pull/merge requests. GitHub and GitLab fall back to
+    /// their CLI's own authentication when unset; Bitbucket requires it.
+    /// May be a `keychain:` or `file:` reference instead of the token itself.
+    #[serde(alias = "github_token")]
+    pub vcs_token: Option<String>,
+
+    /// Directories that `base_instructions_file` must reside in.
+    /// Leave empty to reject the parameter entirely.
+    pub instructions_dirs: Vec<PathBuf>,
+
+    /// Directories under which clients may request extra `writable_roots`
+    /// for the `workspace-write` sandbox. Leave empty to reject the parameter.
+    pub allowed_writable_roots: Vec<PathBuf>,
+
+    /// Directories under which clients may point `stream_to`. Leave empty to
+    /// reject the parameter.
+    pub allowed_stream_dirs: Vec<PathBuf>,
+
+    /// Spawn codex with a scrubbed environment: only a built-in allowlist of
+    /// variables plus `child_env_allowlist`, and `PATH` set to `child_path`.
+    pub scrub_child_env: bool,
+
+    /// Further variables passed to codex when `scrub_child_env` is set; a
+    /// trailing `*` matches any suffix, e.g. `"NPM_CONFIG_*"`.
+    pub child_env_allowlist: Vec<String>,
+
+    /// `PATH` of codex when `scrub_child_env` is set, after the directory of
+    /// the codex binary. Defaults to `/usr/local/bin:/usr/bin:/bin`.
+    pub child_path: Option<String>,
+
+    /// Wrap codex in an OS-level sandbox (bubblewrap or `sandbox-exec`)
+    /// confining its writes to what the run's sandbox policy allows.
+    pub os_sandbox: OsSandbox,
+
+    /// Unix user (name or UID) to run codex as when the server runs as root.
+    pub run_as_user: Option<String>,
+
+    /// Group (name or GID) to run codex as; defaults to the primary group of
+    /// `run_as_user`.
+    pub run_as_group: Option<String>,
+
+    /// Network access for `workspace-write` runs when the client omits `allow_network`.
+    pub network_access_default: bool,
+
+    /// Ceiling for `allow_network`: when `false`, requested network access is
+    /// downgraded to off.
+    pub network_access_permitted: bool,
+
+    /// Let codex ask for approval (`on-request`) and forward each request to
+    /// the MCP client as an elicitation, instead of stopping the run with
+    /// `approval_required`.
+    pub approval_bridge: bool,
+
+    /// How long the client has to answer an approval request before it is
+    /// denied.
+    pub approval_timeout_secs: u64,
+
+    /// Default of `oss`: run on a local model server instead of the cloud,
+    /// e.g. on air-gapped hosts.
+    pub oss_default: bool,
+
+    /// `host:port` of the local model server (Ollama by default) that must
+    /// accept connections before an `oss` run starts.
+    pub oss_server: String,
+
+    /// Server policy for `skip_git_repo_check`.
+    pub git_repo_check: GitRepoCheckPolicy,
+
+    /// `skip_git_repo_check` value used when the client omits it under `client_choice`.
+    pub skip_git_repo_check_default: bool,
+
+    /// How a write-enabled run is handled while another one is active in the
+    /// same workspace: `off`, `fail` (with `workspace_busy`), or `queue`.
+    pub workspace_lock: WorkspaceLockMode,
+
+    /// Let clients of multi-client transports (the Unix socket) see and resume
+    /// each other's sessions. When `false`, each client (identified by its OS
+    /// user) only sees the sessions and resources it created.
+    pub shared_sessions: bool,
+
+    /// User IDs allowed to connect over the Unix socket transport, checked
+    /// against the kernel-reported peer credentials. Empty allows any user
+    /// that the socket file permissions let through.
+    pub allowed_client_uids: Vec<u32>,
+
+    /// TOML file of declarative policy rules checked before every run.
+    pub policy_file: Option<PathBuf>,
+
+    /// Refuse write-enabled runs when the workspace's filesystem has less
+    /// free space than this, in MiB; 0 disables the check.
+    pub min_free_disk_mb: u64,
+
+    /// Refuse write-enabled runs in workspaces with unmerged git paths.
+    pub check_git_conflicts: bool,
+
+    /// Tools to expose, e.g. `["codex"]` for a minimal deployment. Empty
+    /// exposes every tool not listed in `disabled_tools`.
+    pub enabled_tools: Vec<String>,
+
+    /// Tools to hide from clients.
+    pub disabled_tools: Vec<String>,
+
+    /// Prepended to every tool name offered to clients, e.g. `acme_` for
+    /// `acme_codex`, so several instances of the server can be registered
+    /// with one client. `enabled_tools` and `disabled_tools` take the
+    /// unprefixed names.
+    pub tool_name_prefix: Option<String>,
+
+    /// Resume background runs interrupted by a server restart in their codex
+    /// session, instead of marking them failed with `run_interrupted`.
+    pub resume_interrupted_runs: bool,
+
+    /// Resume a run once in its codex session when codex dies mid-turn
+    /// (exiting unsuccessfully before the turn completed), before reporting
+    /// the failure.
+    pub resume_crashed_runs: bool,
+
+    /// What to do at startup with codex processes a previous instance left
+    /// running (tracked in `state_dir/pids/`): `kill` or `adopt`.
+    pub orphaned_processes: OrphanPolicy,
+
+    /// Run a trivial read-only `codex exec` at startup and refuse to serve if
+    /// it fails, like `--self-test`.
+    pub self_test: bool,
+
+    /// Models `model = "auto"` chooses between, in addition to every model
+    /// already run in the workspace.
+    pub auto_models: Vec<String>,
+
+    /// Prices in USD per million tokens by model, used to compute `cost_usd`
+    /// and enforce budgets. `default` applies to models without an entry.
+    pub model_prices: BTreeMap<String, ModelPrice>,
+
+    /// Total cost in USD that runs may spend per local day.
+    pub daily_budget_usd: Option<f64>,
+
+    /// Longest a run waits for a provider rate limit to pass before starting;
+    /// longer holds fail with `rate_limited` instead.
+    pub rate_limit_max_defer_secs: u64,
+
+    /// Log filter (e.g. `debug` or `codex_mcp=debug,warn`) used when
+    /// `RUST_LOG` is unset. Defaults to `info`.
+    pub log_level: Option<String>,
+
+    /// Format of the server's stderr logs: `full` (default), `pretty`,
+    /// `compact`, or `json`.
+    pub log_format: LogFormat,
+
+    /// Language of error messages: `en` or `zh`. When unset, follows the
+    /// run's `language`, then the locale environment (`LANG` and friends).
+    pub locale: Option<Locale>,
+
+    /// Regexes classifying codex error messages as `benign` (ignored),
+    /// `warning` (reported without failing the run), or `fatal` (failing it
+    /// at once), checked before the built-in ones.
+    pub noise_filters: Vec<NoiseFilter>,
+
+    /// Output policies applied, in order, to every run result before it is
+    /// returned: `redact`, `truncate`, `markdown`, or `schema`.
+    pub post_processors: Vec<PostProcessorConfig>,
+
+    /// How often to check the config file for changes and reload it, in
+    /// seconds; 0 reloads only on SIGHUP.
+    pub config_watch_interval_secs: u64,
+
+    /// Prompts run automatically on a recurring schedule.
+    pub schedules: Vec<ScheduleConfig>,
+}
+
+/// A codex run executed on a recurring schedule.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Unique name, recorded on the sessions it starts as the tag `schedule:<name>`.
+    pub name: String,
+
+    /// Five-field cron expression (minute hour day-of-month month
+    /// day-of-week) in the server's local time, e.g. `0 3 * * *`.
+    pub cron: String,
+
+    /// URL the result of each run is POSTed to as JSON. May be a `keychain:`
+    /// or `file:` reference, for URLs that embed a token.
+    pub webhook: Option<String>,
+
+    /// Parameters of the run, as accepted by the `codex` tool.
+    pub run: CodexParams,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            codex_path: None,
+            state_dir: None,
+            state_backend: StateBackend::Files,
+            redis_url: None,
+            redis_key_prefix: "codex-mcp:".to_string(),
+            persist_usage: false,
+            encryption_key: None,
+            inline_messages_max_bytes: 1024 * 1024,
+            patch_max_bytes: 64 * 1024,
+            formatters: BTreeMap::new(),
+            memory_file: PathBuf::from(".codex-mcp/memory.md"),
+            memory_max_bytes: 16 * 1024,
+            prompt_postamble: None,
+            max_image_bytes: 20 * 1024 * 1024,
+            max_upload_bytes: 50 * 1024 * 1024,
+            workspace_read_max_bytes: 256 * 1024,
+            ignore_file: PathBuf::from(".codexignore"),
+            excluded_paths: Vec::new(),
+            artifacts_dir: None,
+            retention_days: None,
+            workspaces: BTreeMap::new(),
+            restrict_to_workspaces: false,
+            allow_create_cd: false,
+            git_remote: "origin".to_string(),
+            vcs_provider: VcsProviderKind::default(),
+            vcs_token: None,
+            instructions_dirs: Vec::new(),
+            allowed_writable_roots: Vec::new(),
+            allowed_stream_dirs: Vec::new(),
+            scrub_child_env: false,
+            child_env_allowlist: Vec::new(),
+            child_path: None,
+            os_sandbox: OsSandbox::default(),
+            run_as_user: None,
+            run_as_group: None,
+            network_access_default: false,
+            network_access_permitted: true,
+            approval_bridge: false,
+            approval_timeout_secs: 60,
+            oss_default: false,
+            oss_server: "localhost:11434".to_string(),
+            git_repo_check: GitRepoCheckPolicy::ClientChoice,
+            skip_git_repo_check_default: true,
+            workspace_lock: WorkspaceLockMode::Off,
+            shared_sessions: false,
+            allowed_client_uids: Vec::new(),
+            policy_file: None,
+            min_free_disk_mb: 0,
+            check_git_conflicts: false,
+            enabled_tools: Vec::new(),
+            disabled_tools: Vec::new(),
+            tool_name_prefix: None,
+            resume_interrupted_runs: false,
+            resume_crashed_runs: false,
+            orphaned_processes: OrphanPolicy::default(),
+            self_test: false,
+            auto_models: Vec::new(),
+            model_prices: BTreeMap::new(),
+            daily_budget_usd: None,
+            rate_limit_max_defer_secs: 300,
+            log_level: None,
+            log_format: LogFormat::default(),
+            locale: None,
+            noise_filters: Vec::new(),
+            post_processors: Vec::new(),
+            config_watch_interval_secs: 2,
+            schedules: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load the configuration from the file named by `CODEX_MCP_CONFIG`,
+    /// falling back to defaults when the variable is unset.
+    pub fn load() -> Result<Self> {
+        match Self::path() {
+            Some(path) => Self::load_file(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The config file named by `CODEX_MCP_CONFIG`, if any.
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os(CONFIG_ENV).map(PathBuf::from)
+    }
+
+    /// Load the configuration from `path` with its secrets resolved, as at
+    /// startup and on every reload.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let mut config = Self::from_file(path)?;
+        config.resolve_secrets()?;
+        memory::check_file(&config.memory_file)?;
+        if config.encryption_key.is_some() {
+            bail!(
+                "`encryption_key` is not supported yet: encryption at rest waits for a vetted AES-GCM implementation"
+            );
+        }
+        Ok(config)
+    }
+
+    /// Keep the settings of `current` that are only read at startup, since
+    /// changing them takes a restart. Returns the names of those that differ.
+    pub fn keep_restart_only(&mut self, current: &Self) -> Vec<&'static str> {
+        let mut kept = Vec::new();
+        macro_rules! keep {
+            ($($field:ident),*) => {$(
+                if format!("{:?}", self.$field) != format!("{:?}", current.$field) {
+                    kept.push(stringify!($field));
+                    self.$field.clone_from(&current.$field);
+                }
+            )*};
+        }
+        keep!(
+            state_dir, state_backend, redis_url, redis_key_prefix, persist_usage,
+            enabled_tools, disabled_tools, tool_name_prefix, schedules, log_format
+        );
+        kept
+    }
+
+    /// Price of `model` (codex's default model when `None`), if configured.
+    pub fn model_price(&self, model: Option<&str>) -> Option<ModelPrice> {
+        model
+            .and_then(|m| self.model_prices.get(m))
+            .or_else(|| self.model_prices.get(cost::DEFAULT_PRICE_KEY))
+            .copied()
+    }
+
+    /// Replace credential references with the secrets they name, registering
+    /// every credential for redaction.
+    fn resolve_secrets(&mut self) -> Result<()> {
+        if let Some(ref token) = self.vcs_token {
+            self.vcs_token = Some(secrets::resolve(token).context("failed to resolve `vcs_token`")?);
+        }
+        if let Some(ref url) = self.redis_url {
+            self.redis_url = Some(secrets::resolve(url).context("failed to resolve `redis_url`")?);
+        }
+        for schedule in &mut self.schedules {
+            if let Some(ref webhook) = schedule.webhook {
+                let resolved = secrets::resolve(webhook)
+                    .with_context(|| format!("failed to resolve the webhook of schedule {:?}", schedule.name))?;
+                schedule.webhook = Some(resolved);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the configuration from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config: ServerConfig = toml::from_str("").unwrap();
+        assert!(config.instructions_dirs.is_empty());
+        assert!(config.allowed_writable_roots.is_empty());
+        assert!(!config.network_access_default);
+        assert!(config.network_access_permitted);
+    }
+
+    #[test]
+    fn test_config_parses_workspaces_table() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            [workspaces]
+            backend = "/srv/repos/backend"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.workspaces["backend"], PathBuf::from("/srv/repos/backend"));
+    }
+
+    #[test]
+    fn test_config_parses_schedules() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            [[schedules]]
+            name = "nightly-deps"
+            cron = "0 3 * * *"
+            run = { PROMPT = "Update dependencies", cd = "backend", sandbox = "workspace-write" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.schedules[0].name, "nightly-deps");
+        assert_eq!(config.schedules[0].run.cd, PathBuf::from("backend"));
+        assert!(config.schedules[0].webhook.is_none());
+    }
+
+    #[test]
+    fn test_config_parses_workspace_lock_mode() {
+        let config: ServerConfig = toml::from_str(r#"workspace_lock = "queue""#).unwrap();
+        assert_eq!(config.workspace_lock, WorkspaceLockMode::Queue);
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_keys() {
+        assert!(toml::from_str::<ServerConfig>("no_such_key = 1").is_err());
+    }
+
+    #[test]
+    fn test_load_file_rejects_memory_file_outside_workspace() {
+        let path = std::env::temp_dir().join(format!("codex-mcp-config-memory-{}.toml", std::process::id()));
+        std::fs::write(&path, "memory_file = \"../../.bashrc\"").unwrap();
+        let error = ServerConfig::load_file(&path).unwrap_err();
+        assert!(error.to_string().contains("memory_file"), "{error:#}");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_file_rejects_encryption_key() {
+        let path = std::env::temp_dir().join(format!("codex-mcp-config-encryption-{}.toml", std::process::id()));
+        std::fs::write(&path, "encryption_key = \"env:CODEX_MCP_ENCRYPTION_KEY\"").unwrap();
+        let error = ServerConfig::load_file(&path).unwrap_err();
+        assert!(error.to_string().contains("encryption_key"), "{error:#}");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+//! Correlation IDs: one per run, attached to its log lines, background-run
+//! reports, and result, so operators of shared deployments can find the
+//! server logs of the run behind a user report.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::state::unix_now_millis;
+
+/// Distinguishes IDs generated within the same millisecond.
+static ID_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A new random (version 4) UUID, e.g. `0f8e9a6c-3b1d-4c2e-9a7f-5d6b8c9e0a1b`.
+///
+/// The randomness comes from the standard library's per-process hasher keys,
+/// which is plenty to keep IDs unique but not meant to be unguessable.
+pub fn new_id() -> String {
+    let seq = ID_SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut bytes = [0u8; 16];
+    for half in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(seq);
+        hasher.write_u128(unix_now_millis());
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_distinct_v4_uuids() {
+        let ids: std::collections::HashSet<String> = (0..1000).map(|_| new_id()).collect();
+        assert_eq!(ids.len(), 1000);
+        for id in &ids {
+            let groups: Vec<&str> = id.split('-').collect();
+            assert_eq!(groups.iter().map(|g| g.len()).collect::<Vec<_>>(), [8, 4, 4, 4, 12], "{id}");
+            assert!(groups[2].starts_with('4'), "{id}");
+            assert!(matches!(&groups[3][..1], "8" | "9" | "a" | "b"), "{id}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Run cost in USD, computed from token usage and the server's `model_prices`.
+
+use serde::Deserialize;
+
+use crate::usage::TokenUsage;
+
+/// Key of `model_prices` used for models without their own entry, including
+/// runs on codex's default model.
+pub const DEFAULT_PRICE_KEY: &str = "default";
+
+/// Prices of a model in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub input: f64,
+    /// Price of prompt tokens served from the provider's cache; defaults to `input`.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// Cost of `usage` in USD. Cached tokens are a subset of the input tokens.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Cap on a run's cost, enforced while reading its event stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostLimit {
+    pub price: ModelPrice,
+    pub max_usd: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_prices_cached_tokens_separately() {
+        let price = ModelPrice {
+            input: 2.0,
+            cached_input: Some(0.5),
+            output: 10.0,
+        };
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            cached_input_tokens: 400_000,
+            output_tokens: 100_000,
+        };
+        assert!((price.cost(&usage) - (1.2 + 0.2 + 1.0)).abs() < 1e-9);
+    }
+}
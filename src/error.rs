@@ -3,17 +3,251 @@
 use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::rules::PolicyViolation;
+
 /// Errors that can occur during Codex execution.
 #[derive(Debug, Error)]
 pub enum CodexError {
     /// Failed to find the codex executable.
-    #[error("Codex executable not found. Please ensure 'codex' is installed and in PATH.")]
+    #[error("Codex executable not found. Please ensure 'codex' is installed and in PATH (or set `codex_path`).")]
     ExecutableNotFound,
 
+    /// The request uses a parameter the installed codex CLI does not support.
+    #[error("`{0}` is not supported by the installed codex CLI; upgrade codex to use it.")]
+    UnsupportedByCodex(&'static str),
+
+    /// An `oss` run was requested but the local model server does not accept connections.
+    #[error("Local model server at {addr} is not reachable: {reason}. Start it (e.g. `ollama serve`) or set `oss_server`.")]
+    OssServerUnreachable { addr: String, reason: String },
+
+    /// `os_sandbox` names a wrapper that is not installed on this host.
+    #[error("`os_sandbox` is set to `{0}`, but it is not installed on this host.")]
+    OsSandboxUnavailable(&'static str),
+
+    /// codex cannot be run as the configured `run_as_user`.
+    #[error("Cannot run codex as `run_as_user`: {0}")]
+    RunAsUnavailable(String),
+
     /// Working directory does not exist or is not a directory.
     #[error("Working directory does not exist or is not a directory: {0:?}")]
     InvalidWorkingDirectory(PathBuf),
 
+    /// `cd` looks like a workspace name but none is configured under it.
+    #[error("Unknown workspace {name:?}.{}", format_known_workspaces(.known))]
+    UnknownWorkspace { name: String, known: Vec<String> },
+
+    /// `cd` is outside every configured workspace while `restrict_to_workspaces` is set.
+    #[error("Working directory is not inside a configured workspace: {0:?}")]
+    WorkspaceNotAllowed(PathBuf),
+
+    /// `create_cd_if_missing` was requested but the server does not allow it.
+    #[error("`create_cd_if_missing` is disabled on this server (see `allow_create_cd`).")]
+    CreateCdNotAllowed,
+
+    /// `commit` was requested for a run that cannot modify the workspace.
+    #[error("`commit` requires a write-enabled run (`workspace-write` or `danger-full-access` sandbox).")]
+    CommitRequiresWriteAccess,
+
+    /// `post_format` was requested but the server has no formatters.
+    #[error("`post_format` requires formatters configured on the server (see `formatters`).")]
+    PostFormatUnavailable,
+
+    /// `commit.pull_request` was requested from a server built without VCS support.
+    #[error("`commit.pull_request` requires a server built with the `vcs` feature.")]
+    PullRequestsUnsupported,
+
+    /// Base instructions file in an allowlisted directory does not exist.
+    #[error("Base instructions file does not exist: {0:?}")]
+    InstructionsFileNotFound(PathBuf),
+
+    /// Base instructions file is outside the server's allowlisted directories.
+    #[error("Base instructions file is not inside an allowed directory: {0:?}")]
+    InstructionsFileNotAllowed(PathBuf),
+
+    /// Extra writable roots were requested without the `workspace-write` sandbox.
+    #[error("`writable_roots` requires the `workspace-write` sandbox.")]
+    WritableRootsRequireWorkspaceWrite,
+
+    /// Writable root is missing or outside the server's allowlisted directories.
+    #[error("Writable root does not exist or is not inside an allowed directory: {0:?}")]
+    WritableRootNotAllowed(PathBuf),
+
+    /// `stream_to` is outside the server's `allowed_stream_dirs`.
+    #[error("`stream_to` path is not within the server's allowed_stream_dirs: {0:?}")]
+    StreamToNotAllowed(PathBuf),
+
+    /// The `stream_to` target could not be opened.
+    #[error("Failed to open `stream_to` path {path:?}: {reason}")]
+    StreamToFailed { path: PathBuf, reason: String },
+
+    /// An attached image does not exist or is not a file.
+    #[error("Image file does not exist: {0:?}")]
+    ImageNotFound(PathBuf),
+
+    /// An attached image is not PNG, JPEG, GIF, or WebP.
+    #[error("Unsupported image format (expected PNG, JPEG, GIF, or WebP): {0:?}")]
+    UnsupportedImageFormat(PathBuf),
+
+    /// An attached image exceeds the server's `max_image_bytes`.
+    #[error("Image {path:?} is {size} bytes, over the limit of {max} bytes")]
+    ImageTooLarge { path: PathBuf, size: u64, max: u64 },
+
+    /// A `context_files` entry does not exist or is outside the workspace and uploads.
+    #[error("Context file does not exist or is not inside the working directory or uploads: {0:?}")]
+    ContextFileNotAllowed(PathBuf),
+
+    /// A `codex_workspace_list`/`codex_workspace_read` path does not exist or is outside the workspace.
+    #[error("Path does not exist or is not inside the working directory: {0:?}")]
+    WorkspacePathNotAllowed(PathBuf),
+
+    /// A path matches the workspace's `ignore_file` or the server's `excluded_paths`.
+    #[error("Path is excluded by the workspace's ignore file or the server's `excluded_paths`: {0:?}")]
+    PathExcluded(PathBuf),
+
+    /// A `codex_upload_context` chunk names an upload that is not in progress.
+    #[error("Upload not found or already complete: {0}")]
+    UploadNotFound(String),
+
+    /// A `codex_upload_context` chunk cannot be added to its upload.
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+
+    /// Capturing a screenshot for `codex_with_screenshot` failed.
+    #[cfg(feature = "screenshot")]
+    #[error("Failed to capture screenshot: {0}")]
+    ScreenshotFailed(String),
+
+    /// A `resource:` prompt refers to nothing the run can read.
+    #[error("Cannot read the prompt from {reference:?}: {reason}")]
+    PromptResourceUnavailable { reference: String, reason: String },
+
+    /// `expected_output_schema` is not a JSON Schema.
+    #[error("Invalid `expected_output_schema`: {0}")]
+    InvalidOutputSchema(String),
+
+    /// The final agent message did not match `expected_output_schema`, even
+    /// after codex was asked to correct it.
+    #[error("The final message does not match `expected_output_schema`: {0}")]
+    OutputSchemaMismatch(String),
+
+    /// A focus path does not exist.
+    #[error("Focus path does not exist: {0:?}")]
+    FocusPathNotFound(PathBuf),
+
+    /// A focus path resolves outside the working directory.
+    #[error("Focus path is outside the working directory: {0:?}")]
+    FocusPathOutsideWorkspace(PathBuf),
+
+    /// `language` is not a short single-line language name or tag.
+    #[error("Invalid `language` {0:?}: expected a language name or tag such as `Japanese` or `ja`.")]
+    InvalidLanguage(String),
+
+    /// Both `SESSION_ID` and `resume_last` were supplied.
+    #[error("`SESSION_ID` and `resume_last` cannot be used together.")]
+    ConflictingResumeOptions,
+
+    /// `summarize_session` was requested without a session to summarize.
+    #[error("`summarize_session` requires `SESSION_ID`.")]
+    SummarizeRequiresSession,
+
+    /// The read-only run summarizing the resumed session failed.
+    #[error("Failed to summarize session {session_id}: {reason}")]
+    SessionSummaryFailed { session_id: String, reason: String },
+
+    /// `resume_last` would pick up whichever session ran last, possibly another client's.
+    #[error("`resume_last` is unavailable while sessions are isolated per client; pass `SESSION_ID` instead.")]
+    ResumeLastUnavailable,
+
+    /// `SESSION_ID` is not a codex session ID.
+    #[error("Invalid `SESSION_ID` {0:?}: expected a UUID such as 019bc4ce-610d-7f50-bd2a-fb5b8ac83b61.")]
+    InvalidSessionId(String),
+
+    /// `SESSION_ID` does not refer to a known codex session.
+    #[error("Session not found: {session_id}.{}", format_alternatives(.alternatives))]
+    SessionNotFound {
+        session_id: String,
+        alternatives: Vec<String>,
+    },
+
+    /// The workspace's filesystem has less free space than `min_free_disk_mb`.
+    #[error(
+        "Only {free_mb} MiB free on the filesystem of {path:?}, below `min_free_disk_mb` ({required_mb} MiB). \
+         Free up space (e.g. delete build outputs or old run artifacts) before starting a write-enabled run."
+    )]
+    InsufficientDiskSpace { path: PathBuf, free_mb: u64, required_mb: u64 },
+
+    /// The workspace has unresolved merge conflicts, with `check_git_conflicts`.
+    #[error(
+        "Workspace has unmerged git conflicts in: {}. Resolve them (or abort the merge or rebase) \
+         before starting a write-enabled run.",
+        .0.join(", ")
+    )]
+    UnmergedConflicts(Vec<String>),
+
+    /// Another write-enabled run is already active in the same workspace.
+    #[error("Workspace is busy: another write-enabled codex run is active in {0:?}")]
+    WorkspaceBusy(PathBuf),
+
+    /// The request violates rules of the server's policy file.
+    #[error("Denied by policy: {}", format_violations(.0))]
+    PolicyDenied(Vec<PolicyViolation>),
+
+    /// `run_id` does not refer to a background run of this server.
+    #[error("Run not found: {0}")]
+    RunNotFound(String),
+
+    /// The server stopped while the background run was in progress.
+    #[error("The server stopped while this run was in progress.")]
+    RunInterrupted,
+
+    /// `codex_result` was called before the background run finished.
+    #[error("Run {0} is still in progress; poll it with `codex_poll`.")]
+    RunInProgress(String),
+
+    /// `codex_compare` was given no models, too many, or duplicates.
+    #[error("Invalid models to compare: {0}")]
+    InvalidCompareModels(String),
+
+    /// `codex_compare` runs always start fresh sessions.
+    #[error("`codex_compare` cannot resume a session; omit `SESSION_ID` and `resume_last`.")]
+    CompareSessionUnsupported,
+
+    /// `codex_self_review` found no changes of the session to review.
+    #[error("Session {0} has no uncommitted changes in its workspace to review.")]
+    NothingToReview(String),
+
+    /// `codex_memory_append` was called with a blank note.
+    #[error("The note to record in project memory is empty.")]
+    EmptyMemoryNote,
+
+    /// `codex_search_sessions` was called with a blank query.
+    #[error("The search query is empty.")]
+    EmptySearchQuery,
+
+    /// Deleting a session's data in `codex_purge_session` failed part way.
+    #[error("Failed to purge session {session_id}: {reason}. Data already deleted stays deleted; retry to finish.")]
+    PurgeFailed { session_id: String, reason: String },
+
+    /// The run would exceed `max_cost_usd` or the server's daily budget.
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    /// A budget applies to the run but its model has no configured price.
+    #[error("No price is configured for model {0:?} in `model_prices`, so its budget cannot be enforced.")]
+    ModelPriceUnknown(String),
+
+    /// The provider is rate limiting and the hold outlasts `rate_limit_max_defer_secs`.
+    #[error("The model provider is rate limiting requests; retry in {0} seconds.")]
+    RateLimited(u64),
+
+    /// A run with the same `idempotency_key` has not finished yet.
+    #[error("A run with idempotency key {0:?} is still in progress.")]
+    IdempotencyKeyInProgress(String),
+
+    /// `idempotency_key` was already used for a different request.
+    #[error("Idempotency key {0:?} was already used for a different request.")]
+    IdempotencyKeyReused(String),
+
     /// Failed to capture stdout from the codex process.
     #[error("Failed to capture codex stdout (pipe not available).")]
     StdoutCaptureFailed,
@@ -26,3 +260,96 @@ pub enum CodexError {
     #[error("Failed to parse JSON: {0}")]
     JsonParseError(#[from] serde_json::Error),
 }
+
+impl CodexError {
+    /// Stable machine-readable code for this error, returned as `error_code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodexError::ExecutableNotFound => "executable_not_found",
+            CodexError::UnsupportedByCodex(_) => "unsupported_by_codex",
+            CodexError::OssServerUnreachable { .. } => "oss_server_unreachable",
+            CodexError::OsSandboxUnavailable(_) => "os_sandbox_unavailable",
+            CodexError::RunAsUnavailable(_) => "run_as_unavailable",
+            CodexError::InvalidWorkingDirectory(_) => "invalid_working_directory",
+            CodexError::UnknownWorkspace { .. } => "unknown_workspace",
+            CodexError::WorkspaceNotAllowed(_) => "workspace_not_allowed",
+            CodexError::CreateCdNotAllowed => "create_cd_not_allowed",
+            CodexError::CommitRequiresWriteAccess => "commit_requires_write_access",
+            CodexError::PostFormatUnavailable => "post_format_unavailable",
+            CodexError::PullRequestsUnsupported => "pull_requests_unsupported",
+            CodexError::InstructionsFileNotFound(_) => "instructions_file_not_found",
+            CodexError::InstructionsFileNotAllowed(_) => "instructions_file_not_allowed",
+            CodexError::WritableRootsRequireWorkspaceWrite => "writable_roots_require_workspace_write",
+            CodexError::WritableRootNotAllowed(_) => "writable_root_not_allowed",
+            CodexError::StreamToNotAllowed(_) => "stream_to_not_allowed",
+            CodexError::StreamToFailed { .. } => "stream_to_failed",
+            CodexError::ImageNotFound(_) => "image_not_found",
+            CodexError::UnsupportedImageFormat(_) => "unsupported_image_format",
+            CodexError::ImageTooLarge { .. } => "image_too_large",
+            CodexError::ContextFileNotAllowed(_) => "context_file_not_allowed",
+            CodexError::WorkspacePathNotAllowed(_) => "workspace_path_not_allowed",
+            CodexError::PathExcluded(_) => "path_excluded",
+            CodexError::UploadNotFound(_) => "upload_not_found",
+            CodexError::InvalidUpload(_) => "invalid_upload",
+            #[cfg(feature = "screenshot")]
+            CodexError::ScreenshotFailed(_) => "screenshot_failed",
+            CodexError::PromptResourceUnavailable { .. } => "prompt_resource_unavailable",
+            CodexError::InvalidOutputSchema(_) => "invalid_output_schema",
+            CodexError::OutputSchemaMismatch(_) => "output_schema_mismatch",
+            CodexError::FocusPathNotFound(_) => "focus_path_not_found",
+            CodexError::FocusPathOutsideWorkspace(_) => "focus_path_outside_workspace",
+            CodexError::InvalidLanguage(_) => "invalid_language",
+            CodexError::ConflictingResumeOptions => "conflicting_resume_options",
+            CodexError::ResumeLastUnavailable => "resume_last_unavailable",
+            CodexError::SummarizeRequiresSession => "summarize_requires_session",
+            CodexError::SessionSummaryFailed { .. } => "session_summary_failed",
+            CodexError::InvalidSessionId(_) => "invalid_session_id",
+            CodexError::SessionNotFound { .. } => "session_not_found",
+            CodexError::InsufficientDiskSpace { .. } => "insufficient_disk_space",
+            CodexError::UnmergedConflicts(_) => "unmerged_conflicts",
+            CodexError::WorkspaceBusy(_) => "workspace_busy",
+            CodexError::PolicyDenied(_) => "policy_denied",
+            CodexError::RunNotFound(_) => "run_not_found",
+            CodexError::RunInProgress(_) => "run_in_progress",
+            CodexError::EmptyMemoryNote => "empty_memory_note",
+            CodexError::EmptySearchQuery => "empty_search_query",
+            CodexError::PurgeFailed { .. } => "purge_failed",
+            CodexError::InvalidCompareModels(_) => "invalid_compare_models",
+            CodexError::CompareSessionUnsupported => "compare_session_unsupported",
+            CodexError::NothingToReview(_) => "nothing_to_review",
+            CodexError::RunInterrupted => "run_interrupted",
+            CodexError::BudgetExceeded(_) => "budget_exceeded",
+            CodexError::ModelPriceUnknown(_) => "model_price_unknown",
+            CodexError::RateLimited(_) => "rate_limited",
+            CodexError::IdempotencyKeyInProgress(_) => "idempotency_key_in_progress",
+            CodexError::IdempotencyKeyReused(_) => "idempotency_key_reused",
+            CodexError::StdoutCaptureFailed => "stdout_capture_failed",
+            CodexError::Io(_) => "io_error",
+            CodexError::JsonParseError(_) => "json_parse_error",
+        }
+    }
+}
+
+fn format_violations(violations: &[PolicyViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| format!("{} (rule {:?})", v.reason, v.rule))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn format_known_workspaces(known: &[String]) -> String {
+    if known.is_empty() {
+        String::new()
+    } else {
+        format!(" Configured workspaces: {}", known.join(", "))
+    }
+}
+
+fn format_alternatives(alternatives: &[String]) -> String {
+    if alternatives.is_empty() {
+        String::new()
+    } else {
+        format!(" Recent sessions in this workspace: {}", alternatives.join(", "))
+    }
+}
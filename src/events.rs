@@ -0,0 +1,870 @@
+//! Parsing of the `codex exec --json` event stream.
+
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::approval::{ApprovalDecision, ApprovalRequest};
+use crate::cost::CostLimit;
+use crate::file_changes::FileChange;
+use crate::noise::{self, NoiseClass, NoiseFilter};
+use crate::state::unix_now_millis;
+use crate::throttle;
+use crate::usage::TokenUsage;
+
+/// Whether to keep reading the event stream after a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// Non-JSON stdout lines kept in `unparsed_lines`; later ones are dropped.
+const MAX_UNPARSED_LINES: usize = 50;
+
+/// When to stop reading a run's event stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StopOn {
+    /// At the first `turn.completed` event (default).
+    #[default]
+    FirstTurn,
+    /// When codex exits, collecting the messages of every turn it runs.
+    ProcessExit,
+}
+
+/// Per-run safety limits enforced while reading the event stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// Maximum number of `turn.started` events.
+    pub max_turns: Option<u32>,
+    /// Maximum number of shell commands codex may start.
+    pub max_commands: Option<u32>,
+    /// Maximum cost of the run, checked whenever codex reports token usage.
+    pub cost: Option<CostLimit>,
+}
+
+/// Output of a failed command that indicates the sandbox blocked it, matched
+/// case-insensitively.
+const DENIAL_MARKERS: &[&str] = &[
+    "operation not permitted",
+    "permission denied",
+    "read-only file system",
+    "denied by sandbox",
+    "sandbox denied",
+];
+
+/// Longest `reason` kept for a sandbox denial.
+const MAX_DENIAL_REASON_CHARS: usize = 200;
+
+/// A command the sandbox blocked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SandboxDenial {
+    pub command: String,
+    /// The line of the command's output reporting the denial, or why codex
+    /// declined to run it.
+    pub reason: String,
+}
+
+/// Progress of a step of the model's plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+/// A step of the plan (todo list) codex keeps during agentic runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanStep {
+    pub step: String,
+    pub status: PlanStatus,
+}
+
+/// The plan carried by `event`, if it is a plan update: a `todo_list` item
+/// (`items` of `text` and `completed`), or an event with a `plan` of `step`
+/// and `status` entries. Each update carries the whole plan.
+pub fn plan(event: &serde_json::Value) -> Option<Vec<PlanStep>> {
+    if let Some(item) = event.get("item")
+        && item.get("type").and_then(|t| t.as_str()) == Some("todo_list")
+    {
+        let items = item.get("items")?.as_array()?;
+        return Some(
+            items
+                .iter()
+                .filter_map(|entry| {
+                    let completed = entry.get("completed").and_then(|c| c.as_bool()).unwrap_or(false);
+                    Some(PlanStep {
+                        step: entry.get("text")?.as_str()?.to_string(),
+                        status: if completed { PlanStatus::Completed } else { PlanStatus::Pending },
+                    })
+                })
+                .collect(),
+        );
+    }
+    let steps = event.get("plan").or_else(|| event.pointer("/msg/plan"))?.as_array()?;
+    Some(
+        steps
+            .iter()
+            .filter_map(|entry| {
+                let status = match entry.get("status").and_then(|s| s.as_str()) {
+                    Some("completed") => PlanStatus::Completed,
+                    Some("in_progress") => PlanStatus::InProgress,
+                    _ => PlanStatus::Pending,
+                };
+                Some(PlanStep {
+                    step: entry.get("step")?.as_str()?.to_string(),
+                    status,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Watches a run's raw stdout lines as they arrive, e.g. to report progress.
+pub trait RunObserver: Send + Sync {
+    fn observe_line(&self, line: &str);
+}
+
+/// Everything extracted from a run's event stream.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedRun {
+    pub success: bool,
+    pub thread_id: Option<String>,
+    /// Model codex reported using, if any event carried one.
+    pub model: Option<String>,
+    pub agent_messages: String,
+    /// The run ended cleanly without an agent message, e.g. after only
+    /// editing files.
+    pub no_final_message: bool,
+    pub reasoning: Option<Vec<String>>,
+    pub all_messages: Option<Vec<serde_json::Value>>,
+    pub usage: Option<TokenUsage>,
+    pub error: String,
+    pub approval_requested: bool,
+    /// How each approval request was decided, with `approval_bridge` on.
+    pub approvals: Vec<ApprovalDecision>,
+    pub limit_exceeded: bool,
+    pub budget_exceeded: bool,
+    /// An error matched a `fatal` noise filter, which stops the run.
+    pub fatal_error: bool,
+    /// How long the provider asked to wait, when the run hit a rate limit.
+    pub rate_limit: Option<Duration>,
+    /// Paths reported by completed `file_change` items, in first-seen order.
+    pub changed_files: Vec<String>,
+    /// What `file_change` items reported for each change, in order.
+    pub file_changes: Vec<FileChange>,
+    /// Commands the sandbox blocked, in order.
+    pub sandbox_denials: Vec<SandboxDenial>,
+    /// Number of `turn.completed` events seen.
+    pub turns_completed: u32,
+    /// A turn started and neither completed nor failed, e.g. because codex died.
+    pub turn_interrupted: bool,
+    /// Non-JSON lines codex printed to stdout, such as log output, in order.
+    pub unparsed_lines: Vec<String>,
+    /// Error messages `noise_filters` classified as warnings, in order.
+    pub warnings: Vec<String>,
+    /// The model's plan as of its latest update.
+    pub plan: Vec<PlanStep>,
+}
+
+/// Incremental parser fed one stdout line at a time.
+#[derive(Debug)]
+pub struct EventStream {
+    run: ParsedRun,
+    limits: RunLimits,
+    stop_on: StopOn,
+    turns: u32,
+    /// Value of `turns` when the last agent message arrived.
+    message_turn: u32,
+    commands: u32,
+    /// Usage reported so far by events of the turn in progress.
+    turn_usage: TokenUsage,
+    /// Decode error of the last non-JSON line, until a JSON event follows it.
+    unrecovered: Option<String>,
+    /// Event or item types kept in `all_messages`; empty keeps every event.
+    event_filter: Vec<String>,
+    /// When the parser was created, for the elapsed time in debug logs.
+    started: Instant,
+    /// Classification of codex error messages, before the built-in filters.
+    noise_filters: Vec<NoiseFilter>,
+    /// Hand approval requests to the caller instead of stopping the run.
+    approval_bridge: bool,
+    /// Approval request codex is waiting on, until the caller takes it.
+    pending_approval: Option<ApprovalRequest>,
+}
+
+impl EventStream {
+    /// Create a parser. `all_messages` and `reasoning` are only collected when requested.
+    pub fn new(collect_all_messages: bool, collect_reasoning: bool) -> Self {
+        Self {
+            run: ParsedRun {
+                success: true,
+                all_messages: collect_all_messages.then_some(Vec::new()),
+                reasoning: collect_reasoning.then_some(Vec::new()),
+                ..Default::default()
+            },
+            limits: RunLimits::default(),
+            stop_on: StopOn::default(),
+            turns: 0,
+            message_turn: 0,
+            commands: 0,
+            turn_usage: TokenUsage::default(),
+            unrecovered: None,
+            event_filter: Vec::new(),
+            started: Instant::now(),
+            noise_filters: Vec::new(),
+            approval_bridge: false,
+            pending_approval: None,
+        }
+    }
+
+    /// Only keep events whose type, or whose item's type, is in `filter` in
+    /// `all_messages`. Every event is still parsed.
+    pub fn with_event_filter(mut self, filter: Vec<String>) -> Self {
+        self.event_filter = filter;
+        self
+    }
+
+    /// Classify codex error messages with `filters` before the built-in ones.
+    pub fn with_noise_filters(mut self, filters: Vec<NoiseFilter>) -> Self {
+        self.noise_filters = filters;
+        self
+    }
+
+    /// Log a parsed event at DEBUG level, so `RUST_LOG=debug` shows where a
+    /// stuck run stopped making progress.
+    fn trace(&self, event_type: &str, item_type: Option<&str>, item_id: Option<&str>) {
+        tracing::debug!(
+            event_type,
+            item_type,
+            item_id,
+            elapsed_ms = self.started.elapsed().as_millis() as u64,
+            "Codex event"
+        );
+    }
+
+    /// Append `event` to `all_messages` if they are collected and it passes the filter.
+    fn collect(&mut self, event: impl FnOnce() -> serde_json::Value, event_type: &str, item_type: Option<&str>) {
+        let Some(all) = self.run.all_messages.as_mut() else {
+            return;
+        };
+        let kept = self.event_filter.is_empty()
+            || self
+                .event_filter
+                .iter()
+                .any(|wanted| wanted == event_type || item_type == Some(wanted.as_str()));
+        if kept {
+            all.push(event());
+        }
+    }
+
+    /// Keep the run going on approval requests, for the caller to answer via
+    /// `take_approval_request`, instead of stopping it.
+    pub fn with_approval_bridge(mut self, enabled: bool) -> Self {
+        self.approval_bridge = enabled;
+        self
+    }
+
+    /// The approval request codex is waiting on since the last line, if any.
+    pub fn take_approval_request(&mut self) -> Option<ApprovalRequest> {
+        self.pending_approval.take()
+    }
+
+    /// Record how an approval request was decided.
+    pub fn record_approval(&mut self, decision: ApprovalDecision) {
+        self.run.approvals.push(decision);
+    }
+
+    /// Keep reading until `stop_on` instead of the first completed turn.
+    pub fn with_stop_on(mut self, stop_on: StopOn) -> Self {
+        self.stop_on = stop_on;
+        self
+    }
+
+    /// Whether the run has produced an agent message so far.
+    pub fn has_agent_message(&self) -> bool {
+        !self.run.agent_messages.is_empty()
+    }
+
+    /// Stop the run once it exceeds `limits`.
+    pub fn with_limits(mut self, limits: RunLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Whether the parser stopped the run before codex finished it, so the
+    /// process must be killed.
+    pub fn aborted(&self) -> bool {
+        self.run.approval_requested || self.run.limit_exceeded || self.run.budget_exceeded || self.run.fatal_error
+    }
+
+    /// Mark the run failed with a tagged message, e.g. for process exit errors.
+    pub fn record_failure(&mut self, tag: &str, detail: &str) {
+        self.run.success = false;
+        self.push_error(tag, detail);
+    }
+
+    fn push_error(&mut self, tag: &str, detail: &str) {
+        self.run.error.push_str("\n\n[");
+        self.run.error.push_str(tag);
+        self.run.error.push_str("] ");
+        self.run.error.push_str(detail);
+    }
+
+    /// Record a line codex wrote to stderr as a synthetic `stderr` event in
+    /// `all_messages`, so diagnostics printed only there stay in order with
+    /// the JSON events.
+    pub fn push_stderr(&mut self, line: &str) {
+        let event = || {
+            serde_json::json!({
+                "type": "stderr",
+                "text": line,
+                "timestamp_ms": unix_now_millis() as u64,
+            })
+        };
+        self.collect(event, "stderr", None);
+    }
+
+    /// Process one line of codex stdout.
+    pub fn push_line(&mut self, line: &str) -> Flow {
+        let line = line.trim();
+        if line.is_empty() {
+            return Flow::Continue;
+        }
+
+        // Diagnostics codex sometimes prints to stdout are kept aside; the
+        // run only fails if no JSON event follows them.
+        let line_dict = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(value) => value,
+            Err(e) => {
+                self.unrecovered = Some(format!("{e}: {line}"));
+                if self.run.unparsed_lines.len() < MAX_UNPARSED_LINES {
+                    self.run.unparsed_lines.push(line.to_string());
+                }
+                let event = || {
+                    serde_json::json!({
+                        "type": "stdout_text",
+                        "text": line,
+                        "timestamp_ms": unix_now_millis() as u64,
+                    })
+                };
+                self.trace("stdout_text", None, None);
+                self.collect(event, "stdout_text", None);
+                return Flow::Continue;
+            }
+        };
+        self.unrecovered = None;
+
+        let event_type = line_dict.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+        let item_type = line_dict.pointer("/item/type").and_then(|t| t.as_str());
+        let item_id = line_dict.pointer("/item/id").and_then(|id| id.as_str());
+        self.trace(event_type, item_type, item_id);
+        // Stamped with the time the server received it, which codex's own
+        // events lack, so think time and command time can be told apart.
+        let event = || {
+            let mut event = line_dict.clone();
+            if let Some(object) = event.as_object_mut() {
+                object
+                    .entry("timestamp_ms")
+                    .or_insert_with(|| (unix_now_millis() as u64).into());
+            }
+            event
+        };
+        self.collect(event, event_type, item_type);
+
+        let run = &mut self.run;
+
+        // Extract agent messages, separating those of later turns
+        if let Some(item) = line_dict.get("item")
+            && let Some(item_type) = item.get("type").and_then(|t| t.as_str())
+            && item_type == "agent_message"
+            && let Some(text) = item.get("text").and_then(|t| t.as_str())
+        {
+            if !run.agent_messages.is_empty() && self.message_turn != self.turns {
+                run.agent_messages.push_str("\n\n");
+            }
+            self.message_turn = self.turns;
+            run.agent_messages.push_str(text);
+        }
+
+        // Extract reasoning summaries
+        if let Some(summaries) = run.reasoning.as_mut()
+            && line_dict.get("type").and_then(|t| t.as_str()) == Some("item.completed")
+            && let Some(item) = line_dict.get("item")
+            && item.get("type").and_then(|t| t.as_str()) == Some("reasoning")
+            && let Some(text) = item.get("text").and_then(|t| t.as_str())
+        {
+            summaries.push(text.to_string());
+        }
+
+        // Keep the latest plan
+        if let Some(steps) = plan(&line_dict) {
+            run.plan = steps;
+        }
+
+        // Extract changed file paths
+        if line_dict.get("type").and_then(|t| t.as_str()) == Some("item.completed")
+            && let Some(item) = line_dict.get("item")
+            && item.get("type").and_then(|t| t.as_str()) == Some("file_change")
+            && let Some(changes) = item.get("changes").and_then(|c| c.as_array())
+        {
+            for change in changes.iter().filter_map(FileChange::from_event) {
+                if !run.changed_files.contains(&change.path) {
+                    run.changed_files.push(change.path.clone());
+                }
+                run.file_changes.push(change);
+            }
+        }
+
+        // Extract commands the sandbox blocked
+        if line_dict.get("type").and_then(|t| t.as_str()) == Some("item.completed")
+            && let Some(item) = line_dict.get("item")
+            && item.get("type").and_then(|t| t.as_str()) == Some("command_execution")
+            && let Some(denial) = sandbox_denial(item)
+        {
+            run.sandbox_denials.push(denial);
+        }
+
+        // Extract thread_id
+        if let Some(tid) = line_dict.get("thread_id").and_then(|t| t.as_str()) {
+            run.thread_id = Some(tid.to_string());
+        }
+
+        // Extract the model, reported on session configuration events
+        if let Some(model) = line_dict
+            .get("model")
+            .or_else(|| line_dict.pointer("/msg/model"))
+            .and_then(|m| m.as_str())
+        {
+            run.model = Some(model.to_string());
+        }
+
+        // Check for failures
+        let Some(msg_type) = line_dict.get("type").and_then(|t| t.as_str()) else {
+            return Flow::Continue;
+        };
+
+        if msg_type.contains("fail") {
+            self.run.success = false;
+            if msg_type == "turn.failed" {
+                self.run.turn_interrupted = false;
+            }
+            if let Some(error) = line_dict.get("error")
+                && let Some(error_msg) = error.get("message").and_then(|m| m.as_str())
+            {
+                self.push_error("codex error", error_msg);
+                self.note_rate_limit(error_msg);
+            }
+        }
+
+        if msg_type.contains("error")
+            && let Some(error_msg) = line_dict.get("message").and_then(|m| m.as_str())
+        {
+            match noise::classify(&self.noise_filters, error_msg) {
+                Some(NoiseClass::Benign) => return Flow::Continue,
+                Some(NoiseClass::Warning) => {
+                    self.run.warnings.push(error_msg.to_string());
+                    return Flow::Continue;
+                }
+                Some(NoiseClass::Fatal) => {
+                    self.run.fatal_error = true;
+                    self.record_failure("codex error", error_msg);
+                    return Flow::Stop;
+                }
+                None => {
+                    self.record_failure("codex error", error_msg);
+                    self.note_rate_limit(error_msg);
+                }
+            }
+        }
+
+        // Without the bridge nothing answers approval prompts, so a request
+        // would otherwise stall the run until it times out.
+        if msg_type.ends_with("approval_request") {
+            let request = ApprovalRequest::from_event(&line_dict);
+            if self.approval_bridge {
+                self.pending_approval = Some(request);
+                return Flow::Continue;
+            }
+            self.run.approval_requested = true;
+            self.record_failure("codex approval", &describe_approval_request(&request));
+            return Flow::Stop;
+        }
+
+        if msg_type == "turn.started" {
+            self.turns += 1;
+            self.run.turn_interrupted = true;
+            if let Some(max) = self.limits.max_turns
+                && self.turns > max
+            {
+                return self.exceed_limit(&format!("codex started more than {max} turns (max_turns)"));
+            }
+        }
+
+        if msg_type == "item.started"
+            && line_dict.pointer("/item/type").and_then(|t| t.as_str()) == Some("command_execution")
+        {
+            self.commands += 1;
+            if let Some(max) = self.limits.max_commands
+                && self.commands > max
+            {
+                return self.exceed_limit(&format!("codex ran more than {max} commands (max_commands)"));
+            }
+        }
+
+        let usage = line_dict
+            .get("usage")
+            .and_then(|u| serde_json::from_value::<TokenUsage>(u.clone()).ok());
+
+        // Check for turn completion
+        if msg_type == "turn.completed" {
+            self.run.turns_completed += 1;
+            self.run.turn_interrupted = false;
+            self.turn_usage = TokenUsage::default();
+            if let Some(turn_usage) = usage {
+                *self.run.usage.get_or_insert_default() += turn_usage;
+            }
+            let flow = self.check_budget();
+            return match self.stop_on {
+                StopOn::FirstTurn => Flow::Stop,
+                StopOn::ProcessExit => flow,
+            };
+        }
+
+        // Usage reported mid-turn, cumulative for the turn.
+        if let Some(turn_usage) = usage {
+            self.turn_usage = turn_usage;
+            return self.check_budget();
+        }
+
+        Flow::Continue
+    }
+
+    /// Stop the run once its cost, including the turn in progress, exceeds the limit.
+    fn check_budget(&mut self) -> Flow {
+        let Some(limit) = self.limits.cost else {
+            return Flow::Continue;
+        };
+        let mut usage = self.run.usage.unwrap_or_default();
+        usage += self.turn_usage;
+        let cost = limit.price.cost(&usage);
+        if cost <= limit.max_usd {
+            return Flow::Continue;
+        }
+        self.run.budget_exceeded = true;
+        self.record_failure(
+            "budget exceeded",
+            &format!("run cost ${cost:.4} exceeds the budget of ${:.4}", limit.max_usd),
+        );
+        Flow::Stop
+    }
+
+    fn note_rate_limit(&mut self, message: &str) {
+        if throttle::is_rate_limit(message) {
+            self.run.rate_limit = Some(throttle::retry_after(message));
+        }
+    }
+
+    fn exceed_limit(&mut self, detail: &str) -> Flow {
+        self.run.limit_exceeded = true;
+        self.record_failure("limit exceeded", detail);
+        Flow::Stop
+    }
+
+    /// Finish parsing, failing the run if no session ID was seen. A run that
+    /// otherwise succeeded without a final message is flagged `no_final_message`.
+    pub fn finish(mut self) -> ParsedRun {
+        if let Some(error) = self.unrecovered.take() {
+            self.record_failure("json decode error", &error);
+        }
+        let mut run = self.run;
+
+        if run.thread_id.is_none() {
+            run.success = false;
+            run.error = format!(
+                "Failed to get `SESSION_ID` from the codex session.\n\n{}",
+                run.error
+            );
+        }
+
+        if run.agent_messages.is_empty() && run.success {
+            run.no_final_message = true;
+        } else if run.agent_messages.is_empty() {
+            run.error = format!(
+                "Failed to get `agent_messages` from the codex session.\n\nYou can try to set `return_all_messages` to `True` to get the full reasoning information. {}",
+                run.error
+            );
+        }
+
+        run
+    }
+}
+
+/// The denial a completed `command_execution` item reports, if any: codex
+/// declined to run it, or it failed with output typical of a sandbox block.
+fn sandbox_denial(item: &serde_json::Value) -> Option<SandboxDenial> {
+    let command = item.get("command").and_then(|c| c.as_str())?.to_string();
+    let status = item.get("status").and_then(|s| s.as_str());
+    if status == Some("declined") {
+        return Some(SandboxDenial {
+            command,
+            reason: "codex declined to run the command under the sandbox policy".to_string(),
+        });
+    }
+    let failed = status == Some("failed") || item.get("exit_code").and_then(|c| c.as_i64()).is_some_and(|c| c != 0);
+    if !failed {
+        return None;
+    }
+    let output = item.get("aggregated_output").and_then(|o| o.as_str())?;
+    let line = output.lines().find(|line| {
+        let line = line.to_ascii_lowercase();
+        DENIAL_MARKERS.iter().any(|marker| line.contains(marker))
+    })?;
+    Some(SandboxDenial {
+        command,
+        reason: line.trim().chars().take(MAX_DENIAL_REASON_CHARS).collect(),
+    })
+}
+
+/// Describe an approval request for the error message.
+fn describe_approval_request(request: &ApprovalRequest) -> String {
+    format!(
+        "codex requested approval to {}, but `codex exec` cannot grant approvals interactively. \
+         Re-run with a sandbox policy that permits the action.",
+        request.action()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Parse a transcript the way `execute_codex` does, stopping where it would stop.
+    fn parse(transcript: &str) -> ParsedRun {
+        let mut stream = EventStream::new(false, true);
+        for line in transcript.lines() {
+            if stream.push_line(line) == Flow::Stop {
+                break;
+            }
+        }
+        stream.finish()
+    }
+
+    fn snapshot(run: &ParsedRun) -> serde_json::Value {
+        serde_json::json!({
+            "success": run.success,
+            "SESSION_ID": run.thread_id,
+            "agent_messages": run.agent_messages,
+            "no_final_message": run.no_final_message,
+            "reasoning_summary": run.reasoning,
+            "usage": run.usage,
+            "approval_requested": run.approval_requested,
+            "changed_files": run.changed_files,
+            "sandbox_denials": run.sandbox_denials,
+            "unparsed_lines": run.unparsed_lines,
+            "plan": run.plan,
+            "error": run.error,
+        })
+    }
+
+    /// Every `tests/fixtures/events/*.jsonl` transcript must parse to the result
+    /// recorded next to it in `*.expected.json`. Run with `UPDATE_FIXTURES=1`
+    /// to rewrite the expectations after an intentional parser change.
+    #[test]
+    fn test_event_fixture_snapshots() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/events");
+        let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+        let mut checked = 0;
+
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            let actual = snapshot(&parse(&std::fs::read_to_string(&path).unwrap()));
+            let expected_path = path.with_extension("expected.json");
+
+            if update {
+                let text = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+                std::fs::write(&expected_path, text).unwrap();
+            } else {
+                let expected: serde_json::Value =
+                    serde_json::from_str(&std::fs::read_to_string(&expected_path).unwrap())
+                        .unwrap();
+                assert_eq!(actual, expected, "snapshot mismatch for {}", path.display());
+            }
+            checked += 1;
+        }
+
+        assert!(checked > 0, "no fixtures found in {}", dir.display());
+    }
+
+    #[test]
+    fn test_describe_approval_request() {
+        let event = serde_json::json!({
+            "type": "exec_approval_request",
+            "command": ["cargo", "publish"]
+        });
+        assert!(describe_approval_request(&ApprovalRequest::from_event(&event)).starts_with(
+            "codex requested approval to run `cargo publish`"
+        ));
+    }
+
+    #[test]
+    fn test_approval_bridge_keeps_the_run_going() {
+        let request = r#"{"type":"exec_approval_request","call_id":"c1","command":["cargo","publish"]}"#;
+        let mut stream = EventStream::new(false, false).with_approval_bridge(true);
+        assert_eq!(stream.push_line(request), Flow::Continue);
+        assert_eq!(stream.take_approval_request().unwrap().action(), "run `cargo publish`");
+        assert!(stream.take_approval_request().is_none());
+        assert!(!stream.aborted());
+
+        let mut stream = EventStream::new(false, false);
+        assert_eq!(stream.push_line(request), Flow::Stop);
+        assert!(stream.take_approval_request().is_none() && stream.aborted());
+    }
+
+    #[test]
+    fn test_command_limit_stops_the_run() {
+        let command = r#"{"type":"item.started","item":{"id":"i","type":"command_execution","command":"ls"}}"#;
+        let mut stream = EventStream::new(false, false).with_limits(RunLimits {
+            max_commands: Some(1),
+            ..RunLimits::default()
+        });
+        assert_eq!(stream.push_line(command), Flow::Continue);
+        assert_eq!(stream.push_line(command), Flow::Stop);
+        assert!(stream.aborted());
+
+        let run = stream.finish();
+        assert!(!run.success);
+        assert!(run.limit_exceeded);
+        assert!(run.error.contains("more than 1 commands"), "{}", run.error);
+    }
+
+    #[test]
+    fn test_cost_limit_stops_the_run() {
+        let mut stream = EventStream::new(false, false).with_limits(RunLimits {
+            cost: Some(CostLimit {
+                price: crate::cost::ModelPrice {
+                    input: 1.0,
+                    cached_input: None,
+                    output: 10.0,
+                },
+                max_usd: 0.01,
+            }),
+            ..RunLimits::default()
+        });
+        stream.push_line(r#"{"type":"thread.started","thread_id":"t"}"#);
+        let progress = r#"{"type":"token_count","usage":{"input_tokens":1000,"output_tokens":100}}"#;
+        assert_eq!(stream.push_line(progress), Flow::Continue);
+        let over = r#"{"type":"token_count","usage":{"input_tokens":1000,"output_tokens":1000}}"#;
+        assert_eq!(stream.push_line(over), Flow::Stop);
+        assert!(stream.aborted());
+        assert!(stream.finish().error.contains("exceeds the budget"));
+    }
+
+    #[test]
+    fn test_process_exit_collects_every_turn() {
+        let transcript = [
+            r#"{"type":"thread.started","thread_id":"t"}"#,
+            r#"{"type":"turn.started"}"#,
+            r#"{"type":"item.completed","item":{"id":"0","type":"agent_message","text":"Planned."}}"#,
+            r#"{"type":"turn.completed","usage":{"input_tokens":10,"output_tokens":1}}"#,
+            r#"{"type":"turn.started"}"#,
+            r#"{"type":"item.completed","item":{"id":"1","type":"agent_message","text":"Done."}}"#,
+            r#"{"type":"turn.completed","usage":{"input_tokens":20,"output_tokens":2}}"#,
+        ];
+        let mut stream = EventStream::new(false, false).with_stop_on(StopOn::ProcessExit);
+        for line in transcript {
+            assert_eq!(stream.push_line(line), Flow::Continue);
+        }
+        let run = stream.finish();
+        assert!(run.success);
+        assert_eq!(run.agent_messages, "Planned.\n\nDone.");
+        assert_eq!(run.turns_completed, 2);
+        assert!(!run.turn_interrupted);
+        assert_eq!(run.usage.unwrap().input_tokens, 30);
+
+        assert_eq!(parse(&transcript.join("\n")).agent_messages, "Planned.");
+        assert!(parse(&transcript[..3].join("\n")).turn_interrupted);
+    }
+
+    #[test]
+    fn test_stderr_lines_become_synthetic_events() {
+        let mut stream = EventStream::new(true, false);
+        stream.push_line(r#"{"type":"thread.started","thread_id":"t"}"#);
+        stream.push_stderr("sandbox denied write to /etc");
+
+        let all = stream.finish().all_messages.unwrap();
+        assert!(all[0]["timestamp_ms"].is_u64());
+        assert_eq!(all[1]["type"], "stderr");
+        assert_eq!(all[1]["text"], "sandbox denied write to /etc");
+        assert!(all[1]["timestamp_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_all_messages_collected_only_when_requested() {
+        let line = r#"{"type":"thread.started","thread_id":"t"}"#;
+
+        let mut stream = EventStream::new(true, false);
+        stream.push_line(line);
+        assert_eq!(stream.finish().all_messages.map(|m| m.len()), Some(1));
+
+        let mut stream = EventStream::new(false, false);
+        stream.push_line(line);
+        assert!(stream.finish().all_messages.is_none());
+    }
+
+    #[test]
+    fn test_event_filter_keeps_matching_events() {
+        let mut stream = EventStream::new(true, false)
+            .with_event_filter(vec!["command_execution".to_string(), "turn.completed".to_string()]);
+        stream.push_line(r#"{"type":"thread.started","thread_id":"t"}"#);
+        stream.push_line(r#"{"type":"item.started","item":{"type":"command_execution","command":"ls"}}"#);
+        stream.push_line(r#"{"type":"item.completed","item":{"type":"agent_message","text":"Done."}}"#);
+        stream.push_stderr("warning: config ignored");
+        stream.push_line(r#"{"type":"turn.completed","usage":{"input_tokens":1,"output_tokens":1}}"#);
+
+        let run = stream.finish();
+        let types: Vec<&str> = run.all_messages.as_ref().unwrap().iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["item.started", "turn.completed"]);
+        // Filtered-out events are still parsed.
+        assert_eq!(run.thread_id.as_deref(), Some("t"));
+        assert_eq!(run.agent_messages, "Done.");
+    }
+
+    #[test]
+    fn test_noise_filters_classify_error_events() {
+        let filters = toml::from_str::<toml::Table>(
+            r#"filters = [{ pattern = "^warning:", class = "warning" }]"#,
+        )
+        .unwrap()["filters"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let mut stream = EventStream::new(false, false).with_noise_filters(filters);
+        stream.push_line(r#"{"type":"thread.started","thread_id":"t"}"#);
+        assert_eq!(stream.push_line(r#"{"type":"error","message":"Reconnecting... 1/5"}"#), Flow::Continue);
+        assert_eq!(stream.push_line(r#"{"type":"error","message":"warning: model is deprecated"}"#), Flow::Continue);
+        let run = stream.finish();
+        assert!(run.success);
+        assert_eq!(run.warnings, ["warning: model is deprecated"]);
+
+        let filters = vec![NoiseFilter {
+            pattern: regex_automata::meta::Regex::new("not logged in").unwrap(),
+            class: NoiseClass::Fatal,
+        }];
+        let mut stream = EventStream::new(false, false).with_noise_filters(filters);
+        assert_eq!(stream.push_line(r#"{"type":"error","message":"codex: not logged in"}"#), Flow::Stop);
+        assert!(stream.aborted(), "the process is killed");
+
+        let mut stream = EventStream::new(false, false);
+        stream.push_line(r#"{"type":"thread.started","thread_id":"t"}"#);
+        stream.push_line(r#"{"type":"error","message":"stream disconnected"}"#);
+        assert!(!stream.finish().success);
+    }
+}
@@ -0,0 +1,174 @@
+//! Workspace paths that must never reach codex's prompt or a client's
+//! result, such as `.env` or `secrets/`: those listed in the workspace's
+//! ignore file (`.codexignore` by default) and in the server's
+//! `excluded_paths`.
+//!
+//! Patterns follow `.gitignore` syntax: `*`, `?`, and `**` wildcards, a
+//! trailing `/` for directories only, a leading or inner `/` anchoring the
+//! pattern at the workspace root, and `!` re-including what an earlier
+//! pattern excluded. A path inside an excluded directory is excluded too.
+//! Excluded files are refused as `context_files`, `resource:` prompts, and
+//! by `codex_workspace_read`, hidden from `codex_workspace_list`, and left
+//! out of `patch`.
+
+use std::path::{Component, Path};
+
+use regex_automata::meta::Regex;
+
+/// One ignore pattern.
+#[derive(Debug)]
+struct Pattern {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The exclusion patterns of a workspace.
+#[derive(Debug, Default)]
+pub struct PathExclusions {
+    patterns: Vec<Pattern>,
+}
+
+impl PathExclusions {
+    /// The exclusions of workspace `cd`: the server's `configured` patterns,
+    /// then those of its `ignore_file`, if it has one.
+    pub fn load(cd: &Path, ignore_file: &Path, configured: &[String]) -> Self {
+        let mut exclusions = Self::parse(configured.iter().map(String::as_str));
+        match std::fs::read_to_string(cd.join(ignore_file)) {
+            Ok(contents) => exclusions.patterns.extend(Self::parse(contents.lines()).patterns),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!(error = %e, file = %ignore_file.display(), "Failed to read ignore file"),
+        }
+        exclusions
+    }
+
+    /// Patterns from `lines`, skipping blank lines, `#` comments, and
+    /// patterns that cannot be compiled.
+    pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let patterns = lines
+            .into_iter()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let pattern = compile(line);
+                if pattern.is_none() {
+                    tracing::warn!(pattern = %line, "Ignoring invalid exclusion pattern");
+                }
+                pattern
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// Whether `path`, relative to the workspace root, is excluded. `is_dir`
+    /// tells whether it is a directory.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let parts: Vec<&str> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect();
+        (1..=parts.len()).any(|len| self.matches(&parts[..len].join("/"), is_dir || len < parts.len()))
+    }
+
+    /// Whether the last pattern matching `path` excludes it.
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| (is_dir || !pattern.dir_only) && pattern.regex.is_match(path))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+fn compile(line: &str) -> Option<Pattern> {
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let anchored = line.contains('/');
+    let glob = line.strip_prefix('/').unwrap_or(line);
+    if glob.is_empty() {
+        return None;
+    }
+    let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+    let regex = Regex::new(&format!("{prefix}{}$", glob_to_regex(glob))).ok()?;
+    Some(Pattern {
+        regex,
+        negated,
+        dir_only,
+    })
+}
+
+/// `glob` as a regex over `/`-separated paths.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // `**/` matches any number of leading directories, including none.
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => {
+                if c.is_ascii_punctuation() {
+                    regex.push('\\');
+                }
+                regex.push(c);
+            }
+        }
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_style_patterns() {
+        let exclusions = PathExclusions::parse([
+            "# secrets",
+            ".env",
+            "*.pem",
+            "secrets/",
+            "/config/local.toml",
+            "docs/**/private",
+            "!keep.pem",
+        ]);
+        let excluded = |path: &str| exclusions.is_excluded(Path::new(path), false);
+
+        assert!(excluded(".env"));
+        assert!(excluded("app/.env"));
+        assert!(!excluded(".env.example"));
+        assert!(excluded("certs/server.pem"));
+        assert!(!excluded("certs/keep.pem"));
+        assert!(excluded("secrets/token.txt"));
+        assert!(excluded("deploy/secrets/token.txt"));
+        assert!(!excluded("secrets"), "`secrets/` only matches directories");
+        assert!(exclusions.is_excluded(Path::new("secrets"), true));
+        assert!(excluded("config/local.toml"));
+        assert!(!excluded("app/config/local.toml"));
+        assert!(excluded("docs/private"));
+        assert!(excluded("docs/a/b/private/notes.md"));
+        assert!(!excluded("src/main.rs"));
+        assert!(!PathExclusions::default().is_excluded(Path::new(".env"), false));
+    }
+}
@@ -0,0 +1,62 @@
+//! `codex-mcp exec`: run codex from the command line without an MCP client.
+//!
+//! Runs go through the same path as the `codex` tool, so the server config's
+//! workspace policy, policy rules, session registry, and artifacts all apply.
+//! This makes it a convenient way to debug server behavior from a shell.
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::cli::ExecArgs;
+use crate::codex::{CodexParams, CodexServer, RunContext};
+
+/// Run the prompt in `args`, or each line of stdin, printing every result as
+/// a line of JSON. Returns whether all runs succeeded.
+pub async fn run(server: &CodexServer, args: &ExecArgs) -> Result<bool> {
+    if let Some(ref prompt) = args.prompt {
+        return run_prompt(server, args, prompt, args.session_id.clone()).await.map(|(success, _)| success);
+    }
+
+    let mut all_succeeded = true;
+    let mut session_id = args.session_id.clone();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.context("failed to read prompts from stdin")? {
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            continue;
+        }
+        let (success, new_session) = run_prompt(server, args, prompt, session_id.clone()).await?;
+        all_succeeded &= success;
+        if args.same_session && new_session.is_some() {
+            session_id = new_session;
+        }
+    }
+    Ok(all_succeeded)
+}
+
+/// Run one prompt and print its result, returning its success and session.
+async fn run_prompt(
+    server: &CodexServer,
+    args: &ExecArgs,
+    prompt: &str,
+    session_id: Option<String>,
+) -> Result<(bool, Option<String>)> {
+    let mut params = serde_json::json!({
+        "PROMPT": prompt,
+        "cd": args.cd.as_deref().unwrap_or("."),
+    });
+    if let Some(ref sandbox) = args.sandbox {
+        params["sandbox"] = sandbox.as_str().into();
+    }
+    if let Some(ref model) = args.model {
+        params["model"] = model.as_str().into();
+    }
+    if let Some(session_id) = session_id {
+        params["SESSION_ID"] = session_id.into();
+    }
+    let params: CodexParams = serde_json::from_value(params).context("invalid exec options")?;
+
+    let result = server.run_codex(params, RunContext::tool("exec")).await;
+    println!("{}", serde_json::to_string(&result)?);
+    Ok((result.success, result.session_id))
+}
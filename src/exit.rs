@@ -0,0 +1,175 @@
+//! How the codex process ended: its exit code or the signal that stopped it,
+//! reported as structured fields rather than `ExitStatus` debug output, with a
+//! distinct error code for kills that are most likely the OOM killer.
+
+use std::fmt;
+use std::process::ExitStatus;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Exit status of the codex process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ProcessExit {
+    /// Exit code, when codex exited on its own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    /// Number of the signal that terminated codex (Unix only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// Name of that signal, e.g. `SIGKILL`, when it is a well-known one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_name: Option<String>,
+    /// Whether the process dumped core.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub core_dumped: bool,
+}
+
+#[cfg(unix)]
+const SIGNAL_NAMES: &[(i32, &str)] = &[
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGXCPU, "SIGXCPU"),
+    (libc::SIGXFSZ, "SIGXFSZ"),
+];
+
+/// Signals a process receives when it crashes rather than being stopped.
+#[cfg(unix)]
+const CRASH_SIGNALS: &[i32] = &[libc::SIGILL, libc::SIGTRAP, libc::SIGABRT, libc::SIGBUS, libc::SIGFPE, libc::SIGSEGV];
+
+impl ProcessExit {
+    #[cfg(unix)]
+    pub fn from_status(status: ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+
+        let signal = status.signal();
+        Self {
+            code: status.code(),
+            signal,
+            signal_name: signal.and_then(|signal| {
+                SIGNAL_NAMES
+                    .iter()
+                    .find(|(number, _)| *number == signal)
+                    .map(|(_, name)| name.to_string())
+            }),
+            core_dumped: status.core_dumped(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_status(status: ExitStatus) -> Self {
+        Self {
+            code: status.code(),
+            signal: None,
+            signal_name: None,
+            core_dumped: false,
+        }
+    }
+
+    /// Whether codex was killed with SIGKILL, which, when the server did not
+    /// send it, almost always comes from the kernel's OOM killer.
+    #[cfg(unix)]
+    fn killed(&self) -> bool {
+        self.signal == Some(libc::SIGKILL)
+    }
+
+    #[cfg(not(unix))]
+    fn killed(&self) -> bool {
+        false
+    }
+
+    /// Machine-readable error code for a run that ended this way:
+    /// `oom_killed` for SIGKILL, `terminated_by_signal` for any other signal,
+    /// and none for an exit code.
+    pub fn error_code(&self) -> Option<&'static str> {
+        if self.killed() {
+            Some("oom_killed")
+        } else if self.signal.is_some() {
+            Some("terminated_by_signal")
+        } else {
+            None
+        }
+    }
+
+    /// What to do about a run that ended this way, if anything is known.
+    pub fn hint(&self) -> Option<&'static str> {
+        if self.killed() {
+            return Some(
+                "codex was most likely stopped by the kernel's OOM killer. Free memory or raise the memory limit of the server's container or cgroup, run fewer codex sessions at once, or narrow the task to a smaller part of the workspace.",
+            );
+        }
+        #[cfg(unix)]
+        if let Some(signal) = self.signal {
+            return Some(if CRASH_SIGNALS.contains(&signal) {
+                "codex crashed. Check its stderr output above, and retry or upgrade codex if it happens again."
+            } else {
+                "codex was stopped by something outside the server, such as a system shutdown, a process supervisor, or a user."
+            });
+        }
+        None
+    }
+}
+
+impl fmt::Display for ProcessExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exited with code {code}")?,
+            (None, Some(signal)) => match self.signal_name {
+                Some(ref name) => write!(f, "terminated by {name} (signal {signal})")?,
+                None => write!(f, "terminated by signal {signal}")?,
+            },
+            (None, None) => f.write_str("exited with an unknown status")?,
+        }
+        if self.core_dumped {
+            f.write_str(", core dumped")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use super::*;
+
+    #[test]
+    fn test_process_exit_from_status() {
+        let exited = ProcessExit::from_status(ExitStatus::from_raw(3 << 8));
+        assert_eq!((exited.code, exited.signal), (Some(3), None));
+        assert_eq!(exited.to_string(), "exited with code 3");
+        assert_eq!((exited.error_code(), exited.hint()), (None, None));
+
+        let killed = ProcessExit::from_status(ExitStatus::from_raw(libc::SIGKILL));
+        assert_eq!((killed.code, killed.signal), (None, Some(9)));
+        assert_eq!(killed.to_string(), "terminated by SIGKILL (signal 9)");
+        assert_eq!(killed.error_code(), Some("oom_killed"));
+        assert!(killed.hint().unwrap().contains("OOM killer"));
+
+        let crashed = ProcessExit::from_status(ExitStatus::from_raw(libc::SIGSEGV | 0x80));
+        assert_eq!(crashed.to_string(), "terminated by SIGSEGV (signal 11), core dumped");
+        assert_eq!(crashed.error_code(), Some("terminated_by_signal"));
+        assert!(crashed.hint().unwrap().starts_with("codex crashed"));
+
+        let stopped = ProcessExit::from_status(ExitStatus::from_raw(libc::SIGTERM));
+        assert_eq!(stopped.error_code(), Some("terminated_by_signal"));
+        assert!(stopped.hint().unwrap().contains("outside the server"));
+        assert_eq!(
+            serde_json::to_value(&stopped).unwrap(),
+            serde_json::json!({"signal": libc::SIGTERM, "signal_name": "SIGTERM"})
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Cached repository overviews for the `codex_explain_repo` tool.
+//!
+//! An overview only changes when the code does, so it is cached per workspace
+//! and keyed by the commit it was produced at. Isolated clients each get their
+//! own overviews, as they do their own sessions.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{Store, load, save, unix_now};
+
+/// Key of the cached overviews in the state store.
+const EXPLAIN_CACHE_KEY: &str = "explain_cache";
+
+/// Canned read-only analysis prompt sent to codex.
+pub const EXPLAIN_PROMPT: &str = "\
+Explain this repository to an engineer who is about to work on it. Do not modify any files.
+
+Cover, concisely:
+1. Purpose: what the project does and who uses it.
+2. Layout: the main directories and modules and what each is responsible for.
+3. Architecture: key abstractions, data flow, and entry points.
+4. Tooling: languages, build system, how to build, test, and run it.
+5. Conventions: coding style, error handling, and testing patterns worth following.
+6. Pitfalls: anything surprising or easy to get wrong.
+
+Reference concrete file paths throughout.";
+
+/// An overview of a workspace at a given commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedExplanation {
+    /// Commit (`HEAD`) the overview describes.
+    pub head: String,
+    pub explanation: String,
+    /// Codex session that produced the overview.
+    pub session_id: Option<String>,
+    /// Unix timestamp of when the overview was produced.
+    pub created_at: u64,
+}
+
+/// Latest overview per workspace and owner, optionally persisted in the
+/// state directory.
+#[derive(Debug, Default)]
+pub struct ExplainCache {
+    store: Option<Store>,
+    entries: Mutex<HashMap<String, CachedExplanation>>,
+}
+
+/// Key of the overview of `cd` produced for `owner`: the path itself for
+/// overviews shared by every client, which keeps older cache files valid.
+fn entry_key(owner: Option<&str>, cd: &Path) -> String {
+    match owner {
+        Some(owner) => format!("{owner}@{}", cd.display()),
+        None => cd.display().to_string(),
+    }
+}
+
+impl ExplainCache {
+    /// Open the cache, loading overviews persisted in `store`.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let entries = load(&*store, EXPLAIN_CACHE_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store: Some(store),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The overview of `cd` for `owner` (`None` when clients share
+    /// overviews), if one was produced at `head`.
+    pub fn get(&self, owner: Option<&str>, cd: &Path, head: &str) -> Option<CachedExplanation> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&entry_key(owner, cd))
+            .filter(|entry| entry.head == head)
+            .cloned()
+    }
+
+    /// Store the overview of `cd` for `owner` at `head`, replacing any older one.
+    pub fn insert(
+        &self,
+        owner: Option<&str>,
+        cd: &Path,
+        head: &str,
+        explanation: String,
+        session_id: Option<String>,
+    ) -> CachedExplanation {
+        let entry = CachedExplanation {
+            head: head.to_string(),
+            explanation,
+            session_id,
+            created_at: unix_now(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(entry_key(owner, cd), entry.clone());
+
+        if let Some(ref store) = self.store
+            && let Err(e) = save(&**store, EXPLAIN_CACHE_KEY, &*entries)
+        {
+            tracing::warn!(key = EXPLAIN_CACHE_KEY, error = %e, "Failed to persist explain cache");
+        }
+        entry
+    }
+
+    /// Drop the overviews produced in `session_id`, returning how many.
+    pub fn remove_session(&self, session_id: &str) -> Result<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, entry| entry.session_id.as_deref() != Some(session_id));
+        let removed = before - entries.len();
+        if removed > 0 && let Some(ref store) = self.store {
+            save(&**store, EXPLAIN_CACHE_KEY, &*entries)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FileStore;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cache_is_keyed_by_head_and_persisted() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-explain-{}", std::process::id()));
+        let cd = Path::new("/srv/repo");
+
+        let cache = ExplainCache::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        assert!(cache.get(None, cd, "aaa").is_none());
+        cache.insert(None, cd, "aaa", "Overview".to_string(), Some("s1".to_string()));
+        assert_eq!(cache.get(None, cd, "aaa").unwrap().explanation, "Overview");
+        assert!(cache.get(None, cd, "bbb").is_none(), "a new commit invalidates the overview");
+
+        let reopened = ExplainCache::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        assert_eq!(reopened.get(None, cd, "aaa").unwrap().session_id.as_deref(), Some("s1"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_isolated_clients_do_not_share_overviews() {
+        let cache = ExplainCache::default();
+        let cd = Path::new("/srv/repo");
+        cache.insert(Some("uid:1000"), cd, "aaa", "A's overview".to_string(), Some("s1".to_string()));
+        assert!(cache.get(Some("uid:1001"), cd, "aaa").is_none());
+        assert!(cache.get(None, cd, "aaa").is_none());
+        assert_eq!(cache.get(Some("uid:1000"), cd, "aaa").unwrap().explanation, "A's overview");
+    }
+
+    #[test]
+    fn test_cache_files_keyed_by_path_still_load() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-explain-compat-{}", std::process::id()));
+        let store = FileStore::new(&dir);
+        let entry = CachedExplanation {
+            head: "aaa".to_string(),
+            explanation: "Overview".to_string(),
+            session_id: None,
+            created_at: 1,
+        };
+        save(&store, EXPLAIN_CACHE_KEY, &HashMap::from([(std::path::PathBuf::from("/srv/repo"), entry)])).unwrap();
+
+        let cache = ExplainCache::open(Some(Arc::new(store))).unwrap();
+        assert!(cache.get(None, Path::new("/srv/repo"), "aaa").is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+//! `files_changed`: what a run did to its workspace's files.
+//!
+//! Codex reports the files it edits with `file_change` items, but not those
+//! written by the commands it runs (generated code, build outputs, scratch
+//! files). For write-enabled runs the server therefore also compares the
+//! workspace before and after the run: with `git status` in git workspaces,
+//! and with a snapshot of every file's size and modification time in others.
+//! A deleted and a created file with the same size and modification time
+//! count as a rename, as do renames git reports.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::SystemTime;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::exclusions::PathExclusions;
+
+/// Most files snapshotted in a workspace that is not a git repository;
+/// larger workspaces are only reported from codex's events.
+const TREE_MAX_FILES: usize = 20_000;
+
+/// Directories left out of snapshots.
+const SKIPPED_DIRS: &[&str] = &[".git"];
+
+/// How a file changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// A file the run changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FileChange {
+    /// Path relative to the workspace root (absolute if outside it).
+    pub path: String,
+    pub kind: FileChangeKind,
+    /// Where a renamed file was before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_path: Option<String>,
+}
+
+impl FileChange {
+    fn new(path: String, kind: FileChangeKind) -> Self {
+        Self {
+            path,
+            kind,
+            previous_path: None,
+        }
+    }
+
+    /// The change a `file_change` event reports, whose `kind` is `add`,
+    /// `delete`, or `update`.
+    pub fn from_event(change: &serde_json::Value) -> Option<Self> {
+        let path = change.get("path")?.as_str()?.to_string();
+        let kind = match change.get("kind").and_then(|k| k.as_str()) {
+            Some("add") => FileChangeKind::Created,
+            Some("delete") => FileChangeKind::Deleted,
+            _ => FileChangeKind::Modified,
+        };
+        Some(Self::new(path, kind))
+    }
+}
+
+/// Size and modification time of a file.
+type Stamp = (u64, Option<SystemTime>);
+
+/// The state of a workspace's files before a run.
+#[derive(Debug)]
+pub enum Snapshot {
+    /// `git status` entries by path, relative to the workspace.
+    Git(HashMap<String, String>),
+    /// Every file by path, relative to the workspace.
+    Tree(HashMap<String, Stamp>),
+}
+
+impl Snapshot {
+    /// Snapshot of workspace `cd`, or `None` if it is too large to take.
+    pub async fn capture(cd: &Path) -> Option<Self> {
+        if let Some(status) = git_status(cd).await {
+            return Some(Snapshot::Git(status));
+        }
+        let cd = cd.to_path_buf();
+        tokio::task::spawn_blocking(move || tree(&cd)).await.ok().flatten().map(Snapshot::Tree)
+    }
+
+    /// How the files of `cd` changed since the snapshot.
+    pub async fn changes(&self, cd: &Path) -> Vec<FileChange> {
+        match self {
+            Snapshot::Git(before) => match git_status(cd).await {
+                Some(after) => status_changes(before, &after),
+                None => Vec::new(),
+            },
+            Snapshot::Tree(before) => {
+                let cd = cd.to_path_buf();
+                match tokio::task::spawn_blocking(move || tree(&cd)).await.ok().flatten() {
+                    Some(after) => tree_changes(before, &after),
+                    None => Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+/// The changes codex reported, relative to `cd`, completed with those
+/// `detected` by comparing the workspace, leaving out excluded paths.
+pub fn merge(
+    cd: &Path,
+    reported: &[FileChange],
+    detected: Vec<FileChange>,
+    exclusions: &PathExclusions,
+) -> Vec<FileChange> {
+    let relative = |path: &str| {
+        let path = Path::new(path);
+        path.strip_prefix(cd).unwrap_or(path).to_string_lossy().into_owned()
+    };
+    let mut changes: Vec<FileChange> = Vec::new();
+    for change in reported {
+        let path = relative(&change.path);
+        match changes.iter_mut().find(|c| c.path == path) {
+            // A file created and then edited is still new.
+            Some(existing) if existing.kind == FileChangeKind::Created && change.kind == FileChangeKind::Modified => {}
+            Some(existing) => existing.kind = change.kind,
+            None => changes.push(FileChange::new(path, change.kind)),
+        }
+    }
+    for change in detected {
+        if change.kind == FileChangeKind::Renamed {
+            // Codex reports a move as a deletion and a creation.
+            changes.retain(|c| Some(&c.path) != change.previous_path.as_ref() && c.path != change.path);
+            changes.push(change);
+        } else if !changes.iter().any(|c| c.path == change.path) {
+            changes.push(change);
+        }
+    }
+    changes.retain(|change| !exclusions.is_excluded(Path::new(&change.path), false));
+    changes
+}
+
+/// `git status` of `cd` by path relative to it, or `None` if `cd` is not in
+/// a git repository.
+async fn git_status(cd: &Path) -> Option<HashMap<String, String>> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(cd)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+    };
+    let prefix = git(&["rev-parse", "--show-prefix"]).await.ok().filter(|o| o.status.success())?;
+    let prefix = String::from_utf8_lossy(&prefix.stdout).trim().to_string();
+    let output = git(&["status", "--porcelain=v1", "-z", "--untracked-files=all", "--", "."])
+        .await
+        .ok()
+        .filter(|o| o.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut entries = HashMap::new();
+    let mut fields = stdout.split('\0').filter(|field| !field.is_empty());
+    while let Some(field) = fields.next() {
+        let Some((code, path)) = field.split_at_checked(3) else {
+            continue;
+        };
+        let path = path.strip_prefix(&prefix).unwrap_or(path).to_string();
+        let mut code = code.trim_end().to_string();
+        // Renames and copies are followed by their source path.
+        if code.contains(['R', 'C'])
+            && let Some(from) = fields.next()
+        {
+            code = format!("{code} {}", from.strip_prefix(&prefix).unwrap_or(from));
+        }
+        entries.insert(path, code);
+    }
+    Some(entries)
+}
+
+/// Entries whose status is new or different after the run, as changes.
+fn status_changes(before: &HashMap<String, String>, after: &HashMap<String, String>) -> Vec<FileChange> {
+    let mut changes: Vec<FileChange> = after
+        .iter()
+        .filter(|(path, code)| before.get(*path) != Some(*code))
+        .map(|(path, code)| {
+            let (xy, from) = code.split_once(' ').unwrap_or((code, ""));
+            let kind = if xy.contains('R') {
+                FileChangeKind::Renamed
+            } else if xy == "??" || xy.contains('A') {
+                FileChangeKind::Created
+            } else if xy.contains('D') {
+                FileChangeKind::Deleted
+            } else {
+                FileChangeKind::Modified
+            };
+            FileChange {
+                path: path.clone(),
+                kind,
+                previous_path: (kind == FileChangeKind::Renamed).then(|| from.to_string()),
+            }
+        })
+        .collect();
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// Every file under `root` by relative path, or `None` if there are more
+/// than `TREE_MAX_FILES`.
+fn tree(root: &Path) -> Option<HashMap<String, Stamp>> {
+    let mut files = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if SKIPPED_DIRS.iter().any(|skipped| entry.file_name() == *skipped) {
+                continue;
+            }
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+            if files.len() == TREE_MAX_FILES {
+                tracing::debug!(cd = %root.display(), "Workspace too large to snapshot");
+                return None;
+            }
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+            files.insert(relative, (metadata.len(), metadata.modified().ok()));
+        }
+    }
+    Some(files)
+}
+
+/// Differences between two trees, pairing a deletion and a creation with
+/// the same stamp as a rename.
+fn tree_changes(before: &HashMap<String, Stamp>, after: &HashMap<String, Stamp>) -> Vec<FileChange> {
+    let mut created: Vec<&String> = after.keys().filter(|path| !before.contains_key(*path)).collect();
+    let mut deleted: Vec<&String> = before.keys().filter(|path| !after.contains_key(*path)).collect();
+    created.sort();
+    deleted.sort();
+    let mut changes: Vec<FileChange> = after
+        .iter()
+        .filter(|(path, stamp)| before.get(*path).is_some_and(|old| old != *stamp))
+        .map(|(path, _)| FileChange::new(path.clone(), FileChangeKind::Modified))
+        .collect();
+    for path in created {
+        let stamp = after[path];
+        let source = deleted.iter().position(|old| stamp.1.is_some() && before[*old] == stamp);
+        match source {
+            Some(index) => changes.push(FileChange {
+                path: path.clone(),
+                kind: FileChangeKind::Renamed,
+                previous_path: Some(deleted.remove(index).clone()),
+            }),
+            None => changes.push(FileChange::new(path.clone(), FileChangeKind::Created)),
+        }
+    }
+    changes.extend(deleted.into_iter().map(|path| FileChange::new(path.clone(), FileChangeKind::Deleted)));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tree_snapshot_finds_every_kind_of_change() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-file-changes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "one").unwrap();
+        std::fs::write(dir.join("old.txt"), "moved").unwrap();
+        std::fs::write(dir.join("gone.txt"), "gone").unwrap();
+        let snapshot = Snapshot::capture(&dir).await.unwrap();
+        assert!(matches!(snapshot, Snapshot::Tree(_)));
+
+        std::fs::write(dir.join("src/lib.rs"), "one two").unwrap();
+        std::fs::rename(dir.join("old.txt"), dir.join("new.txt")).unwrap();
+        std::fs::remove_file(dir.join("gone.txt")).unwrap();
+        std::fs::write(dir.join("build.log"), "generated").unwrap();
+        let detected = snapshot.changes(&dir).await;
+        let change = |path: &str, kind| FileChange::new(path.to_string(), kind);
+        assert_eq!(
+            detected,
+            [
+                change("build.log", FileChangeKind::Created),
+                change("gone.txt", FileChangeKind::Deleted),
+                FileChange {
+                    previous_path: Some("old.txt".to_string()),
+                    ..change("new.txt", FileChangeKind::Renamed)
+                },
+                change("src/lib.rs", FileChangeKind::Modified),
+            ]
+        );
+
+        let reported = [
+            FileChange::new(dir.join("src/lib.rs").to_string_lossy().into_owned(), FileChangeKind::Modified),
+            change("old.txt", FileChangeKind::Deleted),
+        ];
+        let merged = merge(&dir, &reported, detected, &PathExclusions::parse(["*.log"]));
+        let paths: Vec<(&str, FileChangeKind)> = merged.iter().map(|c| (c.path.as_str(), c.kind)).collect();
+        assert_eq!(
+            paths,
+            [
+                ("src/lib.rs", FileChangeKind::Modified),
+                ("gone.txt", FileChangeKind::Deleted),
+                ("new.txt", FileChangeKind::Renamed),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_status_changes_classify_git_codes() {
+        let entries = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(path, code)| (path.to_string(), code.to_string())).collect()
+        };
+        let before = entries(&[("dirty.rs", "M"), ("same.rs", "M")]);
+        let after = entries(&[
+            ("dirty.rs", "MM"),
+            ("same.rs", "M"),
+            ("new.rs", "??"),
+            ("gone.rs", "D"),
+            ("moved.rs", "R old.rs"),
+        ]);
+        let changes: Vec<(String, FileChangeKind, Option<String>)> = status_changes(&before, &after)
+            .into_iter()
+            .map(|c| (c.path, c.kind, c.previous_path))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("dirty.rs".to_string(), FileChangeKind::Modified, None),
+                ("gone.rs".to_string(), FileChangeKind::Deleted, None),
+                ("moved.rs".to_string(), FileChangeKind::Renamed, Some("old.rs".to_string())),
+                ("new.rs".to_string(), FileChangeKind::Created, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! `post_format`: running the server's formatters on the files a write run
+//! changed, so codex's edits follow the repository's style.
+//!
+//! Formatters are configured per file extension as a command line that the
+//! file's path (relative to the workspace root, which is the working
+//! directory) is appended to:
+//!
+//! ```toml
+//! [formatters]
+//! rs = ["rustfmt", "--edition", "2024"]
+//! ts = ["prettier", "--write"]
+//! go = ["gofmt", "-w"]
+//! ```
+//!
+//! What the formatters changed is reported as a diff of its own, and is part
+//! of the run's `patch` and `commit`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::file_changes::{FileChange, FileChangeKind};
+use crate::git;
+
+/// How long a formatter may run on one file.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest formatter error output reported, in bytes.
+const MAX_ERROR_BYTES: usize = 2000;
+
+/// What `post_format` did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct FormatReport {
+    /// Files a formatter changed, relative to `cd`.
+    pub formatted: Vec<String>,
+
+    /// Unified diff of the formatters' changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+
+    /// Files whose formatter failed, left as codex wrote them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FormatError>,
+}
+
+/// A formatter that failed on a file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FormatError {
+    pub path: String,
+    /// The formatter's error output, or why it could not be run.
+    pub message: String,
+}
+
+/// Run the formatter configured for each of `changes` that still exists
+/// inside `cd`.
+pub async fn format_files(
+    cd: &Path,
+    changes: &[FileChange],
+    formatters: &BTreeMap<String, Vec<String>>,
+) -> FormatReport {
+    let mut report = FormatReport::default();
+    let mut diff = String::new();
+    for change in changes.iter().filter(|c| c.kind != FileChangeKind::Deleted) {
+        let path = Path::new(&change.path);
+        let Some(formatter) = path
+            .extension()
+            .and_then(|ext| formatters.get(ext.to_string_lossy().as_ref()))
+            .filter(|_| path.is_relative())
+        else {
+            continue;
+        };
+        let Ok(before) = std::fs::read(cd.join(path)) else {
+            continue;
+        };
+        if let Err(message) = run(cd, formatter, &change.path).await {
+            tracing::warn!(file = %change.path, error = %message, "Formatter failed");
+            report.errors.push(FormatError {
+                path: change.path.clone(),
+                message,
+            });
+            continue;
+        }
+        let after = std::fs::read(cd.join(path)).unwrap_or_default();
+        if after != before {
+            report.formatted.push(change.path.clone());
+            if let Some(file_diff) = git::diff_contents(&change.path, &before, &after).await {
+                diff.push_str(&file_diff);
+            }
+        }
+    }
+    report.diff = (!diff.is_empty()).then_some(diff);
+    report
+}
+
+/// Run `formatter` on `file` in `cd`.
+async fn run(cd: &Path, formatter: &[String], file: &str) -> Result<(), String> {
+    let Some((program, args)) = formatter.split_first() else {
+        return Err("the formatter command is empty".to_string());
+    };
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .arg(file)
+        .current_dir(cd)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = match tokio::time::timeout(FORMAT_TIMEOUT, command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("failed to run `{program}`: {e}")),
+        Err(_) => return Err(format!("`{program}` did not finish within {}s", FORMAT_TIMEOUT.as_secs())),
+    };
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut message = stderr.trim().to_string();
+    if message.len() > MAX_ERROR_BYTES {
+        let end = (0..=MAX_ERROR_BYTES).rev().find(|&i| message.is_char_boundary(i)).unwrap_or(0);
+        message.truncate(end);
+    }
+    Err(format!("`{program}` exited with {}: {message}", output.status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_formatters_run_on_changed_files() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-formatting-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "messy  \n").unwrap();
+        std::fs::write(dir.join("b.txt"), "tidy\n").unwrap();
+        std::fs::write(dir.join("c.md"), "not formatted  \n").unwrap();
+        std::fs::write(dir.join("d.bad"), "x").unwrap();
+        let formatters = BTreeMap::from([
+            // The file's path is appended, becoming `$0` of the script.
+            ("txt".to_string(), ["sh", "-c", r#"printf '%s\n' "$(sed 's/ *$//' "$0")" > "$0""#].map(String::from).to_vec()),
+            ("bad".to_string(), vec!["false".to_string()]),
+        ]);
+        let changes: Vec<FileChange> = ["a.txt", "b.txt", "c.md", "d.bad"]
+            .map(|path| FileChange {
+                path: path.to_string(),
+                kind: FileChangeKind::Modified,
+                previous_path: None,
+            })
+            .to_vec();
+
+        let report = format_files(&dir, &changes, &formatters).await;
+        assert_eq!(report.formatted, ["a.txt"]);
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "messy\n");
+        let diff = report.diff.unwrap();
+        assert!(diff.starts_with("diff --git a/a.txt b/a.txt\n"), "{diff}");
+        assert!(diff.contains("-messy  \n+messy\n"), "{diff}");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, "d.bad");
+        assert!(report.errors[0].message.starts_with("`false` exited with"), "{}", report.errors[0].message);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Git helpers for inspecting and committing what a run changed in its workspace.
+
+use std::path::Path;
+use std::process::Output;
+
+use anyhow::{Context, Result, bail};
+use tokio::process::Command;
+
+use crate::correlation;
+
+/// Run `git -C <cd> <args>`, returning `None` if git could not be started.
+async fn git(cd: &Path, args: &[&str]) -> Option<Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(cd)
+        .args(args)
+        .output()
+        .await
+        .ok()
+}
+
+/// Working-tree diff of `cd` against `HEAD`, or `None` if `cd` is not a git
+/// repository with at least one commit.
+pub async fn workspace_diff(cd: &Path) -> Option<String> {
+    let output = git(cd, &["diff", "--binary", "HEAD"]).await?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Unified diff of `paths` against `HEAD`, including untracked files as
+/// additions. `None` if `cd` is not a git repository with at least one commit.
+pub async fn diff_paths(cd: &Path, paths: &[String]) -> Option<String> {
+    let mut tracked = vec!["diff", "--no-color", "HEAD", "--"];
+    tracked.extend(paths.iter().map(String::as_str));
+    let output = git(cd, &tracked).await?;
+    if !output.status.success() {
+        return None;
+    }
+    let mut patch = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    let mut untracked = vec!["ls-files", "--others", "--exclude-standard", "--"];
+    untracked.extend(paths.iter().map(String::as_str));
+    let output = git(cd, &untracked).await?;
+    for file in String::from_utf8_lossy(&output.stdout).lines() {
+        // Exits with 1 when the files differ, which they always do here.
+        let output = git(cd, &["diff", "--no-color", "--no-index", "--", "/dev/null", file]).await?;
+        patch.push_str(&String::from_utf8_lossy(&output.stdout));
+    }
+    Some(patch)
+}
+
+/// Unified diff of the file at `path` from `before` to `after`, e.g. to show
+/// what a formatter changed. `None` if git could not be run.
+pub async fn diff_contents(path: &str, before: &[u8], after: &[u8]) -> Option<String> {
+    let dir = std::env::temp_dir().join(format!("codex-mcp-diff-{}", correlation::new_id()));
+    let (old, new) = (format!("a/{path}"), format!("b/{path}"));
+    let written = [(&old, before), (&new, after)].into_iter().try_for_each(|(name, contents)| {
+        let file = dir.join(name);
+        std::fs::create_dir_all(file.parent().unwrap_or(&dir))?;
+        std::fs::write(file, contents)
+    });
+    // Exits with 1 when the files differ.
+    let output = match written {
+        Ok(()) => git(&dir, &["diff", "--no-color", "--no-index", "--no-prefix", "--", &old, &new]).await,
+        Err(_) => None,
+    };
+    let _ = std::fs::remove_dir_all(&dir);
+    Some(String::from_utf8_lossy(&output?.stdout).into_owned())
+}
+
+/// Paths with uncommitted changes in `cd`, untracked files included, or
+/// `None` if `cd` is not a git repository with at least one commit.
+pub async fn changed_paths(cd: &Path) -> Option<Vec<String>> {
+    let tracked = git(cd, &["diff", "--name-only", "--relative", "HEAD"]).await?;
+    if !tracked.status.success() {
+        return None;
+    }
+    let untracked = git(cd, &["ls-files", "--others", "--exclude-standard"]).await?;
+    let mut paths: Vec<String> = [tracked.stdout, untracked.stdout]
+        .iter()
+        .flat_map(|stdout| String::from_utf8_lossy(stdout).lines().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Some(paths)
+}
+
+/// Paths with unresolved merge conflicts in `cd`; empty if there are none or
+/// `cd` is not a git repository.
+pub async fn unmerged_paths(cd: &Path) -> Vec<String> {
+    match git(cd, &["diff", "--name-only", "--diff-filter=U"]).await {
+        Some(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Commit SHA of `HEAD`, or `None` if `cd` is not a git repository with at
+/// least one commit.
+pub async fn head_sha(cd: &Path) -> Option<String> {
+    git_checked(cd, &["rev-parse", "--verify", "HEAD"]).await.ok()
+}
+
+/// Run git and fail with its stderr unless it exits successfully.
+async fn git_checked(cd: &Path, args: &[&str]) -> Result<String> {
+    let output = git(cd, args).await.context("failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit `paths` on `branch` (created from the current `HEAD` if missing,
+/// switched to otherwise, carrying the working-tree changes along) and return
+/// the new commit SHA.
+pub async fn commit_changes(cd: &Path, branch: &str, paths: &[String], message: &str) -> Result<String> {
+    if paths.is_empty() {
+        bail!("codex reported no changed files to commit");
+    }
+    git_checked(cd, &["check-ref-format", "--branch", branch]).await?;
+
+    let current = git_checked(cd, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    if current != branch {
+        let exists = git(cd, &["rev-parse", "--verify", "--quiet", &format!("refs/heads/{branch}")])
+            .await
+            .is_some_and(|o| o.status.success());
+        if exists {
+            git_checked(cd, &["switch", branch]).await?;
+        } else {
+            git_checked(cd, &["switch", "-c", branch]).await?;
+        }
+    }
+
+    let mut add = vec!["add", "-A", "--"];
+    add.extend(paths.iter().map(String::as_str));
+    git_checked(cd, &add).await?;
+    let mut commit = vec!["commit", "--quiet", "-m", message, "--"];
+    commit.extend(paths.iter().map(String::as_str));
+    git_checked(cd, &commit).await?;
+    git_checked(cd, &["rev-parse", "HEAD"]).await
+}
+
+/// Push `branch` to `remote`, setting it as the upstream.
+#[cfg(feature = "vcs")]
+pub async fn push_branch(cd: &Path, remote: &str, branch: &str) -> Result<()> {
+    git_checked(cd, &["push", "--quiet", "--set-upstream", remote, branch]).await?;
+    Ok(())
+}
+
+/// Fetch URL of `remote`.
+pub async fn remote_url(cd: &Path, remote: &str) -> Result<String> {
+    git_checked(cd, &["remote", "get-url", remote]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repository with one committed file `a.txt`, whose commits need no
+    /// global git identity.
+    fn init_repo(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-git-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let run = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        run(&["init", "--quiet"]);
+        run(&["config", "user.name", "t"]);
+        run(&["config", "user.email", "t@example.com"]);
+        std::fs::write(dir.join("a.txt"), "one\n").unwrap();
+        run(&["add", "a.txt"]);
+        run(&["commit", "--quiet", "-m", "init"]);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_diff_paths_includes_untracked_files() {
+        let dir = init_repo("diff");
+
+        std::fs::write(dir.join("a.txt"), "two\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "new\n").unwrap();
+        std::fs::write(dir.join("c.txt"), "unrelated\n").unwrap();
+
+        let patch = diff_paths(&dir, &["a.txt".to_string(), "b.txt".to_string()])
+            .await
+            .unwrap();
+        assert!(patch.contains("-one\n+two\n"), "{patch}");
+        assert!(patch.contains("+new\n"), "{patch}");
+        assert!(!patch.contains("unrelated"), "{patch}");
+        assert!(workspace_diff(&dir).await.unwrap().contains("+two"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_changes_on_new_branch() {
+        let dir = init_repo("commit");
+        std::fs::write(dir.join("a.txt"), "two\n").unwrap();
+        std::fs::write(dir.join("other.txt"), "not codex\n").unwrap();
+
+        let sha = commit_changes(&dir, "codex/fix", &["a.txt".to_string()], "Fix a")
+            .await
+            .unwrap();
+        assert_eq!(sha.len(), 40);
+        assert_eq!(head_sha(&dir).await.as_deref(), Some(sha.as_str()));
+        assert_eq!(git_checked(&dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await.unwrap(), "codex/fix");
+        let files = git_checked(&dir, &["show", "--name-only", "--format=", "HEAD"]).await.unwrap();
+        assert_eq!(files, "a.txt");
+
+        assert!(commit_changes(&dir, "bad..name", &["other.txt".to_string()], "x").await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unmerged_paths_lists_conflicts() {
+        let dir = init_repo("unmerged");
+        assert!(unmerged_paths(&dir).await.is_empty());
+        let base = git_checked(&dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await.unwrap();
+
+        std::fs::write(dir.join("a.txt"), "ours\n").unwrap();
+        commit_changes(&dir, "ours", &["a.txt".to_string()], "Ours").await.unwrap();
+        git_checked(&dir, &["checkout", "--quiet", &base]).await.unwrap();
+        std::fs::write(dir.join("a.txt"), "theirs\n").unwrap();
+        commit_changes(&dir, "theirs", &["a.txt".to_string()], "Theirs").await.unwrap();
+        assert!(git_checked(&dir, &["merge", "--quiet", "ours"]).await.is_err());
+        assert_eq!(unmerged_paths(&dir).await, ["a.txt"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Localized error messages, so clients used in Chinese do not get English
+//! errors mixed into otherwise Chinese replies.
+//!
+//! English is the `Display` text of [`CodexError`]; the other catalogs match
+//! every variant exhaustively, so a new error cannot be added without its
+//! translation. The locale is the server's `locale` setting if set, otherwise
+//! the run's `language` if it names a supported language, otherwise the
+//! client's locale environment (`LC_ALL`, `LC_MESSAGES`, `LANG`), which a
+//! stdio server inherits from the client that launched it.
+
+use serde::Deserialize;
+
+use crate::error::CodexError;
+
+/// Language of the server's error messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Locale {
+    /// The locale named by a language tag (`zh-CN`), a POSIX locale
+    /// (`zh_CN.UTF-8`), or a language name (`Chinese`), if supported.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        let language = name.split(['-', '_', '.', '@']).next().unwrap_or_default();
+        match language {
+            "en" | "english" | "c" | "posix" => Some(Locale::En),
+            "zh" | "chinese" | "中文" | "简体中文" | "繁體中文" => Some(Locale::Zh),
+            _ => None,
+        }
+    }
+
+    /// The locale of `configured`, else of `language`, else of the environment.
+    pub fn resolve(configured: Option<Locale>, language: Option<&str>) -> Self {
+        configured
+            .or_else(|| language.and_then(Locale::parse))
+            .or_else(|| {
+                ["LC_ALL", "LC_MESSAGES", "LANG"]
+                    .iter()
+                    .filter_map(|var| std::env::var(var).ok())
+                    .find(|value| !value.is_empty())
+                    .and_then(|value| Locale::parse(&value))
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// `error` as a message in `locale`.
+pub fn message(error: &CodexError, locale: Locale) -> String {
+    match locale {
+        Locale::En => error.to_string(),
+        Locale::Zh => zh(error),
+    }
+}
+
+fn zh(error: &CodexError) -> String {
+    use CodexError::*;
+
+    match error {
+        ExecutableNotFound => "未找到 Codex 可执行文件。请确认已安装 `codex` 且位于 PATH 中（或设置 `codex_path`）。".to_string(),
+        UnsupportedByCodex(parameter) => format!("已安装的 codex CLI 不支持 `{parameter}`，请升级 codex 后再使用。"),
+        OssServerUnreachable { addr, reason } => {
+            format!("无法连接位于 {addr} 的本地模型服务：{reason}。请先启动它（如 `ollama serve`）或设置 `oss_server`。")
+        }
+        OsSandboxUnavailable(wrapper) => format!("`os_sandbox` 设置为 `{wrapper}`，但本机未安装该工具。"),
+        RunAsUnavailable(reason) => format!("无法以 `run_as_user` 运行 codex：{reason}"),
+        InvalidWorkingDirectory(path) => format!("工作目录不存在或不是目录：{path:?}"),
+        UnknownWorkspace { name, known } => {
+            let known = if known.is_empty() { String::new() } else { format!(" 已配置的工作区：{}", known.join("、")) };
+            format!("未知的工作区 {name:?}。{known}")
+        }
+        WorkspaceNotAllowed(path) => format!("工作目录不在任何已配置的工作区内：{path:?}"),
+        CreateCdNotAllowed => "此服务器未启用 `create_cd_if_missing`（参见 `allow_create_cd`）。".to_string(),
+        CommitRequiresWriteAccess => "`commit` 需要可写的运行（`workspace-write` 或 `danger-full-access` 沙箱）。".to_string(),
+        PostFormatUnavailable => "`post_format` 需要服务端配置格式化工具（见 `formatters`）。".to_string(),
+        PullRequestsUnsupported => "`commit.pull_request` 需要以 `vcs` 特性构建的服务器。".to_string(),
+        InstructionsFileNotFound(path) => format!("基础指令文件不存在：{path:?}"),
+        InstructionsFileNotAllowed(path) => format!("基础指令文件不在允许的目录内：{path:?}"),
+        WritableRootsRequireWorkspaceWrite => "`writable_roots` 需要 `workspace-write` 沙箱。".to_string(),
+        WritableRootNotAllowed(path) => format!("可写目录不存在或不在允许的目录内：{path:?}"),
+        StreamToNotAllowed(path) => format!("`stream_to` 路径不在服务器的 allowed_stream_dirs 内：{path:?}"),
+        StreamToFailed { path, reason } => format!("无法打开 `stream_to` 路径 {path:?}：{reason}"),
+        ImageNotFound(path) => format!("图片文件不存在：{path:?}"),
+        UnsupportedImageFormat(path) => format!("不支持的图片格式（应为 PNG、JPEG、GIF 或 WebP）：{path:?}"),
+        ImageTooLarge { path, size, max } => format!("图片 {path:?} 大小为 {size} 字节，超过 {max} 字节的上限"),
+        ContextFileNotAllowed(path) => format!("上下文文件不存在或不在工作目录及上传目录内：{path:?}"),
+        WorkspacePathNotAllowed(path) => format!("路径不存在或不在工作目录内：{path:?}"),
+        PathExcluded(path) => format!("路径已被工作区的忽略文件或服务端的 `excluded_paths` 排除：{path:?}"),
+        UploadNotFound(upload_id) => format!("上传不存在或已完成：{upload_id}"),
+        InvalidUpload(reason) => format!("无效的上传：{reason}"),
+        #[cfg(feature = "screenshot")]
+        ScreenshotFailed(reason) => format!("截图失败：{reason}"),
+        PromptResourceUnavailable { reference, reason } => format!("无法从 {reference:?} 读取提示词：{reason}"),
+        InvalidOutputSchema(reason) => format!("无效的 `expected_output_schema`：{reason}"),
+        OutputSchemaMismatch(problem) => format!("最终消息不符合 `expected_output_schema`：{problem}"),
+        FocusPathNotFound(path) => format!("重点路径不存在：{path:?}"),
+        FocusPathOutsideWorkspace(path) => format!("重点路径在工作目录之外：{path:?}"),
+        InvalidLanguage(language) => format!("无效的 `language` {language:?}：应为语言名称或代码，如 `Japanese` 或 `ja`。"),
+        ConflictingResumeOptions => "`SESSION_ID` 与 `resume_last` 不能同时使用。".to_string(),
+        SummarizeRequiresSession => "`summarize_session` 需要提供 `SESSION_ID`。".to_string(),
+        SessionSummaryFailed { session_id, reason } => format!("总结会话 {session_id} 失败：{reason}"),
+        ResumeLastUnavailable => "会话按客户端隔离时无法使用 `resume_last`，请改为传入 `SESSION_ID`。".to_string(),
+        InvalidSessionId(session_id) => {
+            format!("无效的 `SESSION_ID` {session_id:?}：应为 UUID，如 019bc4ce-610d-7f50-bd2a-fb5b8ac83b61。")
+        }
+        SessionNotFound { session_id, alternatives } => {
+            let alternatives = if alternatives.is_empty() {
+                String::new()
+            } else {
+                format!(" 此工作区最近的会话：{}", alternatives.join("、"))
+            };
+            format!("未找到会话：{session_id}。{alternatives}")
+        }
+        InsufficientDiskSpace { path, free_mb, required_mb } => format!(
+            "{path:?} 所在文件系统仅剩 {free_mb} MiB 可用空间，低于 `min_free_disk_mb`（{required_mb} MiB）。\
+             请先释放空间（如删除构建产物或旧的运行产物）再启动可写运行。"
+        ),
+        UnmergedConflicts(paths) => format!(
+            "工作区存在未解决的 git 冲突：{}。请先解决冲突（或中止合并/变基）再启动可写运行。",
+            paths.join("、")
+        ),
+        WorkspaceBusy(path) => format!("工作区繁忙：{path:?} 中已有另一个可写的 codex 运行"),
+        PolicyDenied(violations) => {
+            let violations: Vec<String> = violations.iter().map(|v| format!("{}（规则 {:?}）", v.reason, v.rule)).collect();
+            format!("被策略拒绝：{}", violations.join("；"))
+        }
+        RunNotFound(run_id) => format!("未找到运行：{run_id}"),
+        RunInterrupted => "此运行进行期间服务器已停止。".to_string(),
+        RunInProgress(run_id) => format!("运行 {run_id} 仍在进行中，请用 `codex_poll` 查询。"),
+        InvalidCompareModels(reason) => format!("要比较的模型无效：{reason}"),
+        CompareSessionUnsupported => "`codex_compare` 不能恢复会话，请省略 `SESSION_ID` 和 `resume_last`。".to_string(),
+        NothingToReview(session_id) => format!("会话 {session_id} 的工作区中没有可审查的未提交改动。"),
+        EmptyMemoryNote => "要记录到项目记忆中的笔记为空。".to_string(),
+        EmptySearchQuery => "搜索关键词为空。".to_string(),
+        PurgeFailed { session_id, reason } => {
+            format!("清除会话 {session_id} 失败：{reason}。已删除的数据不会恢复，请重试以完成清除。")
+        }
+        BudgetExceeded(reason) => format!("超出预算：{reason}"),
+        ModelPriceUnknown(model) => format!("`model_prices` 中未配置模型 {model:?} 的价格，无法执行其预算限制。"),
+        RateLimited(secs) => format!("模型服务正在限流，请在 {secs} 秒后重试。"),
+        IdempotencyKeyInProgress(key) => format!("幂等键 {key:?} 对应的运行仍在进行中。"),
+        IdempotencyKeyReused(key) => format!("幂等键 {key:?} 已用于另一个不同的请求。"),
+        StdoutCaptureFailed => "无法获取 codex 的标准输出（管道不可用）。".to_string(),
+        Io(e) => format!("运行 codex 时发生 I/O 错误：{e}"),
+        JsonParseError(e) => format!("JSON 解析失败：{e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_selection_and_messages() {
+        assert_eq!(Locale::parse("zh-CN"), Some(Locale::Zh));
+        assert_eq!(Locale::parse("zh_TW.UTF-8"), Some(Locale::Zh));
+        assert_eq!(Locale::parse("Chinese"), Some(Locale::Zh));
+        assert_eq!(Locale::parse("en_US.UTF-8"), Some(Locale::En));
+        assert_eq!(Locale::parse("ja"), None);
+        assert_eq!(Locale::resolve(Some(Locale::En), Some("zh")), Locale::En);
+        assert_eq!(Locale::resolve(None, Some("zh-Hans")), Locale::Zh);
+
+        let error = CodexError::RateLimited(30);
+        assert_eq!(message(&error, Locale::En), "The model provider is rate limiting requests; retry in 30 seconds.");
+        assert_eq!(message(&error, Locale::Zh), "模型服务正在限流，请在 30 秒后重试。");
+    }
+}
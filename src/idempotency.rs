@@ -0,0 +1,227 @@
+//! Idempotency keys, so a client retrying a timed-out call gets the original
+//! run's result instead of starting (and paying for) the run again.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::codex::CodexResult;
+use crate::state::{self, Store, load, save, unix_now};
+
+/// Key of the completed keyed runs in the state store.
+const IDEMPOTENCY_KEY: &str = "idempotency";
+
+/// Completed keyed runs kept; the oldest are evicted beyond this.
+const MAX_ENTRIES: usize = 1000;
+
+/// Age after which a claim still in progress in a shared store is taken to
+/// belong to a replica that died mid-run, and is dropped.
+const STALE_CLAIM_SECS: u64 = 24 * 60 * 60;
+
+/// Stable 64-bit FNV-1a fingerprint of `text`, as 16 hex digits.
+///
+/// Not cryptographic: it identifies identical prompts and results, it does
+/// not protect them.
+pub fn fingerprint(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Fingerprint of the request the key was first used with.
+    request: String,
+    /// `None` while the run is in progress.
+    result: Option<CodexResult>,
+    created_at: u64,
+}
+
+/// What to do with a keyed request.
+#[derive(Debug)]
+pub enum Claim {
+    /// First use of the key: run, then `complete` or `release` it.
+    New,
+    /// A run with this key already succeeded.
+    Completed(Box<CodexResult>),
+    /// A run with this key is still going.
+    InProgress,
+    /// The key was used for a different request.
+    Mismatch,
+}
+
+/// Keyed runs, with completed ones optionally persisted in the state directory.
+/// In a shared store (Redis) claims in progress are stored too, so a retry
+/// reaching another replica sees them.
+#[derive(Debug, Default)]
+pub struct IdempotencyStore {
+    store: Option<Store>,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Open the store, loading completed runs persisted in `store`.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let entries = load(&*store, IDEMPOTENCY_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store: Some(store),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Claim `key` for a request with fingerprint `request`.
+    pub fn claim(&self, key: &str, request: &str) -> Claim {
+        self.update_shared(|entries| claim(entries, key, request))
+            .unwrap_or_else(|| claim(&mut self.entries.lock().unwrap(), key, request))
+    }
+
+    /// Store the successful result of the run that claimed `key`.
+    pub fn complete(&self, key: &str, result: &CodexResult) {
+        let complete = |entries: &mut HashMap<String, Entry>| {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.result = Some(result.clone());
+            }
+            evict_oldest(entries);
+        };
+        if self.update_shared(complete).is_none() {
+            let mut entries = self.entries.lock().unwrap();
+            complete(&mut entries);
+            self.persist(&entries);
+        }
+    }
+
+    /// Give up `key` after a failed run, so a retry runs again.
+    pub fn release(&self, key: &str) {
+        if self.update_shared(|entries| entries.remove(key)).is_none() {
+            self.entries.lock().unwrap().remove(key);
+        }
+    }
+
+    /// Forget the completed runs of `session_id`, returning how many.
+    pub fn remove_session(&self, session_id: &str) -> Result<usize> {
+        let remove = |entries: &mut HashMap<String, Entry>| {
+            let before = entries.len();
+            entries.retain(|_, e| e.result.as_ref().is_none_or(|r| r.session_id.as_deref() != Some(session_id)));
+            before - entries.len()
+        };
+        if let Some(store) = self.store.as_ref().filter(|store| store.shared()) {
+            return Ok(state::update(&**store, IDEMPOTENCY_KEY, remove)?.1);
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let removed = remove(&mut entries);
+        if removed > 0 && let Some(ref store) = self.store {
+            let completed: HashMap<&String, &Entry> = entries.iter().filter(|(_, e)| e.result.is_some()).collect();
+            save(&**store, IDEMPOTENCY_KEY, &completed)?;
+        }
+        Ok(removed)
+    }
+
+    /// Apply `f` atomically to the entries in a shared store, dropping stale
+    /// claims first. `None` when the store is not shared or the update failed,
+    /// in which case the caller falls back to the local entries.
+    fn update_shared<R>(&self, mut f: impl FnMut(&mut HashMap<String, Entry>) -> R) -> Option<R> {
+        let store = self.store.as_ref().filter(|store| store.shared())?;
+        let now = unix_now();
+        let result = state::update(&**store, IDEMPOTENCY_KEY, |entries: &mut HashMap<String, Entry>| {
+            entries.retain(|_, e| e.result.is_some() || now.saturating_sub(e.created_at) < STALE_CLAIM_SECS);
+            f(entries)
+        });
+        match result {
+            Ok((_, result)) => Some(result),
+            Err(e) => {
+                tracing::warn!(key = IDEMPOTENCY_KEY, error = %e, "Failed to update shared idempotency keys");
+                None
+            }
+        }
+    }
+
+    fn persist(&self, entries: &HashMap<String, Entry>) {
+        let Some(ref store) = self.store else {
+            return;
+        };
+        let completed: HashMap<&String, &Entry> = entries.iter().filter(|(_, e)| e.result.is_some()).collect();
+        if let Err(e) = save(&**store, IDEMPOTENCY_KEY, &completed) {
+            tracing::warn!(key = IDEMPOTENCY_KEY, error = %e, "Failed to persist idempotency keys");
+        }
+    }
+}
+
+/// Claim `key` in `entries`.
+fn claim(entries: &mut HashMap<String, Entry>, key: &str, request: &str) -> Claim {
+    match entries.get(key) {
+        Some(entry) if entry.request != request => Claim::Mismatch,
+        Some(Entry { result: Some(result), .. }) => Claim::Completed(Box::new(result.clone())),
+        Some(_) => Claim::InProgress,
+        None => {
+            entries.insert(
+                key.to_string(),
+                Entry {
+                    request: request.to_string(),
+                    result: None,
+                    created_at: unix_now(),
+                },
+            );
+            Claim::New
+        }
+    }
+}
+
+fn evict_oldest(entries: &mut HashMap<String, Entry>) {
+    if entries.len() <= MAX_ENTRIES {
+        return;
+    }
+    let mut completed: Vec<(u64, String)> = entries
+        .iter()
+        .filter(|(_, e)| e.result.is_some())
+        .map(|(key, e)| (e.created_at, key.clone()))
+        .collect();
+    completed.sort();
+    let excess = entries.len() - MAX_ENTRIES;
+    for (_, key) in completed.into_iter().take(excess) {
+        entries.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FileStore;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        assert_eq!(fingerprint(""), "cbf29ce484222325");
+        assert_eq!(fingerprint("a"), "af63dc4c8601ec8c");
+        assert_ne!(fingerprint("fix the bug"), fingerprint("fix the bugs"));
+    }
+
+    #[test]
+    fn test_claim_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-idempotency-{}", std::process::id()));
+        let store = IdempotencyStore::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        assert!(matches!(store.claim("k", "req"), Claim::New));
+        assert!(matches!(store.claim("k", "req"), Claim::InProgress));
+        assert!(matches!(store.claim("k", "other"), Claim::Mismatch));
+
+        store.release("k");
+        assert!(matches!(store.claim("k", "req"), Claim::New));
+        store.complete(
+            "k",
+            &CodexResult {
+                success: true,
+                ..Default::default()
+            },
+        );
+
+        let reopened = IdempotencyStore::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        assert!(matches!(reopened.claim("k", "req"), Claim::Completed(r) if r.success));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
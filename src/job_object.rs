@@ -0,0 +1,72 @@
+//! Windows Job Objects, so stopping a run stops everything codex started.
+//!
+//! Killing a process on Windows leaves its children running, which
+//! `kill_on_drop` alone cannot help with. Each codex process is assigned to
+//! a job that kills all of its processes when its last handle closes, i.e.
+//! when the run ends, however it ends. Processes codex starts are assigned to
+//! the job automatically; only those started in the moment between spawning
+//! codex and assigning it can escape.
+
+use std::io;
+use std::ptr;
+
+use tokio::process::Child;
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    JobObjectExtendedLimitInformation, SetInformationJobObject,
+};
+
+/// A job holding a codex process and its descendants, all killed on drop.
+#[derive(Debug)]
+pub struct JobObject(HANDLE);
+
+// SAFETY: job handles may be used and closed from any thread.
+unsafe impl Send for JobObject {}
+unsafe impl Sync for JobObject {}
+
+impl JobObject {
+    /// Put `child` in a new kill-on-close job.
+    pub fn assign(child: &Child) -> io::Result<Self> {
+        let process = child
+            .raw_handle()
+            .ok_or_else(|| io::Error::other("codex has already exited"))?;
+        // SAFETY: null attributes and name create an anonymous job with
+        // default security.
+        let handle = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // Closes the job if configuring it fails.
+        let job = Self(handle);
+
+        // SAFETY: the struct is plain old data, valid when zeroed.
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        // SAFETY: `limits` is valid for reads of the given size for the call.
+        let configured = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                (&raw const limits).cast(),
+                size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if configured == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: both handles are open: the job is owned, and the process
+        // handle is owned by `child`, which is borrowed for the call.
+        if unsafe { AssignProcessToJobObject(job.0, process) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(job)
+    }
+}
+
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        // SAFETY: the handle is owned and closed exactly once.
+        unsafe { CloseHandle(self.0) };
+    }
+}
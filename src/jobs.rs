@@ -0,0 +1,384 @@
+//! Background codex runs started with `codex_start`, for clients whose tool
+//! calls time out before a run finishes.
+//!
+//! With a state directory, every run is persisted as `jobs/<run_id>` from
+//! the moment it starts, so a run interrupted by a crash or restart is
+//! reported as failed (or resumed) afterwards instead of vanishing.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::codex::{CodexParams, CodexResult};
+use crate::correlation;
+use crate::events::{self, PlanStep, RunObserver};
+use crate::state::{Store, load, save, unix_now, unix_now_millis};
+
+/// Namespace in the state store holding one record per run.
+const JOBS_NAMESPACE: &str = "jobs";
+
+/// Finished runs kept for `codex_result`; the oldest are evicted beyond this.
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// Distinguishes runs started within the same millisecond.
+static JOB_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Persisted description of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JobRecord {
+    id: String,
+    /// Correlation ID of the run in log lines, given when it is started so
+    /// `codex_poll` reports it from the beginning.
+    #[serde(default = "correlation::new_id")]
+    correlation_id: String,
+    /// Client that started the run, on multi-client transports.
+    owner: Option<String>,
+    started_at: u64,
+    /// Parameters the run was started with, needed to resume it.
+    params: CodexParams,
+    /// Codex session of the run, known once codex has started it.
+    session_id: Option<String>,
+    finished_at: Option<u64>,
+    result: Option<CodexResult>,
+}
+
+/// Whether a background run has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+}
+
+/// A run started in the background.
+#[derive(Debug)]
+pub struct Job {
+    /// Store and key the record is persisted under.
+    store: Option<(Store, String)>,
+    events: AtomicUsize,
+    last_event_type: Mutex<Option<String>>,
+    plan: Mutex<Vec<PlanStep>>,
+    record: Mutex<JobRecord>,
+}
+
+impl Job {
+    fn new(store: Option<(Store, String)>, record: JobRecord) -> Self {
+        Self {
+            store,
+            events: AtomicUsize::new(0),
+            last_event_type: Mutex::new(None),
+            plan: Mutex::new(Vec::new()),
+            record: Mutex::new(record),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.record.lock().unwrap().id.clone()
+    }
+
+    pub fn correlation_id(&self) -> String {
+        self.record.lock().unwrap().correlation_id.clone()
+    }
+
+    pub fn owner(&self) -> Option<String> {
+        self.record.lock().unwrap().owner.clone()
+    }
+
+    pub fn params(&self) -> CodexParams {
+        self.record.lock().unwrap().params.clone()
+    }
+
+    pub fn session_id(&self) -> Option<String> {
+        self.record.lock().unwrap().session_id.clone()
+    }
+
+    fn finished_at(&self) -> Option<u64> {
+        self.record.lock().unwrap().finished_at
+    }
+
+    /// Store the finished run's result.
+    pub fn finish(&self, result: CodexResult) {
+        let mut record = self.record.lock().unwrap();
+        if result.session_id.is_some() {
+            record.session_id.clone_from(&result.session_id);
+        }
+        record.finished_at = Some(unix_now());
+        record.result = Some(result);
+        self.persist(&record);
+    }
+
+    /// The result, once the run has finished.
+    pub fn result(&self) -> Option<CodexResult> {
+        self.record.lock().unwrap().result.clone()
+    }
+
+    /// Status report returned by `codex_poll`.
+    pub fn status(&self) -> JobStatusReport {
+        let last_event_type = self.last_event_type.lock().unwrap().clone();
+        let plan = self.plan.lock().unwrap().clone();
+        let record = self.record.lock().unwrap();
+        JobStatusReport {
+            run_id: record.id.clone(),
+            correlation_id: record.correlation_id.clone(),
+            status: if record.result.is_some() { JobStatus::Completed } else { JobStatus::Running },
+            session_id: record.session_id.clone(),
+            success: record.result.as_ref().map(|r| r.success),
+            started_at: record.started_at,
+            finished_at: record.finished_at,
+            elapsed_secs: record.finished_at.unwrap_or_else(unix_now).saturating_sub(record.started_at),
+            events: self.events.load(Ordering::Relaxed),
+            last_event_type,
+            plan,
+        }
+    }
+
+    fn persist(&self, record: &JobRecord) {
+        if let Some((ref store, ref key)) = self.store
+            && let Err(e) = save(&**store, key, record)
+        {
+            tracing::warn!(key, error = %e, "Failed to persist background run");
+        }
+    }
+}
+
+impl RunObserver for Job {
+    fn observe_line(&self, line: &str) {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(line) else {
+            return;
+        };
+        self.events.fetch_add(1, Ordering::Relaxed);
+        if let Some(kind) = event["type"].as_str() {
+            *self.last_event_type.lock().unwrap() = Some(kind.to_string());
+        }
+        if let Some(steps) = events::plan(&event) {
+            *self.plan.lock().unwrap() = steps;
+        }
+        if let Some(id) = event["thread_id"].as_str() {
+            let mut record = self.record.lock().unwrap();
+            if record.session_id.as_deref() != Some(id) {
+                record.session_id = Some(id.to_string());
+                // Persisted right away so the run can be resumed after a crash.
+                self.persist(&record);
+            }
+        }
+    }
+}
+
+/// Result returned by the codex_poll tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobStatusReport {
+    pub run_id: String,
+
+    /// ID of the run in the server's log lines.
+    pub correlation_id: String,
+
+    pub status: JobStatus,
+
+    /// Codex session of the run, known once codex has started it.
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// Whether the run succeeded, once it has finished.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+
+    /// Unix timestamps of when the run started and finished.
+    pub started_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+
+    pub elapsed_secs: u64,
+
+    /// Number of codex events received so far.
+    pub events: usize,
+
+    /// Type of the most recent codex event, e.g. `item.completed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_event_type: Option<String>,
+
+    /// The model's plan as of its latest update while the run is going.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plan: Vec<PlanStep>,
+}
+
+/// Background runs, optionally persisted in the state directory.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    store: Option<Store>,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+    /// Runs that were still running when the server last stopped.
+    interrupted: Mutex<Vec<Arc<Job>>>,
+}
+
+impl JobTable {
+    /// Open the table, loading runs persisted in `store`.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let mut jobs = HashMap::new();
+        let mut interrupted = Vec::new();
+        for key in store.list(JOBS_NAMESPACE)? {
+            let Some(record) = load::<JobRecord>(&*store, &key)? else {
+                continue;
+            };
+            let job = Arc::new(Job::new(Some((store.clone(), key)), record));
+            if job.result().is_none() {
+                interrupted.push(job.clone());
+            }
+            jobs.insert(job.id(), job);
+        }
+        Ok(Self {
+            store: Some(store),
+            jobs: Mutex::new(jobs),
+            interrupted: Mutex::new(interrupted),
+        })
+    }
+
+    /// Register and persist a new running job.
+    pub fn create(&self, owner: Option<String>, params: CodexParams) -> Arc<Job> {
+        let seq = JOB_SEQ.fetch_add(1, Ordering::Relaxed);
+        let id = format!("run-{}-{seq}", unix_now_millis());
+        let record = JobRecord {
+            id: id.clone(),
+            correlation_id: correlation::new_id(),
+            owner,
+            started_at: unix_now(),
+            params,
+            session_id: None,
+            finished_at: None,
+            result: None,
+        };
+        let key = format!("{JOBS_NAMESPACE}/{id}");
+        let job = Arc::new(Job::new(self.store.clone().map(|store| (store, key)), record));
+        job.persist(&job.record.lock().unwrap());
+
+        let mut jobs = self.jobs.lock().unwrap();
+        evict_finished(&mut jobs);
+        jobs.insert(id, job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Forget the finished background runs of `session_id`, returning how many.
+    pub fn remove_session(&self, session_id: &str) -> Result<usize> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let ids: Vec<String> = jobs
+            .iter()
+            .filter(|(_, job)| job.finished_at().is_some() && job.session_id().as_deref() == Some(session_id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &ids {
+            if let Some(job) = jobs.remove(id)
+                && let Some((ref store, ref key)) = job.store
+            {
+                store.remove(key)?;
+            }
+        }
+        Ok(ids.len())
+    }
+
+    /// Runs left unfinished by the previous server process, returned once.
+    pub fn take_interrupted(&self) -> Vec<Arc<Job>> {
+        std::mem::take(&mut *self.interrupted.lock().unwrap())
+    }
+}
+
+/// Drop the oldest finished jobs so that at most `MAX_FINISHED_JOBS` remain.
+fn evict_finished(jobs: &mut HashMap<String, Arc<Job>>) {
+    let mut finished: Vec<(u64, String)> = jobs
+        .iter()
+        .filter_map(|(id, job)| job.finished_at().map(|at| (at, id.clone())))
+        .collect();
+    if finished.len() < MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+        if let Some(job) = jobs.remove(id)
+            && let Some((ref store, ref key)) = job.store
+        {
+            let _ = store.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::PlanStatus;
+    use crate::state::FileStore;
+
+    fn params() -> CodexParams {
+        serde_json::from_value(serde_json::json!({"PROMPT": "p", "cd": "/w"})).unwrap()
+    }
+
+    #[test]
+    fn test_job_progress_and_completion() {
+        let table = JobTable::default();
+        let job = table.create(None, params());
+        job.observe_line(r#"{"type":"thread.started","thread_id":"abc"}"#);
+        job.observe_line("not json");
+        job.observe_line(r#"{"msg":{"type":"plan_update","plan":[{"step":"Read","status":"completed"},{"step":"Fix","status":"in_progress"}]}}"#);
+        job.observe_line(r#"{"type":"turn.started"}"#);
+
+        let status = table.get(&job.id()).unwrap().status();
+        assert_eq!(status.status, JobStatus::Running);
+        assert_eq!(status.session_id.as_deref(), Some("abc"));
+        assert_eq!(status.events, 3);
+        assert_eq!(status.last_event_type.as_deref(), Some("turn.started"));
+        let statuses: Vec<PlanStatus> = status.plan.iter().map(|step| step.status).collect();
+        assert_eq!(statuses, [PlanStatus::Completed, PlanStatus::InProgress]);
+        assert!(job.result().is_none());
+
+        job.finish(CodexResult {
+            success: true,
+            ..Default::default()
+        });
+        let status = job.status();
+        assert_eq!(status.status, JobStatus::Completed);
+        assert_eq!(status.success, Some(true));
+        assert!(job.result().unwrap().success);
+    }
+
+    #[test]
+    fn test_unfinished_jobs_are_interrupted_after_reopen() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-jobs-{}", std::process::id()));
+        let table = JobTable::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        let running = table.create(Some("uid:1000".to_string()), params());
+        running.observe_line(r#"{"type":"thread.started","thread_id":"abc"}"#);
+        let done = table.create(None, params());
+        done.finish(CodexResult::default());
+        drop(table);
+
+        let reopened = JobTable::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        let interrupted = reopened.take_interrupted();
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].id(), running.id());
+        assert_eq!(interrupted[0].session_id().as_deref(), Some("abc"));
+        assert_eq!(interrupted[0].owner().as_deref(), Some("uid:1000"));
+        assert!(reopened.take_interrupted().is_empty());
+        assert!(reopened.get(&done.id()).unwrap().result().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_finished_jobs_are_evicted() {
+        let table = JobTable::default();
+        let running = table.create(None, params());
+        for _ in 0..MAX_FINISHED_JOBS + 5 {
+            table.create(None, params()).finish(CodexResult::default());
+        }
+        let jobs = table.jobs.lock().unwrap();
+        assert!(jobs.contains_key(&running.id()), "running jobs are never evicted");
+        assert!(jobs.len() <= MAX_FINISHED_JOBS + 1);
+    }
+}
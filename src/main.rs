@@ -2,28 +2,165 @@
 //!
 //! A Model Context Protocol server that wraps the Codex CLI for AI-assisted coding tasks.
 
+mod approval;
+mod artifacts;
+mod capabilities;
+mod child_env;
+mod cli;
 mod codex;
+mod codex_home;
+mod completions;
+mod compression;
+mod config;
+mod correlation;
+mod cost;
+mod environment;
 mod error;
+mod events;
+mod exclusions;
+mod exec;
+mod exit;
+mod explain;
+mod file_changes;
+mod formatting;
+mod git;
+mod i18n;
+mod idempotency;
+#[cfg(windows)]
+mod job_object;
+mod jobs;
+mod log_format;
+mod memory;
+mod model_stats;
+mod noise;
+mod orphans;
+mod os_sandbox;
+mod output_schema;
+mod policy;
+mod post_process;
+mod preflight;
+mod prompt;
+mod random;
+mod registry;
+#[cfg(feature = "redis")]
+mod redis;
+mod reload;
+mod resources;
+mod review;
+mod rules;
+mod run_as;
+mod scheduler;
+mod search;
+mod self_test;
+mod server_info;
+mod shutdown;
+#[cfg(feature = "screenshot")]
+mod screenshot;
+mod secrets;
+mod state;
+mod status;
+mod tee;
+#[cfg(unix)]
+mod transport;
+mod tuning;
+mod throttle;
+mod timings;
+mod uploads;
+mod usage;
+#[cfg(feature = "vcs")]
+mod vcs;
+mod workspace;
+mod workspace_files;
 
 use anyhow::Result;
 use rmcp::{transport::stdio, ServiceExt};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
+use crate::cli::{Cli, Command, Transport};
 use crate::codex::CodexServer;
+use crate::config::ServerConfig;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing with environment filter
+    let cli = Cli::parse()?;
+    if cli.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    match cli.command {
+        Command::Completions(shell) => {
+            print!("{}", completions::completions(shell));
+            return Ok(());
+        }
+        Command::Man => {
+            print!("{}", completions::man_page());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = ServerConfig::load()?;
+
+    // Initialize tracing with environment filter, falling back to `log_level`,
+    // which can be changed by reloading the config.
+    let filter = EnvFilter::try_from_default_env().or_else(|_| {
+        EnvFilter::try_new(config.log_level.as_deref().unwrap_or(reload::DEFAULT_LOG_FILTER))
+    })?;
+    let (filter, log_filter) = tracing_subscriber::reload::Layer::new(filter);
     tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
-        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(filter)
+        .with(log_format::layer(config.log_format))
         .init();
 
     tracing::info!("Starting Codex MCP Server");
 
-    let server = CodexServer::new();
-    let service = server.serve(stdio()).await?;
-    service.waiting().await?;
+    let server = CodexServer::new(config.clone())?;
+    server.refresh_capabilities().await;
+    let transport = match cli.command {
+        Command::Serve(transport) => transport,
+        Command::Exec(args) => {
+            if !exec::run(&server, &args).await? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Completions(_) | Command::Man => unreachable!("handled before startup"),
+    };
+    if cli.self_test || config.self_test {
+        server.self_test().await?;
+    }
+    server.clean_up_orphans().await;
+    server.recover_interrupted_runs();
+    server.watch_capabilities();
+    server.enforce_retention();
+    scheduler::start(&server, &config.schedules)?;
+    if let Some(path) = ServerConfig::path() {
+        reload::spawn(
+            server.clone(),
+            path,
+            Box::new(move |filter| Ok(log_filter.reload(EnvFilter::try_new(filter)?)?)),
+        );
+    }
+    match transport {
+        Transport::Stdio => {
+            let service = server.clone().serve(stdio()).await?;
+            tokio::select! {
+                quit = service.waiting() => tracing::info!(reason = ?quit?, "MCP client disconnected, shutting down"),
+                _ = shutdown::signal() => tracing::info!("Received shutdown signal"),
+            }
+            server.shut_down().await;
+            // The blocking stdin reader may still be waiting for input that
+            // will never come; don't wait for it on the way out.
+            std::process::exit(0);
+        }
+        #[cfg(unix)]
+        Transport::Unix { socket, mode } => {
+            transport::serve_unix(server.clone(), &socket, mode).await?;
+            server.shut_down().await;
+        }
+        #[cfg(not(unix))]
+        Transport::Unix { .. } => anyhow::bail!("the unix transport is only available on Unix"),
+    }
 
     Ok(())
 }
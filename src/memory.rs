@@ -0,0 +1,124 @@
+//! Project memory: a notes file in each workspace, maintained through the
+//! `codex_memory_get`/`codex_memory_append` tools and injected into the
+//! prompts of later runs so conventions and decisions outlive sessions.
+
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Heading written at the top of a new notes file.
+const HEADER: &str = "# Project memory\n\nNotes recorded through codex-mcp, injected into the prompts of runs in this workspace.\n";
+
+/// Path of the notes file of `cd`, configured relative to the workspace root.
+/// Fails for a `file` that could point outside the workspace.
+pub fn path(cd: &Path, file: &Path) -> io::Result<PathBuf> {
+    check_file(file)?;
+    Ok(cd.join(file))
+}
+
+/// Fail unless `file` is a plain relative path: no root, prefix, `.` or `..`
+/// components.
+pub fn check_file(file: &Path) -> io::Result<()> {
+    let plain = file.components().next().is_some() && file.components().all(|c| matches!(c, Component::Normal(_)));
+    if !plain {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("memory_file {file:?} must be a relative path inside the workspace, without `..`"),
+        ));
+    }
+    Ok(())
+}
+
+/// The notes of `cd`; empty when none were recorded yet.
+pub fn read(cd: &Path, file: &Path) -> io::Result<String> {
+    match std::fs::read_to_string(path(cd, file)?) {
+        Ok(notes) => Ok(notes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Append `note` to the notes of `cd` as a list item, creating the file
+/// (and its directory) on first use. Returns the directories created for it,
+/// outermost first, followed by the file.
+pub fn append(cd: &Path, file: &Path, note: &str) -> io::Result<Vec<PathBuf>> {
+    let path = path(cd, file)?;
+    let mut written: Vec<PathBuf> =
+        path.ancestors().skip(1).take_while(|dir| !dir.exists()).map(Path::to_path_buf).collect();
+    written.reverse();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut out = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    if out.metadata()?.len() == 0 {
+        writeln!(out, "{HEADER}")?;
+    }
+    writeln!(out, "{}", list_item(note))?;
+    written.push(path);
+    Ok(written)
+}
+
+/// `note` as a Markdown list item, with continuation lines indented.
+fn list_item(note: &str) -> String {
+    let mut item = String::new();
+    for (i, line) in note.trim().lines().enumerate() {
+        item.push_str(match (i, line.is_empty()) {
+            (0, _) => "- ",
+            (_, true) => "\n",
+            _ => "\n  ",
+        });
+        item.push_str(line);
+    }
+    item
+}
+
+/// The most recent `max_bytes` of `notes`, as injected into prompts.
+pub fn for_prompt(notes: &str, max_bytes: usize) -> Option<&str> {
+    let notes = notes.trim();
+    if notes.is_empty() || max_bytes == 0 {
+        return None;
+    }
+    let mut start = notes.len().saturating_sub(max_bytes);
+    while !notes.is_char_boundary(start) {
+        start += 1;
+    }
+    Some(&notes[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_creates_notes_and_keeps_recent_for_prompt() {
+        let cd = std::env::temp_dir().join(format!("codex-mcp-memory-{}", std::process::id()));
+        let file = Path::new(".codex-mcp/memory.md");
+        assert_eq!(read(&cd, file).unwrap(), "");
+
+        std::fs::create_dir_all(cd.join("docs")).unwrap();
+        let written = append(&cd, Path::new("docs/notes/memory.md"), "Only the new paths.").unwrap();
+        assert_eq!(written, [cd.join("docs/notes"), cd.join("docs/notes/memory.md")]);
+
+        append(&cd, file, "Use `thiserror` for error types.").unwrap();
+        let written = append(&cd, file, "Integration tests live in tests/.\nRun them with --all-features.").unwrap();
+        assert_eq!(written, [cd.join(file)]);
+        let notes = read(&cd, file).unwrap();
+        assert!(notes.starts_with(HEADER));
+        assert!(notes.ends_with(
+            "- Use `thiserror` for error types.\n- Integration tests live in tests/.\n  Run them with --all-features.\n"
+        ));
+
+        assert_eq!(for_prompt(&notes, 29), Some("Run them with --all-features."));
+        assert_eq!(for_prompt("  \n", 100), None);
+        std::fs::remove_dir_all(&cd).unwrap();
+    }
+
+    #[test]
+    fn test_memory_file_must_stay_in_the_workspace() {
+        let cd = Path::new("/work");
+        assert_eq!(path(cd, Path::new(".codex-mcp/memory.md")).unwrap(), cd.join(".codex-mcp/memory.md"));
+        for file in ["../memory.md", "notes/../../memory.md", "/etc/memory.md", "./memory.md", ""] {
+            assert!(path(cd, Path::new(file)).is_err(), "{file}");
+            assert!(append(cd, Path::new(file), "note").is_err(), "{file}");
+        }
+    }
+}
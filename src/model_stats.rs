@@ -0,0 +1,158 @@
+//! Per-workspace model track record, used by `model = "auto"` to pick the
+//! model that has historically done best in a workspace.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{Store, load, save};
+
+/// Key of the per-workspace model records in the state store.
+const MODEL_STATS_KEY: &str = "model_stats";
+
+/// Value of the `model` parameter that selects a model automatically.
+pub const AUTO_MODEL: &str = "auto";
+
+/// Outcomes of one model's runs in one workspace.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ModelRecord {
+    runs: u64,
+    successful_runs: u64,
+    total_duration_ms: u64,
+}
+
+impl ModelRecord {
+    /// Success rate with one virtual success and failure, so untried models
+    /// score 0.5 and a single lucky run does not dominate.
+    fn score(&self) -> f64 {
+        (self.successful_runs + 1) as f64 / (self.runs + 2) as f64
+    }
+
+    fn mean_duration_ms(&self) -> Option<u64> {
+        self.total_duration_ms.checked_div(self.runs)
+    }
+}
+
+/// Why `auto` picked the model it did, reported in `effective_options`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AutoModelChoice {
+    /// Chosen model; absent when there was no candidate and codex's default was used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Models that were considered.
+    pub candidates: Vec<String>,
+    /// Previous runs of the chosen model in this workspace.
+    pub runs: u64,
+    /// Fraction of those runs that succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_rate: Option<f64>,
+    /// Mean duration of those runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_duration_ms: Option<u64>,
+}
+
+/// Model records per workspace, optionally persisted in the state directory.
+#[derive(Debug, Default)]
+pub struct ModelStats {
+    store: Option<Store>,
+    workspaces: Mutex<BTreeMap<PathBuf, BTreeMap<String, ModelRecord>>>,
+}
+
+impl ModelStats {
+    /// Open the records, loading those persisted in `store`.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let workspaces = load(&*store, MODEL_STATS_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store: Some(store),
+            workspaces: Mutex::new(workspaces),
+        })
+    }
+
+    /// Record the outcome of a run of `model` in `cd`.
+    pub fn record(&self, cd: &Path, model: &str, success: bool, duration: Duration) {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        let record = workspaces
+            .entry(cd.to_path_buf())
+            .or_default()
+            .entry(model.to_string())
+            .or_default();
+        record.runs += 1;
+        if success {
+            record.successful_runs += 1;
+        }
+        record.total_duration_ms += duration.as_millis() as u64;
+
+        if let Some(ref store) = self.store
+            && let Err(e) = save(&**store, MODEL_STATS_KEY, &*workspaces)
+        {
+            tracing::warn!(key = MODEL_STATS_KEY, error = %e, "Failed to persist model stats");
+        }
+    }
+
+    /// Pick the best model for `cd` among `configured` and every model with
+    /// runs there: highest smoothed success rate, then shortest mean duration.
+    pub fn choose(&self, cd: &Path, configured: &[String]) -> AutoModelChoice {
+        let workspaces = self.workspaces.lock().unwrap();
+        let history = workspaces.get(cd);
+        let mut candidates = configured.to_vec();
+        for model in history.into_iter().flat_map(|h| h.keys()) {
+            if !candidates.contains(model) {
+                candidates.push(model.clone());
+            }
+        }
+
+        let record = |model: &String| history.and_then(|h| h.get(model)).copied().unwrap_or_default();
+        let best = candidates.iter().max_by(|a, b| {
+            let (a, b) = (record(a), record(b));
+            a.score()
+                .total_cmp(&b.score())
+                .then_with(|| b.mean_duration_ms().unwrap_or(u64::MAX).cmp(&a.mean_duration_ms().unwrap_or(u64::MAX)))
+        });
+        let chosen = best.map(&record).unwrap_or_default();
+        AutoModelChoice {
+            model: best.cloned(),
+            runs: chosen.runs,
+            success_rate: (chosen.runs > 0).then(|| chosen.successful_runs as f64 / chosen.runs as f64),
+            mean_duration_ms: chosen.mean_duration_ms(),
+            candidates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_prefers_success_then_speed() {
+        let stats = ModelStats::default();
+        let cd = Path::new("/srv/repo");
+        let secs = Duration::from_secs;
+        for success in [true, true, false] {
+            stats.record(cd, "o3", success, secs(60));
+        }
+        for _ in 0..3 {
+            stats.record(cd, "gpt-5", true, secs(90));
+            stats.record(cd, "gpt-5-mini", true, secs(30));
+        }
+
+        let choice = stats.choose(cd, &["o3".to_string()]);
+        assert_eq!(choice.model.as_deref(), Some("gpt-5-mini"));
+        assert_eq!(choice.candidates.len(), 3);
+        assert_eq!(choice.success_rate, Some(1.0));
+        assert_eq!(choice.mean_duration_ms, Some(30_000));
+
+        let fresh = stats.choose(Path::new("/srv/other"), &[]);
+        assert_eq!(fresh.model, None);
+        assert_eq!(fresh.runs, 0);
+    }
+}
@@ -0,0 +1,85 @@
+//! Classifying the error messages codex reports, so noise that does not mean
+//! the run failed (such as `Reconnecting...` while the CLI retries a dropped
+//! stream) can be told apart from real errors without a new server release.
+//!
+//! `noise_filters` in the config are checked first, in order, then the
+//! built-in filters; a message no filter matches fails the run as before.
+
+use std::sync::LazyLock;
+
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Deserializer};
+
+/// What an error message matching a filter means for the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseClass {
+    /// Ignored entirely.
+    Benign,
+    /// Reported in the result's `warnings` without failing the run.
+    Warning,
+    /// Fails the run and stops codex right away.
+    Fatal,
+}
+
+/// A regex over codex error messages and how matches are classified.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoiseFilter {
+    /// Searched for anywhere in the message; anchor with `^` to match a prefix.
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    pub class: NoiseClass,
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(|e| serde::de::Error::custom(format!("invalid regex {pattern:?}: {e}")))
+}
+
+/// Noise of the codex CLI known to be benign.
+static BUILT_IN: LazyLock<Vec<NoiseFilter>> = LazyLock::new(|| {
+    vec![NoiseFilter {
+        pattern: Regex::new(r"^Reconnecting\.\.\.").expect("built-in pattern is valid"),
+        class: NoiseClass::Benign,
+    }]
+});
+
+/// How `message` is classified by `filters` or the built-in filters, if any matches.
+pub fn classify(filters: &[NoiseFilter], message: &str) -> Option<NoiseClass> {
+    filters
+        .iter()
+        .chain(BUILT_IN.iter())
+        .find(|filter| filter.pattern.is_match(message))
+        .map(|filter| filter.class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_checks_configured_filters_first() {
+        let filters: Vec<NoiseFilter> = toml::from_str::<toml::Table>(
+            r#"
+            filters = [
+                { pattern = "(?i)deprecated", class = "warning" },
+                { pattern = "^Reconnecting\\.\\.\\. 5/5", class = "fatal" },
+            ]
+            "#,
+        )
+        .unwrap()["filters"]
+            .clone()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(classify(&filters, "Reconnecting... 1/5"), Some(NoiseClass::Benign));
+        assert_eq!(classify(&filters, "Reconnecting... 5/5"), Some(NoiseClass::Fatal));
+        assert_eq!(classify(&filters, "flag --foo is DEPRECATED"), Some(NoiseClass::Warning));
+        assert_eq!(classify(&filters, "stream disconnected"), None);
+        assert_eq!(classify(&[], "stream disconnected before completion"), None);
+
+        let invalid = toml::from_str::<NoiseFilter>("pattern = \"(\"\nclass = \"benign\"");
+        assert!(invalid.unwrap_err().to_string().contains("invalid regex"));
+    }
+}
@@ -0,0 +1,258 @@
+//! PID files for the codex processes the server spawns, so that processes
+//! left behind by an instance that died abruptly (e.g. a stdio server killed
+//! along with its crashed client) are cleaned up by the next one instead of
+//! accumulating.
+//!
+//! Each running codex has a file in `state_dir/pids/` naming it and the
+//! server that spawned it. Process identities include their start time, read
+//! from `/proc` on Linux, so a reused PID is never mistaken for an orphan;
+//! elsewhere orphans are only reported.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Directory under `state_dir` holding the PID files.
+const PIDS_DIR: &str = "pids";
+
+/// How long a terminated orphan gets to exit before it is killed outright.
+const TERMINATE_GRACE: Duration = Duration::from_secs(5);
+
+/// How often an adopted orphan is checked for having exited.
+const ADOPT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What happens to codex processes spawned by a previous server instance
+/// that are still running at startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanPolicy {
+    /// Terminate them, as nothing reads their output anymore (default).
+    #[default]
+    Kill,
+    /// Let them finish, removing their PID files once they exit.
+    Adopt,
+}
+
+/// A process, identified by its PID and start time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Process {
+    pid: u32,
+    /// Start time in clock ticks since boot, where the platform reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started: Option<u64>,
+}
+
+impl Process {
+    fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            started: start_time(pid),
+        }
+    }
+
+    /// Whether this exact process is still running.
+    fn is_running(&self) -> bool {
+        is_alive(self.pid) && (self.started.is_none() || start_time(self.pid) == self.started)
+    }
+}
+
+/// Contents of a PID file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    codex: Process,
+    server: Process,
+}
+
+/// PID file of a running codex, removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Record that this server spawned codex as `pid`.
+    pub fn create(state_dir: &Path, pid: u32) -> Result<Self> {
+        let dir = state_dir.join(PIDS_DIR);
+        std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let record = Record {
+            codex: Process::new(pid),
+            server: Process::new(std::process::id()),
+        };
+        let path = dir.join(format!("{pid}.json"));
+        std::fs::write(&path, serde_json::to_vec(&record)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Handle the codex processes of server instances that are no longer
+/// running, returning how many were still alive.
+pub async fn clean_up(state_dir: &Path, policy: OrphanPolicy) -> usize {
+    let entries = match std::fs::read_dir(state_dir.join(PIDS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return 0,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read codex PID files");
+            return 0;
+        }
+    };
+
+    let mut orphans = 0;
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let record = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Record>(&bytes).ok());
+        let Some(record) = record else {
+            tracing::warn!(path = %path.display(), "Removing unreadable codex PID file");
+            let _ = std::fs::remove_file(&path);
+            continue;
+        };
+        // Spawned by this or another live instance sharing the state directory.
+        if record.server.is_running() {
+            continue;
+        }
+        if !record.codex.is_running() {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+
+        orphans += 1;
+        let pid = record.codex.pid;
+        if record.codex.started.is_none() {
+            tracing::warn!(pid, "Found a codex process left by a previous server instance; its identity cannot be verified on this platform, leaving it running");
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        match policy {
+            OrphanPolicy::Kill => {
+                tracing::warn!(pid, "Terminating codex process left by a previous server instance");
+                terminate(record.codex).await;
+                let _ = std::fs::remove_file(&path);
+            }
+            OrphanPolicy::Adopt => {
+                tracing::info!(pid, "Adopting codex process left by a previous server instance");
+                let codex = record.codex;
+                tokio::spawn(async move {
+                    while codex.is_running() {
+                        tokio::time::sleep(ADOPT_POLL_INTERVAL).await;
+                    }
+                    tracing::info!(pid, "Adopted codex process exited");
+                    let _ = std::fs::remove_file(&path);
+                });
+            }
+        }
+    }
+    orphans
+}
+
+/// Ask `process` to exit, killing it if it is still running after the grace period.
+async fn terminate(process: Process) {
+    signal(process.pid, Signal::Terminate);
+    let deadline = tokio::time::Instant::now() + TERMINATE_GRACE;
+    while process.is_running() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    if process.is_running() {
+        signal(process.pid, Signal::Kill);
+    }
+}
+
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn signal(pid: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: `kill` has no memory-safety preconditions.
+    unsafe { libc::kill(pid as libc::pid_t, signal) };
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32, _signal: Signal) {}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists.
+    let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+    false
+}
+
+/// Start time of `pid` in clock ticks since boot, the 22nd field of
+/// `/proc/<pid>/stat`, or `None` once it has exited and is only a zombie.
+#[cfg(target_os = "linux")]
+fn start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name in parentheses may contain spaces; fields follow the last `)`.
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace();
+    if fields.next()? == "Z" {
+        return None;
+    }
+    fields.nth(18)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clean_up_kills_orphans_of_dead_servers() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-orphans-{}", std::process::id()));
+        let pids = dir.join(PIDS_DIR);
+        std::fs::create_dir_all(&pids).unwrap();
+
+        let mut orphan = std::process::Command::new("sleep").arg("60").spawn().unwrap();
+        let record = Record {
+            codex: Process::new(orphan.id()),
+            // The server that spawned it is gone; its PID was reused.
+            server: Process {
+                pid: std::process::id(),
+                started: Some(0),
+            },
+        };
+        let orphan_file = pids.join(format!("{}.json", orphan.id()));
+        std::fs::write(&orphan_file, serde_json::to_vec(&record).unwrap()).unwrap();
+        // Spawned by this live server, so left alone.
+        let mut own = std::process::Command::new("sleep").arg("60").spawn().unwrap();
+        let pid_file = PidFile::create(&dir, own.id()).unwrap();
+        std::fs::write(pids.join("garbage.json"), "{").unwrap();
+
+        assert_eq!(clean_up(&dir, OrphanPolicy::Kill).await, 1);
+        assert!(orphan.wait().unwrap().code().is_none(), "orphan was terminated by a signal");
+        assert!(!orphan_file.exists());
+        assert!(!pids.join("garbage.json").exists());
+        assert!(Process::new(own.id()).is_running());
+
+        drop(pid_file);
+        assert!(!pids.join(format!("{}.json", own.id())).exists());
+        own.kill().unwrap();
+        own.wait().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+//! `os_sandbox`: run codex itself inside an OS-level sandbox (bubblewrap on
+//! Linux, `sandbox-exec` on macOS) derived from the run's sandbox policy, as
+//! a second line of defense should the CLI's own enforcement fail.
+//!
+//! The wrapper only confines writes: the whole filesystem stays readable and
+//! the network reachable, since codex needs both to talk to the model
+//! provider. Writes are allowed to the codex home directory, the temporary
+//! directory, and, for `workspace-write` runs, `cd` and the extra writable
+//! roots. `danger-full-access` and `yolo` runs are not wrapped.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::codex::SandboxPolicy;
+use crate::codex_home;
+use crate::error::CodexError;
+
+/// The `sandbox-exec` binary shipped with macOS.
+const SANDBOX_EXEC: &str = "/usr/bin/sandbox-exec";
+
+/// Seatbelt profile denying writes outside the paths passed as `WRITABLE_<n>`.
+const SEATBELT_PROFILE: &str = "(version 1)\n(allow default)\n(deny file-write*)\n(allow file-write* (subpath \"/dev\"))\n";
+
+/// Whether codex is wrapped in an OS-level sandbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OsSandbox {
+    /// Rely on the codex CLI's own sandbox only (default).
+    #[default]
+    Off,
+    /// Use the wrapper available on this platform, if any.
+    Auto,
+    /// Wrap codex in bubblewrap (`bwrap`), failing runs if it is missing.
+    Bubblewrap,
+    /// Wrap codex in `sandbox-exec`, failing runs if it is missing.
+    SandboxExec,
+}
+
+/// OS-level sandbox a run's codex process was wrapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrapper {
+    Bubblewrap,
+    SandboxExec,
+}
+
+impl Wrapper {
+    fn as_str(self) -> &'static str {
+        match self {
+            Wrapper::Bubblewrap => "bubblewrap",
+            Wrapper::SandboxExec => "sandbox_exec",
+        }
+    }
+
+    /// Absolute path of the wrapper binary, if it is installed.
+    fn program(self) -> Option<PathBuf> {
+        match self {
+            Wrapper::Bubblewrap => which::which("bwrap").ok(),
+            Wrapper::SandboxExec => Some(PathBuf::from(SANDBOX_EXEC)).filter(|path| path.is_file()),
+        }
+    }
+}
+
+/// The wrapper for a run under `sandbox`, or `None` if it runs unwrapped.
+pub fn resolve(mode: OsSandbox, sandbox: &SandboxPolicy, yolo: bool) -> Result<Option<Wrapper>, CodexError> {
+    if yolo || matches!(sandbox, SandboxPolicy::DangerFullAccess) {
+        return Ok(None);
+    }
+    let wrapper = match mode {
+        OsSandbox::Off => return Ok(None),
+        OsSandbox::Auto => {
+            let native = if cfg!(target_os = "linux") {
+                Some(Wrapper::Bubblewrap)
+            } else if cfg!(target_os = "macos") {
+                Some(Wrapper::SandboxExec)
+            } else {
+                None
+            };
+            return Ok(native.filter(|wrapper| wrapper.program().is_some()));
+        }
+        OsSandbox::Bubblewrap => Wrapper::Bubblewrap,
+        OsSandbox::SandboxExec => Wrapper::SandboxExec,
+    };
+    match wrapper.program() {
+        Some(_) => Ok(Some(wrapper)),
+        None => Err(CodexError::OsSandboxUnavailable(wrapper.as_str())),
+    }
+}
+
+/// Paths a run under `sandbox` in `cd` may write to inside the wrapper.
+pub fn writable_paths(sandbox: &SandboxPolicy, cd: &Path, writable_roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = codex_home::codex_home().into_iter().collect();
+    paths.push(std::env::temp_dir());
+    if matches!(sandbox, SandboxPolicy::WorkspaceWrite) {
+        paths.push(cd.to_path_buf());
+        paths.extend(writable_roots.iter().cloned());
+    }
+    // Both wrappers need existing paths: bubblewrap cannot bind a missing one,
+    // and Seatbelt matches resolved paths (e.g. `/private/tmp`, not `/tmp`).
+    let mut paths: Vec<PathBuf> = paths.iter().filter_map(|path| path.canonicalize().ok()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// A command running `codex_path`, inside `wrapper` if any, that may write
+/// only to `writable`.
+pub fn command(codex_path: &Path, wrapper: Option<Wrapper>, writable: &[PathBuf]) -> Result<Command, CodexError> {
+    let Some(wrapper) = wrapper else {
+        return Ok(Command::new(codex_path));
+    };
+    let program = wrapper
+        .program()
+        .ok_or(CodexError::OsSandboxUnavailable(wrapper.as_str()))?;
+    let mut cmd = Command::new(program);
+    cmd.args(match wrapper {
+        Wrapper::Bubblewrap => bubblewrap_args(writable),
+        Wrapper::SandboxExec => sandbox_exec_args(writable),
+    });
+    cmd.arg(codex_path);
+    Ok(cmd)
+}
+
+/// `bwrap` arguments mounting the filesystem read-only except for `writable`.
+fn bubblewrap_args(writable: &[PathBuf]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = ["--die-with-parent", "--ro-bind", "/", "/", "--dev", "/dev"]
+        .map(OsString::from)
+        .into();
+    for path in writable {
+        args.extend(["--bind".into(), path.into(), path.into()]);
+    }
+    args.push("--".into());
+    args
+}
+
+/// `sandbox-exec` arguments denying writes outside `writable`. The paths are
+/// passed as parameters rather than spliced into the profile, so they need no
+/// escaping.
+fn sandbox_exec_args(writable: &[PathBuf]) -> Vec<OsString> {
+    let mut profile = SEATBELT_PROFILE.to_string();
+    let mut params = Vec::new();
+    for (i, path) in writable.iter().enumerate() {
+        profile.push_str(&format!("(allow file-write* (subpath (param \"WRITABLE_{i}\")))\n"));
+        let mut param = OsString::from(format!("WRITABLE_{i}="));
+        param.push(path);
+        params.extend([OsString::from("-D"), param]);
+    }
+    let mut args = vec![OsString::from("-p"), profile.into()];
+    args.extend(params);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapper_args_confine_writes() {
+        let writable = [PathBuf::from("/home/dev/.codex"), PathBuf::from("/work/app")];
+        assert_eq!(
+            bubblewrap_args(&writable),
+            [
+                "--die-with-parent", "--ro-bind", "/", "/", "--dev", "/dev",
+                "--bind", "/home/dev/.codex", "/home/dev/.codex",
+                "--bind", "/work/app", "/work/app",
+                "--",
+            ]
+        );
+
+        let args = sandbox_exec_args(&writable);
+        let profile = args[1].to_str().unwrap();
+        assert!(profile.contains("(deny file-write*)"));
+        assert!(profile.contains("(allow file-write* (subpath (param \"WRITABLE_1\")))"));
+        assert_eq!(args[2..], ["-D", "WRITABLE_0=/home/dev/.codex", "-D", "WRITABLE_1=/work/app"]);
+
+        assert_eq!(resolve(OsSandbox::SandboxExec, &SandboxPolicy::DangerFullAccess, false).unwrap(), None);
+        assert_eq!(resolve(OsSandbox::Bubblewrap, &SandboxPolicy::ReadOnly, true).unwrap(), None);
+        assert_eq!(resolve(OsSandbox::Off, &SandboxPolicy::WorkspaceWrite, false).unwrap(), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! `expected_output_schema`: runs whose final message must be a JSON value
+//! matching a JSON Schema, for pipelines that consume codex's answer
+//! programmatically.
+//!
+//! Codex is told to answer with JSON only; the answer is parsed (tolerating
+//! a surrounding Markdown code fence) and checked against the schema. The
+//! keywords checked are `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `minItems`/`maxItems`,
+//! `minLength`/`maxLength`, `minimum`/`maximum`, and `allOf`/`anyOf`/`oneOf`;
+//! others (such as `format` or `$ref`) are accepted without checking.
+
+use serde_json::{Map, Value};
+
+/// Most mismatches quoted back to codex or to the client.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Reject schemas that are not JSON objects or booleans.
+pub fn check(schema: &Value) -> Result<(), String> {
+    match schema {
+        Value::Object(_) | Value::Bool(_) => Ok(()),
+        other => Err(format!("expected a JSON Schema object, got {other}")),
+    }
+}
+
+/// Instruction appended to the prompt, telling codex how to answer.
+pub fn instruction(schema: &Value) -> String {
+    format!(
+        "Output: your final message must consist only of a single JSON value matching the JSON Schema below, \
+         without any prose or code fences around it.\n<output-schema>\n{}\n</output-schema>",
+        serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string())
+    )
+}
+
+/// Prompt resuming a session whose final message did not match the schema.
+pub fn retry_prompt(problem: &str) -> String {
+    format!(
+        "Your final message did not match the required output schema: {problem}. \
+         Do not change any files; reply again with only the corrected JSON value."
+    )
+}
+
+/// The JSON value of `message` if it matches `schema`, or what is wrong with it.
+pub fn parse_answer(schema: &Value, message: &str) -> Result<Value, String> {
+    let text = strip_code_fence(message.trim());
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("the message is not valid JSON ({e})"))?;
+    let mut errors = Vec::new();
+    validate(schema, &value, "", &mut errors);
+    if errors.is_empty() {
+        return Ok(value);
+    }
+    let omitted = errors.len().saturating_sub(MAX_REPORTED_ERRORS);
+    errors.truncate(MAX_REPORTED_ERRORS);
+    let mut problem = errors.join("; ");
+    if omitted > 0 {
+        problem.push_str(&format!("; and {omitted} more"));
+    }
+    Err(problem)
+}
+
+/// `text` without a Markdown code fence around it, if it has one.
+fn strip_code_fence(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let Some(body) = rest.strip_suffix("```") else {
+        return text;
+    };
+    // Skip the info string, e.g. `json`.
+    body.split_once('\n').map_or(body, |(_, body)| body).trim()
+}
+
+/// Append to `errors` every way `value` at JSON pointer `path` fails `schema`.
+fn validate(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => return errors.push(format!("{} is not allowed", at(path))),
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type")
+        && !type_matches(expected, value)
+    {
+        return errors.push(format!("{} should be of type {expected}, got {}", at(path), type_name(value)));
+    }
+    if let Some(Value::Array(options)) = schema.get("enum")
+        && !options.contains(value)
+    {
+        errors.push(format!("{} should be one of {}", at(path), Value::Array(options.clone())));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        errors.push(format!("{} should be {expected}", at(path)));
+    }
+
+    match value {
+        Value::Object(object) => validate_object(schema, object, path, errors),
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate(item_schema, item, &format!("{path}/{i}"), errors);
+                }
+            }
+            check_bound(schema, "minItems", items.len() as f64, path, errors, |len, min| len >= min);
+            check_bound(schema, "maxItems", items.len() as f64, path, errors, |len, max| len <= max);
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as f64;
+            check_bound(schema, "minLength", len, path, errors, |len, min| len >= min);
+            check_bound(schema, "maxLength", len, path, errors, |len, max| len <= max);
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            check_bound(schema, "minimum", n, path, errors, |n, min| n >= min);
+            check_bound(schema, "maximum", n, path, errors, |n, max| n <= max);
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            validate(sub, value, path, errors);
+        }
+    }
+    if let Some(Value::Array(options)) = schema.get("anyOf")
+        && !options.iter().any(|sub| matches(sub, value))
+    {
+        errors.push(format!("{} should match at least one of the `anyOf` schemas", at(path)));
+    }
+    if let Some(Value::Array(options)) = schema.get("oneOf")
+        && options.iter().filter(|sub| matches(sub, value)).count() != 1
+    {
+        errors.push(format!("{} should match exactly one of the `oneOf` schemas", at(path)));
+    }
+}
+
+fn validate_object(schema: &Map<String, Value>, object: &Map<String, Value>, path: &str, errors: &mut Vec<String>) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(format!("{} is missing required property {name:?}", at(path)));
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, property) in object {
+        let property_path = format!("{path}/{name}");
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property_schema) => validate(property_schema, property, &property_path, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => errors.push(format!("{} has unexpected property {name:?}", at(path))),
+                Some(additional) => validate(additional, property, &property_path, errors),
+                None => {}
+            },
+        }
+    }
+}
+
+fn matches(schema: &Value, value: &Value) -> bool {
+    let mut errors = Vec::new();
+    validate(schema, value, "", &mut errors);
+    errors.is_empty()
+}
+
+fn check_bound(
+    schema: &Map<String, Value>,
+    keyword: &str,
+    actual: f64,
+    path: &str,
+    errors: &mut Vec<String>,
+    within: fn(f64, f64) -> bool,
+) {
+    if let Some(bound) = schema.get(keyword).and_then(Value::as_f64)
+        && !within(actual, bound)
+    {
+        errors.push(format!("{} violates `{keyword}` {bound}", at(path)));
+    }
+}
+
+fn type_matches(expected: &Value, value: &Value) -> bool {
+    match expected {
+        Value::String(name) => is_type(name, value),
+        Value::Array(names) => names.iter().filter_map(Value::as_str).any(|name| is_type(name, value)),
+        _ => true,
+    }
+}
+
+fn is_type(name: &str, value: &Value) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// `path` as shown in messages; the answer itself when empty.
+fn at(path: &str) -> String {
+    if path.is_empty() { "the answer".to_string() } else { format!("`{path}`") }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_answer_validates_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["verdict", "issues"],
+            "additionalProperties": false,
+            "properties": {
+                "verdict": {"enum": ["pass", "fail"]},
+                "issues": {"type": "array", "items": {"type": "string", "minLength": 1}, "maxItems": 2},
+                "score": {"type": "integer", "minimum": 0, "maximum": 10},
+            },
+        });
+
+        let answer = parse_answer(&schema, "```json\n{\"verdict\": \"pass\", \"issues\": [], \"score\": 7}\n```");
+        assert_eq!(answer.unwrap(), json!({"verdict": "pass", "issues": [], "score": 7}));
+
+        let problem = parse_answer(&schema, r#"{"verdict": "maybe", "issues": [""], "score": 7.5, "extra": 1}"#).unwrap_err();
+        assert!(problem.contains("`/verdict` should be one of [\"pass\",\"fail\"]"), "{problem}");
+        assert!(problem.contains("`/issues/0` violates `minLength` 1"), "{problem}");
+        assert!(problem.contains("`/score` should be of type \"integer\", got number"), "{problem}");
+        assert!(problem.contains("the answer has unexpected property \"extra\""), "{problem}");
+
+        let problem = parse_answer(&schema, r#"{"verdict": "pass"}"#).unwrap_err();
+        assert_eq!(problem, "the answer is missing required property \"issues\"");
+        assert!(parse_answer(&schema, "All good!").unwrap_err().starts_with("the message is not valid JSON"));
+        assert!(check(&json!("object")).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Server-side policy checks applied to client-supplied parameters.
+
+use std::path::{Path, PathBuf};
+
+/// Resolve `path` and return its canonical form if it lies under one of `roots`.
+///
+/// Both sides are canonicalized so that `..` components and symlinks cannot be
+/// used to escape an allowlisted directory. Returns `None` if `path` does not
+/// exist or is outside every root; roots that fail to resolve are ignored.
+pub fn resolve_within(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let resolved = path.canonicalize().ok()?;
+    roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root))
+        .then_some(resolved)
+}
+
+/// Like [`resolve_within`], but for a path that does not exist yet: its
+/// nearest existing ancestor must lie under one of `roots`, and the missing
+/// remainder may only consist of plain names (no `..`).
+pub fn resolve_new_within(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let mut ancestor = path;
+    let mut missing = Vec::new();
+    while !ancestor.exists() {
+        // `file_name` is `None` for a path ending in `..`.
+        let name = ancestor.file_name()?;
+        missing.push(name.to_os_string());
+        ancestor = ancestor.parent()?;
+    }
+    let mut resolved = resolve_within(ancestor, roots)?;
+    resolved.extend(missing.iter().rev());
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_within_checks_canonical_paths() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-policy-{}", std::process::id()));
+        let allowed = root.join("allowed");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::write(allowed.join("a.md"), "x").unwrap();
+        std::fs::write(root.join("b.md"), "x").unwrap();
+
+        let roots = vec![allowed.clone()];
+        assert!(resolve_within(&allowed.join("a.md"), &roots).is_some());
+        assert!(resolve_within(&allowed.join("../b.md"), &roots).is_none());
+        assert!(resolve_within(&allowed.join("missing.md"), &roots).is_none());
+        assert!(resolve_within(&allowed.join("a.md"), &[]).is_none());
+
+        assert_eq!(
+            resolve_new_within(&allowed.join("new/dir"), &roots),
+            Some(allowed.canonicalize().unwrap().join("new/dir"))
+        );
+        assert!(resolve_new_within(&root.join("new"), &roots).is_none());
+        assert!(resolve_new_within(&allowed.join("new/../../escape"), &roots).is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
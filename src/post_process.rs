@@ -0,0 +1,356 @@
+//! Result post-processors: output policies applied, in order, to every run
+//! result before it is returned, so deployments can compose them in the
+//! config instead of patching the server.
+//!
+//! ```toml
+//! [[post_processors]]
+//! kind = "redact"
+//! patterns = ["(?i)password=\\S+", "AKIA[0-9A-Z]{16}"]
+//!
+//! [[post_processors]]
+//! kind = "truncate"
+//! max_chars = 20000
+//!
+//! [[post_processors]]
+//! kind = "markdown"
+//! ```
+//!
+//! The built-ins are `redact`, `truncate`, `markdown`, and `schema`; each is
+//! a [`ResultPostProcessor`], the extension point for further policies. They
+//! run after the server's own secret redaction and before `compress`.
+
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::codex::{CodexResult, truncate_middle};
+use crate::error::CodexError;
+use crate::i18n::{self, Locale};
+use crate::output_schema;
+
+/// Default replacement of `redact` matches.
+const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+/// A step of the result pipeline.
+pub trait ResultPostProcessor: std::fmt::Debug + Send + Sync {
+    /// Name the step is logged under.
+    fn name(&self) -> &'static str;
+
+    /// Rewrite `result`, reporting errors in `locale`.
+    fn process(&self, result: &mut CodexResult, locale: Locale);
+}
+
+/// A built-in post-processor selected in the config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PostProcessorConfig {
+    /// Replace every match of `patterns` in the result's text (messages,
+    /// errors, patch, reasoning, and events) with `replacement`.
+    Redact {
+        #[serde(deserialize_with = "deserialize_regexes")]
+        patterns: Vec<Regex>,
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+    /// Cap `agent_messages` at `max_chars`, keeping its beginning and end.
+    Truncate { max_chars: usize },
+    /// Add a Markdown rendering of the result as `markdown`, for clients
+    /// that show results to people.
+    Markdown,
+    /// Fail runs whose final message is not JSON matching `schema`, as
+    /// `expected_output_schema` does for every run, without the retry.
+    Schema {
+        #[serde(deserialize_with = "deserialize_schema")]
+        schema: Value,
+    },
+}
+
+fn default_replacement() -> String {
+    DEFAULT_REPLACEMENT.to_string()
+}
+
+fn deserialize_regexes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|e| serde::de::Error::custom(format!("invalid regex {pattern:?}: {e}")))
+        })
+        .collect()
+}
+
+fn deserialize_schema<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    let schema = Value::deserialize(deserializer)?;
+    output_schema::check(&schema).map_err(serde::de::Error::custom)?;
+    Ok(schema)
+}
+
+impl PostProcessorConfig {
+    fn build(&self) -> Box<dyn ResultPostProcessor> {
+        match self.clone() {
+            PostProcessorConfig::Redact { patterns, replacement } => Box::new(Redact { patterns, replacement }),
+            PostProcessorConfig::Truncate { max_chars } => Box::new(Truncate { max_chars }),
+            PostProcessorConfig::Markdown => Box::new(Markdown),
+            PostProcessorConfig::Schema { schema } => Box::new(Schema { schema }),
+        }
+    }
+}
+
+/// The post-processors of `configs`, in order.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn ResultPostProcessor>>,
+}
+
+impl Pipeline {
+    pub fn new(configs: &[PostProcessorConfig]) -> Self {
+        Self {
+            steps: configs.iter().map(PostProcessorConfig::build).collect(),
+        }
+    }
+
+    /// Run every step on `result`.
+    pub fn apply(&self, result: &mut CodexResult, locale: Locale) {
+        for step in &self.steps {
+            step.process(result, locale);
+            tracing::trace!(post_processor = step.name(), "Post-processed result");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Redact {
+    patterns: Vec<Regex>,
+    replacement: String,
+}
+
+impl Redact {
+    /// `text` with every match replaced, if anything matched.
+    fn redact(&self, text: &str) -> Option<String> {
+        let mut text = std::borrow::Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            let mut out = String::new();
+            let mut last = 0;
+            for found in pattern.find_iter(text.as_ref()) {
+                out.push_str(&text[last..found.start()]);
+                out.push_str(&self.replacement);
+                last = found.end();
+            }
+            if last > 0 {
+                out.push_str(&text[last..]);
+                text = std::borrow::Cow::Owned(out);
+            }
+        }
+        match text {
+            std::borrow::Cow::Owned(text) => Some(text),
+            std::borrow::Cow::Borrowed(_) => None,
+        }
+    }
+
+    fn redact_in_place(&self, text: &mut String) {
+        if let Some(redacted) = self.redact(text) {
+            *text = redacted;
+        }
+    }
+
+    fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => self.redact_in_place(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_json(item)),
+            Value::Object(object) => object.values_mut().for_each(|item| self.redact_json(item)),
+            _ => {}
+        }
+    }
+}
+
+impl ResultPostProcessor for Redact {
+    fn name(&self) -> &'static str {
+        "redact"
+    }
+
+    fn process(&self, result: &mut CodexResult, _locale: Locale) {
+        let texts = [
+            &mut result.agent_messages,
+            &mut result.error,
+            &mut result.patch,
+            &mut result.commit_error,
+            &mut result.pull_request_error,
+        ];
+        for text in texts.into_iter().flatten() {
+            self.redact_in_place(text);
+        }
+        for summary in result.reasoning_summary.iter_mut().flatten() {
+            self.redact_in_place(summary);
+        }
+        for value in result.all_messages.iter_mut().flatten().chain(result.structured_answer.as_mut()) {
+            self.redact_json(value);
+        }
+        for denial in &mut result.sandbox_denials {
+            self.redact_in_place(&mut denial.command);
+            self.redact_in_place(&mut denial.reason);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Truncate {
+    max_chars: usize,
+}
+
+impl ResultPostProcessor for Truncate {
+    fn name(&self) -> &'static str {
+        "truncate"
+    }
+
+    fn process(&self, result: &mut CodexResult, _locale: Locale) {
+        if let Some(truncated) = result.agent_messages.as_deref().and_then(|m| truncate_middle(m, self.max_chars)) {
+            result.agent_messages = Some(truncated);
+            result.truncated = true;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Markdown;
+
+impl ResultPostProcessor for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn process(&self, result: &mut CodexResult, _locale: Locale) {
+        let mut sections = Vec::new();
+        match (result.agent_messages.as_deref(), result.error.as_deref()) {
+            (_, Some(error)) if !result.success => sections.push(format!("**Failed:** {error}")),
+            (Some(message), _) => sections.push(message.trim().to_string()),
+            _ => {}
+        }
+        if !result.files_changed.is_empty() {
+            let files: Vec<String> = result
+                .files_changed
+                .iter()
+                .map(|change| match change.previous_path {
+                    Some(ref from) => format!("- `{}` (renamed from `{from}`)", change.path),
+                    None => format!("- `{}` ({})", change.path, format!("{:?}", change.kind).to_lowercase()),
+                })
+                .collect();
+            sections.push(format!("### Changed files\n\n{}", files.join("\n")));
+        }
+        if let Some(ref patch) = result.patch {
+            sections.push(format!("### Patch\n\n```diff\n{}\n```", patch.trim_end()));
+        }
+        let mut details = Vec::new();
+        if let Some(ref session_id) = result.session_id {
+            details.push(format!("session `{session_id}`"));
+        }
+        if let Some(ref usage) = result.usage {
+            details.push(format!("{} input / {} output tokens", usage.input_tokens, usage.output_tokens));
+        }
+        if let Some(cost) = result.cost_usd {
+            details.push(format!("${cost:.4}"));
+        }
+        if !details.is_empty() {
+            sections.push(format!("_{}_", details.join(" · ")));
+        }
+        result.markdown = Some(sections.join("\n\n"));
+    }
+}
+
+#[derive(Debug)]
+struct Schema {
+    schema: Value,
+}
+
+impl ResultPostProcessor for Schema {
+    fn name(&self) -> &'static str {
+        "schema"
+    }
+
+    fn process(&self, result: &mut CodexResult, locale: Locale) {
+        if !result.success {
+            return;
+        }
+        let message = result.agent_messages.as_deref().unwrap_or_default();
+        match output_schema::parse_answer(&self.schema, message) {
+            Ok(answer) => result.structured_answer = Some(answer),
+            Err(problem) => {
+                let e = CodexError::OutputSchemaMismatch(problem);
+                result.success = false;
+                result.error = Some(i18n::message(&e, locale));
+                result.error_code = Some(e.code().to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(config: &str) -> Pipeline {
+        #[derive(Deserialize)]
+        struct Config {
+            post_processors: Vec<PostProcessorConfig>,
+        }
+        let config: Config = toml::from_str(config).unwrap();
+        Pipeline::new(&config.post_processors)
+    }
+
+    #[test]
+    fn test_pipeline_applies_steps_in_order() {
+        let pipeline = pipeline(
+            r#"
+            [[post_processors]]
+            kind = "redact"
+            patterns = ["password=\\S+"]
+
+            [[post_processors]]
+            kind = "truncate"
+            max_chars = 40
+
+            [[post_processors]]
+            kind = "markdown"
+            "#,
+        );
+        let mut result = CodexResult {
+            success: true,
+            agent_messages: Some(format!("Set password=hunter2 in the config.{}", " More.".repeat(20))),
+            patch: Some("+password=hunter2\n".to_string()),
+            ..CodexResult::default()
+        };
+        pipeline.apply(&mut result, Locale::En);
+
+        let message = result.agent_messages.clone().unwrap();
+        assert!(message.starts_with("Set [REDACTED] in"), "{message}");
+        assert!(result.truncated);
+        assert_eq!(result.patch.as_deref(), Some("+[REDACTED]\n"));
+        let markdown = result.markdown.unwrap();
+        assert!(markdown.starts_with(&message), "{markdown}");
+        assert!(markdown.ends_with("### Patch\n\n```diff\n+[REDACTED]\n```"), "{markdown}");
+    }
+
+    #[test]
+    fn test_schema_step_fails_mismatching_answers() {
+        let pipeline = pipeline(
+            r#"
+            [[post_processors]]
+            kind = "schema"
+            schema = { type = "object", required = ["ok"] }
+            "#,
+        );
+        let mut result = CodexResult {
+            success: true,
+            agent_messages: Some("{\"ok\": true}".to_string()),
+            ..CodexResult::default()
+        };
+        pipeline.apply(&mut result, Locale::En);
+        assert_eq!(result.structured_answer, Some(serde_json::json!({"ok": true})));
+
+        result.agent_messages = Some("{}".to_string());
+        pipeline.apply(&mut result, Locale::En);
+        assert!(!result.success);
+        assert_eq!(result.error_code.as_deref(), Some("output_schema_mismatch"));
+
+        let invalid = toml::from_str::<PostProcessorConfig>("kind = \"redact\"\npatterns = [\"(\"]");
+        assert!(invalid.unwrap_err().to_string().contains("invalid regex"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Checks of a workspace before a write-enabled run, so a run that could
+//! only fail late (codex running out of disk space, or editing files with
+//! unresolved conflicts) fails at once with an error saying what to fix.
+//! Both checks are off unless the server enables them with
+//! `min_free_disk_mb` and `check_git_conflicts`.
+
+use std::path::Path;
+
+use crate::config::ServerConfig;
+use crate::error::CodexError;
+use crate::git;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Fail if `cd` is on a filesystem with less free space than configured,
+/// or has unmerged git paths.
+pub async fn check(cd: &Path, config: &ServerConfig) -> Result<(), CodexError> {
+    if config.min_free_disk_mb > 0
+        && let Some(free) = free_bytes(cd)
+        && free < config.min_free_disk_mb * MIB
+    {
+        return Err(CodexError::InsufficientDiskSpace {
+            path: cd.to_path_buf(),
+            free_mb: free / MIB,
+            required_mb: config.min_free_disk_mb,
+        });
+    }
+    if config.check_git_conflicts {
+        let unmerged = git::unmerged_paths(cd).await;
+        if !unmerged.is_empty() {
+            return Err(CodexError::UnmergedConflicts(unmerged));
+        }
+    }
+    Ok(())
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`,
+/// or `None` if that cannot be determined.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // The `statvfs` field types differ between platforms.
+pub fn free_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stats` is valid for writes.
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        tracing::debug!(error = %std::io::Error::last_os_error(), "Failed to read free disk space");
+        return None;
+    }
+    // SAFETY: `statvfs` succeeded, so it initialized `stats`.
+    let stats = unsafe { stats.assume_init() };
+    Some((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub fn free_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disk_space_check() {
+        let dir = std::env::temp_dir();
+        assert!(free_bytes(&dir).is_some_and(|free| free > 0));
+        assert!(check(&dir, &ServerConfig::default()).await.is_ok());
+
+        let config = ServerConfig {
+            min_free_disk_mb: u64::MAX / MIB,
+            ..ServerConfig::default()
+        };
+        let e = check(&dir, &config).await.unwrap_err();
+        assert_eq!(e.code(), "insufficient_disk_space");
+        assert!(e.to_string().contains("`min_free_disk_mb`"), "{e}");
+    }
+}
@@ -0,0 +1,220 @@
+//! Composition of the final prompt sent to codex from the client prompt and
+//! server-injected context.
+
+use std::path::PathBuf;
+
+/// Extra context the server adds around the client's prompt.
+#[derive(Debug, Clone, Default)]
+pub struct PromptContext {
+    /// Workspace-relative files the task is scoped to.
+    pub focus_paths: Vec<PathBuf>,
+    /// Files provided as context for the task (`context_files`).
+    pub context_files: Vec<PathBuf>,
+    /// Notes from the workspace's project memory.
+    pub memory: Option<String>,
+    /// Language codex is told to respond in (`language`).
+    pub language: Option<String>,
+    /// Output instructions appended after the prompt (`prompt_postamble`).
+    pub postamble: Option<String>,
+    /// Instruction to answer in JSON matching `expected_output_schema`, last
+    /// so nothing after it asks for prose.
+    pub output_format: Option<String>,
+}
+
+/// English names of common BCP 47 language tags, so the instruction names the
+/// language whichever form the client passes.
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("hi", "Hindi"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("pt-br", "Brazilian Portuguese"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+    ("zh-cn", "Simplified Chinese"),
+    ("zh-hans", "Simplified Chinese"),
+    ("zh-hant", "Traditional Chinese"),
+    ("zh-tw", "Traditional Chinese"),
+];
+
+/// `language` as named in the instruction: the English name of a known tag
+/// such as `ja` or `pt_BR`, with the tag kept when only its primary language
+/// is known (`Japanese (ja-JP)`), and anything else as given.
+pub fn language_name(language: &str) -> String {
+    let tag = language.to_ascii_lowercase().replace('_', "-");
+    let name = |tag: &str| LANGUAGE_NAMES.iter().find(|(known, _)| *known == tag).map(|(_, name)| *name);
+    if let Some(name) = name(&tag) {
+        return name.to_string();
+    }
+    match tag.split_once('-').and_then(|(primary, _)| name(primary)) {
+        Some(name) => format!("{name} ({language})"),
+        None => language.to_string(),
+    }
+}
+
+/// Prompt of the read-only run that summarizes a session before it is
+/// continued in a fresh one (`summarize_session`).
+pub const SUMMARY_PROMPT: &str = "\
+Summarize this conversation so far so that it can be continued in a new session that has none of \
+its history. Do not modify any files.
+
+Include: the overall goal; decisions made and why; what has been done, with concrete file paths; \
+anything tried that did not work; open questions and the next steps. Be concise but keep every detail \
+needed to continue the work.";
+
+/// Seed the first prompt of a fresh session with a summary of the session it continues.
+pub fn with_summary(summary: &str, prompt: &str) -> String {
+    format!(
+        "This continues an earlier session whose history is not available. Summary of that session:\n\
+         <session-summary>\n{}\n</session-summary>\n\n{prompt}",
+        summary.trim()
+    )
+}
+
+/// Build the prompt passed to codex.
+pub fn compose(prompt: &str, context: &PromptContext) -> String {
+    let mut sections = Vec::new();
+
+    if let Some(ref memory) = context.memory {
+        sections.push(format!(
+            "Project memory: notes recorded in earlier sessions about this workspace's conventions and decisions. \
+             Follow them unless this task says otherwise.\n<project-memory>\n{memory}\n</project-memory>"
+        ));
+    }
+
+    if !context.context_files.is_empty() {
+        let files: Vec<String> = context
+            .context_files
+            .iter()
+            .map(|p| format!("- {}", p.display()))
+            .collect();
+        sections.push(format!(
+            "Context: read the following files, provided as context for this task, before starting.\n{}",
+            files.join("\n")
+        ));
+    }
+
+    if !context.focus_paths.is_empty() {
+        let files: Vec<String> = context
+            .focus_paths
+            .iter()
+            .map(|p| format!("- {}", p.display()))
+            .collect();
+        sections.push(format!(
+            "Scope: this task concerns only the following paths (relative to the workspace root). \
+             Read other files only when needed to understand them, and do not modify anything else.\n{}",
+            files.join("\n")
+        ));
+    }
+
+    let language = context.language.as_deref().map(|language| {
+        format!(
+            "Language: write all of your messages, explanations, and summaries in {}. \
+             Keep code, identifiers, file paths, and command output as they are.",
+            language_name(language)
+        )
+    });
+    let postamble = context.postamble.as_deref().map(str::trim).filter(|p| !p.is_empty());
+    if sections.is_empty() && language.is_none() && postamble.is_none() && context.output_format.is_none() {
+        return prompt.to_string();
+    }
+    sections.push(prompt.to_string());
+    sections.extend(language);
+    sections.extend(postamble.map(str::to_string));
+    sections.extend(context.output_format.clone());
+    sections.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_without_context_is_unchanged() {
+        assert_eq!(compose("fix it", &PromptContext::default()), "fix it");
+    }
+
+    #[test]
+    fn test_compose_prepends_focus_paths() {
+        let context = PromptContext {
+            focus_paths: vec![PathBuf::from("src/lib.rs"), PathBuf::from("tests")],
+            ..PromptContext::default()
+        };
+        let prompt = compose("fix it", &context);
+        assert!(prompt.starts_with("Scope: "));
+        assert!(prompt.contains("\n- src/lib.rs\n- tests\n\nfix it"));
+    }
+
+    #[test]
+    fn test_compose_puts_memory_first() {
+        let context = PromptContext {
+            focus_paths: vec![PathBuf::from("src")],
+            memory: Some("- Prefer small commits.".to_string()),
+            ..PromptContext::default()
+        };
+        let prompt = compose("fix it", &context);
+        assert!(prompt.starts_with("Project memory: "));
+        assert!(prompt.contains("<project-memory>\n- Prefer small commits.\n</project-memory>\n\nScope: "));
+
+        let with_context_files = PromptContext {
+            context_files: vec![PathBuf::from("/srv/uploads/1/design.md")],
+            ..context
+        };
+        let prompt = compose("fix it", &with_context_files);
+        assert!(prompt.contains("</project-memory>\n\nContext: "));
+        assert!(prompt.contains("\n- /srv/uploads/1/design.md\n\nScope: "));
+    }
+
+    #[test]
+    fn test_compose_appends_postamble() {
+        let context = PromptContext {
+            memory: Some("- Prefer small commits.".to_string()),
+            postamble: Some("End with a summary.\n".to_string()),
+            ..PromptContext::default()
+        };
+        let prompt = compose("fix it", &context);
+        assert!(prompt.ends_with("</project-memory>\n\nfix it\n\nEnd with a summary."), "{prompt}");
+
+        let structured = PromptContext {
+            output_format: Some("Output: JSON only.".to_string()),
+            ..context
+        };
+        assert!(compose("fix it", &structured).ends_with("End with a summary.\n\nOutput: JSON only."));
+
+        let blank = PromptContext {
+            postamble: Some("  ".to_string()),
+            ..PromptContext::default()
+        };
+        assert_eq!(compose("fix it", &blank), "fix it");
+    }
+
+    #[test]
+    fn test_compose_adds_language_instruction() {
+        let context = PromptContext {
+            language: Some("ja".to_string()),
+            postamble: Some("End with a summary.".to_string()),
+            ..PromptContext::default()
+        };
+        let prompt = compose("fix it", &context);
+        assert!(prompt.starts_with("fix it\n\nLanguage: write all of your messages, explanations, and summaries in Japanese."));
+        assert!(prompt.ends_with("\n\nEnd with a summary."));
+
+        assert_eq!(language_name("pt_BR"), "Brazilian Portuguese");
+        assert_eq!(language_name("ja-JP"), "Japanese (ja-JP)");
+        assert_eq!(language_name("Klingon"), "Klingon");
+    }
+}
@@ -0,0 +1,52 @@
+//! Random bytes from the operating system's generator, for values that must
+//! not be guessable (unlike correlation IDs, see `correlation`).
+
+use std::io;
+
+/// `N` fresh random bytes.
+#[cfg(unix)]
+pub fn bytes<const N: usize>() -> io::Result<[u8; N]> {
+    use std::io::Read;
+    let mut bytes = [0u8; N];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(windows)]
+pub fn bytes<const N: usize>() -> io::Result<[u8; N]> {
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 2;
+    #[link(name = "bcrypt")]
+    unsafe extern "system" {
+        fn BCryptGenRandom(algorithm: *mut std::ffi::c_void, buffer: *mut u8, len: u32, flags: u32) -> i32;
+    }
+    let mut bytes = [0u8; N];
+    // SAFETY: the buffer is valid for `N` bytes.
+    let status =
+        unsafe { BCryptGenRandom(std::ptr::null_mut(), bytes.as_mut_ptr(), N as u32, BCRYPT_USE_SYSTEM_PREFERRED_RNG) };
+    if status != 0 {
+        return Err(io::Error::other(format!("BCryptGenRandom failed with status {status:#x}")));
+    }
+    Ok(bytes)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn bytes<const N: usize>() -> io::Result<[u8; N]> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "no random number generator on this platform"))
+}
+
+/// A random 128-bit token as 32 lowercase hex digits.
+pub fn token() -> io::Result<String> {
+    Ok(bytes::<16>()?.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_distinct_hex() {
+        let (a, b) = (token().unwrap(), token().unwrap());
+        assert_ne!(a, b);
+        assert!(a.len() == 32 && a.bytes().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()), "{a}");
+    }
+}
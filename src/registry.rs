@@ -0,0 +1,334 @@
+//! Registry of codex sessions started through this server.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{self, Store, load, save, unix_now};
+
+/// Key of the persisted registry in the state store.
+const REGISTRY_KEY: &str = "sessions";
+
+/// Metadata recorded for a codex session.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionRecord {
+    /// Codex session (thread) ID, usable as `SESSION_ID` to resume.
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Workspace root the session runs in.
+    pub cd: PathBuf,
+
+    /// Human-readable label for the workstream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Free-form tags for finding the session later.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Model used by the most recent run, if one was specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Language the most recent run was asked to respond in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// Number of runs executed in this session through the server.
+    pub run_count: u64,
+
+    /// Whether the most recent run succeeded.
+    pub last_success: bool,
+
+    /// Unix timestamp (seconds) of the first run.
+    pub created_at: u64,
+
+    /// Unix timestamp (seconds) of the most recent run.
+    pub updated_at: u64,
+
+    /// Client that created the session on a multi-client transport.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// Outcome of a single run, used to update the registry.
+#[derive(Debug, Clone)]
+pub struct RunRecord<'a> {
+    pub session_id: &'a str,
+    pub cd: &'a Path,
+    pub label: Option<&'a str>,
+    pub tags: &'a [String],
+    pub model: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub success: bool,
+    pub owner: Option<&'a str>,
+}
+
+/// Criteria for listing sessions. Empty criteria match every session.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    /// Sessions must carry every one of these tags.
+    pub tags: Vec<String>,
+    /// Case-insensitive substring the session label must contain.
+    pub label: Option<String>,
+    /// Workspace root the session must run in.
+    pub cd: Option<PathBuf>,
+    /// Client that must own the session.
+    pub owner: Option<String>,
+}
+
+impl SessionFilter {
+    fn matches(&self, record: &SessionRecord) -> bool {
+        let tags_match = self.tags.iter().all(|tag| record.tags.contains(tag));
+        let label_match = match self.label {
+            Some(ref needle) => record
+                .label
+                .as_deref()
+                .is_some_and(|label| label.to_lowercase().contains(&needle.to_lowercase())),
+            None => true,
+        };
+        let cd_match = self.cd.as_ref().is_none_or(|cd| *cd == record.cd);
+        let owner_match = self
+            .owner
+            .as_ref()
+            .is_none_or(|owner| record.owner.as_ref() == Some(owner));
+        tags_match && label_match && cd_match && owner_match
+    }
+}
+
+/// Session registry, optionally persisted as JSON in the server state directory.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    store: Option<Store>,
+    sessions: Mutex<HashMap<String, SessionRecord>>,
+}
+
+impl SessionRegistry {
+    /// Open the registry, loading previously persisted sessions from `store`.
+    /// Without a store the registry lives in memory only.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let sessions = load(&*store, REGISTRY_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store: Some(store),
+            sessions: Mutex::new(sessions),
+        })
+    }
+
+    /// Record a run, creating the session entry on first use.
+    ///
+    /// Tags accumulate across runs; a non-empty label replaces the previous one.
+    pub fn record_run(&self, run: RunRecord<'_>) {
+        let now = unix_now();
+        if let Some(store) = self.shared_store() {
+            match state::update(&**store, REGISTRY_KEY, |sessions| apply_run(sessions, &run, now)) {
+                Ok((sessions, ())) => {
+                    *self.sessions.lock().unwrap() = sessions;
+                    return;
+                }
+                Err(e) => tracing::warn!(key = REGISTRY_KEY, error = %e, "Failed to update shared session registry"),
+            }
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        apply_run(&mut sessions, &run, now);
+        self.persist(&sessions);
+    }
+
+    /// Look up a session by ID.
+    pub fn get(&self, session_id: &str) -> Option<SessionRecord> {
+        self.refresh();
+        self.sessions.lock().unwrap().get(session_id).cloned()
+    }
+
+    /// List sessions matching `filter`, most recently used first.
+    pub fn list(&self, filter: &SessionFilter) -> Vec<SessionRecord> {
+        self.refresh();
+        let sessions = self.sessions.lock().unwrap();
+        let mut records: Vec<SessionRecord> = sessions
+            .values()
+            .filter(|record| filter.matches(record))
+            .cloned()
+            .collect();
+        records.sort_by_key(|r| std::cmp::Reverse(r.updated_at));
+        records
+    }
+
+    /// Forget a session, returning whether it was recorded.
+    pub fn remove(&self, session_id: &str) -> Result<bool> {
+        let remove = |sessions: &mut HashMap<String, SessionRecord>| sessions.remove(session_id).is_some();
+        if let Some(store) = self.shared_store() {
+            let (sessions, removed) = state::update(&**store, REGISTRY_KEY, remove)?;
+            *self.sessions.lock().unwrap() = sessions;
+            return Ok(removed);
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        let removed = remove(&mut sessions);
+        if removed && let Some(ref store) = self.store {
+            save(&**store, REGISTRY_KEY, &*sessions)?;
+        }
+        Ok(removed)
+    }
+
+    /// The store, when other replicas write to it too.
+    fn shared_store(&self) -> Option<&Store> {
+        self.store.as_ref().filter(|store| store.shared())
+    }
+
+    /// Pick up sessions other replicas recorded in a shared store. On failure
+    /// the last copy read is used.
+    fn refresh(&self) {
+        let Some(store) = self.shared_store() else {
+            return;
+        };
+        match load(&**store, REGISTRY_KEY) {
+            Ok(sessions) => *self.sessions.lock().unwrap() = sessions.unwrap_or_default(),
+            Err(e) => tracing::warn!(key = REGISTRY_KEY, error = %e, "Failed to read shared session registry"),
+        }
+    }
+
+    /// Persist the registry. Failures are logged rather than failing the run.
+    fn persist(&self, sessions: &HashMap<String, SessionRecord>) {
+        let Some(ref store) = self.store else {
+            return;
+        };
+        if let Err(e) = save(&**store, REGISTRY_KEY, sessions) {
+            tracing::warn!(key = REGISTRY_KEY, error = %e, "Failed to persist session registry");
+        }
+    }
+}
+
+/// Add `run` to `sessions`.
+fn apply_run(sessions: &mut HashMap<String, SessionRecord>, run: &RunRecord<'_>, now: u64) {
+    let record = sessions
+        .entry(run.session_id.to_string())
+        .or_insert_with(|| SessionRecord {
+            session_id: run.session_id.to_string(),
+            cd: run.cd.to_path_buf(),
+            label: None,
+            tags: Vec::new(),
+            model: None,
+            language: None,
+            run_count: 0,
+            last_success: false,
+            created_at: now,
+            updated_at: now,
+            owner: run.owner.map(str::to_string),
+        });
+
+    record.cd = run.cd.to_path_buf();
+    if let Some(label) = run.label.filter(|l| !l.is_empty()) {
+        record.label = Some(label.to_string());
+    }
+    for tag in run.tags {
+        if !tag.is_empty() && !record.tags.contains(tag) {
+            record.tags.push(tag.clone());
+        }
+    }
+    if let Some(model) = run.model {
+        record.model = Some(model.to_string());
+    }
+    record.language = run.language.map(str::to_string);
+    record.run_count += 1;
+    record.last_success = run.success;
+    record.updated_at = now;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FileStore;
+    use std::sync::Arc;
+
+    fn run<'a>(id: &'a str, label: Option<&'a str>, tags: &'a [String]) -> RunRecord<'a> {
+        RunRecord {
+            session_id: id,
+            cd: Path::new("/work"),
+            label,
+            tags,
+            model: None,
+            language: None,
+            success: true,
+            owner: None,
+        }
+    }
+
+    #[test]
+    fn test_record_run_merges_tags_and_label() {
+        let registry = SessionRegistry::default();
+        registry.record_run(run("s1", Some("Auth refactor"), &["backend".to_string()]));
+        registry.record_run(run("s1", None, &["auth".to_string(), "backend".to_string()]));
+
+        let sessions = registry.list(&SessionFilter::default());
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].label.as_deref(), Some("Auth refactor"));
+        assert_eq!(sessions[0].tags, ["backend", "auth"]);
+        assert_eq!(sessions[0].run_count, 2);
+    }
+
+    #[test]
+    fn test_list_filters_by_tags_and_label() {
+        let registry = SessionRegistry::default();
+        registry.record_run(run("s1", Some("Auth refactor"), &["backend".to_string()]));
+        registry.record_run(run("s2", Some("Docs"), &["frontend".to_string()]));
+
+        let by_tag = SessionFilter {
+            tags: vec!["frontend".to_string()],
+            ..SessionFilter::default()
+        };
+        assert_eq!(registry.list(&by_tag)[0].session_id, "s2");
+
+        let by_label = SessionFilter {
+            label: Some("auth".to_string()),
+            ..SessionFilter::default()
+        };
+        assert_eq!(registry.list(&by_label)[0].session_id, "s1");
+
+        let none = SessionFilter {
+            tags: vec!["backend".to_string()],
+            label: Some("docs".to_string()),
+            ..SessionFilter::default()
+        };
+        assert!(registry.list(&none).is_empty());
+    }
+
+    #[test]
+    fn test_list_filters_by_owner() {
+        let registry = SessionRegistry::default();
+        registry.record_run(RunRecord {
+            owner: Some("uid:1000"),
+            ..run("s1", None, &[])
+        });
+        registry.record_run(RunRecord {
+            owner: Some("uid:1001"),
+            ..run("s2", None, &[])
+        });
+
+        let mine = SessionFilter {
+            owner: Some("uid:1000".to_string()),
+            ..SessionFilter::default()
+        };
+        let sessions = registry.list(&mine);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, "s1");
+    }
+
+    #[test]
+    fn test_registry_persists_to_state_dir() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-registry-{}", std::process::id()));
+        let registry = SessionRegistry::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        registry.record_run(run("s1", Some("persisted"), &[]));
+
+        let reopened = SessionRegistry::open(Some(Arc::new(FileStore::new(&dir)))).unwrap();
+        let sessions = reopened.list(&SessionFilter::default());
+        assert_eq!(sessions[0].label.as_deref(), Some("persisted"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+//! Config hot reload: re-read the config file on SIGHUP or when it changes,
+//! and apply it to the running server without disconnecting clients.
+//!
+//! Limits, allowlists, policy rules, and the log filter apply from the next
+//! run or log line; settings only read at startup (`state_dir`, the tool
+//! lists, schedules) keep their values until a restart.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::Notify;
+
+use crate::codex::CodexServer;
+use crate::config::ServerConfig;
+
+/// Log filter used when neither `RUST_LOG` nor `log_level` is set.
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Replaces the active log filter, e.g. through a `tracing_subscriber` reload handle.
+pub type SetLogFilter = Box<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+/// Reload `path` into `server` on SIGHUP, or whenever its modification time
+/// changes, checked every `config_watch_interval_secs`.
+pub fn spawn(server: CodexServer, path: PathBuf, set_log_filter: SetLogFilter) {
+    let hangup = Arc::new(Notify::new());
+    #[cfg(unix)]
+    spawn_hangup_listener(hangup.clone());
+
+    tokio::spawn(async move {
+        let mut modified = modified_time(&path);
+        loop {
+            let interval = server.config().config_watch_interval_secs;
+            tokio::select! {
+                () = hangup.notified() => tracing::info!("Reloading config on SIGHUP"),
+                () = sleep_secs(interval) => {
+                    if modified_time(&path) == modified {
+                        continue;
+                    }
+                    tracing::info!(path = %path.display(), "Config file changed; reloading");
+                }
+            }
+            modified = modified_time(&path);
+            reload(&server, &path, &set_log_filter);
+        }
+    });
+}
+
+#[cfg(unix)]
+fn spawn_hangup_listener(hangup: Arc<Notify>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::hangup()) {
+        Ok(mut signal) => {
+            tokio::spawn(async move {
+                while signal.recv().await.is_some() {
+                    hangup.notify_one();
+                }
+            });
+        }
+        Err(e) => tracing::warn!(error = %e, "Failed to install SIGHUP handler"),
+    }
+}
+
+/// Sleep for `secs`, or forever when 0.
+async fn sleep_secs(secs: u64) {
+    if secs == 0 {
+        std::future::pending::<()>().await;
+    }
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Load `path` and apply it, keeping the current config when it is invalid.
+fn reload(server: &CodexServer, path: &Path, set_log_filter: &SetLogFilter) {
+    let config = match ServerConfig::load_file(path) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!(error = format!("{e:#}"), "Failed to reload config; keeping the current one");
+            return;
+        }
+    };
+    let log_level = config.log_level.clone();
+    match server.reload_config(config) {
+        Ok(kept) if kept.is_empty() => tracing::info!("Config reloaded"),
+        Ok(kept) => tracing::warn!(
+            settings = kept.join(", "),
+            "Config reloaded; changes to settings read only at startup take a restart"
+        ),
+        Err(e) => {
+            tracing::warn!(error = format!("{e:#}"), "Failed to reload policy rules; keeping the current config");
+            return;
+        }
+    }
+    if std::env::var_os("RUST_LOG").is_none()
+        && let Err(e) = set_log_filter(log_level.as_deref().unwrap_or(DEFAULT_LOG_FILTER))
+    {
+        tracing::warn!(error = %e, "Failed to apply reloaded `log_level`");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_applies_changeable_settings() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        let filters = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let set_log_filter: SetLogFilter = {
+            let filters = filters.clone();
+            Box::new(move |filter| {
+                filters.lock().unwrap().push(filter.to_string());
+                Ok(())
+            })
+        };
+
+        std::fs::write(&path, "daily_budget_usd = 5.0\nstate_dir = \"/tmp/elsewhere\"\nlog_level = \"debug\"\n").unwrap();
+        reload(&server, &path, &set_log_filter);
+        assert_eq!(server.config().daily_budget_usd, Some(5.0));
+        assert_eq!(server.config().state_dir, None, "state_dir takes a restart");
+        if std::env::var_os("RUST_LOG").is_none() {
+            assert_eq!(*filters.lock().unwrap(), ["debug"]);
+        }
+
+        std::fs::write(&path, "daily_budget_usd = \"lots\"\n").unwrap();
+        reload(&server, &path, &set_log_filter);
+        assert_eq!(server.config().daily_budget_usd, Some(5.0), "an invalid file is ignored");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
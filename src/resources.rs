@@ -0,0 +1,275 @@
+//! Run outputs stored in the state directory and exposed to clients as MCP resources.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use rmcp::model::RawResource;
+
+use crate::secrets;
+
+/// URI scheme of resources served by this server.
+pub const URI_SCHEME: &str = "codex-mcp://";
+
+/// Kinds of stored run output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Full JSONL event transcript of a run.
+    Transcript,
+    /// Untruncated final agent message of a run.
+    AgentMessages,
+}
+
+impl ResourceKind {
+    const ALL: [ResourceKind; 2] = [ResourceKind::Transcript, ResourceKind::AgentMessages];
+
+    /// Directory inside the state directory, also used as the URI authority.
+    fn dir_name(self) -> &'static str {
+        match self {
+            ResourceKind::Transcript => "transcripts",
+            ResourceKind::AgentMessages => "agent_messages",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ResourceKind::Transcript => "jsonl",
+            ResourceKind::AgentMessages => "txt",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ResourceKind::Transcript => "application/x-ndjson",
+            ResourceKind::AgentMessages => "text/plain",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ResourceKind::Transcript => "Codex JSONL event transcript",
+            ResourceKind::AgentMessages => "Full codex agent message",
+        }
+    }
+
+    fn uri_prefix(self) -> String {
+        format!("{URI_SCHEME}{}/", self.dir_name())
+    }
+}
+
+/// A run output written to the state directory.
+#[derive(Debug, Clone)]
+pub struct StoredResource {
+    pub kind: ResourceKind,
+    pub name: String,
+    pub size: u64,
+}
+
+impl StoredResource {
+    /// Resource URI, e.g. `codex-mcp://transcripts/<name>`.
+    pub fn uri(&self) -> String {
+        format!("{}{}", self.kind.uri_prefix(), self.name)
+    }
+
+    /// Session the output belongs to, from the `<SESSION_ID>-<millis>` name.
+    pub fn session_id(&self) -> Option<&str> {
+        self.name
+            .rsplit_once('-')
+            .map(|(session, _)| session)
+            .filter(|session| *session != "run")
+    }
+
+    /// MCP resource description.
+    pub fn to_resource(&self) -> RawResource {
+        let mut resource = RawResource::new(self.uri(), self.name.clone());
+        resource.description = Some(self.kind.description().to_string());
+        resource.mime_type = Some(self.kind.mime_type().to_string());
+        resource.size = u32::try_from(self.size).ok();
+        resource
+    }
+}
+
+/// Stored run outputs under `<state_dir>/<kind>/`. Disabled without a state directory.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceStore {
+    root: Option<PathBuf>,
+}
+
+impl ResourceStore {
+    pub fn new(state_dir: Option<&Path>) -> Self {
+        Self {
+            root: state_dir.map(Path::to_path_buf),
+        }
+    }
+
+    /// Whether outputs can be stored.
+    pub fn is_enabled(&self) -> bool {
+        self.root.is_some()
+    }
+
+    fn path(&self, kind: ResourceKind, name: &str) -> Result<PathBuf> {
+        let Some(ref root) = self.root else {
+            bail!("resource storage requires `state_dir` to be configured");
+        };
+        validate_name(name)?;
+        Ok(root
+            .join(kind.dir_name())
+            .join(format!("{name}.{}", kind.extension())))
+    }
+
+    /// Write `contents` as a resource of `kind` named `name`.
+    pub fn save(&self, kind: ResourceKind, name: &str, contents: &str) -> Result<StoredResource> {
+        let path = self.path(kind, name)?;
+        let contents = secrets::redact(contents);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, contents.as_bytes())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        Ok(StoredResource {
+            kind,
+            name: name.to_string(),
+            size: contents.len() as u64,
+        })
+    }
+
+    /// Write `events` as a JSONL transcript named `name`.
+    pub fn save_transcript(&self, name: &str, events: &[serde_json::Value]) -> Result<StoredResource> {
+        let mut text = String::new();
+        for event in events {
+            text.push_str(&event.to_string());
+            text.push('\n');
+        }
+        self.save(ResourceKind::Transcript, name, &text)
+    }
+
+    /// Read a resource by URI. Returns `None` if the URI is not served by this
+    /// store or the resource does not exist.
+    pub fn read(&self, uri: &str) -> Result<Option<(StoredResource, String)>> {
+        let Some((kind, name)) = ResourceKind::ALL
+            .into_iter()
+            .find_map(|kind| uri.strip_prefix(&kind.uri_prefix()).map(|name| (kind, name)))
+        else {
+            return Ok(None);
+        };
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let path = self.path(kind, name)?;
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some((
+                StoredResource {
+                    kind,
+                    name: name.to_string(),
+                    size: text.len() as u64,
+                },
+                text,
+            ))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete a stored resource, if it still exists.
+    pub fn remove(&self, resource: &StoredResource) -> Result<()> {
+        let path = self.path(resource.kind, &resource.name)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("failed to delete {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Delete the outputs last written before `cutoff`, returning how many.
+    pub fn remove_older_than(&self, cutoff: SystemTime) -> Result<usize> {
+        let mut removed = 0;
+        for resource in self.list()? {
+            let modified = std::fs::metadata(self.path(resource.kind, &resource.name)?).and_then(|m| m.modified());
+            if modified.is_ok_and(|modified| modified < cutoff) {
+                self.remove(&resource)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// List stored resources of every kind.
+    pub fn list(&self) -> Result<Vec<StoredResource>> {
+        let Some(ref root) = self.root else {
+            return Ok(Vec::new());
+        };
+
+        let mut resources = Vec::new();
+        for kind in ResourceKind::ALL {
+            let entries = match std::fs::read_dir(root.join(kind.dir_name())) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == kind.extension())
+                    && let Some(name) = path.file_stem().and_then(|s| s.to_str())
+                {
+                    resources.push(StoredResource {
+                        kind,
+                        name: name.to_string(),
+                        size: entry.metadata()?.len(),
+                    });
+                }
+            }
+        }
+        resources.sort_by_key(StoredResource::uri);
+        Ok(resources)
+    }
+}
+
+/// Resource names become file names, so only allow a conservative character set.
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("invalid resource name: {name:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_read_round_trip() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-resources-{}", std::process::id()));
+        let store = ResourceStore::new(Some(&dir));
+        let events = vec![serde_json::json!({"type": "thread.started"})];
+
+        let stored = store.save_transcript("run-1", &events).unwrap();
+        assert_eq!(stored.uri(), "codex-mcp://transcripts/run-1");
+        let (resource, text) = store.read(&stored.uri()).unwrap().unwrap();
+        assert_eq!(resource.kind, ResourceKind::Transcript);
+        assert_eq!(resource.session_id(), None);
+        assert_eq!(text, "{\"type\":\"thread.started\"}\n");
+
+        let message = store.save(ResourceKind::AgentMessages, "run-1", "hello").unwrap();
+        assert_eq!(message.uri(), "codex-mcp://agent_messages/run-1");
+        assert_eq!(store.list().unwrap().len(), 2);
+        assert!(store.read("codex-mcp://transcripts/missing").unwrap().is_none());
+        assert!(store.read("file:///etc/passwd").unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_path_traversal_names() {
+        let store = ResourceStore::new(Some(Path::new("/tmp")));
+        assert!(store.read("codex-mcp://transcripts/../etc/passwd").is_err());
+        assert!(store.save(ResourceKind::Transcript, "a/b", "").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! `codex_self_review`: a read-only codex run critiquing what an earlier
+//! session changed, for plan → edit → review pipelines.
+//!
+//! The diff under review covers the files the session's stored transcripts
+//! report changing, or every uncommitted change in its workspace when no
+//! transcript is stored. The reviewer answers with JSON matching
+//! [`answer_schema`], parsed into structured comments.
+
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::file_changes::FileChange;
+use crate::usage::TokenUsage;
+
+/// Largest diff inlined in the review prompt, in bytes. The reviewer reads
+/// the rest with git.
+pub const MAX_DIFF_BYTES: usize = 64 * 1024;
+
+/// Parameters for the codex_self_review tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SelfReviewParams {
+    /// Session whose changes to review.
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Model for the review run, e.g. a different one than made the changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// What to pay particular attention to, e.g. "error handling".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+}
+
+/// The reviewer's overall judgement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewVerdict {
+    Approve,
+    RequestChanges,
+}
+
+/// How much a review comment matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewSeverity {
+    Critical,
+    Major,
+    Minor,
+    Nit,
+}
+
+/// One finding of the review.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReviewComment {
+    /// File the comment is about, relative to the workspace root.
+    pub path: String,
+    /// Line of the file after the change, if the comment is about one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    pub severity: ReviewSeverity,
+    pub comment: String,
+}
+
+/// The reviewer's answer, as [`answer_schema`] describes it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReviewAnswer {
+    pub verdict: ReviewVerdict,
+    pub summary: String,
+    pub comments: Vec<ReviewComment>,
+}
+
+/// Result returned by the codex_self_review tool.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SelfReviewResult {
+    pub success: bool,
+
+    /// Session whose changes were reviewed.
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Workspace of the reviewed session.
+    pub cd: PathBuf,
+
+    /// Files the review covered.
+    pub files: Vec<String>,
+
+    /// Whether the diff was too large to include in the prompt in full; the
+    /// reviewer was told to read the rest with git.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub diff_truncated: bool,
+
+    /// Session of the review run, which can be resumed to discuss it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_session_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<ReviewVerdict>,
+
+    /// The reviewer's overall assessment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+
+    /// Findings, most severe first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<ReviewComment>,
+
+    /// Token usage of the review run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+impl SelfReviewResult {
+    /// Fill in the review from the run's `answer`.
+    pub fn set_answer(&mut self, answer: ReviewAnswer) {
+        self.verdict = Some(answer.verdict);
+        self.summary = Some(answer.summary);
+        self.comments = answer.comments;
+        self.comments.sort_by_key(|comment| comment.severity as u8);
+    }
+}
+
+/// Paths that the `file_change` items among `events` report, in first-seen
+/// order.
+pub fn changed_paths(events: &[Value]) -> Vec<String> {
+    let mut paths = Vec::new();
+    let file_changes = events
+        .iter()
+        .filter(|event| event["type"] == "item.completed" && event["item"]["type"] == "file_change");
+    for event in file_changes {
+        let changes = event["item"]["changes"].as_array().into_iter().flatten();
+        for change in changes.filter_map(FileChange::from_event) {
+            if !paths.contains(&change.path) {
+                paths.push(change.path);
+            }
+        }
+    }
+    paths
+}
+
+/// JSON Schema of the reviewer's answer.
+pub fn answer_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["verdict", "summary", "comments"],
+        "properties": {
+            "verdict": {"enum": ["approve", "request_changes"]},
+            "summary": {"type": "string", "minLength": 1},
+            "comments": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["path", "severity", "comment"],
+                    "properties": {
+                        "path": {"type": "string"},
+                        "line": {"type": "integer", "minimum": 1},
+                        "severity": {"enum": ["critical", "major", "minor", "nit"]},
+                        "comment": {"type": "string", "minLength": 1},
+                    },
+                },
+            },
+        },
+    })
+}
+
+/// Prompt asking codex to review `diff` of `files`, of which only the first
+/// `MAX_DIFF_BYTES` are inlined when `truncated`.
+pub fn prompt(diff: &str, files: &[String], truncated: bool, focus: Option<&str>) -> String {
+    let mut prompt = String::from(
+        "\
+Review the following uncommitted changes to this repository as a meticulous senior engineer would review a pull \
+request. Do not modify any files.
+
+Look for bugs, unhandled edge cases and errors, security problems, missing or inadequate tests, and code that does \
+not follow the repository's existing conventions. Check the changed code against the code around it, reading other \
+files as needed. Report each finding once, on the line it concerns, with a concrete suggestion; do not comment on \
+code the changes did not touch, and do not pad the review with praise.
+
+Verdict: `approve` if the changes can be merged as they are (at most minor issues and nits), otherwise \
+`request_changes`.",
+    );
+    if let Some(focus) = focus.map(str::trim).filter(|focus| !focus.is_empty()) {
+        prompt.push_str(&format!("\n\nPay particular attention to: {focus}"));
+    }
+    prompt.push_str(&format!("\n\nChanged files:\n{}", files.iter().map(|f| format!("- {f}\n")).collect::<String>()));
+    if truncated {
+        prompt.push_str(&format!(
+            "\nThe diff below is cut off after {} KiB; run `git diff HEAD -- <file>` to read the rest.\n",
+            MAX_DIFF_BYTES / 1024
+        ));
+    }
+    prompt.push_str(&format!("\n```diff\n{}\n```", diff.trim_end()));
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_schema;
+
+    #[test]
+    fn test_review_answers_are_checked_and_sorted() {
+        let answer = r#"{
+            "verdict": "request_changes",
+            "summary": "Mostly fine.",
+            "comments": [
+                {"path": "src/lib.rs", "severity": "nit", "comment": "Typo."},
+                {"path": "src/lib.rs", "line": 3, "severity": "critical", "comment": "Panics on empty input."}
+            ]
+        }"#;
+        let value = output_schema::parse_answer(&answer_schema(), answer).unwrap();
+        let mut result = SelfReviewResult::default();
+        result.set_answer(serde_json::from_value(value).unwrap());
+        assert_eq!(result.verdict, Some(ReviewVerdict::RequestChanges));
+        let severities: Vec<ReviewSeverity> = result.comments.iter().map(|c| c.severity).collect();
+        assert_eq!(severities, [ReviewSeverity::Critical, ReviewSeverity::Nit]);
+
+        let invalid = r#"{"verdict": "lgtm", "summary": "ok", "comments": []}"#;
+        assert!(output_schema::parse_answer(&answer_schema(), invalid).is_err());
+
+        let prompt = prompt("+fn f() {}\n", &["src/lib.rs".to_string()], true, Some("naming"));
+        assert!(prompt.contains("Pay particular attention to: naming"));
+        assert!(prompt.contains("- src/lib.rs\n"));
+        assert!(prompt.contains("cut off after 64 KiB"));
+        assert!(prompt.ends_with("```diff\n+fn f() {}\n```"));
+    }
+}
@@ -0,0 +1,218 @@
+//! Declarative policy rules loaded from the server's `policy_file`.
+//!
+//! Each rule has conditions (`when`) selecting the runs it applies to and
+//! requirements (`require`) those runs must meet, e.g. "runs under /srv/prod
+//! must be read-only". Every applicable rule is checked against the options
+//! a run would actually use, and all violations are reported together.
+//!
+//! ```toml
+//! [[rules]]
+//! name = "prod is read-only"
+//! when = { cd_under = ["/srv/prod"] }
+//! require = { max_sandbox = "read-only", allow_yolo = false }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::codex::{EffectiveOptions, SandboxPolicy};
+use crate::policy;
+
+/// Rules loaded from a policy file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRules {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    /// Name reported with violations.
+    name: String,
+    #[serde(default)]
+    when: Conditions,
+    require: Requirements,
+}
+
+/// Which runs a rule applies to. Empty lists match everything; all
+/// non-empty conditions must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Conditions {
+    /// Tools (or `scheduler`) the run was requested through.
+    tools: Vec<String>,
+    /// Directories the run's `cd` lies under.
+    cd_under: Vec<PathBuf>,
+    /// Client identities on multi-client transports, e.g. `uid:1000`.
+    clients: Vec<String>,
+}
+
+/// What an applicable rule requires of a run.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Requirements {
+    /// Most permissive sandbox allowed; `yolo` counts as `danger-full-access`.
+    max_sandbox: Option<SandboxPolicy>,
+    /// Set to `false` to forbid `yolo`.
+    allow_yolo: Option<bool>,
+    /// Set to `false` to forbid network access.
+    allow_network: Option<bool>,
+    /// Models that may be requested; runs using codex's default model pass.
+    models: Vec<String>,
+}
+
+/// The request a rule is evaluated against.
+#[derive(Debug)]
+pub struct RuleInput<'a> {
+    pub tool: &'a str,
+    pub client: Option<&'a str>,
+    pub options: &'a EffectiveOptions,
+}
+
+/// Why a rule denied a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PolicyViolation {
+    /// Name of the violated rule.
+    pub rule: String,
+    /// Parameter that violated it.
+    pub parameter: String,
+    pub reason: String,
+}
+
+impl PolicyRules {
+    /// Load rules from a TOML policy file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read policy file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse policy file {}", path.display()))
+    }
+
+    /// Violations of every rule that applies to `input`.
+    pub fn evaluate(&self, input: &RuleInput<'_>) -> Vec<PolicyViolation> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.when.matches(input))
+            .flat_map(|rule| rule.require.violations(&rule.name, input.options))
+            .collect()
+    }
+}
+
+impl Conditions {
+    fn matches(&self, input: &RuleInput<'_>) -> bool {
+        (self.tools.is_empty() || self.tools.iter().any(|t| t == input.tool))
+            && (self.clients.is_empty() || input.client.is_some_and(|c| self.clients.iter().any(|e| e == c)))
+            // `cd` may not exist yet, for a run that creates it.
+            && (self.cd_under.is_empty() || policy::resolve_new_within(&input.options.cd, &self.cd_under).is_some())
+    }
+}
+
+impl Requirements {
+    fn violations(&self, rule: &str, options: &EffectiveOptions) -> Vec<PolicyViolation> {
+        let mut violations = Vec::new();
+        let mut deny = |parameter: &str, reason: String| {
+            violations.push(PolicyViolation {
+                rule: rule.to_string(),
+                parameter: parameter.to_string(),
+                reason,
+            })
+        };
+
+        if let Some(ref max) = self.max_sandbox {
+            let sandbox = if options.yolo { &SandboxPolicy::DangerFullAccess } else { &options.sandbox };
+            if sandbox.rank() > max.rank() {
+                deny("sandbox", format!("sandbox `{}` exceeds the maximum `{}`", sandbox.as_str(), max.as_str()));
+            }
+        }
+        if self.allow_yolo == Some(false) && options.yolo {
+            deny("yolo", "`yolo` is not allowed".to_string());
+        }
+        if self.allow_network == Some(false) && options.network_access == Some(true) {
+            deny("allow_network", "network access is not allowed".to_string());
+        }
+        if let Some(ref model) = options.model
+            && !self.models.is_empty()
+            && !self.models.contains(model)
+        {
+            deny("model", format!("model `{model}` is not one of: {}", self.models.join(", ")));
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(cd: &Path, sandbox: SandboxPolicy, model: Option<&str>) -> EffectiveOptions {
+        serde_json::from_value(serde_json::json!({
+            "sandbox": sandbox,
+            "approval_policy": "never",
+            "cd": cd,
+            "model": model,
+            "resume_last": false,
+            "images": [],
+            "writable_roots": [],
+            "focus_paths": [],
+            "yolo": false,
+            "skip_git_repo_check": false,
+            "args": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rules_apply_by_cd_and_report_violations() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-rules-{}", std::process::id()));
+        let prod = root.join("prod");
+        let dev = root.join("dev");
+        std::fs::create_dir_all(&prod).unwrap();
+        std::fs::create_dir_all(&dev).unwrap();
+        let rules: PolicyRules = toml::from_str(&format!(
+            r#"
+            [[rules]]
+            name = "prod is read-only"
+            when = {{ cd_under = [{prod:?}] }}
+            require = {{ max_sandbox = "read-only" }}
+
+            [[rules]]
+            name = "approved models"
+            when = {{ tools = ["codex"] }}
+            require = {{ models = ["gpt-5"] }}
+            "#
+        ))
+        .unwrap();
+        let check = |tool, options: &EffectiveOptions| {
+            rules.evaluate(&RuleInput {
+                tool,
+                client: None,
+                options,
+            })
+        };
+
+        let write_prod = options(&prod, SandboxPolicy::WorkspaceWrite, Some("o3"));
+        let violations = check("codex", &write_prod);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].rule, "prod is read-only");
+        assert_eq!(violations[0].parameter, "sandbox");
+        assert_eq!(violations[1].parameter, "model");
+        assert_eq!(check("codex_start", &write_prod).len(), 1);
+
+        assert!(check("codex", &options(&dev, SandboxPolicy::WorkspaceWrite, None)).is_empty());
+        assert!(check("codex", &options(&prod, SandboxPolicy::ReadOnly, Some("gpt-5"))).is_empty());
+        let new_in_prod = options(&prod.join("scratch"), SandboxPolicy::WorkspaceWrite, Some("gpt-5"));
+        assert_eq!(check("codex", &new_in_prod).len(), 1);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_policy_file_rejects_unknown_keys() {
+        assert!(toml::from_str::<PolicyRules>("[[rules]]\nname = \"x\"\nrequire = { max_sandbx = \"read-only\" }").is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! `run_as_user`/`run_as_group`: run codex as a dedicated low-privilege user
+//! when the server itself runs as root, as is common in containers, so a
+//! run's commands cannot touch what only root may.
+//!
+//! Codex gets the user's `HOME` and `USER`, and the server's `CODEX_HOME`, so
+//! both keep reading and writing the same session files; that directory must
+//! be writable by the user. Files the server itself writes into a workspace
+//! (a created `cd`, the memory file, commits under `.git`) are handed over to
+//! the user, so the next run can still modify them.
+
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+use crate::codex_home;
+use crate::config::ServerConfig;
+use crate::error::CodexError;
+
+/// Unix identity codex runs as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunAs {
+    uid: u32,
+    gid: u32,
+    name: Option<String>,
+    home: Option<PathBuf>,
+}
+
+/// The identity codex runs as under `config`, or `None` to keep the server's.
+pub fn resolve(config: &ServerConfig) -> Result<Option<RunAs>, CodexError> {
+    let Some(ref user) = config.run_as_user else {
+        if config.run_as_group.is_some() {
+            return Err(CodexError::RunAsUnavailable("`run_as_group` requires `run_as_user`".to_string()));
+        }
+        return Ok(None);
+    };
+    imp::resolve(user, config.run_as_group.as_deref()).map(Some)
+}
+
+impl RunAs {
+    /// Run `cmd` as this identity.
+    pub fn apply(&self, cmd: &mut Command) {
+        imp::apply(self, cmd);
+        if let Some(ref home) = self.home {
+            cmd.env("HOME", home);
+        }
+        if let Some(ref name) = self.name {
+            cmd.env("USER", name).env("LOGNAME", name);
+        }
+        if let Some(dir) = codex_home::codex_home() {
+            cmd.env("CODEX_HOME", dir);
+        }
+    }
+
+    /// Give `path`, and everything under it if it is a directory, to this
+    /// identity. Failures are logged rather than failing the operation that
+    /// wrote the files.
+    pub fn take_ownership(&self, path: &Path) {
+        if let Err(e) = imp::chown_tree(self, path) {
+            tracing::warn!(path = %path.display(), uid = self.uid, error = %e, "Failed to hand files over to `run_as_user`");
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    use tokio::process::Command;
+
+    use super::RunAs;
+    use crate::error::CodexError;
+
+    fn unavailable(reason: impl Into<String>) -> CodexError {
+        CodexError::RunAsUnavailable(reason.into())
+    }
+
+    /// A passwd entry's UID, primary GID, name, and home directory.
+    fn passwd(user: &str) -> Result<Option<(u32, u32, String, PathBuf)>, CodexError> {
+        // SAFETY: `passwd` is plain old data, valid when zeroed.
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; 16 * 1024];
+        let mut found = std::ptr::null_mut();
+        let status = match user.parse::<u32>() {
+            // SAFETY: every pointer is valid for the call, and `buf` outlives the
+            // borrowed strings in `entry`, which are copied below.
+            Ok(uid) => unsafe { libc::getpwuid_r(uid, &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) },
+            Err(_) => {
+                let name = CString::new(user).map_err(|_| unavailable(format!("invalid user name {user:?}")))?;
+                // SAFETY: as above.
+                unsafe { libc::getpwnam_r(name.as_ptr(), &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) }
+            }
+        };
+        if status != 0 {
+            return Err(unavailable(format!(
+                "failed to look up user {user:?}: {}",
+                std::io::Error::from_raw_os_error(status)
+            )));
+        }
+        if found.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: on success the strings point into `buf`, NUL-terminated.
+        let (name, home) = unsafe { (CStr::from_ptr(entry.pw_name), CStr::from_ptr(entry.pw_dir)) };
+        Ok(Some((
+            entry.pw_uid,
+            entry.pw_gid,
+            name.to_string_lossy().into_owned(),
+            PathBuf::from(std::ffi::OsStr::from_bytes(home.to_bytes())),
+        )))
+    }
+
+    fn group(group: &str) -> Result<u32, CodexError> {
+        if let Ok(gid) = group.parse() {
+            return Ok(gid);
+        }
+        let name = CString::new(group).map_err(|_| unavailable(format!("invalid group name {group:?}")))?;
+        // SAFETY: `group` is plain old data, valid when zeroed.
+        let mut entry: libc::group = unsafe { std::mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; 16 * 1024];
+        let mut found = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the call.
+        let status = unsafe { libc::getgrnam_r(name.as_ptr(), &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) };
+        if status != 0 || found.is_null() {
+            return Err(unavailable(format!("unknown group {group:?}")));
+        }
+        Ok(entry.gr_gid)
+    }
+
+    pub fn resolve(user: &str, group_name: Option<&str>) -> Result<RunAs, CodexError> {
+        let gid = group_name.map(group).transpose()?;
+        let run_as = match (passwd(user)?, user.parse::<u32>()) {
+            (Some((uid, primary_gid, name, home)), _) => RunAs {
+                uid,
+                gid: gid.unwrap_or(primary_gid),
+                name: Some(name),
+                home: Some(home),
+            },
+            // A bare UID without a passwd entry, as container images often use.
+            (None, Ok(uid)) => RunAs {
+                uid,
+                gid: gid.ok_or_else(|| unavailable(format!("UID {uid} has no passwd entry; set `run_as_group`")))?,
+                name: None,
+                home: None,
+            },
+            (None, Err(_)) => return Err(unavailable(format!("unknown user {user:?}"))),
+        };
+        // SAFETY: `geteuid` has no preconditions.
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 && euid != run_as.uid {
+            return Err(unavailable("the server is not running as root"));
+        }
+        Ok(run_as)
+    }
+
+    pub fn apply(run_as: &RunAs, cmd: &mut Command) {
+        // Dropping from root also clears supplementary groups.
+        cmd.uid(run_as.uid).gid(run_as.gid);
+    }
+
+    pub fn chown_tree(run_as: &RunAs, path: &Path) -> std::io::Result<()> {
+        let metadata = std::fs::symlink_metadata(path)?;
+        if (metadata.uid(), metadata.gid()) != (run_as.uid, run_as.gid) {
+            std::os::unix::fs::lchown(path, Some(run_as.uid), Some(run_as.gid))?;
+        }
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                chown_tree(run_as, &entry?.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::path::Path;
+
+    use tokio::process::Command;
+
+    use super::RunAs;
+    use crate::error::CodexError;
+
+    pub fn resolve(_user: &str, _group: Option<&str>) -> Result<RunAs, CodexError> {
+        Err(CodexError::RunAsUnavailable("switching users is only supported on Unix".to_string()))
+    }
+
+    pub fn apply(_run_as: &RunAs, _cmd: &mut Command) {}
+
+    pub fn chown_tree(_run_as: &RunAs, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_run_as_user() {
+        // SAFETY: `geteuid` has no preconditions.
+        let euid = unsafe { libc::geteuid() };
+        let mut config = ServerConfig {
+            run_as_user: Some(euid.to_string()),
+            ..ServerConfig::default()
+        };
+        let own = resolve(&config).unwrap().unwrap();
+        assert_eq!(own.uid, euid);
+
+        config.run_as_user = Some("no-such-user-codex-mcp".to_string());
+        assert!(matches!(resolve(&config), Err(CodexError::RunAsUnavailable(_))));
+
+        config.run_as_user = None;
+        config.run_as_group = Some("0".to_string());
+        assert!(matches!(resolve(&config), Err(CodexError::RunAsUnavailable(_))));
+    }
+}
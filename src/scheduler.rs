@@ -0,0 +1,283 @@
+//! Recurring codex runs configured under `[[schedules]]`.
+//!
+//! Each schedule runs in its own task: it sleeps until the next time its cron
+//! expression matches, runs the configured prompt through the same path as the
+//! `codex` tool (so results land in the session registry), and optionally
+//! POSTs the result to a webhook. A run that outlasts the next trigger time
+//! makes the schedule skip that trigger rather than overlap.
+
+use std::collections::HashSet;
+use std::process::Stdio;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::codex::{CodexResult, CodexServer, RunContext};
+use crate::config::ScheduleConfig;
+
+/// Upper bound on how far ahead the next match of a cron expression is
+/// searched for, so impossible dates like `0 0 31 2 *` terminate.
+const MAX_LOOKAHEAD_DAYS: i64 = 5 * 366;
+
+/// Seconds a webhook delivery may take.
+const WEBHOOK_TIMEOUT_SECS: u32 = 30;
+
+/// A parsed five-field cron expression. Each field is a bitset of the values it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month / day-of-week were `*`. When both are
+    /// restricted, a day matching either one matches, as in cron.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Parse `minute hour day-of-month month day-of-week`. Fields accept `*`,
+    /// values, ranges (`1-5`), steps (`*/15`, `0-30/10`), and comma lists.
+    /// Day-of-week is 0-7 with both 0 and 7 meaning Sunday.
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("expected 5 fields (minute hour day-of-month month day-of-week), got {}", fields.len());
+        };
+        let mut weekdays = parse_field(weekday, 0, 7).context("invalid day-of-week")?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).context("invalid minute")?,
+            hours: parse_field(hour, 0, 23).context("invalid hour")?,
+            days: parse_field(day, 1, 31).context("invalid day-of-month")?,
+            months: parse_field(month, 1, 12).context("invalid month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    fn matches_day(&self, time: &NaiveDateTime) -> bool {
+        if self.months & (1 << time.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// First matching minute strictly after `after`.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_LOOKAHEAD_DAYS);
+        let mut time = start;
+        while time < limit {
+            if !self.matches_day(&time) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    /// Next local time the schedule fires after `now`, skipping matches that
+    /// fall into a daylight-saving gap.
+    fn next_occurrence(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut after = now.naive_local();
+        loop {
+            let next = self.next_after(after)?;
+            if let Some(time) = Local.from_local_datetime(&next).earliest() {
+                return Some(time);
+            }
+            after = next;
+        }
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().with_context(|| format!("invalid step {step:?}"))?)),
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max)?, parse_value(end, min, max)?)
+        } else {
+            let value = parse_value(range, min, max)?;
+            (value, if step.is_some() { max } else { value })
+        };
+        if start > end {
+            bail!("range {range:?} is reversed");
+        }
+        let step = step.unwrap_or(1);
+        if step == 0 {
+            bail!("step must be positive");
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(text: &str, min: u32, max: u32) -> Result<u32> {
+    text.parse::<u32>()
+        .ok()
+        .filter(|v| (min..=max).contains(v))
+        .with_context(|| format!("{text:?} is not a number between {min} and {max}"))
+}
+
+/// Validate `schedules` and start a task for each.
+pub fn start(server: &CodexServer, schedules: &[ScheduleConfig]) -> Result<()> {
+    let mut parsed = Vec::new();
+    let mut names = HashSet::new();
+    for schedule in schedules {
+        if !names.insert(schedule.name.as_str()) {
+            bail!("duplicate schedule name {:?}", schedule.name);
+        }
+        let cron = CronSchedule::parse(&schedule.cron)
+            .with_context(|| format!("invalid cron expression for schedule {:?}", schedule.name))?;
+        parsed.push((schedule.clone(), cron));
+    }
+    for (schedule, cron) in parsed {
+        tracing::info!(schedule = %schedule.name, cron = %schedule.cron, "Scheduled codex run");
+        tokio::spawn(run_schedule(server.clone(), schedule, cron));
+    }
+    Ok(())
+}
+
+async fn run_schedule(server: CodexServer, schedule: ScheduleConfig, cron: CronSchedule) {
+    loop {
+        let now = Local::now();
+        let Some(next) = cron.next_occurrence(now) else {
+            tracing::warn!(schedule = %schedule.name, "Schedule never fires again");
+            return;
+        };
+        tracing::debug!(schedule = %schedule.name, next = %next, "Waiting for next scheduled run");
+        tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+        run_once(&server, &schedule).await;
+    }
+}
+
+/// Execute one run of `schedule` and deliver its result.
+async fn run_once(server: &CodexServer, schedule: &ScheduleConfig) {
+    let mut params = schedule.run.clone();
+    params.tags.push(format!("schedule:{}", schedule.name));
+    if params.label.is_none() {
+        params.label = Some(schedule.name.clone());
+    }
+
+    tracing::info!(schedule = %schedule.name, "Starting scheduled codex run");
+    let result = server.run_codex(params, RunContext::tool("scheduler")).await;
+    tracing::info!(
+        schedule = %schedule.name,
+        success = result.success,
+        session_id = result.session_id.as_deref().unwrap_or(""),
+        "Scheduled codex run finished"
+    );
+
+    if let Some(ref url) = schedule.webhook
+        && let Err(e) = post_webhook(url, &schedule.name, &result).await
+    {
+        tracing::warn!(schedule = %schedule.name, error = %e, "Failed to deliver schedule webhook");
+    }
+}
+
+/// POST `{"schedule": ..., "result": CodexResult}` to `url` with `curl`.
+///
+/// The URL and payload are passed as a curl config on stdin, so a token in
+/// the URL never shows up in the process list.
+async fn post_webhook(url: &str, schedule: &str, result: &CodexResult) -> Result<()> {
+    let payload = serde_json::json!({"schedule": schedule, "result": result});
+    let config = format!(
+        "url = {}\ndata-binary = {}\n",
+        curl_config_string(url),
+        curl_config_string(&payload.to_string())
+    );
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time"])
+        .arg(WEBHOOK_TIMEOUT_SECS.to_string())
+        .args(["--request", "POST", "--header", "Content-Type: application/json", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run curl")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!("curl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Quote `value` for a curl config file.
+fn curl_config_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d).unwrap().and_hms_opt(h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cron_fields() {
+        let cron = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        assert_eq!(cron.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(cron.hours.count_ones(), 9);
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap().weekdays, 1);
+
+        assert!(CronSchedule::parse("0 3 * *").is_err());
+        assert!(CronSchedule::parse("60 3 * * *").is_err());
+        assert!(CronSchedule::parse("0 5-3 * * *").is_err());
+        assert!(CronSchedule::parse("*/0 3 * * *").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let nightly = CronSchedule::parse("0 3 * * *").unwrap();
+        assert_eq!(nightly.next_after(at(2026, 1, 10, 2, 59)), Some(at(2026, 1, 10, 3, 0)));
+        assert_eq!(nightly.next_after(at(2026, 1, 10, 3, 0)), Some(at(2026, 1, 11, 3, 0)));
+
+        // 2026-01-10 is a Saturday.
+        let weekdays = CronSchedule::parse("30 9 * * 1-5").unwrap();
+        assert_eq!(weekdays.next_after(at(2026, 1, 10, 12, 0)), Some(at(2026, 1, 12, 9, 30)));
+
+        // Restricted day-of-month and day-of-week match either one.
+        let either = CronSchedule::parse("0 0 13 * 5").unwrap();
+        assert_eq!(either.next_after(at(2026, 1, 10, 0, 0)), Some(at(2026, 1, 13, 0, 0)));
+        assert_eq!(either.next_after(at(2026, 1, 13, 0, 0)), Some(at(2026, 1, 16, 0, 0)));
+
+        assert_eq!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(at(2026, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_curl_config_string_escapes_quotes() {
+        assert_eq!(curl_config_string(r#"{"a":"x\ny"}"#), r#""{\"a\":\"x\\ny\"}""#);
+    }
+}
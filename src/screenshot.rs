@@ -0,0 +1,183 @@
+//! `codex_with_screenshot`: capture the screen or a window and attach it to a
+//! codex prompt, for "why does my UI look wrong" questions from desktop clients.
+//!
+//! Captures shell out to the platform's screenshot tool: `screencapture` on
+//! macOS, `grim` on Wayland or ImageMagick's `import` on X11, and PowerShell
+//! on Windows.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rmcp::{handler::server::wrapper::Parameters, model::CallToolResult, tool, tool_router, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::codex::{error_result, tool_result, CodexParams, CodexServer, RunContext};
+use crate::error::CodexError;
+
+/// Distinguishes the temporary files of concurrent captures.
+static CAPTURE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Parameters for the codex_with_screenshot tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ScreenshotParams {
+    /// Title of the window to capture (the application name on macOS, where
+    /// its front window is captured). Captures the whole screen when absent.
+    #[serde(default)]
+    pub window: Option<String>,
+
+    #[serde(flatten)]
+    pub codex: CodexParams,
+}
+
+#[tool_router(router = screenshot_router, vis = "pub(crate)")]
+impl CodexServer {
+    /// Runs codex with a screenshot of the screen or a window attached.
+    #[tool(
+        name = "codex_with_screenshot",
+        description = r#"Captures the current screen, or the window titled `window`, and runs codex with the screenshot attached as an image to the prompt, for questions about what is on screen (e.g. "why does my UI look wrong?").
+Takes the same parameters as the `codex` tool plus the optional `window`. Only works when the server runs on a desktop session; fails with `screenshot_failed` otherwise."#
+    )]
+    pub async fn codex_with_screenshot(
+        &self,
+        params: Parameters<ScreenshotParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let ScreenshotParams { window, codex: mut params } = params.0;
+        let path = std::env::temp_dir().join(format!(
+            "codex-mcp-screenshot-{}-{}.png",
+            std::process::id(),
+            CAPTURE_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let format = params.response_format;
+        let locale = self.locale(params.language.as_deref());
+        let result = match capture(&path, window.as_deref()).await {
+            Ok(()) => {
+                params.image.push(path.clone());
+                self.run_codex(params, RunContext::tool("codex_with_screenshot")).await
+            }
+            Err(e) => error_result(e, locale),
+        };
+        let _ = std::fs::remove_file(&path);
+
+        Ok(tool_result(&result, format))
+    }
+}
+
+/// Capture the screen, or `window`, to a PNG at `path`.
+async fn capture(path: &Path, window: Option<&str>) -> Result<(), CodexError> {
+    #[cfg(target_os = "macos")]
+    let (program, args) = macos_command(path, window).await?;
+    #[cfg(windows)]
+    let (program, args) = windows_command(path, window)?;
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let (program, args) = linux_command(path, window, std::env::var_os("WAYLAND_DISPLAY").is_some())?;
+
+    run(program, &args).await?;
+    if !path.is_file() {
+        return Err(CodexError::ScreenshotFailed(format!("{program} did not write a screenshot")));
+    }
+    Ok(())
+}
+
+/// Run `program`, returning its stdout, or its stderr as the error.
+async fn run(program: &str, args: &[String]) -> Result<String, CodexError> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| CodexError::ScreenshotFailed(format!("failed to run {program}: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CodexError::ScreenshotFailed(format!("{program} failed: {}", stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `screencapture`, limited to the bounds of the application's front window
+/// as reported by System Events.
+#[cfg(target_os = "macos")]
+async fn macos_command(path: &Path, window: Option<&str>) -> Result<(&'static str, Vec<String>), CodexError> {
+    let mut args = vec!["-x".to_string()];
+    if let Some(app) = window {
+        let name = app.replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!(
+            r#"tell application "System Events" to get {{position, size}} of front window of (first process whose name is "{name}")"#
+        );
+        let bounds = run("osascript", &["-e".to_string(), script]).await?;
+        // "x, y, width, height"
+        let bounds: Vec<&str> = bounds.split(',').map(str::trim).collect();
+        if bounds.len() != 4 {
+            return Err(CodexError::ScreenshotFailed(format!("no window found for {app:?}")));
+        }
+        args.push(format!("-R{}", bounds.join(",")));
+    }
+    args.push(path.display().to_string());
+    Ok(("screencapture", args))
+}
+
+/// A PowerShell script copying the virtual screen to a PNG.
+#[cfg(windows)]
+fn windows_command(path: &Path, window: Option<&str>) -> Result<(&'static str, Vec<String>), CodexError> {
+    if window.is_some() {
+        return Err(CodexError::ScreenshotFailed("capturing a single window is not supported on Windows".to_string()));
+    }
+    let path = path.display().to_string().replace('\'', "''");
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+         $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+         $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+         [System.Drawing.Graphics]::FromImage($bmp).CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size); \
+         $bmp.Save('{path}', [System.Drawing.Imaging.ImageFormat]::Png)"
+    );
+    Ok(("powershell", vec!["-NoProfile".to_string(), "-NonInteractive".to_string(), "-Command".to_string(), script]))
+}
+
+/// `grim` on Wayland, which has no portable way to pick a window, or
+/// ImageMagick's `import` on X11.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn linux_command(path: &Path, window: Option<&str>, wayland: bool) -> Result<(&'static str, Vec<String>), CodexError> {
+    let path = path.display().to_string();
+    if wayland {
+        if window.is_some() {
+            return Err(CodexError::ScreenshotFailed("capturing a single window is not supported on Wayland".to_string()));
+        }
+        return Ok(("grim", vec!["-t".to_string(), "png".to_string(), path]));
+    }
+    Ok(("import", vec!["-window".to_string(), window.unwrap_or("root").to_string(), path]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_linux_capture_commands() {
+        let path = Path::new("/tmp/shot.png");
+        let (program, args) = linux_command(path, None, false).unwrap();
+        assert_eq!((program, args.as_slice()), ("import", ["-window", "root", "/tmp/shot.png"].map(String::from).as_slice()));
+
+        let (_, args) = linux_command(path, Some("Settings, v2"), false).unwrap();
+        assert_eq!(args[1], "Settings, v2");
+
+        assert_eq!(linux_command(path, None, true).unwrap().0, "grim");
+        assert!(matches!(linux_command(path, Some("x"), true), Err(CodexError::ScreenshotFailed(_))));
+    }
+
+    #[test]
+    fn test_params_accept_codex_parameters() {
+        let params: ScreenshotParams = serde_json::from_value(serde_json::json!({
+            "PROMPT": "why is the sidebar clipped?",
+            "cd": "/work",
+            "window": "Preview",
+            "sandbox": "read-only"
+        }))
+        .unwrap();
+        assert_eq!(params.window.as_deref(), Some("Preview"));
+        assert_eq!(params.codex.prompt, "why is the sidebar clipped?");
+    }
+}
@@ -0,0 +1,141 @@
+//! Full-text search over the sessions started through this server: their
+//! registry metadata and transcripts (`codex_search_sessions`).
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::registry::SessionRecord;
+
+/// Characters of context kept on each side of a match in a snippet.
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+
+/// Snippets returned per session; further matches are only counted.
+pub const MAX_SNIPPETS: usize = 3;
+
+/// Where a session matched the query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SearchMatch {
+    /// `label`, `tag`, `cd`, or `transcript`.
+    pub field: String,
+    /// The matching text, shortened to the match and its surroundings.
+    pub snippet: String,
+}
+
+/// A session matching the query.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionHit {
+    #[serde(flatten)]
+    pub session: SessionRecord,
+    /// Up to three places the query was found, metadata first.
+    pub matches: Vec<SearchMatch>,
+    /// Total number of matches, including those without a snippet.
+    pub match_count: usize,
+}
+
+/// Byte offset of the first ASCII-case-insensitive occurrence of `needle`
+/// (already lowercased) in `haystack`.
+fn find(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(needle)
+}
+
+/// `text` around the match at `start..start + len`, on one line.
+fn snippet(text: &str, start: usize, len: usize) -> String {
+    let before: Vec<(usize, char)> = text[..start].char_indices().collect();
+    let from = before.len().checked_sub(SNIPPET_CONTEXT_CHARS).map_or(0, |i| before[i].0);
+    let to = text[start + len..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT_CHARS)
+        .map_or(text.len(), |(i, _)| start + len + i);
+
+    let mut out = String::new();
+    if from > 0 {
+        out.push('…');
+    }
+    out.push_str(&text[from..to].split_whitespace().collect::<Vec<_>>().join(" "));
+    if to < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// Every string in `value`, depth first.
+fn strings(value: &serde_json::Value, out: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(text) => out.push(text.clone()),
+        serde_json::Value::Array(items) => items.iter().for_each(|item| strings(item, out)),
+        serde_json::Value::Object(fields) => fields.values().for_each(|field| strings(field, out)),
+        _ => {}
+    }
+}
+
+/// Match `query` against `session`'s metadata and transcript `events`, or
+/// `None` when it appears in neither.
+pub fn search_session(
+    session: SessionRecord,
+    events: &[serde_json::Value],
+    query: &str,
+) -> Option<SessionHit> {
+    let needle = query.to_ascii_lowercase();
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    fields.extend(session.label.clone().map(|label| ("label", label)));
+    fields.extend(session.tags.iter().map(|tag| ("tag", tag.clone())));
+    fields.push(("cd", session.cd.display().to_string()));
+    for event in events {
+        let mut texts = Vec::new();
+        strings(event, &mut texts);
+        fields.extend(texts.into_iter().map(|text| ("transcript", text)));
+    }
+
+    let mut matches = Vec::new();
+    let mut match_count = 0;
+    for (field, text) in &fields {
+        if let Some(start) = find(text, &needle) {
+            match_count += 1;
+            if matches.len() < MAX_SNIPPETS {
+                matches.push(SearchMatch {
+                    field: field.to_string(),
+                    snippet: snippet(text, start, needle.len()),
+                });
+            }
+        }
+    }
+    (match_count > 0).then_some(SessionHit {
+        session,
+        matches,
+        match_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> SessionRecord {
+        serde_json::from_value(serde_json::json!({
+            "SESSION_ID": "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61",
+            "cd": "/repo",
+            "label": "Auth refactor",
+            "run_count": 1,
+            "last_success": true,
+            "created_at": 0,
+            "updated_at": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_matches_metadata_and_transcript() {
+        let events = [
+            serde_json::json!({"type": "item.completed", "item": {"type": "file_change", "changes": [{"path": "src/auth/login.rs"}]}}),
+            serde_json::json!({"type": "item.completed", "item": {"type": "agent_message", "text": format!("{}Moved the AUTH checks\ninto middleware.", "x".repeat(100))}}),
+        ];
+        let hit = search_session(record(), &events, "auth").unwrap();
+        assert_eq!(hit.match_count, 3);
+        let fields: Vec<&str> = hit.matches.iter().map(|m| m.field.as_str()).collect();
+        assert_eq!(fields, ["label", "transcript", "transcript"]);
+        assert_eq!(hit.matches[1].snippet, "src/auth/login.rs");
+        assert_eq!(hit.matches[2].snippet, format!("…{}Moved the AUTH checks into middleware.", "x".repeat(50)));
+
+        assert!(search_session(record(), &events, "billing").is_none());
+    }
+}
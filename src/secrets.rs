@@ -0,0 +1,207 @@
+//! Credentials for integrations (`vcs_token`, schedule webhooks), and their
+//! redaction from logs and results.
+//!
+//! A credential in the config can be a reference instead of the literal
+//! value, so it need not be stored in the config file or the environment:
+//!
+//! - `keychain:<service>` or `keychain:<service>/<account>` reads the OS
+//!   keychain (`security` on macOS, `secret-tool` on Linux).
+//! - `file:<path>` reads the trimmed contents of a file, which must not be
+//!   readable by group or others.
+//! - `env:<NAME>` reads an environment variable of the server.
+//!
+//! Every resolved credential is registered for redaction: it is replaced with
+//! `[REDACTED]` in log output and in the text of run results.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result, bail};
+
+/// Replacement for redacted secrets.
+const REDACTED: &str = "[REDACTED]";
+
+/// Secrets too short to redact without mangling ordinary text.
+const MIN_REDACTED_LEN: usize = 4;
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Resolve a credential from the config, registering it for redaction.
+pub fn resolve(value: &str) -> Result<String> {
+    let secret = if let Some(path) = value.strip_prefix("file:") {
+        read_secret_file(Path::new(path))?
+    } else if let Some(item) = value.strip_prefix("keychain:") {
+        let (service, account) = match item.split_once('/') {
+            Some((service, account)) => (service, Some(account)),
+            None => (item, None),
+        };
+        read_keychain(service, account)?
+    } else if let Some(name) = value.strip_prefix("env:") {
+        std::env::var(name)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .with_context(|| format!("environment variable {name} is not set"))?
+    } else {
+        value.to_string()
+    };
+    register(&secret);
+    Ok(secret)
+}
+
+/// Redact `secret` from logs and results from now on.
+pub fn register(secret: &str) {
+    if secret.len() < MIN_REDACTED_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap();
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+        // Longest first, so a secret containing another is redacted whole.
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+/// `text` with every registered secret replaced by `[REDACTED]`.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap();
+    let mut text = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    text
+}
+
+/// Redact every string inside a JSON value in place.
+pub fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Cow::Owned(redacted) = redact(s) {
+                *s = redacted;
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::Object(map) => map.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Redact a string field in place.
+pub fn redact_in_place(text: &mut String) {
+    if let Cow::Owned(redacted) = redact(text) {
+        *text = redacted;
+    }
+}
+
+/// Redact an optional string field in place.
+pub fn redact_option(text: &mut Option<String>) {
+    if let Some(s) = text {
+        redact_in_place(s);
+    }
+}
+
+fn read_secret_file(path: &Path) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)
+            .with_context(|| format!("failed to read secret file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            bail!(
+                "secret file {} is accessible by group or others (mode {:o}); restrict it with `chmod 600`",
+                path.display(),
+                mode & 0o777
+            );
+        }
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read secret file {}", path.display()))?;
+    let secret = text.trim();
+    if secret.is_empty() {
+        bail!("secret file {} is empty", path.display());
+    }
+    Ok(secret.to_string())
+}
+
+fn read_keychain(service: &str, account: Option<&str>) -> Result<String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args(["find-generic-password", "-w", "-s", service]);
+        if let Some(account) = account {
+            command.args(["-a", account]);
+        }
+        command
+    } else if cfg!(target_os = "linux") {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", service]);
+        if let Some(account) = account {
+            command.args(["account", account]);
+        }
+        command
+    } else {
+        bail!("keychain secrets are only supported on macOS and Linux");
+    };
+    let output = command
+        .output()
+        .with_context(|| format!("failed to query the keychain for {service:?}"))?;
+    let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || secret.is_empty() {
+        bail!("no keychain secret found for service {service:?}");
+    }
+    Ok(secret)
+}
+
+/// Log writer that redacts registered secrets before writing to stderr.
+pub struct RedactingStderr;
+
+impl Write for RedactingStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        io::stderr().write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_registered_secrets() {
+        register("ghp_test_secret_value");
+        register("abc");
+        assert_eq!(redact("token ghp_test_secret_value in use"), "token [REDACTED] in use");
+        assert!(matches!(redact("nothing to hide, abc"), Cow::Borrowed(_)));
+
+        let mut value = serde_json::json!({"cmd": ["curl", "ghp_test_secret_value"]});
+        redact_json(&mut value);
+        assert_eq!(value["cmd"][1], "[REDACTED]");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secret_file_requires_strict_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("codex-mcp-secret-{}", std::process::id()));
+        std::fs::write(&path, "file_secret_value\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let reference = format!("file:{}", path.display());
+        assert!(resolve(&reference).is_err());
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(resolve(&reference).unwrap(), "file_secret_value");
+        assert_eq!(redact("x file_secret_value"), "x [REDACTED]");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
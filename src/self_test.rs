@@ -0,0 +1,106 @@
+//! Startup self-test (`--self-test`, or `self_test = true` in the config): a
+//! trivial read-only `codex exec` in a scratch directory, so a broken install,
+//! a missing login, or an unreachable model provider stops the server at boot
+//! with a diagnosis instead of failing the first real run.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use crate::child_env;
+use crate::codex::SandboxPolicy;
+use crate::config::ServerConfig;
+use crate::environment;
+use crate::events::EventStream;
+use crate::exit::ProcessExit;
+use crate::os_sandbox;
+use crate::run_as;
+use crate::state::unix_now_millis;
+
+const PROMPT: &str = "Reply with OK and nothing else. Do not run any commands.";
+
+/// Longest the self-test run may take, including the model's reply.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Lines of codex stderr included in the diagnosis of a failed self-test.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Run the self-test with the codex at `codex_path`, in the environment real
+/// runs get under `config`, returning the reply.
+pub async fn run(codex_path: &Path, config: &ServerConfig) -> Result<String> {
+    let dir = std::env::temp_dir().join(format!("codex-mcp-self-test-{}-{}", std::process::id(), unix_now_millis()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let result = run_in(codex_path, config, &dir).await;
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+async fn run_in(codex_path: &Path, config: &ServerConfig, dir: &Path) -> Result<String> {
+    let wrapper = os_sandbox::resolve(config.os_sandbox, &SandboxPolicy::ReadOnly, false)?;
+    let writable = os_sandbox::writable_paths(&SandboxPolicy::ReadOnly, dir, &[]);
+    let mut cmd = os_sandbox::command(codex_path, wrapper, &writable)?;
+    cmd.args(["exec", "--sandbox", "read-only", "--cd"])
+        .arg(dir)
+        .args(["--json", "--skip-git-repo-check", "--", PROMPT])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    child_env::apply(&mut cmd, codex_path, config);
+    if let Some(run_as) = run_as::resolve(config)? {
+        run_as.apply(&mut cmd);
+    }
+    let output = cmd.output();
+    let output = match tokio::time::timeout(TIMEOUT, output).await {
+        Ok(output) => output.with_context(|| format!("failed to start {}", codex_path.display()))?,
+        Err(_) => bail!(
+            "codex self-test failed: `codex exec` did not finish within {}s\n\n{}",
+            TIMEOUT.as_secs(),
+            context(codex_path).await
+        ),
+    };
+
+    let mut stream = EventStream::new(false, false);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        stream.push_line(line);
+    }
+    let exit = ProcessExit::from_status(output.status);
+    if !output.status.success() {
+        let mut detail = exit.to_string();
+        if let Some(hint) = exit.hint() {
+            detail.push_str(". ");
+            detail.push_str(hint);
+        }
+        stream.record_failure("codex exit", &detail);
+    }
+    let run = stream.finish();
+    if run.success && !run.agent_messages.trim().is_empty() {
+        return Ok(run.agent_messages.trim().to_string());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().filter(|line| !line.trim().is_empty()).collect();
+    let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+    let error = if run.error.trim().is_empty() {
+        "codex finished without replying".to_string()
+    } else {
+        run.error.trim().to_string()
+    };
+    bail!(
+        "codex self-test failed: {error}\n\n{}\nexit: {exit}\nstderr:\n{}",
+        context(codex_path).await,
+        if tail.is_empty() { "(empty)" } else { &tail }
+    )
+}
+
+/// The codex binary and version the self-test ran, for its diagnosis.
+async fn context(codex_path: &Path) -> String {
+    let version = environment::codex_version(codex_path).await;
+    format!(
+        "codex: {} ({})",
+        codex_path.display(),
+        version.as_deref().unwrap_or("version unknown")
+    )
+}
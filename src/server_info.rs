@@ -0,0 +1,168 @@
+//! What this deployment supports, for the `codex_mcp_version` tool, so
+//! orchestration layers can adapt to it instead of probing tool by tool:
+//! the build's optional features, the configured backends and integrations,
+//! the limits runs are held to, and what the installed codex CLI can do.
+
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::config::ServerConfig;
+use crate::os_sandbox::OsSandbox;
+use crate::state::StateBackend;
+
+/// Optional features compiled into this build.
+const BUILD_FEATURES: &[(&str, bool)] = &[
+    ("vcs", cfg!(feature = "vcs")),
+    ("screenshot", cfg!(feature = "screenshot")),
+    ("redis", cfg!(feature = "redis")),
+];
+
+/// Transports this build can serve MCP over.
+const TRANSPORTS: &[&str] = if cfg!(unix) { &["stdio", "unix"] } else { &["stdio"] };
+
+/// Backends and integrations the server is configured with.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerFeatures {
+    /// Transports this build supports (`--transport`).
+    pub transports: Vec<String>,
+    /// Optional cargo features compiled in: `vcs`, `screenshot`, `redis`.
+    pub build_features: Vec<String>,
+    /// `files`, `sqlite`, or `redis`.
+    pub state_backend: String,
+    /// Whether sessions, runs, and usage survive a restart.
+    pub persistent_state: bool,
+    /// Whether stored transcripts and artifacts are encrypted.
+    pub encrypted_state: bool,
+    /// Whether each run gets an artifacts directory.
+    pub artifacts: bool,
+    /// `off`, `auto`, `bubblewrap`, or `sandbox_exec`.
+    pub os_sandbox: String,
+    /// Whether codex runs as a dedicated `run_as_user`.
+    pub run_as_user: bool,
+    /// Whether codex gets a scrubbed environment.
+    pub scrub_child_env: bool,
+    /// Whether codex approval requests are forwarded to the client.
+    pub approval_bridge: bool,
+    /// Whether declarative policy rules are checked before runs.
+    pub policy_rules: bool,
+    /// Number of configured recurring schedules.
+    pub schedules: usize,
+}
+
+/// Limits and policies runs are held to.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConfiguredLimits {
+    /// How concurrent write runs in one workspace are handled: `off`, `fail`, or `queue`.
+    pub workspace_lock: String,
+    pub restrict_to_workspaces: bool,
+    pub allow_create_cd: bool,
+    /// Whether runs may be granted network access at all.
+    pub network_access_permitted: bool,
+    pub shared_sessions: bool,
+    pub inline_messages_max_bytes: usize,
+    pub patch_max_bytes: usize,
+    pub max_image_bytes: u64,
+    pub max_upload_bytes: u64,
+    pub workspace_read_max_bytes: usize,
+    pub memory_max_bytes: usize,
+    pub rate_limit_max_defer_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_budget_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u64>,
+}
+
+/// The installed codex CLI.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CodexCli {
+    /// Resolved path of the executable, if it was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Output of `codex --version`, when codex can be run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Optional features it was detected to support.
+    pub capabilities: Capabilities,
+}
+
+/// Result returned by the codex_mcp_version tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VersionReport {
+    /// Version of this server.
+    pub server_version: String,
+    pub features: ServerFeatures,
+    /// Tools offered to clients, sorted by name.
+    pub tools: Vec<String>,
+    /// Names of the configured workspaces.
+    pub workspaces: Vec<String>,
+    pub limits: ConfiguredLimits,
+    pub codex: CodexCli,
+}
+
+impl VersionReport {
+    /// The report for a server running with `config` and offering `tools`.
+    pub fn new(config: &ServerConfig, mut tools: Vec<String>, codex: CodexCli) -> Self {
+        tools.sort();
+        let persistent_state = config.state_dir.is_some() || config.state_backend == StateBackend::Redis;
+        Self {
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            features: ServerFeatures {
+                transports: TRANSPORTS.iter().map(|t| t.to_string()).collect(),
+                build_features: BUILD_FEATURES
+                    .iter()
+                    .filter(|(_, enabled)| *enabled)
+                    .map(|(name, _)| name.to_string())
+                    .collect(),
+                state_backend: state_backend_name(config.state_backend).to_string(),
+                persistent_state,
+                encrypted_state: config.encryption_key.is_some(),
+                artifacts: config.artifacts_dir.is_some(),
+                os_sandbox: os_sandbox_name(config.os_sandbox).to_string(),
+                run_as_user: config.run_as_user.is_some(),
+                scrub_child_env: config.scrub_child_env,
+                approval_bridge: config.approval_bridge,
+                policy_rules: config.policy_file.is_some(),
+                schedules: config.schedules.len(),
+            },
+            tools,
+            workspaces: config.workspaces.keys().cloned().collect(),
+            limits: ConfiguredLimits {
+                workspace_lock: config.workspace_lock.as_str().to_string(),
+                restrict_to_workspaces: config.restrict_to_workspaces,
+                allow_create_cd: config.allow_create_cd,
+                network_access_permitted: config.network_access_permitted,
+                shared_sessions: config.shared_sessions,
+                inline_messages_max_bytes: config.inline_messages_max_bytes,
+                patch_max_bytes: config.patch_max_bytes,
+                max_image_bytes: config.max_image_bytes,
+                max_upload_bytes: config.max_upload_bytes,
+                workspace_read_max_bytes: config.workspace_read_max_bytes,
+                memory_max_bytes: config.memory_max_bytes,
+                rate_limit_max_defer_secs: config.rate_limit_max_defer_secs,
+                daily_budget_usd: config.daily_budget_usd,
+                retention_days: config.retention_days.filter(|&days| days > 0),
+            },
+            codex,
+        }
+    }
+}
+
+fn state_backend_name(backend: StateBackend) -> &'static str {
+    match backend {
+        StateBackend::Files => "files",
+        StateBackend::Sqlite => "sqlite",
+        StateBackend::Redis => "redis",
+    }
+}
+
+fn os_sandbox_name(mode: OsSandbox) -> &'static str {
+    match mode {
+        OsSandbox::Off => "off",
+        OsSandbox::Auto => "auto",
+        OsSandbox::Bubblewrap => "bubblewrap",
+        OsSandbox::SandboxExec => "sandbox_exec",
+    }
+}
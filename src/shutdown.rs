@@ -0,0 +1,50 @@
+//! Shutting down cleanly when the MCP client goes away or the server is
+//! asked to stop, so codex processes never outlive the server that reads
+//! their output.
+//!
+//! Runs in progress are stopped by sending codex SIGTERM, giving it the
+//! chance to stop the commands it started, and killed if they have not
+//! exited within the grace period. Background runs are left recorded as in
+//! progress, so the next server start reports or resumes them like after a
+//! crash.
+
+use std::time::Duration;
+
+use tokio::process::Child;
+
+/// How long runs get to stop after shutdown begins.
+pub const GRACE: Duration = Duration::from_secs(10);
+
+/// Resolve on SIGINT or, on Unix, SIGTERM.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(signal) => signal,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to install SIGTERM handler");
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Ask `child` to exit: SIGTERM on Unix, where codex can stop its own
+/// commands, and an immediate kill elsewhere.
+pub fn terminate(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: `kill` has no memory-safety preconditions, and `pid` is our
+        // unreaped child, so it cannot have been reused.
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+        return;
+    }
+    let _ = child.start_kill();
+}
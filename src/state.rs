@@ -0,0 +1,431 @@
+//! Persistence of server state (session registry, background runs, caches)
+//! as JSON documents, in the state directory, a SQLite database there, or a
+//! Redis server shared by several replicas.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ServerConfig;
+
+/// Database file of the `sqlite` backend, inside the state directory.
+const SQLITE_FILE: &str = "state.sqlite3";
+
+/// How long a `sqlite` write waits for a lock held by another process.
+const SQLITE_BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// Attempts of a `sqlite` update that keeps losing the race with other
+/// writers.
+const SQLITE_MAX_UPDATE_ATTEMPTS: usize = 16;
+
+/// Where persisted state is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateBackend {
+    /// One JSON file per document in the state directory (default).
+    #[default]
+    Files,
+    /// A SQLite database in the state directory, written in transactions
+    /// through the `sqlite3` CLI.
+    Sqlite,
+    /// A Redis server at `redis_url`, shared by every replica pointing at it
+    /// (`redis` feature).
+    Redis,
+}
+
+/// Key-value store of JSON documents. Keys are `/`-separated, e.g. `sessions`
+/// or `jobs/<run_id>`.
+pub trait StateStore: Send + Sync + std::fmt::Debug {
+    /// The document stored under `key`, if any.
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Store `json` under `key`, replacing any previous document atomically.
+    fn put(&self, key: &str, json: &str) -> Result<()>;
+
+    /// Delete the document under `key`, if any.
+    fn remove(&self, key: &str) -> Result<()>;
+
+    /// Keys directly under `namespace`, e.g. `jobs/<run_id>` for `jobs`.
+    fn list(&self, namespace: &str) -> Result<Vec<String>>;
+
+    /// Whether other server processes write to the store too, so documents
+    /// must be re-read instead of cached and changed with `update`.
+    fn shared(&self) -> bool {
+        false
+    }
+
+    /// Replace the document under `key` with the result of `f` on its current
+    /// value, atomically with respect to other writers. `f` may run more than
+    /// once; returning `None` leaves the document as it is.
+    fn update(&self, key: &str, f: &mut dyn FnMut(Option<String>) -> Result<Option<String>>) -> Result<()> {
+        if let Some(json) = f(self.get(key)?)? {
+            self.put(key, &json)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared handle to the server's state store.
+pub type Store = Arc<dyn StateStore>;
+
+/// Open the store `config` selects, or `None` when state is kept in memory.
+pub fn open_store(config: &ServerConfig) -> Result<Option<Store>> {
+    let state_dir = config.state_dir.as_deref();
+    Ok(match (config.state_backend, state_dir) {
+        (StateBackend::Redis, _) => Some(open_redis(config)?),
+        (_, None) => None,
+        (StateBackend::Files, Some(dir)) => Some(Arc::new(FileStore::new(dir))),
+        (StateBackend::Sqlite, Some(dir)) => Some(Arc::new(SqliteStore::open(&dir.join(SQLITE_FILE))?)),
+    })
+}
+
+#[cfg(feature = "redis")]
+fn open_redis(config: &ServerConfig) -> Result<Store> {
+    let Some(ref url) = config.redis_url else {
+        bail!("`state_backend = \"redis\"` needs `redis_url`");
+    };
+    Ok(Arc::new(crate::redis::RedisStore::open(url, &config.redis_key_prefix)?))
+}
+
+#[cfg(not(feature = "redis"))]
+fn open_redis(_config: &ServerConfig) -> Result<Store> {
+    bail!("`state_backend = \"redis\"` needs a server built with the `redis` feature")
+}
+
+/// Read and parse the document under `key`, returning `None` if there is none yet.
+pub fn load<T: DeserializeOwned>(store: &dyn StateStore, key: &str) -> Result<Option<T>> {
+    let Some(json) = store.get(key)? else {
+        return Ok(None);
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .with_context(|| format!("failed to parse state {key:?}"))
+}
+
+/// Serialize `value` under `key`.
+pub fn save<T: Serialize>(store: &dyn StateStore, key: &str, value: &T) -> Result<()> {
+    store.put(key, &serde_json::to_string_pretty(value)?)
+}
+
+/// Apply `f` to the document under `key` (the default value when there is
+/// none) atomically, returning the stored result.
+pub fn update<T, R>(store: &dyn StateStore, key: &str, mut f: impl FnMut(&mut T) -> R) -> Result<(T, R)>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    let mut outcome = None;
+    store.update(key, &mut |json| {
+        let mut value: T = match json {
+            Some(json) => serde_json::from_str(&json).with_context(|| format!("failed to parse state {key:?}"))?,
+            None => T::default(),
+        };
+        let result = f(&mut value);
+        let json = serde_json::to_string_pretty(&value)?;
+        outcome = Some((value, result));
+        Ok(Some(json))
+    })?;
+    Ok(outcome.expect("update ran `f`"))
+}
+
+/// Documents as `<key>.json` files under a directory, written via temporary
+/// files so readers never see a partial write.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+impl StateStore for FileStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let path = self.path(key);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    fn put(&self, key: &str, json: &str) -> Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(self.dir.join(namespace)) {
+            Ok(entries) => entries.collect::<std::io::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut keys: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(|name| format!("{namespace}/{name}")))
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Documents in a SQLite table, accessed through the `sqlite3` CLI so the
+/// server needs no SQLite library. Every write runs in its own transaction.
+#[derive(Debug)]
+pub struct SqliteStore {
+    sqlite3: PathBuf,
+    db: PathBuf,
+}
+
+/// `text` as an SQL string literal.
+fn sql_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+impl SqliteStore {
+    /// Open (creating if needed) the database at `db`.
+    pub fn open(db: &Path) -> Result<Self> {
+        let sqlite3 = which::which("sqlite3").context("the `sqlite` state backend needs the `sqlite3` CLI on PATH")?;
+        if let Some(parent) = db.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let store = Self {
+            sqlite3,
+            db: db.to_path_buf(),
+        };
+        store.execute(
+            "PRAGMA journal_mode = WAL;\n\
+             CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+        )?;
+        Ok(store)
+    }
+
+    /// Run `sql`, returning the rows of its last statement as JSON objects.
+    fn execute(&self, sql: &str) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+        blocking(|| self.execute_blocking(sql))
+    }
+
+    fn execute_blocking(&self, sql: &str) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+        let mut child = Command::new(&self.sqlite3)
+            .arg("-bail")
+            .arg("-json")
+            .arg("-cmd")
+            .arg(format!(".timeout {SQLITE_BUSY_TIMEOUT_MS}"))
+            .arg(&self.db)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", self.sqlite3.display()))?;
+        child.stdin.take().expect("stdin is piped").write_all(sql.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(
+                "sqlite3 failed on {}: {}",
+                self.db.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        // One JSON array per statement that returned rows; none for the others.
+        let mut rows = Vec::new();
+        for batch in serde_json::Deserializer::from_slice(&output.stdout).into_iter() {
+            rows = batch.with_context(|| format!("unexpected sqlite3 output for {}", self.db.display()))?;
+        }
+        Ok(rows)
+    }
+
+    fn column(rows: Vec<serde_json::Map<String, serde_json::Value>>, name: &str) -> Vec<String> {
+        rows.into_iter()
+            .filter_map(|mut row| match row.remove(name) {
+                Some(serde_json::Value::String(text)) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let rows = self.execute(&format!("SELECT value FROM state WHERE key = {};", sql_quote(key)))?;
+        Ok(Self::column(rows, "value").pop())
+    }
+
+    fn put(&self, key: &str, json: &str) -> Result<()> {
+        self.execute(&format!(
+            "BEGIN IMMEDIATE;\n\
+             INSERT INTO state (key, value) VALUES ({}, {}) ON CONFLICT (key) DO UPDATE SET value = excluded.value;\n\
+             COMMIT;",
+            sql_quote(key),
+            sql_quote(json)
+        ))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.execute(&format!("DELETE FROM state WHERE key = {};", sql_quote(key)))?;
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<String>> {
+        let prefix = format!("{namespace}/");
+        let rows = self.execute(&format!(
+            "SELECT key FROM state WHERE substr(key, 1, {len}) = {prefix} AND instr(substr(key, {start}), '/') = 0 ORDER BY key;",
+            len = prefix.chars().count(),
+            start = prefix.chars().count() + 1,
+            prefix = sql_quote(&prefix),
+        ))?;
+        Ok(Self::column(rows, "key"))
+    }
+
+    /// Compare and swap: `f` runs on the value read, and its result is
+    /// written in a `BEGIN IMMEDIATE` transaction only if the value is still
+    /// the one read, retrying otherwise. `sqlite3` cannot call back into the
+    /// server mid-transaction, so this keeps the write atomic without one.
+    fn update(&self, key: &str, f: &mut dyn FnMut(Option<String>) -> Result<Option<String>>) -> Result<()> {
+        let quoted = sql_quote(key);
+        for _ in 0..SQLITE_MAX_UPDATE_ATTEMPTS {
+            let current = self.get(key)?;
+            let Some(json) = f(current.clone())? else {
+                return Ok(());
+            };
+            let write = match current {
+                Some(ref old) => format!(
+                    "UPDATE state SET value = {} WHERE key = {quoted} AND value = {};",
+                    sql_quote(&json),
+                    sql_quote(old)
+                ),
+                None => format!(
+                    "INSERT INTO state (key, value) SELECT {quoted}, {} \
+                     WHERE NOT EXISTS (SELECT 1 FROM state WHERE key = {quoted});",
+                    sql_quote(&json)
+                ),
+            };
+            let rows = self.execute(&format!("BEGIN IMMEDIATE;\n{write}\nSELECT changes() AS changed;\nCOMMIT;"))?;
+            if rows.first().and_then(|row| row.get("changed")).and_then(serde_json::Value::as_u64) == Some(1) {
+                return Ok(());
+            }
+        }
+        bail!("gave up updating {key:?} after {SQLITE_MAX_UPDATE_ATTEMPTS} conflicting writes")
+    }
+}
+
+/// Run `f`, which blocks on I/O (a `sqlite3` process, a Redis socket), so
+/// that on a multi-threaded runtime the worker's other tasks are moved to
+/// other threads meanwhile instead of stalling behind it. Store calls come
+/// from synchronous code inside async tool handlers, which cannot await
+/// `spawn_blocking`.
+pub fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Current time as Unix seconds.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Current time as Unix milliseconds.
+pub fn unix_now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(store: &dyn StateStore) {
+        assert_eq!(store.get("sessions").unwrap(), None);
+        save(store, "sessions", &serde_json::json!({"it's": "a\nb"})).unwrap();
+        save(store, "jobs/run-2", &2).unwrap();
+        save(store, "jobs/run-1", &1).unwrap();
+        save(store, "jobs/run-1", &3).unwrap();
+        assert_eq!(load::<serde_json::Value>(store, "sessions").unwrap(), Some(serde_json::json!({"it's": "a\nb"})));
+        assert_eq!(load::<u32>(store, "jobs/run-1").unwrap(), Some(3));
+        assert_eq!(store.list("jobs").unwrap(), ["jobs/run-1", "jobs/run-2"]);
+        store.remove("jobs/run-2").unwrap();
+        store.remove("jobs/missing").unwrap();
+        assert_eq!(store.list("jobs").unwrap(), ["jobs/run-1"]);
+        assert!(store.list("nothing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backends_store_documents() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-state-{}", std::process::id()));
+        exercise(&FileStore::new(dir.join("files")));
+        assert!(dir.join("files/jobs/run-1.json").is_file());
+        if which::which("sqlite3").is_ok() {
+            exercise(&SqliteStore::open(&dir.join("db").join(SQLITE_FILE)).unwrap());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sqlite_store_can_be_used_from_async_tasks() {
+        if which::which("sqlite3").is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("codex-mcp-state-async-{}", std::process::id()));
+        let store = SqliteStore::open(&dir.join(SQLITE_FILE)).unwrap();
+        save(&store, "sessions", &1).unwrap();
+        assert_eq!(load::<u32>(&store, "sessions").unwrap(), Some(1));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_updates_are_atomic_across_processes() {
+        if which::which("sqlite3").is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("codex-mcp-state-update-{}", std::process::id()));
+        let db = dir.join(SQLITE_FILE);
+        SqliteStore::open(&db).unwrap();
+        // Separate stores, like servers sharing the database.
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let store = SqliteStore::open(&db).unwrap();
+                scope.spawn(move || {
+                    for _ in 0..5 {
+                        update(&store, "counter", |count: &mut u32| *count += 1).unwrap();
+                    }
+                });
+            }
+        });
+        let store = SqliteStore::open(&db).unwrap();
+        assert_eq!(load::<u32>(&store, "counter").unwrap(), Some(20));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
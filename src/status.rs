@@ -0,0 +1,218 @@
+//! Live server state for the `codex_server_status` tool: which runs are active
+//! or waiting to start, so orchestrators can decide where to submit work.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::unix_now;
+use crate::throttle::ThrottleState;
+
+/// Whether an active run has started codex yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActiveRunState {
+    /// Waiting for a workspace lock or a provider rate limit to pass.
+    Queued,
+    Running,
+}
+
+/// A run in progress, as reported by `codex_server_status`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ActiveRunStatus {
+    /// `run_id` of background runs; an ID local to this report otherwise.
+    pub run_id: String,
+    /// Tool the run was requested through.
+    pub tool: String,
+    pub state: ActiveRunState,
+    pub cd: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub elapsed_secs: u64,
+}
+
+/// Limits the server is configured with.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerLimits {
+    /// How concurrent write runs in one workspace are handled: `off`, `fail`, or `queue`.
+    pub workspace_lock: String,
+    /// Longest provider rate-limit hold new runs wait out instead of failing.
+    pub rate_limit_max_defer_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_budget_usd: Option<f64>,
+    /// Cost of today's runs so far.
+    pub spent_today_usd: f64,
+}
+
+/// Result returned by the codex_server_status tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerStatus {
+    /// Version of this server.
+    pub server_version: String,
+    /// Output of `codex --version`, when codex can be run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codex_version: Option<String>,
+    /// Unix timestamp of server start, and seconds since.
+    pub started_at: u64,
+    pub uptime_secs: u64,
+    /// Number of runs executing codex.
+    pub running: usize,
+    /// Number of runs waiting to start.
+    pub queued: usize,
+    /// Runs in progress, oldest first. On multi-client transports with
+    /// isolated sessions, only the calling client's runs are listed.
+    pub runs: Vec<ActiveRunStatus>,
+    pub limits: ServerLimits,
+    /// Hold on new runs after a provider rate limit, while one is in place.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<ThrottleState>,
+}
+
+#[derive(Debug)]
+struct ActiveRun {
+    run_id: Option<String>,
+    tool: String,
+    owner: Option<String>,
+    state: ActiveRunState,
+    cd: PathBuf,
+    model: Option<String>,
+    started: Instant,
+}
+
+/// Tracks the runs in progress since server start.
+#[derive(Debug)]
+pub struct StatusTracker {
+    started: Instant,
+    started_at: u64,
+    runs: Mutex<BTreeMap<u64, ActiveRun>>,
+    next_id: AtomicU64,
+}
+
+impl Default for StatusTracker {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: unix_now(),
+            runs: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+/// Registration of an active run; unregisters it on drop.
+#[derive(Debug)]
+pub struct ActiveRunGuard<'a> {
+    tracker: &'a StatusTracker,
+    id: u64,
+}
+
+impl ActiveRunGuard<'_> {
+    /// The run has started codex with `model`.
+    pub fn running(&self, model: Option<&str>) {
+        if let Some(run) = self.tracker.runs.lock().unwrap().get_mut(&self.id) {
+            run.state = ActiveRunState::Running;
+            run.model = model.map(str::to_string);
+        }
+    }
+}
+
+impl Drop for ActiveRunGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.runs.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl StatusTracker {
+    /// Register a queued run in `cd`, until the returned guard is dropped.
+    pub fn register(&self, run_id: Option<&str>, tool: &str, owner: Option<&str>, cd: PathBuf) -> ActiveRunGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.runs.lock().unwrap().insert(
+            id,
+            ActiveRun {
+                run_id: run_id.map(str::to_string),
+                tool: tool.to_string(),
+                owner: owner.map(str::to_string),
+                state: ActiveRunState::Queued,
+                cd,
+                model: None,
+                started: Instant::now(),
+            },
+        );
+        ActiveRunGuard { tracker: self, id }
+    }
+
+    /// Number of runs that have started codex and not finished yet.
+    pub fn running_count(&self) -> usize {
+        self.runs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|run| run.state == ActiveRunState::Running)
+            .count()
+    }
+
+    /// Status of the server; `client` limits the listed runs to its own.
+    pub fn status(&self, client: Option<&str>, limits: ServerLimits) -> ServerStatus {
+        let runs = self.runs.lock().unwrap();
+        let count = |state| runs.values().filter(|run| run.state == state).count();
+        ServerStatus {
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            codex_version: None,
+            started_at: self.started_at,
+            uptime_secs: self.started.elapsed().as_secs(),
+            running: count(ActiveRunState::Running),
+            queued: count(ActiveRunState::Queued),
+            runs: runs
+                .iter()
+                .filter(|(_, run)| client.is_none_or(|client| run.owner.as_deref() == Some(client)))
+                .map(|(id, run)| ActiveRunStatus {
+                    run_id: run.run_id.clone().unwrap_or_else(|| format!("active-{id}")),
+                    tool: run.tool.clone(),
+                    state: run.state,
+                    cd: run.cd.clone(),
+                    model: run.model.clone(),
+                    elapsed_secs: run.started.elapsed().as_secs(),
+                })
+                .collect(),
+            limits,
+            throttle: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_counts_and_filters_runs() {
+        let tracker = StatusTracker::default();
+        let limits = || ServerLimits {
+            workspace_lock: "off".to_string(),
+            rate_limit_max_defer_secs: 300,
+            daily_budget_usd: None,
+            spent_today_usd: 0.0,
+        };
+        let first = tracker.register(Some("run-1"), "codex_start", Some("uid:1"), PathBuf::from("/a"));
+        first.running(Some("o3"));
+        let second = tracker.register(None, "codex", Some("uid:2"), PathBuf::from("/b"));
+
+        let status = tracker.status(None, limits());
+        assert_eq!((status.running, status.queued), (1, 1));
+        assert_eq!(status.runs[0].run_id, "run-1");
+        assert_eq!(status.runs[0].model.as_deref(), Some("o3"));
+        assert_eq!(status.runs[1].state, ActiveRunState::Queued);
+
+        let status = tracker.status(Some("uid:2"), limits());
+        assert_eq!(status.runs.len(), 1);
+        assert_eq!(status.runs[0].tool, "codex");
+
+        drop((first, second));
+        assert_eq!(tracker.status(None, limits()).queued, 0);
+    }
+}
@@ -0,0 +1,92 @@
+//! `stream_to`: copy a run's raw event lines to a file or FIFO as they arrive,
+//! so external monitors can tail a run without MCP notification support.
+//!
+//! Lines are written from a dedicated thread so that a slow or stalled reader
+//! on a FIFO never holds up the run itself.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+
+/// Writer of a run's event lines to its `stream_to` target.
+#[derive(Debug)]
+pub struct StreamTee {
+    lines: Sender<String>,
+}
+
+impl StreamTee {
+    /// Open `path` for appending, creating a regular file if it does not
+    /// exist. A FIFO must already have a reader.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut out = open_for_append(path)?;
+        let (lines, received) = mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            for line in received {
+                if writeln!(out, "{line}").and_then(|()| out.flush()).is_err() {
+                    // The reader went away; nothing left to do.
+                    break;
+                }
+            }
+        });
+        Ok(Self { lines })
+    }
+
+    /// Queue `line` to be written.
+    pub fn write_line(&self, line: &str) {
+        let _ = self.lines.send(line.to_string());
+    }
+}
+
+#[cfg(unix)]
+fn open_for_append(path: &Path) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    // Opening a FIFO for writing blocks until there is a reader; open it
+    // non-blocking so a missing reader fails with ENXIO instead, then switch
+    // back to blocking writes.
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    // SAFETY: `file` owns a valid open descriptor for the duration of the calls.
+    unsafe {
+        let flags = libc::fcntl(file.as_raw_fd(), libc::F_GETFL);
+        if flags < 0 || libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags & !libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_for_append(path: &Path) -> io::Result<std::fs::File> {
+    std::fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_are_appended_in_order() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-tee-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        std::fs::write(&path, "earlier\n").unwrap();
+
+        let tee = StreamTee::open(&path).unwrap();
+        tee.write_line(r#"{"type":"thread.started"}"#);
+        tee.write_line(r#"{"type":"turn.completed"}"#);
+        drop(tee);
+
+        let expected = "earlier\n{\"type\":\"thread.started\"}\n{\"type\":\"turn.completed\"}\n";
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while std::fs::read_to_string(&path).unwrap() != expected && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! Provider rate limiting: when a run reports it, later runs are held back
+//! until the provider's retry-after time instead of all failing in a burst.
+//! With a shared state store the hold applies to every replica.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::error::CodexError;
+use crate::state::{self, Store, unix_now, unix_now_millis};
+
+/// Key of the shared hold in the state store.
+const THROTTLE_KEY: &str = "throttle";
+
+/// Hold applied when a rate-limit error gives no retry-after time.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Whether `message` reports provider rate limiting.
+pub fn is_rate_limit(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["rate limit", "rate_limit", "ratelimit", "too many requests", "status 429", "http 429"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+/// How long a rate-limit `message` asks to wait, e.g. "Please try again in
+/// 20s", "retry after 1.5 seconds", or "Retry-After: 30". Falls back to a
+/// minute when no time is given.
+pub fn retry_after(message: &str) -> Duration {
+    let lower = message.to_ascii_lowercase();
+    ["try again in", "retry after", "retry-after:", "retry in"]
+        .iter()
+        .filter_map(|marker| lower.find(marker).map(|i| &lower[i + marker.len()..]))
+        .find_map(parse_duration)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+/// Parse a leading `<number><unit>`, where the unit is ms, s, or m (seconds by default).
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim_start();
+    let end = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let value: f64 = text[..end].parse().ok()?;
+    let unit = text[end..].trim_start();
+    let secs = if unit.starts_with("ms") || unit.starts_with("millisecond") {
+        value / 1000.0
+    } else if unit.starts_with('m') && !unit.starts_with("ms") {
+        value * 60.0
+    } else {
+        value
+    };
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Throttling state reported by `codex_usage`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ThrottleState {
+    /// Unix timestamp until which new runs are held.
+    pub throttled_until: u64,
+    /// Seconds left on the hold.
+    pub remaining_secs: u64,
+    /// Number of runs currently waiting for the hold to pass.
+    pub deferred_runs: usize,
+}
+
+/// Hold shared between replicas, as a Unix time so it means the same to all.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SharedHold {
+    until_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct Hold {
+    until: Option<Instant>,
+    deferred: usize,
+}
+
+/// Server-wide hold on starting runs after a provider rate limit.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    hold: Mutex<Hold>,
+    /// Store the hold is shared through, when other replicas use it too.
+    store: Option<Store>,
+}
+
+impl Throttle {
+    /// A throttle whose holds are shared through `store` if it is shared.
+    pub fn new(store: Option<Store>) -> Self {
+        Self {
+            hold: Mutex::default(),
+            store: store.filter(|store| store.shared()),
+        }
+    }
+
+    /// Hold new runs for `duration`, unless a longer hold is already in place.
+    pub fn defer(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        {
+            let mut hold = self.hold.lock().unwrap();
+            if hold.until.is_none_or(|current| current < until) {
+                tracing::warn!(retry_after_secs = duration.as_secs_f64(), "Provider rate limit reached; holding new runs");
+                hold.until = Some(until);
+            }
+        }
+        if let Some(ref store) = self.store {
+            let until_ms = u64::try_from(unix_now_millis() + duration.as_millis()).unwrap_or(u64::MAX);
+            let shared = state::update(&**store, THROTTLE_KEY, |hold: &mut SharedHold| {
+                hold.until_ms = hold.until_ms.max(until_ms);
+            });
+            if let Err(e) = shared {
+                tracing::warn!(key = THROTTLE_KEY, error = %e, "Failed to share rate-limit hold");
+            }
+        }
+    }
+
+    /// Time left on the hold, if any: the longer of this server's and the
+    /// shared one.
+    fn remaining(&self) -> Option<Duration> {
+        let local = self
+            .hold
+            .lock()
+            .unwrap()
+            .until
+            .map(|until| until.saturating_duration_since(Instant::now()));
+        let shared = self.shared_remaining();
+        local.max(shared).filter(|d| !d.is_zero())
+    }
+
+    /// Time left on the hold another replica shared, if any.
+    fn shared_remaining(&self) -> Option<Duration> {
+        let store = self.store.as_ref()?;
+        let hold: SharedHold = match state::load(&**store, THROTTLE_KEY) {
+            Ok(hold) => hold?,
+            Err(e) => {
+                tracing::warn!(key = THROTTLE_KEY, error = %e, "Failed to read shared rate-limit hold");
+                return None;
+            }
+        };
+        let now_ms = u64::try_from(unix_now_millis()).unwrap_or(u64::MAX);
+        Some(Duration::from_millis(hold.until_ms.saturating_sub(now_ms)))
+    }
+
+    /// Wait for the hold to pass. A hold longer than `max_wait` fails
+    /// immediately with `rate_limited` rather than blocking the caller.
+    pub async fn wait(&self, max_wait: Duration) -> Result<(), CodexError> {
+        while let Some(remaining) = self.remaining() {
+            if remaining > max_wait {
+                return Err(CodexError::RateLimited(remaining.as_secs().max(1)));
+            }
+            tracing::info!(wait_secs = remaining.as_secs_f64(), "Deferring run until the rate limit passes");
+            self.hold.lock().unwrap().deferred += 1;
+            tokio::time::sleep(remaining).await;
+            self.hold.lock().unwrap().deferred -= 1;
+        }
+        Ok(())
+    }
+
+    /// The current hold, if runs are being held.
+    pub fn state(&self) -> Option<ThrottleState> {
+        let remaining = self.remaining()?;
+        Some(ThrottleState {
+            throttled_until: unix_now() + remaining.as_secs(),
+            remaining_secs: remaining.as_secs(),
+            deferred_runs: self.hold.lock().unwrap().deferred,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert!(is_rate_limit("Rate limit reached for gpt-5 in organization org-x on tokens per min"));
+        assert!(is_rate_limit("stream error: 429 Too Many Requests"));
+        assert!(!is_rate_limit("model overloaded"));
+        assert!(!is_rate_limit("syntax error on line 429"));
+
+        assert_eq!(retry_after("Rate limit reached. Please try again in 20s."), Duration::from_secs(20));
+        assert_eq!(retry_after("Please try again in 1.5 seconds"), Duration::from_millis(1500));
+        assert_eq!(retry_after("Please try again in 450ms"), Duration::from_millis(450));
+        assert_eq!(retry_after("retry after 2 minutes"), Duration::from_secs(120));
+        assert_eq!(retry_after("Retry-After: 30"), Duration::from_secs(30));
+        assert_eq!(retry_after("429 Too Many Requests"), DEFAULT_RETRY_AFTER);
+    }
+
+    #[tokio::test]
+    async fn test_wait_holds_runs_until_the_limit_passes() {
+        let throttle = Throttle::default();
+        throttle.wait(Duration::ZERO).await.unwrap();
+
+        throttle.defer(Duration::from_millis(200));
+        assert!(throttle.state().is_some());
+        assert!(matches!(throttle.wait(Duration::from_millis(10)).await, Err(CodexError::RateLimited(1))));
+
+        let started = Instant::now();
+        throttle.wait(Duration::from_secs(5)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert!(throttle.state().is_none());
+    }
+}
@@ -0,0 +1,136 @@
+//! Timing breakdown of runs (`collect_timings`), for diagnosing where the time
+//! of slow runs goes: starting codex, waiting for its first event, or the model.
+
+use std::time::Instant;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Timing breakdown of one run, in milliseconds since just before codex was spawned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RunTimings {
+    /// Time taken to spawn the codex process.
+    pub spawn_ms: u64,
+    /// Time until codex printed its first event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_event_ms: Option<u64>,
+    /// Time until the first agent message arrived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_agent_message_ms: Option<u64>,
+    /// Time until codex exited.
+    pub total_ms: u64,
+}
+
+/// Records the timing points of a run as it happens.
+#[derive(Debug)]
+pub struct TimingRecorder {
+    started: Instant,
+    timings: RunTimings,
+}
+
+impl TimingRecorder {
+    /// Start timing, just before spawning codex.
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            timings: RunTimings::default(),
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// The codex process was spawned.
+    pub fn spawned(&mut self) {
+        self.timings.spawn_ms = self.elapsed_ms();
+    }
+
+    /// An event arrived; `has_agent_message` tells whether the run has
+    /// produced an agent message so far.
+    pub fn event(&mut self, has_agent_message: bool) {
+        let now = self.elapsed_ms();
+        self.timings.first_event_ms.get_or_insert(now);
+        if has_agent_message {
+            self.timings.first_agent_message_ms.get_or_insert(now);
+        }
+    }
+
+    /// codex exited.
+    pub fn finish(mut self) -> RunTimings {
+        self.timings.total_ms = self.elapsed_ms();
+        self.timings
+    }
+}
+
+/// Aggregate of one timing point over many runs.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TimingStat {
+    /// Number of runs that reached this point.
+    pub runs: u64,
+    pub sum_ms: u64,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+}
+
+impl TimingStat {
+    fn record(&mut self, ms: Option<u64>) {
+        let Some(ms) = ms else { return };
+        self.runs += 1;
+        self.sum_ms += ms;
+        self.mean_ms = self.sum_ms / self.runs;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+/// Timings aggregated over runs with `collect_timings`, in `codex_usage`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TimingTotals {
+    pub spawn: TimingStat,
+    pub first_event: TimingStat,
+    pub first_agent_message: TimingStat,
+    pub total: TimingStat,
+}
+
+impl TimingTotals {
+    pub fn record(&mut self, timings: &RunTimings) {
+        self.spawn.record(Some(timings.spawn_ms));
+        self.first_event.record(timings.first_event_ms);
+        self.first_agent_message.record(timings.first_agent_message_ms);
+        self.total.record(Some(timings.total_ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_recorder_keeps_first_occurrences() {
+        let mut recorder = TimingRecorder::start();
+        recorder.spawned();
+        recorder.event(false);
+        let first_event = recorder.timings.first_event_ms;
+        std::thread::sleep(Duration::from_millis(5));
+        recorder.event(true);
+        recorder.event(true);
+        let timings = recorder.finish();
+        assert_eq!(timings.first_event_ms, first_event);
+        assert!(timings.first_agent_message_ms.unwrap() >= first_event.unwrap() + 5);
+        assert!(timings.total_ms >= timings.first_agent_message_ms.unwrap());
+
+        let mut totals = TimingTotals::default();
+        totals.record(&timings);
+        totals.record(&RunTimings {
+            total_ms: timings.total_ms + 10,
+            ..RunTimings::default()
+        });
+        assert_eq!(totals.total.runs, 2);
+        assert_eq!(totals.first_agent_message.runs, 1);
+        assert_eq!(totals.total.max_ms, timings.total_ms + 10);
+    }
+}
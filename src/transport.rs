@@ -0,0 +1,234 @@
+//! Serving the MCP server over a Unix domain socket.
+//!
+//! Each accepted connection is an independent MCP session sharing the same
+//! server state (session registry, usage, workspace locks). Clients are
+//! identified by the OS user on the other end of the socket, which scopes the
+//! sessions they can see unless `shared_sessions` is enabled.
+
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use rmcp::ServiceExt;
+use tokio::sync::mpsc;
+use tokio_util::io::SyncIoBridge;
+
+use crate::codex::CodexServer;
+use crate::config::ServerConfig;
+use crate::shutdown;
+
+/// Buffer size of the in-process pipe between a socket and its MCP session.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Accept MCP clients on `path` until SIGINT/SIGTERM, then remove the socket.
+pub async fn serve_unix(
+    server: CodexServer,
+    path: &Path,
+    mode: u32,
+) -> Result<()> {
+    let listener = bind_unix(path, mode)?;
+    tracing::info!(socket = %path.display(), mode = format!("{mode:o}"), "Listening on Unix socket");
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if tx.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to accept Unix socket connection"),
+            }
+        }
+    });
+
+    let result = loop {
+        tokio::select! {
+            stream = rx.recv() => {
+                let Some(stream) = stream else {
+                    break Err(anyhow!("Unix socket listener stopped"));
+                };
+                let uid = match peer_uid(&stream) {
+                    Ok(uid) => uid,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Rejecting Unix socket client with unknown credentials");
+                        continue;
+                    }
+                };
+                if !is_allowed(&server.config(), uid) {
+                    tracing::warn!(uid, "Rejecting Unix socket client not in `allowed_client_uids`");
+                    continue;
+                }
+                tracing::debug!(uid, "Accepted Unix socket client");
+                let server = server.for_client(format!("uid:{uid}"));
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(server, stream).await {
+                        tracing::warn!(error = %e, "Unix socket session ended with an error");
+                    }
+                });
+            }
+            _ = shutdown::signal() => break Ok(()),
+        }
+    };
+
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::warn!(error = %e, socket = %path.display(), "Failed to remove Unix socket");
+    }
+    result
+}
+
+/// Bind `path`, replacing a stale socket left behind by a server that did not
+/// shut down cleanly, and restrict it to `mode`.
+fn bind_unix(path: &Path, mode: u32) -> Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        if UnixStream::connect(path).is_ok() {
+            bail!("{} is already in use by a running server", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("failed to bind {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("failed to set permissions on {}", path.display()))?;
+    Ok(listener)
+}
+
+fn is_allowed(config: &ServerConfig, uid: u32) -> bool {
+    config.allowed_client_uids.is_empty() || config.allowed_client_uids.contains(&uid)
+}
+
+/// User ID of the process on the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` is its size.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut cred).cast(),
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// User ID of the process on the other end of `stream`.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: `uid` and `gid` are valid for writes.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Run one MCP session over an accepted socket.
+///
+/// The socket is blocking, so two blocking tasks pump bytes between it and
+/// an in-process pipe that the async MCP service reads and writes.
+async fn serve_connection(server: CodexServer, stream: UnixStream) -> Result<()> {
+    let (local, remote) = tokio::io::duplex(PIPE_CAPACITY);
+    let (remote_read, remote_write) = tokio::io::split(remote);
+
+    let mut socket_read = stream.try_clone()?;
+    let mut to_service = SyncIoBridge::new(remote_write);
+    tokio::task::spawn_blocking(move || {
+        let _ = std::io::copy(&mut socket_read, &mut to_service);
+        let _ = to_service.shutdown();
+    });
+
+    let mut socket_write = stream;
+    let mut from_service = SyncIoBridge::new(remote_read);
+    tokio::task::spawn_blocking(move || {
+        let _ = std::io::copy(&mut from_service, &mut socket_write);
+        let _ = socket_write.flush();
+        let _ = socket_write.shutdown(Shutdown::Both);
+    });
+
+    let service = server.serve(local).await?;
+    service.waiting().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_bind_replaces_stale_socket_and_sets_mode() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mcp.sock");
+
+        drop(bind_unix(&path, 0o600).unwrap());
+        assert!(path.exists(), "dropping the listener leaves a stale socket");
+
+        let listener = bind_unix(&path, 0o660).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        assert!(bind_unix(&path, 0o600).is_err(), "live socket must not be replaced");
+        drop(listener);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_peer_uid_is_current_user() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&a).unwrap(), unsafe { libc::getuid() });
+    }
+
+    #[test]
+    fn test_allowed_client_uids() {
+        let mut config = ServerConfig::default();
+        assert!(is_allowed(&config, 1000));
+        config.allowed_client_uids = vec![0, 1001];
+        assert!(!is_allowed(&config, 1000));
+        assert!(is_allowed(&config, 1001));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_connection_speaks_mcp() {
+        let (client, server_side) = UnixStream::pair().unwrap();
+        let server = CodexServer::new(ServerConfig::default()).unwrap();
+        tokio::spawn(serve_connection(server, server_side));
+
+        let response = tokio::task::spawn_blocking(move || {
+            let mut writer = client.try_clone().unwrap();
+            writeln!(
+                writer,
+                r#"{{"jsonrpc":"2.0","id":1,"method":"initialize","params":{{"protocolVersion":"2025-06-18","capabilities":{{}},"clientInfo":{{"name":"t","version":"0"}}}}}}"#
+            )
+            .unwrap();
+            let mut line = String::new();
+            BufReader::new(client).read_line(&mut line).unwrap();
+            line
+        })
+        .await
+        .unwrap();
+
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["serverInfo"]["name"], "Codex MCP Server");
+    }
+}
@@ -0,0 +1,115 @@
+//! Model tuning parameters (`reasoning_effort`, `text_verbosity`), translated
+//! into the codex config overrides the selected model family understands, so
+//! callers need not know codex's config keys or which models accept what.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Codex config key setting the reasoning effort of reasoning models.
+pub const REASONING_EFFORT_KEY: &str = "model_reasoning_effort";
+
+/// Codex config key setting the output verbosity of GPT-5 models.
+pub const VERBOSITY_KEY: &str = "model_verbosity";
+
+/// How much reasoning the model does before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    /// Barely any reasoning; GPT-5 models only, `low` elsewhere.
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// How long and detailed the model's answers are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TextVerbosity {
+    Low,
+    Medium,
+    High,
+}
+
+impl TextVerbosity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// Model families that differ in the tuning parameters they accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelFamily {
+    /// GPT-5 and the codex models built on it: effort (with `minimal`) and verbosity.
+    Gpt5,
+    /// The o-series: effort from `low` up, no verbosity.
+    OSeries,
+    /// Everything else (e.g. GPT-4.1 or open-weight models): neither.
+    Other,
+}
+
+impl ModelFamily {
+    /// Family of `model`; codex's default model is a GPT-5 one.
+    fn of(model: Option<&str>) -> Self {
+        let Some(model) = model.map(str::to_ascii_lowercase) else {
+            return Self::Gpt5;
+        };
+        let name = model.rsplit('/').next().unwrap_or(&model);
+        if name.starts_with("gpt-5") || name.starts_with("codex") {
+            Self::Gpt5
+        } else if name.strip_prefix('o').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())) {
+            Self::OSeries
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Tuning as applied to `model`: settings its family does not accept are
+/// dropped, and `minimal` effort is raised to `low` outside GPT-5.
+pub fn for_model(
+    model: Option<&str>,
+    effort: Option<ReasoningEffort>,
+    verbosity: Option<TextVerbosity>,
+) -> (Option<ReasoningEffort>, Option<TextVerbosity>) {
+    match ModelFamily::of(model) {
+        ModelFamily::Gpt5 => (effort, verbosity),
+        ModelFamily::OSeries => (
+            effort.map(|e| if e == ReasoningEffort::Minimal { ReasoningEffort::Low } else { e }),
+            None,
+        ),
+        ModelFamily::Other => (None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuning_follows_model_family() {
+        let requested = (Some(ReasoningEffort::Minimal), Some(TextVerbosity::Low));
+        assert_eq!(for_model(None, requested.0, requested.1), requested);
+        assert_eq!(for_model(Some("gpt-5-codex"), requested.0, requested.1), requested);
+        assert_eq!(for_model(Some("openai/GPT-5"), requested.0, requested.1), requested);
+        assert_eq!(for_model(Some("o4-mini"), requested.0, requested.1), (Some(ReasoningEffort::Low), None));
+        assert_eq!(for_model(Some("o3"), Some(ReasoningEffort::High), None), (Some(ReasoningEffort::High), None));
+        assert_eq!(for_model(Some("gpt-4.1"), requested.0, requested.1), (None, None));
+        assert_eq!(for_model(Some("oss-20b"), requested.0, requested.1), (None, None));
+    }
+}
@@ -0,0 +1,367 @@
+//! Files uploaded in chunks with `codex_upload_context`, for clients that do
+//! not share a filesystem with the server.
+//!
+//! An upload is assembled in `<uploads dir>/<upload_id>/<file_name>.part`
+//! and renamed to `<file_name>` when its last chunk arrives. The resulting
+//! path is the handle clients pass in `image` or `context_files`. The uploads
+//! directory is `uploads` in the state directory, or a directory in the
+//! system temp directory when the server has no state directory.
+//!
+//! Upload IDs are random, and an upload belongs to the client that started
+//! it: only that client can add chunks to it or use its file in a run. Each
+//! client can have `MAX_PENDING_UPLOADS` uploads in progress; one that gets
+//! no chunk for an hour is dropped, and assembled files are deleted after a
+//! day, as are files left behind by an earlier server process.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CodexError;
+use crate::random;
+
+/// Name of an upload whose first chunk gives no `file_name`.
+const DEFAULT_FILE_NAME: &str = "context";
+
+/// Parameters for the codex_upload_context tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UploadParams {
+    /// Upload to append the chunk to. Omit on the first chunk to start a new
+    /// upload; its ID is returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_id: Option<String>,
+
+    /// Name of the assembled file, e.g. `design.md` or `screenshot.png`.
+    /// Only read on the first chunk.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+
+    /// The chunk's bytes, base64-encoded. May be empty, e.g. on a final call
+    /// that only sets `done`.
+    #[serde(default)]
+    pub data: String,
+
+    /// Bytes of the upload received before this chunk. When given, a chunk
+    /// at any other offset is rejected, so a retried chunk is not appended twice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+
+    /// This is the last chunk: assemble the file and return its `path`.
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// Result returned by the codex_upload_context tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UploadResult {
+    /// Pass it with the next chunk.
+    pub upload_id: String,
+
+    /// Bytes of the upload received so far.
+    pub received_bytes: u64,
+
+    /// Whether the upload is assembled.
+    pub complete: bool,
+
+    /// The assembled file, once complete: pass it in `context_files` or
+    /// `image` of a run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// Most uploads a client may have in progress at once.
+const MAX_PENDING_UPLOADS: usize = 8;
+
+/// How long an upload may go without a chunk before it is dropped.
+const PENDING_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long an assembled file is kept for runs to use.
+const COMPLETE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An upload, in progress or assembled.
+#[derive(Debug)]
+struct Upload {
+    /// The assembled file's final path; chunks go to it with `.part` appended.
+    path: PathBuf,
+    /// Client that started the upload; only it may add to or use it.
+    owner: Option<String>,
+    received: u64,
+    complete: bool,
+    /// When the last chunk arrived.
+    updated: Instant,
+}
+
+impl Upload {
+    fn part_path(&self) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(".part");
+        PathBuf::from(name)
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        let ttl = if self.complete { COMPLETE_TTL } else { PENDING_TTL };
+        now.saturating_duration_since(self.updated) > ttl
+    }
+
+    /// Remove the upload's files.
+    fn discard(&self) {
+        let _ = std::fs::remove_dir_all(self.path.parent().unwrap_or(&self.path));
+    }
+}
+
+/// Uploads and the directory they are assembled in.
+#[derive(Debug)]
+pub struct UploadStore {
+    dir: PathBuf,
+    uploads: Mutex<HashMap<String, Upload>>,
+}
+
+impl UploadStore {
+    /// The store for a server with `state_dir`, if any.
+    pub fn new(state_dir: Option<&Path>) -> Self {
+        let dir = match state_dir {
+            Some(state_dir) => state_dir.join("uploads"),
+            None => std::env::temp_dir().join(format!("codex-mcp-uploads-{}", std::process::id())),
+        };
+        Self {
+            dir,
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Directory the assembled files live in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether `owner` may use `path` in a run: any path outside the uploads
+    /// directory, and inside it only the assembled files of its own uploads.
+    pub fn can_use(&self, owner: Option<&str>, path: &Path) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let dir = self.dir.canonicalize().unwrap_or_else(|_| self.dir.clone());
+        let Ok(relative) = path.strip_prefix(&dir) else {
+            return true;
+        };
+        let id = relative.components().next().and_then(|c| c.as_os_str().to_str());
+        let uploads = self.uploads.lock().unwrap();
+        id.and_then(|id| uploads.get(id)).is_some_and(|upload| {
+            upload.complete
+                && upload.owner.as_deref() == owner
+                && upload.path.canonicalize().is_ok_and(|assembled| assembled == path)
+        })
+    }
+
+    /// Append a chunk to an upload of `owner` (starting one without
+    /// `upload_id`), holding uploads to `max_bytes`.
+    pub fn append(&self, owner: Option<&str>, params: &UploadParams, max_bytes: u64) -> Result<UploadResult, CodexError> {
+        self.append_at(owner, params, max_bytes, Instant::now())
+    }
+
+    fn append_at(
+        &self,
+        owner: Option<&str>,
+        params: &UploadParams,
+        max_bytes: u64,
+        now: Instant,
+    ) -> Result<UploadResult, CodexError> {
+        let data = BASE64
+            .decode(params.data.trim())
+            .map_err(|e| CodexError::InvalidUpload(format!("`data` is not valid base64 ({e})")))?;
+        let mut uploads = self.uploads.lock().unwrap();
+        self.expire(&mut uploads, now);
+        let upload_id = match params.upload_id {
+            Some(ref id) => {
+                // Another client's upload is reported as missing, like one that never existed.
+                if !uploads.get(id).is_some_and(|upload| !upload.complete && upload.owner.as_deref() == owner) {
+                    return Err(CodexError::UploadNotFound(id.clone()));
+                }
+                id.clone()
+            }
+            None => {
+                let in_progress = uploads.values().filter(|u| !u.complete && u.owner.as_deref() == owner).count();
+                if in_progress >= MAX_PENDING_UPLOADS {
+                    return Err(CodexError::InvalidUpload(format!(
+                        "{in_progress} uploads are already in progress; finish them first (at most {MAX_PENDING_UPLOADS})"
+                    )));
+                }
+                let id = random::token()?;
+                let path = self.dir.join(&id).join(file_name(params.file_name.as_deref())?);
+                std::fs::create_dir_all(self.dir.join(&id))?;
+                let upload = Upload {
+                    path,
+                    owner: owner.map(str::to_string),
+                    received: 0,
+                    complete: false,
+                    updated: now,
+                };
+                uploads.insert(id.clone(), upload);
+                id
+            }
+        };
+        let upload = uploads.get_mut(&upload_id).expect("upload is pending");
+        if let Some(offset) = params.offset
+            && offset != upload.received
+        {
+            return Err(CodexError::InvalidUpload(format!(
+                "chunk is at offset {offset}, but {} bytes were received so far",
+                upload.received
+            )));
+        }
+        let size = upload.received + data.len() as u64;
+        if size > max_bytes {
+            uploads.remove(&upload_id).expect("upload is pending").discard();
+            return Err(CodexError::InvalidUpload(format!(
+                "the upload would be {size} bytes, over the limit of {max_bytes} bytes"
+            )));
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(upload.part_path())?
+            .write_all(&data)?;
+        upload.received = size;
+        upload.updated = now;
+
+        if !params.done {
+            return Ok(UploadResult {
+                upload_id,
+                received_bytes: size,
+                complete: false,
+                path: None,
+            });
+        }
+        std::fs::rename(upload.part_path(), &upload.path)?;
+        upload.complete = true;
+        tracing::info!(upload_id = %upload_id, bytes = size, "Assembled uploaded context file");
+        Ok(UploadResult {
+            upload_id,
+            received_bytes: size,
+            complete: true,
+            path: Some(upload.path.clone()),
+        })
+    }
+
+    /// Drop expired uploads, and directories no upload of this process uses
+    /// that have not changed for `COMPLETE_TTL`.
+    fn expire(&self, uploads: &mut HashMap<String, Upload>, now: Instant) {
+        uploads.retain(|_, upload| {
+            let expired = upload.expired(now);
+            if expired {
+                upload.discard();
+            }
+            !expired
+        });
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let stale = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > COMPLETE_TTL));
+            if stale && entry.file_name().to_str().is_none_or(|id| !uploads.contains_key(id)) {
+                let _ = std::fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+/// `name` as the file name of an upload, rejecting anything that is not a
+/// plain file name.
+fn file_name(name: Option<&str>) -> Result<String, CodexError> {
+    let name = name.map(str::trim).filter(|name| !name.is_empty()).unwrap_or(DEFAULT_FILE_NAME);
+    let plain = Path::new(name).file_name().is_some_and(|file| file == name);
+    if !plain || name.starts_with('.') || name.ends_with(".part") {
+        return Err(CodexError::InvalidUpload(format!("{name:?} is not a plain file name")));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(upload_id: Option<&str>, data: &[u8], offset: Option<u64>, done: bool) -> UploadParams {
+        UploadParams {
+            upload_id: upload_id.map(str::to_string),
+            file_name: Some("notes.md".to_string()),
+            data: BASE64.encode(data),
+            offset,
+            done,
+        }
+    }
+
+    #[test]
+    fn test_chunks_are_assembled_into_one_file() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-uploads-test-{}", random::token().unwrap()));
+        let store = UploadStore::new(Some(&dir));
+
+        let first = store.append(None, &chunk(None, b"hello ", None, false), 16).unwrap();
+        assert_eq!((first.received_bytes, first.complete, first.path.clone()), (6, false, None));
+        let id = first.upload_id.as_str();
+        let retried = store.append(None, &chunk(Some(id), b"hello ", Some(0), false), 16).unwrap_err();
+        assert_eq!(retried.code(), "invalid_upload");
+        let last = store.append(None, &chunk(Some(id), b"world", Some(6), true), 16).unwrap();
+        let path = last.path.unwrap();
+        assert_eq!(path, dir.join("uploads").join(id).join("notes.md"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+        assert_eq!(store.append(None, &chunk(Some(id), b"", None, true), 16).unwrap_err().code(), "upload_not_found");
+
+        let big = store.append(None, &chunk(None, &[0; 17], None, true), 16).unwrap_err();
+        assert!(big.to_string().contains("over the limit of 16 bytes"), "{big}");
+        let mut bad_name = chunk(None, b"", None, true);
+        bad_name.file_name = Some("../escape.md".to_string());
+        assert_eq!(store.append(None, &bad_name, 16).unwrap_err().code(), "invalid_upload");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_uploads_belong_to_the_client_that_started_them() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-uploads-owner-{}", random::token().unwrap()));
+        let store = UploadStore::new(Some(&dir));
+        let (alice, bob) = (Some("uid:1000"), Some("uid:1001"));
+
+        let first = store.append(alice, &chunk(None, b"hello", None, false), 16).unwrap();
+        let id = first.upload_id.as_str();
+        assert_eq!(store.append(bob, &chunk(Some(id), b"!", None, true), 16).unwrap_err().code(), "upload_not_found");
+        let path = store.append(alice, &chunk(Some(id), b"", None, true), 16).unwrap().path.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+
+        assert!(store.can_use(alice, &path));
+        assert!(!store.can_use(bob, &path));
+        assert!(!store.can_use(alice, &dir.join("uploads").join("0123").join("notes.md")));
+        assert!(store.can_use(bob, Path::new("/etc/hostname")), "paths outside the uploads are not for it to judge");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pending_uploads_are_capped_and_expire() {
+        let dir = std::env::temp_dir().join(format!("codex-mcp-uploads-expiry-{}", random::token().unwrap()));
+        let store = UploadStore::new(Some(&dir));
+        let start = Instant::now();
+
+        let ids: Vec<String> = (0..MAX_PENDING_UPLOADS)
+            .map(|_| store.append_at(None, &chunk(None, b"x", None, false), 16, start).unwrap().upload_id)
+            .collect();
+        let refused = store.append_at(None, &chunk(None, b"x", None, false), 16, start).unwrap_err();
+        assert!(refused.to_string().contains("already in progress"), "{refused}");
+        assert!(store.append_at(Some("uid:1000"), &chunk(None, b"x", None, false), 16, start).is_ok());
+
+        let later = start + PENDING_TTL + Duration::from_secs(1);
+        assert!(store.append_at(None, &chunk(None, b"x", None, false), 16, later).is_ok());
+        let expired = store.append_at(None, &chunk(Some(&ids[0]), b"x", None, true), 16, later).unwrap_err();
+        assert_eq!(expired.code(), "upload_not_found");
+        assert!(!dir.join("uploads").join(&ids[0]).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! Token usage accounting across codex runs.
+
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{Store, load, save, unix_now};
+use crate::throttle::ThrottleState;
+use crate::timings::{RunTimings, TimingTotals};
+
+/// Key of the persisted usage totals in the state store.
+const USAGE_KEY: &str = "usage";
+
+/// Key used in the per-model breakdown for runs without an explicit model.
+const DEFAULT_MODEL_KEY: &str = "(default)";
+
+/// Days of per-day cost kept in the report.
+const MAX_COST_DAYS: usize = 31;
+
+/// Token counts reported by codex in `turn.completed` events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TokenUsage {
+    /// Prompt tokens sent to the model.
+    pub input_tokens: u64,
+    /// Prompt tokens served from the provider's cache.
+    pub cached_input_tokens: u64,
+    /// Tokens generated by the model.
+    pub output_tokens: u64,
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// Run counts and token totals for one bucket (overall or per model).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct UsageTotals {
+    /// Number of runs executed.
+    pub runs: u64,
+    /// Number of runs that succeeded.
+    pub successful_runs: u64,
+    /// Fraction of runs that succeeded, from 0.0 to 1.0.
+    pub success_rate: f64,
+    /// Cumulative token usage.
+    pub tokens: TokenUsage,
+}
+
+impl UsageTotals {
+    fn record(&mut self, success: bool, tokens: TokenUsage) {
+        self.runs += 1;
+        if success {
+            self.successful_runs += 1;
+        }
+        self.success_rate = self.successful_runs as f64 / self.runs as f64;
+        self.tokens += tokens;
+    }
+}
+
+/// Aggregate usage reported by the `codex_usage` tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UsageReport {
+    /// Unix timestamp (seconds) since which usage has been accumulated.
+    pub since: u64,
+    /// Totals across all runs.
+    pub total: UsageTotals,
+    /// Totals broken down by requested model.
+    pub models: BTreeMap<String, UsageTotals>,
+    /// Cumulative cost in USD of runs whose model has a configured price.
+    #[serde(default)]
+    pub cost_usd: f64,
+    /// Cost in USD per local day (`YYYY-MM-DD`), for the last 31 days.
+    #[serde(default)]
+    pub daily_cost_usd: BTreeMap<String, f64>,
+    /// Timings aggregated over runs with `collect_timings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<TimingTotals>,
+    /// Hold on new runs after a provider rate limit, while one is in place.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<ThrottleState>,
+}
+
+impl Default for UsageReport {
+    fn default() -> Self {
+        Self {
+            since: unix_now(),
+            total: UsageTotals::default(),
+            models: BTreeMap::new(),
+            cost_usd: 0.0,
+            daily_cost_usd: BTreeMap::new(),
+            timings: None,
+            throttle: None,
+        }
+    }
+}
+
+/// Accumulates usage since server start, optionally persisted across restarts.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    store: Option<Store>,
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    /// Open the tracker. With a store, totals are loaded from and
+    /// saved to it so they survive restarts.
+    pub fn open(store: Option<Store>) -> Result<Self> {
+        let Some(store) = store else {
+            return Ok(Self::default());
+        };
+        let report = load(&*store, USAGE_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store: Some(store),
+            report: Mutex::new(report),
+        })
+    }
+
+    /// Record the outcome of a run, and its cost when known.
+    pub fn record(&self, model: Option<&str>, success: bool, tokens: TokenUsage, cost_usd: Option<f64>) {
+        let mut report = self.report.lock().unwrap();
+        if let Some(cost) = cost_usd {
+            report.cost_usd += cost;
+            *report.daily_cost_usd.entry(today()).or_default() += cost;
+            while report.daily_cost_usd.len() > MAX_COST_DAYS {
+                report.daily_cost_usd.pop_first();
+            }
+        }
+        report.total.record(success, tokens);
+        report
+            .models
+            .entry(model.unwrap_or(DEFAULT_MODEL_KEY).to_string())
+            .or_default()
+            .record(success, tokens);
+
+        self.persist(&report);
+    }
+
+    /// Add the timings of a run with `collect_timings` to the aggregate.
+    pub fn record_timings(&self, timings: &RunTimings) {
+        let mut report = self.report.lock().unwrap();
+        report.timings.get_or_insert_default().record(timings);
+        self.persist(&report);
+    }
+
+    fn persist(&self, report: &UsageReport) {
+        if let Some(ref store) = self.store
+            && let Err(e) = save(&**store, USAGE_KEY, report)
+        {
+            tracing::warn!(key = USAGE_KEY, error = %e, "Failed to persist usage totals");
+        }
+    }
+
+    /// Snapshot of the accumulated usage.
+    pub fn report(&self) -> UsageReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Cost in USD of today's runs so far.
+    pub fn spent_today(&self) -> f64 {
+        self.report.lock().unwrap().daily_cost_usd.get(&today()).copied().unwrap_or(0.0)
+    }
+}
+
+fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_aggregates_per_model() {
+        let tracker = UsageTracker::default();
+        let tokens = TokenUsage {
+            input_tokens: 10,
+            cached_input_tokens: 4,
+            output_tokens: 2,
+        };
+        tracker.record(Some("gpt-5"), true, tokens, Some(0.25));
+        tracker.record(Some("gpt-5"), false, tokens, Some(0.5));
+        tracker.record(None, true, tokens, None);
+
+        let report = tracker.report();
+        assert_eq!(report.total.runs, 3);
+        assert_eq!(report.total.tokens.input_tokens, 30);
+        assert_eq!(report.models["gpt-5"].runs, 2);
+        assert_eq!(report.models["gpt-5"].success_rate, 0.5);
+        assert_eq!(report.models[DEFAULT_MODEL_KEY].successful_runs, 1);
+        assert_eq!(report.cost_usd, 0.75);
+        assert_eq!(tracker.spent_today(), 0.75);
+    }
+}
@@ -0,0 +1,300 @@
+//! Opening pull/merge requests for committed runs on the configured hosting service.
+//!
+//! Each provider only describes the command that opens the request and how to
+//! find the request's URL in its output, so they share pushing and process
+//! handling.
+
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::codex::PullRequestOptions;
+use crate::config::{ServerConfig, VcsProviderKind};
+use crate::git;
+
+/// A pull/merge request to open from an already-pushed branch.
+#[derive(Debug)]
+pub struct ChangeRequest<'a> {
+    /// Fetch URL of the remote the branch was pushed to.
+    pub remote_url: &'a str,
+    pub branch: &'a str,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub options: &'a PullRequestOptions,
+}
+
+/// A process invocation that opens a change request.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: &'static str,
+    pub args: Vec<String>,
+    pub env: Vec<(&'static str, String)>,
+    /// Written to the process's stdin, keeping secrets out of its arguments.
+    pub stdin: Option<String>,
+}
+
+/// A hosting service that pull/merge requests can be opened on.
+pub trait VcsProvider: Send + Sync {
+    /// How to open `request`, authenticating with `token` if configured.
+    fn invocation(&self, request: &ChangeRequest<'_>, token: Option<&str>) -> Result<Invocation>;
+
+    /// URL of the opened request, from the invocation's stdout.
+    fn parse_url(&self, stdout: &str) -> Result<String>;
+}
+
+/// The provider selected by `vcs_provider`.
+pub fn provider(kind: VcsProviderKind) -> &'static dyn VcsProvider {
+    match kind {
+        VcsProviderKind::Github => &GitHub,
+        VcsProviderKind::Gitlab => &GitLab,
+        VcsProviderKind::Bitbucket => &Bitbucket,
+    }
+}
+
+/// Push `branch` to the configured remote and open a pull/merge request for
+/// it, returning the request's URL.
+pub async fn open_change_request(
+    cd: &Path,
+    config: &ServerConfig,
+    branch: &str,
+    title: &str,
+    body: &str,
+    options: &PullRequestOptions,
+) -> Result<String> {
+    git::push_branch(cd, &config.git_remote, branch).await?;
+    let remote_url = git::remote_url(cd, &config.git_remote).await?;
+    let request = ChangeRequest {
+        remote_url: &remote_url,
+        branch,
+        title,
+        body,
+        options,
+    };
+    let provider = provider(config.vcs_provider);
+    let invocation = provider.invocation(&request, config.vcs_token.as_deref())?;
+
+    let mut child = Command::new(invocation.program)
+        .current_dir(cd)
+        .args(&invocation.args)
+        .envs(invocation.env.iter().map(|(k, v)| (k, v)))
+        .stdin(if invocation.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", invocation.program))?;
+    if let Some(input) = invocation.stdin
+        && let Some(mut stdin) = child.stdin.take()
+    {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = if stderr.trim().is_empty() { stdout.trim() } else { stderr.trim() };
+        bail!("{} failed: {detail}", invocation.program);
+    }
+    provider.parse_url(&stdout)
+}
+
+/// Last line of CLI output that is a URL, which is where `gh` and `glab`
+/// print the request they created.
+fn last_url_line(program: &str, stdout: &str) -> Result<String> {
+    stdout
+        .lines()
+        .map(str::trim)
+        .rfind(|l| l.starts_with("https://") || l.starts_with("http://"))
+        .map(str::to_string)
+        .with_context(|| format!("{program} did not print a request URL"))
+}
+
+/// GitHub, through `gh pr create`.
+struct GitHub;
+
+impl VcsProvider for GitHub {
+    fn invocation(&self, request: &ChangeRequest<'_>, token: Option<&str>) -> Result<Invocation> {
+        let mut args: Vec<String> = ["pr", "create", "--head", request.branch, "--title", request.title, "--body", request.body]
+            .map(str::to_string)
+            .into();
+        if let Some(ref base) = request.options.base {
+            args.extend(["--base".to_string(), base.clone()]);
+        }
+        if request.options.draft {
+            args.push("--draft".to_string());
+        }
+        Ok(Invocation {
+            program: "gh",
+            args,
+            env: token.map(|t| ("GH_TOKEN", t.to_string())).into_iter().collect(),
+            stdin: None,
+        })
+    }
+
+    fn parse_url(&self, stdout: &str) -> Result<String> {
+        last_url_line("gh", stdout)
+    }
+}
+
+/// GitLab, through `glab mr create`.
+struct GitLab;
+
+impl VcsProvider for GitLab {
+    fn invocation(&self, request: &ChangeRequest<'_>, token: Option<&str>) -> Result<Invocation> {
+        let mut args: Vec<String> = [
+            "mr",
+            "create",
+            "--yes",
+            "--source-branch",
+            request.branch,
+            "--title",
+            request.title,
+            "--description",
+            request.body,
+        ]
+        .map(str::to_string)
+        .into();
+        if let Some(ref base) = request.options.base {
+            args.extend(["--target-branch".to_string(), base.clone()]);
+        }
+        if request.options.draft {
+            args.push("--draft".to_string());
+        }
+        Ok(Invocation {
+            program: "glab",
+            args,
+            env: token.map(|t| ("GITLAB_TOKEN", t.to_string())).into_iter().collect(),
+            stdin: None,
+        })
+    }
+
+    fn parse_url(&self, stdout: &str) -> Result<String> {
+        last_url_line("glab", stdout)
+    }
+}
+
+/// Bitbucket Cloud, through its pull request REST API with `curl`.
+struct Bitbucket;
+
+impl VcsProvider for Bitbucket {
+    fn invocation(&self, request: &ChangeRequest<'_>, token: Option<&str>) -> Result<Invocation> {
+        let token = token.context("Bitbucket pull requests require `vcs_token` to be configured")?;
+        let (workspace, repo) = bitbucket_repo(request.remote_url)
+            .with_context(|| format!("not a Bitbucket remote: {}", request.remote_url))?;
+        let mut payload = serde_json::json!({
+            "title": request.title,
+            "description": request.body,
+            "source": {"branch": {"name": request.branch}},
+            "draft": request.options.draft,
+        });
+        if let Some(ref base) = request.options.base {
+            payload["destination"] = serde_json::json!({"branch": {"name": base}});
+        }
+        let url = format!("https://api.bitbucket.org/2.0/repositories/{workspace}/{repo}/pullrequests");
+        Ok(Invocation {
+            program: "curl",
+            args: [
+                "--silent",
+                "--show-error",
+                "--fail-with-body",
+                "--request",
+                "POST",
+                "--header",
+                "Content-Type: application/json",
+                "--header",
+                "@-",
+                "--data-binary",
+                &payload.to_string(),
+                &url,
+            ]
+            .map(str::to_string)
+            .into(),
+            env: Vec::new(),
+            stdin: Some(format!("Authorization: Bearer {token}\n")),
+        })
+    }
+
+    fn parse_url(&self, stdout: &str) -> Result<String> {
+        let response: serde_json::Value =
+            serde_json::from_str(stdout).context("Bitbucket returned invalid JSON")?;
+        response["links"]["html"]["href"]
+            .as_str()
+            .map(str::to_string)
+            .context("Bitbucket response has no pull request URL")
+    }
+}
+
+/// `(workspace, repository)` of a `bitbucket.org` remote URL, in either the
+/// `git@bitbucket.org:ws/repo.git` or `https://bitbucket.org/ws/repo.git` form.
+fn bitbucket_repo(remote_url: &str) -> Option<(&str, &str)> {
+    let (_, path) = remote_url.split_once("bitbucket.org")?;
+    let path = path.trim_start_matches([':', '/']);
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (workspace, repo) = path.trim_end_matches('/').split_once('/')?;
+    (!workspace.is_empty() && !repo.is_empty() && !repo.contains('/')).then_some((workspace, repo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request<'a>(remote_url: &'a str, options: &'a PullRequestOptions) -> ChangeRequest<'a> {
+        ChangeRequest {
+            remote_url,
+            branch: "codex/fix",
+            title: "Fix a",
+            body: "Body",
+            options,
+        }
+    }
+
+    #[test]
+    fn test_cli_invocations() {
+        let options = PullRequestOptions {
+            title: None,
+            base: Some("main".to_string()),
+            draft: true,
+        };
+        let request = request("git@github.com:o/r.git", &options);
+
+        let gh = provider(VcsProviderKind::Github).invocation(&request, Some("tok")).unwrap();
+        assert_eq!(gh.program, "gh");
+        assert_eq!(
+            gh.args,
+            ["pr", "create", "--head", "codex/fix", "--title", "Fix a", "--body", "Body", "--base", "main", "--draft"]
+        );
+        assert_eq!(gh.env, [("GH_TOKEN", "tok".to_string())]);
+
+        let glab = provider(VcsProviderKind::Gitlab).invocation(&request, None).unwrap();
+        assert_eq!(glab.program, "glab");
+        assert!(glab.args.windows(2).any(|w| w == ["--target-branch", "main"]));
+        assert!(glab.env.is_empty());
+
+        let output = "Creating merge request for codex/fix into main\n!7 Fix a (codex/fix)\n https://gitlab.com/o/r/-/merge_requests/7\n";
+        assert_eq!(GitLab.parse_url(output).unwrap(), "https://gitlab.com/o/r/-/merge_requests/7");
+        assert!(GitHub.parse_url("").is_err());
+    }
+
+    #[test]
+    fn test_bitbucket_invocation() {
+        assert_eq!(bitbucket_repo("git@bitbucket.org:team/app.git"), Some(("team", "app")));
+        assert_eq!(bitbucket_repo("https://me@bitbucket.org/team/app"), Some(("team", "app")));
+        assert_eq!(bitbucket_repo("git@github.com:team/app.git"), None);
+
+        let options = PullRequestOptions::default();
+        let request = request("git@bitbucket.org:team/app.git", &options);
+        assert!(Bitbucket.invocation(&request, None).is_err());
+        let curl = Bitbucket.invocation(&request, Some("secret")).unwrap();
+        assert!(!curl.args.iter().any(|a| a.contains("secret")), "token must not be in argv");
+        assert_eq!(curl.stdin.as_deref(), Some("Authorization: Bearer secret\n"));
+        assert_eq!(
+            curl.args.last().unwrap(),
+            "https://api.bitbucket.org/2.0/repositories/team/app/pullrequests"
+        );
+
+        let response = r#"{"id": 3, "links": {"html": {"href": "https://bitbucket.org/team/app/pull-requests/3"}}}"#;
+        assert_eq!(Bitbucket.parse_url(response).unwrap(), "https://bitbucket.org/team/app/pull-requests/3");
+    }
+}
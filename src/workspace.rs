@@ -0,0 +1,107 @@
+//! Workspace-level coordination between concurrent codex runs.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::error::CodexError;
+
+/// How concurrent write-enabled runs in the same workspace are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceLockMode {
+    /// No locking; runs may edit the same checkout simultaneously (default).
+    #[default]
+    Off,
+    /// Reject a new write run with `workspace_busy` while another is active.
+    Fail,
+    /// Wait until the active write run in the workspace finishes.
+    Queue,
+}
+
+impl WorkspaceLockMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkspaceLockMode::Off => "off",
+            WorkspaceLockMode::Fail => "fail",
+            WorkspaceLockMode::Queue => "queue",
+        }
+    }
+}
+
+/// Per-workspace locks keyed by canonicalized working directory.
+#[derive(Debug, Default)]
+pub struct WorkspaceLocks {
+    locks: Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>,
+}
+
+/// Held for the duration of a write-enabled run; releases the workspace on drop.
+#[derive(Debug)]
+pub struct WorkspaceGuard {
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl WorkspaceLocks {
+    /// Acquire the lock for `cd` according to `mode`.
+    ///
+    /// Returns `Ok(None)` when locking is disabled.
+    pub async fn acquire(
+        &self,
+        cd: &Path,
+        mode: WorkspaceLockMode,
+    ) -> Result<Option<WorkspaceGuard>, CodexError> {
+        if mode == WorkspaceLockMode::Off {
+            return Ok(None);
+        }
+
+        let key = cd.canonicalize().unwrap_or_else(|_| cd.to_path_buf());
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.entry(key.clone()).or_default().clone()
+        };
+
+        let guard = match mode {
+            WorkspaceLockMode::Fail => lock
+                .try_lock_owned()
+                .map_err(|_| CodexError::WorkspaceBusy(key))?,
+            WorkspaceLockMode::Queue => {
+                tracing::debug!(cd = %key.display(), "Waiting for workspace lock");
+                lock.lock_owned().await
+            }
+            WorkspaceLockMode::Off => unreachable!(),
+        };
+        Ok(Some(WorkspaceGuard { _guard: guard }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fail_mode_rejects_concurrent_runs() {
+        let locks = WorkspaceLocks::default();
+        let cd = Path::new(".");
+
+        let first = locks.acquire(cd, WorkspaceLockMode::Fail).await.unwrap();
+        assert!(first.is_some());
+        assert!(matches!(
+            locks.acquire(cd, WorkspaceLockMode::Fail).await,
+            Err(CodexError::WorkspaceBusy(_))
+        ));
+
+        drop(first);
+        assert!(locks.acquire(cd, WorkspaceLockMode::Fail).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_off_mode_never_locks() {
+        let locks = WorkspaceLocks::default();
+        let cd = Path::new(".");
+        let _first = locks.acquire(cd, WorkspaceLockMode::Off).await.unwrap();
+        assert!(locks.acquire(cd, WorkspaceLockMode::Off).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//! Listing and reading workspace files for the `codex_workspace_list` and
+//! `codex_workspace_read` tools, so an orchestrating agent can inspect what a
+//! run produced without spending a codex turn on it.
+//!
+//! The workspace must be one configured on the server (or lie inside one),
+//! whether or not `restrict_to_workspaces` is set. Paths are resolved
+//! against the workspace and must stay inside it
+//! (symlinks included); listings skip `.git` and excluded paths (see
+//! `exclusions`), and reads are capped by the server's
+//! `workspace_read_max_bytes`.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::exclusions::PathExclusions;
+
+/// Default and maximum numbers of entries returned by codex_workspace_list.
+pub const LIST_LIMIT_DEFAULT: usize = 200;
+pub const LIST_LIMIT_MAX: usize = 2000;
+
+/// Directories left out of listings.
+const SKIPPED_DIRS: &[&str] = &[".git"];
+
+/// Parameters for the codex_workspace_list tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceListParams {
+    /// Workspace to list. Either the name of a workspace configured on the
+    /// server or a path inside one.
+    pub cd: PathBuf,
+
+    /// Directory to list, relative to `cd`. Defaults to the workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+
+    /// List subdirectories too, depth first.
+    #[serde(default)]
+    pub recursive: bool,
+
+    /// Maximum number of entries to return (default 200, at most 2000).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Parameters for the codex_workspace_read tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceReadParams {
+    /// Workspace the file is in. Either the name of a workspace configured
+    /// on the server or a path inside one.
+    pub cd: PathBuf,
+
+    /// File to read, relative to `cd`.
+    pub path: PathBuf,
+
+    /// Byte offset to start reading at, to page through large files.
+    #[serde(default)]
+    pub offset: u64,
+
+    /// Most bytes to return; at most the server's `workspace_read_max_bytes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+}
+
+/// What a listed path is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// One listed path.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceEntry {
+    /// Path relative to the workspace root.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// Size in bytes, for files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// Result returned by the codex_workspace_list tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceListResult {
+    /// Resolved workspace directory.
+    pub cd: PathBuf,
+    /// Each directory's entries in name order, followed by those of its
+    /// subdirectories.
+    pub entries: Vec<WorkspaceEntry>,
+    /// Whether entries were left out because of `limit`.
+    pub truncated: bool,
+}
+
+/// Result returned by the codex_workspace_read tool.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceReadResult {
+    /// Resolved workspace directory.
+    pub cd: PathBuf,
+    /// File read, relative to the workspace root.
+    pub path: PathBuf,
+    /// Size of the whole file in bytes.
+    pub size: u64,
+    /// Byte offset the content starts at.
+    pub offset: u64,
+    /// The text read; absent for binary files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Whether the file is binary (not UTF-8 text).
+    pub binary: bool,
+    /// Whether the file continues after the content; read on from
+    /// `next_offset`.
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<u64>,
+}
+
+/// Entries of `dir` (inside `root`) that are not excluded, at most `limit`
+/// of them, and whether more were left out.
+pub fn list(
+    root: &Path,
+    dir: &Path,
+    recursive: bool,
+    limit: usize,
+    exclusions: &PathExclusions,
+) -> io::Result<(Vec<WorkspaceEntry>, bool)> {
+    let mut entries = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut children: Vec<_> = std::fs::read_dir(&dir)?.collect::<io::Result<_>>()?;
+        children.sort_by_key(|child| child.file_name());
+        let mut subdirs = Vec::new();
+        for child in children {
+            if SKIPPED_DIRS.iter().any(|skipped| child.file_name() == *skipped) {
+                continue;
+            }
+            let file_type = child.file_type()?;
+            let kind = if file_type.is_symlink() {
+                EntryKind::Symlink
+            } else if file_type.is_dir() {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            };
+            let path = child.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if exclusions.is_excluded(relative, kind == EntryKind::Dir) {
+                continue;
+            }
+            if entries.len() == limit {
+                return Ok((entries, true));
+            }
+            entries.push(WorkspaceEntry {
+                path: relative.to_path_buf(),
+                kind,
+                size: (kind == EntryKind::File).then(|| child.metadata().map_or(0, |m| m.len())),
+            });
+            if recursive && kind == EntryKind::Dir {
+                subdirs.push(path);
+            }
+        }
+        // Popped in reverse, so subdirectories are listed in name order.
+        pending.extend(subdirs.into_iter().rev());
+    }
+    Ok((entries, false))
+}
+
+/// Up to `max_bytes` of `file` from `offset`: its size, the text read (or
+/// `None` if the file is binary), and the offset after it.
+pub fn read(file: &Path, offset: u64, max_bytes: usize) -> io::Result<(u64, Option<String>, u64)> {
+    let mut handle = std::fs::File::open(file)?;
+    let size = handle.metadata()?.len();
+    let offset = offset.min(size);
+    handle.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    handle.take(max_bytes as u64).read_to_end(&mut bytes)?;
+    let end = offset + bytes.len() as u64;
+    let valid = match std::str::from_utf8(&bytes) {
+        Ok(_) => bytes.len(),
+        // Cut inside a multi-byte character: stop before it.
+        Err(e) if e.error_len().is_none() && end < size => e.valid_up_to(),
+        Err(_) => return Ok((size, None, end)),
+    };
+    bytes.truncate(valid);
+    let text = String::from_utf8(bytes).expect("truncated to valid UTF-8");
+    if text.contains('\0') {
+        return Ok((size, None, end));
+    }
+    Ok((size, Some(text), offset + valid as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_and_read_workspace_files() {
+        let root = std::env::temp_dir().join(format!("codex-mcp-workspace-files-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join("README.md"), "héllo").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/nested/blob.bin"), b"\0\x01").unwrap();
+
+        let exclusions = PathExclusions::parse(["*.bin"]);
+        let (entries, truncated) = list(&root, &root, true, 10, &exclusions).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["README.md", "src", "src/main.rs", "src/nested"]);
+        assert!(!truncated);
+        assert_eq!((entries[0].kind, entries[0].size), (EntryKind::File, Some(6)));
+        assert_eq!((entries[1].kind, entries[1].size), (EntryKind::Dir, None));
+        let (entries, truncated) = list(&root, &root, false, 1, &PathExclusions::default()).unwrap();
+        assert_eq!((entries.len(), truncated), (1, true));
+
+        assert_eq!(read(&root.join("README.md"), 0, 100).unwrap(), (6, Some("héllo".to_string()), 6));
+        // Stops before the `é` cut in half.
+        assert_eq!(read(&root.join("README.md"), 0, 2).unwrap(), (6, Some("h".to_string()), 1));
+        assert_eq!(read(&root.join("README.md"), 3, 100).unwrap(), (6, Some("llo".to_string()), 6));
+        assert_eq!(read(&root.join("src/nested/blob.bin"), 0, 100).unwrap(), (2, None, 2));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
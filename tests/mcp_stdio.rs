@@ -0,0 +1,1575 @@
+//! End-to-end tests driving the MCP stdio loop against the `fake-codex` binary.
+//!
+//! Run with `cargo test --features fake-codex`.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use base64::Engine;
+use serde_json::{Value, json};
+
+/// A running `codex-mcp` server wired to `fake-codex` with a scripted event stream.
+struct Harness {
+    child: Child,
+    /// `None` once closed, as when the client goes away.
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+    dir: PathBuf,
+    /// Result sent back for `elicitation/create` requests; left unanswered when `None`.
+    elicitation_reply: Option<Value>,
+    /// Params of the `elicitation/create` requests the server sent.
+    elicitations: Vec<Value>,
+}
+
+impl Harness {
+    /// Start the server with `script` as the fake codex JSONL output.
+    fn start(name: &str, script: &str) -> Self {
+        Self::start_with_config(name, script, "")
+    }
+
+    /// Like `start`, appending `extra_config` to the server config file.
+    /// `{dir}` in it is replaced with the harness temp directory.
+    fn start_with_config(name: &str, script: &str, extra_config: &str) -> Self {
+        Self::start_with_client(name, script, extra_config, json!({}))
+    }
+
+    /// Like `start_with_config`, declaring `capabilities` as the client's.
+    fn start_with_client(name: &str, script: &str, extra_config: &str, capabilities: Value) -> Self {
+        let dir = Self::dir(name);
+        let workspace = dir.join("workspace");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(dir.join("script.jsonl"), script).unwrap();
+        std::fs::write(
+            dir.join("config.toml"),
+            format!(
+                "codex_path = {}\n{}\n",
+                Value::from(env!("CARGO_BIN_EXE_fake-codex")),
+                extra_config.replace("{dir}", &dir.display().to_string())
+            ),
+        )
+        .unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_codex-mcp"))
+            .env("CODEX_MCP_CONFIG", dir.join("config.toml"))
+            .env("FAKE_CODEX_SCRIPT", dir.join("script.jsonl"))
+            .env("FAKE_CODEX_ARGS_OUT", dir.join("args.json"))
+            .env("FAKE_CODEX_ENV_OUT", dir.join("env.json"))
+            .env("FAKE_CODEX_HELP", dir.join("help.txt"))
+            .env("FAKE_CODEX_STDIN_OUT", dir.join("stdin.jsonl"))
+            .env("RUST_LOG", "warn")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("spawn codex-mcp");
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut harness = Self {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+            dir,
+            elicitation_reply: None,
+            elicitations: Vec::new(),
+        };
+        harness.request(
+            "initialize",
+            json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": capabilities,
+                "clientInfo": {"name": "mcp-stdio-test", "version": "0.0.0"}
+            }),
+        );
+        harness.send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}));
+        harness
+    }
+
+    /// Temp directory of the harness `name`, which tests may populate before
+    /// starting it (e.g. with `help.txt`, the fake's `codex exec --help`).
+    fn dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("codex-mcp-it-{name}-{}", std::process::id()))
+    }
+
+    fn workspace(&self) -> PathBuf {
+        self.dir.join("workspace")
+    }
+
+    fn send(&mut self, message: Value) {
+        let stdin = self.stdin.as_mut().expect("stdin is open");
+        writeln!(stdin, "{message}").unwrap();
+        stdin.flush().unwrap();
+    }
+
+    /// Send a JSON-RPC request and wait for its response, skipping
+    /// notifications and answering elicitations with `elicitation_reply`.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}));
+
+        loop {
+            let mut line = String::new();
+            let read = self.stdout.read_line(&mut line).unwrap();
+            assert!(read > 0, "server closed stdout while waiting for {method}");
+            let message: Value = serde_json::from_str(&line).unwrap();
+            if message["method"] == "elicitation/create" {
+                self.elicitations.push(message["params"].clone());
+                if let Some(reply) = self.elicitation_reply.clone() {
+                    self.send(json!({"jsonrpc": "2.0", "id": message["id"], "result": reply}));
+                }
+            } else if message.get("method").is_none() && message.get("id") == Some(&json!(id)) {
+                return message;
+            }
+        }
+    }
+
+    /// Call the `codex` tool and return the parsed `CodexResult` JSON.
+    fn call_codex(&mut self, mut arguments: Value) -> Value {
+        arguments["cd"] = json!(self.workspace());
+        let response = self.request(
+            "tools/call",
+            json!({"name": "codex", "arguments": arguments}),
+        );
+        let text = response["result"]["content"][0]["text"]
+            .as_str()
+            .unwrap_or_else(|| panic!("unexpected response: {response}"));
+        serde_json::from_str(text).unwrap()
+    }
+
+    /// Arguments the fake codex was invoked with on the last run.
+    fn codex_args(&self) -> Vec<String> {
+        let text = std::fs::read_to_string(self.dir.join("args.json")).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+const THREAD_ID: &str = "019bc4ce-610d-7f50-bd2a-fb5b8ac83b61";
+
+fn script(lines: &[Value]) -> String {
+    lines.iter().map(|l| format!("{l}\n")).collect()
+}
+
+#[test]
+fn test_successful_run_returns_agent_message_and_session() {
+    let mut harness = Harness::start(
+        "success",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "All done."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 12, "cached_input_tokens": 2, "output_tokens": 5}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "do the thing"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["SESSION_ID"], json!(THREAD_ID));
+    assert_eq!(result["agent_messages"], json!("All done."));
+    assert_eq!(result["usage"]["input_tokens"], json!(12));
+    assert_eq!(result["environment"]["codex_version"], json!("codex-cli 0.0.0-fake"));
+    assert_eq!(result["environment"]["os"], json!(std::env::consts::OS));
+
+    let args = harness.codex_args();
+    assert_eq!(args[0], "exec");
+    assert_eq!(args.last().unwrap(), "do the thing");
+}
+
+#[test]
+fn test_text_response_format_splits_message_and_metadata() {
+    let mut harness = Harness::start(
+        "response-format",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "All done."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 12, "output_tokens": 5}}),
+        ]),
+    );
+
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex", "arguments": {"PROMPT": "go", "cd": harness.workspace(), "response_format": "text"}}),
+    );
+    let result = &response["result"];
+    assert_eq!(result["content"][0]["text"], json!("All done."), "{response}");
+    let metadata: Value = serde_json::from_str(result["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(metadata, result["structuredContent"]);
+    assert_eq!(metadata["SESSION_ID"], json!(THREAD_ID));
+    assert_eq!(metadata["usage"]["input_tokens"], json!(12));
+    assert!(metadata.get("agent_messages").is_none());
+}
+
+#[test]
+fn test_errors_follow_the_run_language() {
+    let mut harness = Harness::start("i18n", "");
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "SESSION_ID": "not-a-session", "language": "zh-CN"}));
+    assert_eq!(result["error_code"], json!("invalid_session_id"));
+    assert!(result["error"].as_str().unwrap().starts_with("无效的 `SESSION_ID`"), "{result}");
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "SESSION_ID": "not-a-session", "language": "en"}));
+    assert!(result["error"].as_str().unwrap().starts_with("Invalid `SESSION_ID`"), "{result}");
+}
+
+#[test]
+fn test_explain_repo_errors_follow_the_server_locale() {
+    let mut harness = Harness::start_with_config("i18n-explain", "", "locale = \"zh\"\nrestrict_to_workspaces = true");
+    let response = harness.request("tools/call", json!({"name": "codex_explain_repo", "arguments": {"cd": "/"}}));
+    let result: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(result["error_code"], json!("workspace_not_allowed"), "{result}");
+    assert!(result["error"].as_str().unwrap().starts_with("工作目录不在"), "{result}");
+}
+
+#[test]
+fn test_failed_turn_is_reported() {
+    let mut harness = Harness::start(
+        "failure",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.failed", "error": {"message": "model overloaded"}}),
+            json!({"fake": {"exit": 1}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "do the thing"}));
+    assert_eq!(result["success"], json!(false));
+    assert_eq!(result["SESSION_ID"], json!(THREAD_ID));
+    let error = result["error"].as_str().unwrap();
+    assert!(error.contains("model overloaded"), "{error}");
+    assert_eq!(result["exit"], json!({"code": 1}));
+    assert!(result.get("error_code").is_none(), "{result}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigkill_is_reported_as_likely_oom() {
+    let mut harness = Harness::start(
+        "sigkill",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.started"}),
+            json!({"fake": {"signal": 9}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "do the thing"}));
+    assert_eq!(result["success"], json!(false));
+    assert_eq!(result["error_code"], json!("oom_killed"));
+    assert_eq!(result["exit"], json!({"signal": 9, "signal_name": "SIGKILL"}));
+    let error = result["error"].as_str().unwrap();
+    assert!(error.contains("terminated by SIGKILL (signal 9). codex was most likely stopped by the kernel's OOM killer"), "{error}");
+}
+
+#[test]
+fn test_run_without_final_message_succeeds() {
+    let mut harness = Harness::start(
+        "no-final-message",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.started"}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 10, "output_tokens": 1}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "do the thing"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["no_final_message"], json!(true));
+    assert_eq!(result["SESSION_ID"], json!(THREAD_ID));
+    assert!(result.get("agent_messages").is_none(), "{result}");
+}
+
+#[test]
+fn test_usage_tool_counts_runs() {
+    let mut harness = Harness::start(
+        "usage",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+
+    harness.call_codex(json!({"PROMPT": "one"}));
+    harness.call_codex(json!({"PROMPT": "two"}));
+
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex_usage", "arguments": {}}),
+    );
+    let report: Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(report["total"]["runs"], json!(2));
+    assert_eq!(report["total"]["tokens"]["input_tokens"], json!(6));
+}
+
+#[test]
+fn test_stderr_is_interleaved_into_all_messages() {
+    let mut harness = Harness::start(
+        "stderr",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"stderr": "network unreachable"}}),
+            json!({"fake": {"sleep_ms": 50}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 1, "output_tokens": 1}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "return_all_messages": true}));
+    let messages = result["all_messages"].as_array().unwrap();
+    let stderr: Vec<&Value> = messages.iter().filter(|m| m["type"] == "stderr").collect();
+    assert_eq!(stderr.len(), 1, "{result}");
+    assert_eq!(stderr[0]["text"], json!("network unreachable"));
+}
+
+#[test]
+fn test_run_artifacts_are_written() {
+    let mut harness = Harness::start_with_config(
+        "artifacts",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"stderr": "note from codex"}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+            json!({"fake": {"exit": 0}}),
+        ]),
+        r#"artifacts_dir = "{dir}/artifacts""#,
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go"}));
+    let dir = PathBuf::from(result["artifacts_dir"].as_str().unwrap());
+    assert!(dir.starts_with(harness.dir.join("artifacts")));
+
+    let transcript = std::fs::read_to_string(dir.join("transcript.jsonl")).unwrap();
+    assert_eq!(transcript.lines().count(), 2);
+    let stderr = std::fs::read_to_string(dir.join("stderr.log")).unwrap();
+    assert_eq!(stderr, "note from codex\n");
+    let summary: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("run.json")).unwrap()).unwrap();
+    assert_eq!(summary["SESSION_ID"], json!(THREAD_ID));
+    assert!(!dir.join("diff.patch").exists(), "read-only runs have no diff");
+}
+
+#[test]
+fn test_post_processors_apply_in_order() {
+    let mut harness = Harness::start_with_config(
+        "post-processors",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "token tok_12345 done"}}),
+        ]),
+        r#"
+[[post_processors]]
+kind = "redact"
+patterns = ["tok_[0-9]+"]
+replacement = "<token>"
+
+[[post_processors]]
+kind = "markdown"
+"#,
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "return_all_messages": true}));
+    assert_eq!(result["agent_messages"], json!("token <token> done"), "{result}");
+    assert!(!result.to_string().contains("tok_12345"), "{result}");
+    let markdown = result["markdown"].as_str().unwrap();
+    assert!(markdown.starts_with("token <token> done\n\n_session `"), "{markdown}");
+}
+
+#[test]
+fn test_files_changed_include_generated_files() {
+    let mut harness = Harness::start(
+        "files-changed",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"write": "src/main.rs", "content": "fn main() {}"}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "file_change", "changes": [{"path": "src/main.rs", "kind": "add"}], "status": "completed"}}),
+            json!({"fake": {"write": "dist/app.js", "content": "generated"}}),
+            json!({"type": "item.completed", "item": {"id": "item_1", "type": "agent_message", "text": "built"}}),
+        ]),
+    );
+    std::fs::write(harness.workspace().join("README.md"), "read me").unwrap();
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "sandbox": "workspace-write"}));
+    assert_eq!(
+        result["files_changed"],
+        json!([
+            {"path": "src/main.rs", "kind": "created"},
+            {"path": "dist/app.js", "kind": "created"},
+        ]),
+        "{result}"
+    );
+}
+
+#[test]
+fn test_post_format_runs_configured_formatters() {
+    let mut harness = Harness::start_with_config(
+        "post-format",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"write": "notes.txt", "content": "messy  \n"}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "file_change", "changes": [{"path": "notes.txt", "kind": "add"}], "status": "completed"}}),
+            json!({"type": "item.completed", "item": {"id": "item_1", "type": "agent_message", "text": "done"}}),
+        ]),
+        r#"
+[formatters]
+txt = ["sh", "-c", "printf '%s\\n' \"$(sed 's/ *$//' \"$0\")\" > \"$0\""]
+"#,
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "sandbox": "workspace-write", "post_format": true}));
+    assert_eq!(result["formatting"]["formatted"], json!(["notes.txt"]), "{result}");
+    let diff = result["formatting"]["diff"].as_str().unwrap();
+    assert!(diff.contains("-messy  \n+messy\n"), "{diff}");
+    assert_eq!(std::fs::read_to_string(harness.workspace().join("notes.txt")).unwrap(), "messy\n");
+}
+
+#[test]
+fn test_self_review_critiques_a_sessions_changes() {
+    let answer = json!({
+        "verdict": "request_changes",
+        "summary": "Missing tests.",
+        "comments": [
+            {"path": "src/lib.rs", "severity": "minor", "comment": "Add a test."},
+            {"path": "src/lib.rs", "line": 1, "severity": "major", "comment": "Panics on empty input."},
+        ],
+    });
+    let mut harness = Harness::start(
+        "self-review",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"write": "src/lib.rs", "content": "pub fn first(v: &[u8]) -> u8 { v[0] }\n"}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "file_change", "changes": [{"path": "src/lib.rs", "kind": "add"}], "status": "completed"}}),
+            json!({"type": "item.completed", "item": {"id": "item_1", "type": "agent_message", "text": answer.to_string()}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    for args in [&["init", "--quiet"][..], &["commit", "--quiet", "--allow-empty", "-m", "init"]] {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&workspace)
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    let run = harness.call_codex(json!({"PROMPT": "go", "sandbox": "workspace-write"}));
+    assert_eq!(run["success"], json!(true), "{run}");
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex_self_review", "arguments": {"SESSION_ID": THREAD_ID, "focus": "panics"}}),
+    );
+    let review: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(review["success"], json!(true), "{review}");
+    assert_eq!(review["files"], json!(["src/lib.rs"]));
+    assert_eq!(review["verdict"], json!("request_changes"));
+    assert_eq!(review["comments"][0]["severity"], json!("major"));
+    assert_eq!(review["comments"][1]["severity"], json!("minor"));
+
+    let args = harness.codex_args();
+    assert!(args.contains(&"read-only".to_string()), "{args:?}");
+    let prompt = args.last().unwrap();
+    assert!(prompt.contains("+pub fn first(v: &[u8]) -> u8 { v[0] }"), "{prompt}");
+    assert!(prompt.contains("Pay particular attention to: panics"), "{prompt}");
+}
+
+#[test]
+fn test_scratch_dir_is_created_only_for_allowed_runs() {
+    let mut harness = Harness::start_with_config(
+        "create-cd",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+        ]),
+        "allow_create_cd = true",
+    );
+    let scratch = harness.dir.join("scratch");
+    let instructions = harness.dir.join("script.jsonl");
+    let mut run = |arguments: Value| -> Value {
+        let response = harness.request("tools/call", json!({"name": "codex", "arguments": arguments}));
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+
+    let refused = run(json!({
+        "PROMPT": "go", "cd": scratch, "create_cd_if_missing": true, "init_git": true,
+        "base_instructions_file": instructions,
+    }));
+    assert_eq!(refused["error_code"], json!("instructions_file_not_allowed"), "{refused}");
+    assert!(!scratch.exists(), "a refused run creates nothing");
+
+    let result = run(json!({"PROMPT": "go", "cd": scratch, "create_cd_if_missing": true, "init_git": true}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert!(scratch.join(".git").is_dir());
+}
+
+#[test]
+fn test_policy_denied_run_leaves_no_trace() {
+    let dir = Harness::dir("policy-denied");
+    std::fs::create_dir_all(&dir).unwrap();
+    let policy = format!(
+        "[[rules]]\nname = \"read-only\"\nwhen = {{ cd_under = [{}] }}\nrequire = {{ max_sandbox = \"read-only\" }}\n",
+        Value::from(dir.to_str().unwrap())
+    );
+    std::fs::write(dir.join("policy.toml"), policy).unwrap();
+    let mut harness = Harness::start_with_config(
+        "policy-denied",
+        "",
+        "allow_create_cd = true\npolicy_file = \"{dir}/policy.toml\"",
+    );
+    let scratch = harness.dir.join("scratch");
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex", "arguments": {
+            "PROMPT": "go", "cd": scratch, "create_cd_if_missing": true, "init_git": true, "sandbox": "workspace-write",
+        }}),
+    );
+    let result: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(result["error_code"], json!("policy_denied"), "{result}");
+    assert!(!scratch.exists(), "a denied run creates nothing");
+}
+
+#[test]
+fn test_write_runs_check_free_disk_space() {
+    let mut harness = Harness::start_with_config(
+        "disk-space",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+        ]),
+        "min_free_disk_mb = 1000000000000",
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "sandbox": "workspace-write"}));
+    assert_eq!(result["error_code"], json!("insufficient_disk_space"), "{result}");
+    let result = harness.call_codex(json!({"PROMPT": "go"}));
+    assert_eq!(result["success"], json!(true), "read-only runs are not checked: {result}");
+}
+
+#[test]
+fn test_stream_to_tees_raw_events() {
+    let mut harness = Harness::start_with_config(
+        "stream-to",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+        r#"allowed_stream_dirs = ["{dir}/streams"]"#,
+    );
+    let streams = harness.dir.join("streams");
+    std::fs::create_dir_all(&streams).unwrap();
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "stream_to": streams.join("run.jsonl")}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    let path = streams.join("run.jsonl");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::fs::read_to_string(&path).map_or(0, |s| s.lines().count()) < 3 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let lines: Vec<Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines[0]["type"], json!("thread.started"));
+    assert_eq!(lines[2]["type"], json!("turn.completed"));
+
+    let outside = harness.call_codex(json!({"PROMPT": "go", "stream_to": "run.jsonl"}));
+    assert_eq!(outside["error_code"], json!("stream_to_not_allowed"), "{outside}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_config_is_reloaded_on_sighup() {
+    let mut harness = Harness::start_with_config(
+        "reload",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+        "config_watch_interval_secs = 0",
+    );
+    let stream_to = harness.dir.join("run.jsonl");
+    let denied = harness.call_codex(json!({"PROMPT": "go", "stream_to": stream_to}));
+    assert_eq!(denied["error_code"], json!("stream_to_not_allowed"), "{denied}");
+
+    let config = harness.dir.join("config.toml");
+    let text = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(&config, format!("{text}allowed_stream_dirs = [{}]\n", Value::from(harness.dir.display().to_string())))
+        .unwrap();
+    let status = Command::new("kill").args(["-HUP", &harness.child.id().to_string()]).status().unwrap();
+    assert!(status.success());
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        let result = harness.call_codex(json!({"PROMPT": "go", "stream_to": stream_to}));
+        if result["success"] == json!(true) {
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "config was not reloaded: {result}");
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_tool_names_take_configured_prefix() {
+    let mut harness = Harness::start_with_config(
+        "tool-prefix",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+        ]),
+        r#"tool_name_prefix = "acme_""#,
+    );
+
+    let tools = harness.request("tools/list", json!({}));
+    let tools = tools["result"]["tools"].as_array().unwrap();
+    let names: Vec<&str> = tools.iter().map(|tool| tool["name"].as_str().unwrap()).collect();
+    assert!(names.contains(&"acme_codex") && names.contains(&"acme_codex_usage"), "{names:?}");
+    assert!(!names.contains(&"codex"), "{names:?}");
+
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "acme_codex", "arguments": {"PROMPT": "go", "cd": harness.workspace()}}),
+    );
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert_eq!(serde_json::from_str::<Value>(text).unwrap()["agent_messages"], json!("ok"), "{text}");
+    let unprefixed = harness.request("tools/call", json!({"name": "codex", "arguments": {"PROMPT": "go"}}));
+    assert!(unprefixed["error"].is_object(), "{unprefixed}");
+}
+
+#[test]
+fn test_tools_follow_codex_capabilities() {
+    let dir = Harness::dir("capabilities");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("help.txt"), "Usage: codex exec [OPTIONS] [PROMPT]\n\nOptions:\n  -m, --model <MODEL>\n").unwrap();
+    let mut harness = Harness::start("capabilities", &script(&[json!({"type": "thread.started", "thread_id": THREAD_ID})]));
+
+    let tools = harness.request("tools/list", json!({}));
+    let tools = tools["result"]["tools"].as_array().unwrap();
+    let names: Vec<&str> = tools.iter().map(|tool| tool["name"].as_str().unwrap()).collect();
+    assert!(!names.contains(&"codex_with_screenshot"), "{names:?}");
+    let codex = tools.iter().find(|tool| tool["name"] == "codex").unwrap();
+    let properties = codex["inputSchema"]["properties"].as_object().unwrap();
+    assert!(properties.contains_key("model"));
+    for param in ["image", "profile", "SESSION_ID", "resume_last"] {
+        assert!(!properties.contains_key(param), "{param} is offered");
+    }
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "profile": "fast"}));
+    assert_eq!(result["error_code"], json!("unsupported_by_codex"), "{result}");
+}
+
+#[test]
+fn test_explain_repo_is_cached_by_head() {
+    let mut harness = Harness::start(
+        "explain",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "A repo."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&workspace)
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    };
+    git(&["init", "--quiet"]);
+    git(&["commit", "--quiet", "--allow-empty", "-m", "init"]);
+
+    let mut explain = |arguments: Value| -> Value {
+        let response = harness.request(
+            "tools/call",
+            json!({"name": "codex_explain_repo", "arguments": arguments}),
+        );
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+    let first = explain(json!({"cd": workspace}));
+    assert_eq!(first["success"], json!(true), "{first}");
+    assert_eq!(first["cached"], json!(false));
+    assert_eq!(first["explanation"], json!("A repo."));
+    let second = explain(json!({"cd": workspace}));
+    assert_eq!(second["cached"], json!(true));
+    assert_eq!(second["head"], first["head"]);
+    assert_eq!(explain(json!({"cd": workspace, "refresh": true}))["cached"], json!(false));
+
+    assert!(harness.codex_args().contains(&"read-only".to_string()));
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex_usage", "arguments": {}}),
+    );
+    let report: Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(report["total"]["runs"], json!(2));
+}
+
+#[test]
+fn test_explain_repo_redacts_secrets_before_caching() {
+    let mut harness = Harness::start_with_config(
+        "explain-redacted",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Uses ghp_explain_secret."}}),
+        ]),
+        "vcs_token = \"ghp_explain_secret\"\n",
+    );
+    let workspace = harness.workspace();
+    for args in [&["init", "--quiet"][..], &["commit", "--quiet", "--allow-empty", "-m", "init"]] {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&workspace)
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    for cached in [false, true] {
+        let response = harness.request(
+            "tools/call",
+            json!({"name": "codex_explain_repo", "arguments": {"cd": workspace}}),
+        );
+        let result: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(result["cached"], json!(cached), "{result}");
+        assert_eq!(result["explanation"], json!("Uses [REDACTED]."), "{result}");
+    }
+}
+
+#[test]
+fn test_project_memory_is_injected_into_prompts() {
+    let mut harness = Harness::start_with_config(
+        "memory",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+        "[workspaces]\napp = \"{dir}/workspace\"",
+    );
+    let workspace = harness.workspace();
+    let outside = harness.request(
+        "tools/call",
+        json!({"name": "codex_memory_append", "arguments": {"cd": harness.dir, "note": "Planted."}}),
+    );
+    assert_eq!(outside["error"]["data"]["error_code"], json!("workspace_not_allowed"), "{outside}");
+    assert!(!harness.dir.join(".codex-mcp").exists());
+
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex_memory_append", "arguments": {"cd": workspace, "note": "Run tests with --all-features."}}),
+    );
+    let memory: Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert!(memory["notes"].as_str().unwrap().ends_with("- Run tests with --all-features.\n"), "{memory}");
+    assert!(workspace.join(".codex-mcp/memory.md").is_file());
+
+    harness.call_codex(json!({"PROMPT": "add a test"}));
+    let prompt = harness.codex_args().pop().unwrap();
+    assert!(prompt.starts_with("Project memory: "), "{prompt}");
+    assert!(prompt.contains("- Run tests with --all-features.\n</project-memory>\n\nadd a test"));
+
+    harness.call_codex(json!({"PROMPT": "add a test", "skip_memory": true}));
+    assert_eq!(harness.codex_args().pop().unwrap(), "add a test");
+}
+
+#[test]
+fn test_expected_output_schema_parses_and_retries_the_answer() {
+    let answer = |text: &str| {
+        script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": text}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ])
+    };
+    let mut harness = Harness::start("output-schema", &answer("```json\n{\"verdict\": \"pass\"}\n```"));
+    let schema = json!({"type": "object", "required": ["verdict"], "properties": {"verdict": {"enum": ["pass", "fail"]}}});
+
+    let result = harness.call_codex(json!({"PROMPT": "review it", "expected_output_schema": schema}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["structured_answer"], json!({"verdict": "pass"}));
+    let prompt = harness.codex_args().pop().unwrap();
+    assert!(prompt.starts_with("review it\n\nOutput: "), "{prompt}");
+    assert!(prompt.contains("<output-schema>"), "{prompt}");
+
+    std::fs::write(harness.dir.join("script.jsonl"), answer("Looks good to me.")).unwrap();
+    let result = harness.call_codex(json!({"PROMPT": "review it", "expected_output_schema": schema}));
+    assert_eq!(result["success"], json!(false), "{result}");
+    assert_eq!(result["error_code"], json!("output_schema_mismatch"));
+    assert_eq!(result["usage"]["input_tokens"], json!(6), "both attempts are counted: {result}");
+    let args = harness.codex_args();
+    assert!(args.contains(&"resume".to_string()) && args.contains(&THREAD_ID.to_string()), "{args:?}");
+    assert!(args.last().unwrap().starts_with("Your final message did not match"), "{args:?}");
+}
+
+#[test]
+fn test_structured_answers_are_redacted() {
+    let mut harness = Harness::start_with_config(
+        "output-schema-redacted",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "{\"token\": \"ghp_answer_secret\"}"}}),
+        ]),
+        "vcs_token = \"ghp_answer_secret\"\n",
+    );
+    let schema = json!({"type": "object", "required": ["token"]});
+    let result = harness.call_codex(json!({"PROMPT": "print it", "expected_output_schema": schema}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["structured_answer"], json!({"token": "[REDACTED]"}), "{result}");
+    assert!(!result.to_string().contains("ghp_answer_secret"), "{result}");
+}
+
+#[test]
+fn test_stored_outputs_are_redacted_when_read() {
+    let text = format!("The token is ghp_stored_secret. {}", "More detail. ".repeat(20));
+    let mut harness = Harness::start_with_config(
+        "stored-redacted",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": text}}),
+        ]),
+        "vcs_token = \"ghp_stored_secret\"\nstate_dir = \"{dir}/state\"\ninline_messages_max_bytes = 1\n",
+    );
+    let result = harness.call_codex(json!({"PROMPT": "go", "return_all_messages": true, "max_output_chars": 40}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    for field in ["all_messages_uri", "agent_messages_uri"] {
+        let uri = result[field].as_str().unwrap_or_else(|| panic!("no {field}: {result}"));
+        let response = harness.request("resources/read", json!({"uri": uri}));
+        let text = response["result"]["contents"][0]["text"].as_str().unwrap();
+        assert!(text.contains("[REDACTED]") && !text.contains("ghp_stored_secret"), "{field}: {text}");
+    }
+
+    // Stored before the secret was configured, e.g. by an earlier server version.
+    let older = harness.dir.join(format!("state/agent_messages/{THREAD_ID}-1.txt"));
+    std::fs::write(&older, "old ghp_stored_secret").unwrap();
+    let response = harness.request("resources/read", json!({"uri": format!("codex-mcp://agent_messages/{THREAD_ID}-1")}));
+    assert_eq!(response["result"]["contents"][0]["text"], json!("old [REDACTED]"), "{response}");
+}
+
+#[test]
+fn test_truncation_never_cuts_a_secret_in_half() {
+    let text = format!("ghp_cut_secret_token{}", "z".repeat(100));
+    let mut harness = Harness::start_with_config(
+        "truncate-redacted",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": text}}),
+        ]),
+        "vcs_token = \"ghp_cut_secret_token\"\n",
+    );
+    let result = harness.call_codex(json!({"PROMPT": "go", "max_output_chars": 20}));
+    assert_eq!(result["truncated"], json!(true), "{result}");
+    let messages = result["agent_messages"].as_str().unwrap();
+    assert!(messages.starts_with("[REDACTED]") && !messages.contains("ghp_"), "{messages}");
+}
+
+#[test]
+fn test_fatal_noise_kills_codex_promptly() {
+    let mut harness = Harness::start_with_config(
+        "fatal-noise",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "error", "message": "codex: not logged in"}),
+            json!({"fake": {"sleep_ms": 20000}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "late"}}),
+        ]),
+        "noise_filters = [{ pattern = \"not logged in\", class = \"fatal\" }]\n",
+    );
+    let started = std::time::Instant::now();
+    let result = harness.call_codex(json!({"PROMPT": "go"}));
+    assert!(started.elapsed() < std::time::Duration::from_secs(4), "took {:?}", started.elapsed());
+    assert_eq!(result["success"], json!(false), "{result}");
+    let error = result["error"].as_str().unwrap();
+    assert!(error.contains("not logged in") && !error.contains("timed out"), "{error}");
+}
+
+#[test]
+fn test_crashed_run_is_resumed_once() {
+    let mut harness = Harness::start_with_config(
+        "crash-resume",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.started"}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "file_change", "changes": [{"path": "a.txt", "kind": "add"}]}}),
+            json!({"fake": {"exit": 101}}),
+        ]),
+        "resume_crashed_runs = true",
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "do the thing", "return_all_messages": true}));
+    assert_eq!(result["success"], json!(false), "{result}");
+    assert_eq!(result["resumed_after_crash"], json!(true), "{result}");
+    let starts = result["all_messages"].as_array().unwrap().iter().filter(|e| e["type"] == "turn.started").count();
+    assert_eq!(starts, 2, "events of both attempts are kept: {result}");
+    let args = harness.codex_args();
+    assert!(args.contains(&"resume".to_string()) && args.contains(&THREAD_ID.to_string()), "{args:?}");
+    assert!(args.last().unwrap().contains("the codex process exited unexpectedly"), "{args:?}");
+}
+
+#[test]
+fn test_compressed_all_messages_decode_with_gzip() {
+    let mut harness = Harness::start(
+        "compress",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Done."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "return_all_messages": true, "compress": true}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert!(result.get("all_messages").is_none(), "{result}");
+    assert_eq!(result["encoding"], json!("gzip+base64"));
+    let compressed = base64::engine::general_purpose::STANDARD
+        .decode(result["all_messages_compressed"].as_str().unwrap())
+        .unwrap();
+
+    let mut gunzip = Command::new("gzip")
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("gzip is installed");
+    gunzip.stdin.take().unwrap().write_all(&compressed).unwrap();
+    let output = gunzip.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let messages: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(messages[1]["item"]["text"], json!("Done."), "{messages}");
+}
+
+#[test]
+fn test_prompt_read_from_workspace_file() {
+    let mut harness = Harness::start(
+        "prompt-resource",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    std::fs::create_dir_all(workspace.join("docs")).unwrap();
+    std::fs::write(workspace.join("docs/task.md"), "Implement the spec in full.").unwrap();
+
+    let result = harness.call_codex(json!({"PROMPT": "resource:docs/task.md"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(harness.codex_args().pop().unwrap(), "Implement the spec in full.");
+
+    let outside = harness.call_codex(json!({"PROMPT": "resource:../script.jsonl"}));
+    assert_eq!(outside["error_code"], json!("prompt_resource_unavailable"), "{outside}");
+    let missing = harness.call_codex(json!({"PROMPT": "resource:codex-mcp://transcripts/none"}));
+    assert_eq!(missing["error_code"], json!("prompt_resource_unavailable"), "{missing}");
+}
+
+#[test]
+fn test_workspace_files_are_listed_and_read_within_the_workspace() {
+    let mut harness = Harness::start_with_config(
+        "workspace-files",
+        "",
+        "workspace_read_max_bytes = 4\n[workspaces]\napp = \"{dir}/workspace\"",
+    );
+    let workspace = harness.workspace();
+    std::fs::create_dir_all(workspace.join("src")).unwrap();
+    std::fs::write(workspace.join("src/lib.rs"), "pub fn f() {}").unwrap();
+    let mut call = |name: &str, arguments: Value| -> Value {
+        let response = harness.request("tools/call", json!({"name": name, "arguments": arguments}));
+        match response["result"]["content"][0]["text"].as_str() {
+            Some(text) => serde_json::from_str(text).unwrap(),
+            None => response["error"].clone(),
+        }
+    };
+
+    let listing = call("codex_workspace_list", json!({"cd": workspace, "path": "src"}));
+    assert_eq!(listing["entries"], json!([{"path": "src/lib.rs", "kind": "file", "size": 13}]), "{listing}");
+
+    let read = call("codex_workspace_read", json!({"cd": workspace, "path": "src/lib.rs"}));
+    assert_eq!((read["content"].clone(), read["next_offset"].clone()), (json!("pub "), json!(4)), "{read}");
+    let rest = call("codex_workspace_read", json!({"cd": workspace, "path": "src/lib.rs", "offset": 12}));
+    assert_eq!((rest["content"].clone(), rest["truncated"].clone()), (json!("}"), json!(false)), "{rest}");
+
+    let outside = call("codex_workspace_read", json!({"cd": workspace, "path": "../script.jsonl"}));
+    assert_eq!(outside["data"]["error_code"], json!("workspace_path_not_allowed"), "{outside}");
+    let outside = call("codex_workspace_list", json!({"cd": "/"}));
+    assert_eq!(outside["data"]["error_code"], json!("workspace_not_allowed"), "{outside}");
+}
+
+#[test]
+fn test_workspace_files_need_a_configured_workspace() {
+    let mut harness = Harness::start("workspace-files-default", "");
+    let workspace = harness.workspace();
+    std::fs::write(workspace.join("notes.md"), "hello").unwrap();
+    for (name, arguments) in [
+        ("codex_workspace_list", json!({"cd": "/"})),
+        ("codex_workspace_read", json!({"cd": "/", "path": "etc/hostname"})),
+        ("codex_workspace_read", json!({"cd": workspace, "path": "notes.md"})),
+    ] {
+        let response = harness.request("tools/call", json!({"name": name, "arguments": arguments}));
+        assert_eq!(response["error"]["data"]["error_code"], json!("workspace_not_allowed"), "{name}: {response}");
+    }
+}
+
+#[test]
+fn test_ignore_file_keeps_secrets_out_of_tools_and_prompts() {
+    let mut harness = Harness::start_with_config(
+        "codexignore",
+        "",
+        "excluded_paths = [\"*.pem\"]\nvcs_token = \"ghp_read_secret\"\n[workspaces]\napp = \"{dir}/workspace\"",
+    );
+    let workspace = harness.workspace();
+    std::fs::write(workspace.join(".codexignore"), "# secrets\n.env\nsecrets/\n").unwrap();
+    std::fs::write(workspace.join(".env"), "TOKEN=hunter2").unwrap();
+    std::fs::write(workspace.join("server.pem"), "key").unwrap();
+    std::fs::create_dir_all(workspace.join("secrets")).unwrap();
+    std::fs::write(workspace.join("secrets/db.txt"), "password").unwrap();
+    std::fs::write(workspace.join("notes.md"), "use ghp_read_secret").unwrap();
+    let mut call = |name: &str, arguments: Value| -> Value {
+        let response = harness.request("tools/call", json!({"name": name, "arguments": arguments}));
+        match response["result"]["content"][0]["text"].as_str() {
+            Some(text) => serde_json::from_str(text).unwrap(),
+            None => response["error"]["data"].clone(),
+        }
+    };
+
+    let listing = call("codex_workspace_list", json!({"cd": workspace, "recursive": true}));
+    let paths: Vec<&str> = listing["entries"].as_array().unwrap().iter().map(|e| e["path"].as_str().unwrap()).collect();
+    assert!(!paths.iter().any(|p| p.contains(".env") || p.ends_with(".pem") || p.starts_with("secrets")), "{listing}");
+    assert!(paths.contains(&"notes.md"), "{listing}");
+    for path in [".env", "server.pem", "secrets/db.txt"] {
+        let read = call("codex_workspace_read", json!({"cd": workspace, "path": path}));
+        assert_eq!(read["error_code"], json!("path_excluded"), "{path}: {read}");
+    }
+    let read = call("codex_workspace_read", json!({"cd": "app", "path": "notes.md"}));
+    assert_eq!(read["content"], json!("use [REDACTED]"), "tracked files may hold secrets too: {read}");
+
+    let result = harness.call_codex(json!({"PROMPT": "go", "context_files": ["secrets/db.txt"]}));
+    assert_eq!(result["error_code"], json!("path_excluded"), "{result}");
+    let result = harness.call_codex(json!({"PROMPT": "resource:.env"}));
+    assert_eq!(result["error_code"], json!("prompt_resource_unavailable"), "{result}");
+}
+
+#[test]
+fn test_uploaded_context_file_is_passed_to_codex() {
+    let mut harness = Harness::start_with_config(
+        "upload-context",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "ok"}}),
+        ]),
+        "state_dir = \"{dir}/state\"",
+    );
+    let mut upload = |arguments: Value| -> Value {
+        let response = harness.request("tools/call", json!({"name": "codex_upload_context", "arguments": arguments}));
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+
+    let first = upload(json!({"file_name": "spec.md", "data": "aGVsbG8g"}));
+    assert_eq!(first["complete"], json!(false), "{first}");
+    let last = upload(json!({"upload_id": first["upload_id"], "data": "d29ybGQ=", "offset": 6, "done": true}));
+    assert_eq!(last["received_bytes"], json!(11), "{last}");
+    let path = last["path"].as_str().unwrap().to_string();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+
+    let result = harness.call_codex(json!({"PROMPT": "implement it", "context_files": [path]}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    let prompt = harness.codex_args().pop().unwrap();
+    assert!(prompt.starts_with("Context: ") && prompt.contains(&format!("\n- {path}\n")), "{prompt}");
+
+    let outside = harness.call_codex(json!({"PROMPT": "implement it", "context_files": ["../script.jsonl"]}));
+    assert_eq!(outside["error_code"], json!("context_file_not_allowed"), "{outside}");
+}
+
+#[test]
+fn test_summarize_session_continues_in_a_fresh_session() {
+    let mut harness = Harness::start(
+        "summarize",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Goal: fix the parser."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+    harness.call_codex(json!({"PROMPT": "start"}));
+
+    let result = harness.call_codex(json!({"PROMPT": "keep going", "SESSION_ID": THREAD_ID, "summarize_session": true}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["previous_session_id"], json!(THREAD_ID));
+    assert_eq!(result["session_summary"], json!("Goal: fix the parser."));
+    assert_eq!(result["usage"]["input_tokens"], json!(6), "the summary run is counted: {result}");
+    let args = harness.codex_args();
+    assert!(!args.contains(&"resume".to_string()), "{args:?}");
+    let prompt = args.last().unwrap();
+    assert!(prompt.contains("<session-summary>\nGoal: fix the parser.\n</session-summary>\n\nkeep going"));
+
+    let result = harness.call_codex(json!({"PROMPT": "keep going", "summarize_session": true}));
+    assert_eq!(result["error_code"], json!("summarize_requires_session"));
+}
+
+#[test]
+fn test_stop_on_process_exit_collects_every_turn() {
+    let mut harness = Harness::start(
+        "stop-on",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "First."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+            json!({"type": "turn.started"}),
+            json!({"type": "item.completed", "item": {"id": "item_1", "type": "agent_message", "text": "Second."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 4, "output_tokens": 2}}),
+        ]),
+    );
+    let first = harness.call_codex(json!({"PROMPT": "go"}));
+    assert_eq!(first["agent_messages"], json!("First."));
+    assert!(first.get("turns_completed").is_none());
+
+    let all = harness.call_codex(json!({"PROMPT": "go", "stop_on": "process_exit"}));
+    assert_eq!(all["agent_messages"], json!("First.\n\nSecond."), "{all}");
+    assert_eq!(all["turns_completed"], json!(2));
+    assert_eq!(all["usage"]["output_tokens"], json!(3));
+}
+
+#[test]
+fn test_compare_runs_each_model_read_only() {
+    let mut harness = Harness::start(
+        "compare",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Looks fine."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    let mut compare = |arguments: Value| -> Value {
+        harness.request("tools/call", json!({"name": "codex_compare", "arguments": arguments}))
+    };
+
+    let response = compare(json!({"PROMPT": "review", "cd": workspace, "models": ["gpt-5", "o3"], "sandbox": "workspace-write"}));
+    let result: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    let runs = result["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    for (run, model) in runs.iter().zip(["gpt-5", "o3"]) {
+        assert_eq!(run["model"], json!(model));
+        assert_eq!(run["result"]["success"], json!(true), "{run}");
+        assert_eq!(run["result"]["effective_options"]["model"], json!(model));
+        assert_eq!(run["result"]["effective_options"]["sandbox"], json!("read-only"));
+        assert_eq!(run["result"]["usage"]["output_tokens"], json!(1));
+    }
+
+    let response = compare(json!({"PROMPT": "review", "cd": workspace, "models": ["o3", "o3"]}));
+    assert_eq!(response["error"]["data"]["error_code"], json!("invalid_compare_models"), "{response}");
+}
+
+#[test]
+fn test_background_run_start_poll_result() {
+    let mut harness = Harness::start(
+        "jobs",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"sleep_ms": 200}}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Finished."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    let mut call = |name: &str, arguments: Value| -> Value {
+        harness.request("tools/call", json!({"name": name, "arguments": arguments}))
+    };
+    let text = |response: &Value| -> Value {
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+
+    let started = text(&call("codex_start", json!({"PROMPT": "slow", "cd": workspace})));
+    assert_eq!(started["status"], json!("running"));
+    let run_id = started["run_id"].clone();
+
+    let early = call("codex_result", json!({"run_id": run_id}));
+    assert_eq!(early["error"]["data"]["error_code"], json!("run_in_progress"), "{early}");
+
+    // The background task registers the run once it starts.
+    let server = loop {
+        let server = text(&call("codex_server_status", json!({})));
+        if server["runs"] != json!([]) {
+            break server;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    };
+    assert_eq!(server["runs"][0]["run_id"], run_id, "{server}");
+    assert_eq!(server["runs"][0]["tool"], json!("codex_start"));
+    assert_eq!(server["running"].as_u64().unwrap() + server["queued"].as_u64().unwrap(), 1);
+
+    let status = loop {
+        let status = text(&call("codex_poll", json!({"run_id": run_id})));
+        if status["status"] == json!("completed") {
+            break status;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+    assert_eq!(status["success"], json!(true));
+    assert_eq!(status["SESSION_ID"], json!(THREAD_ID));
+
+    let result = text(&call("codex_result", json!({"run_id": run_id})));
+    assert_eq!(result["agent_messages"], json!("Finished."));
+    assert_eq!(result["correlation_id"].as_str().unwrap().len(), 36, "{result}");
+    assert_eq!(result["correlation_id"], status["correlation_id"]);
+
+    let server = text(&call("codex_server_status", json!({})));
+    assert_eq!(server["runs"], json!([]));
+    assert_eq!(server["codex_version"], json!("codex-cli 0.0.0-fake"));
+    assert_eq!(server["limits"]["workspace_lock"], json!("off"));
+
+    let missing = call("codex_poll", json!({"run_id": "run-0-0"}));
+    assert_eq!(missing["error"]["data"]["error_code"], json!("run_not_found"));
+}
+
+#[test]
+fn test_version_reports_features_limits_and_codex() {
+    let mut harness = Harness::start_with_config(
+        "mcp-version",
+        "",
+        "disabled_tools = [\"codex_usage\"]\ndaily_budget_usd = 5.0\n[workspaces]\napp = \"{dir}/workspace\"",
+    );
+    let response = harness.request("tools/call", json!({"name": "codex_mcp_version", "arguments": {}}));
+    let report: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+
+    assert_eq!(report["server_version"], json!(env!("CARGO_PKG_VERSION")));
+    assert!(report["features"]["transports"].as_array().unwrap().contains(&json!("stdio")), "{report}");
+    assert_eq!(report["features"]["state_backend"], json!("files"));
+    assert_eq!(report["features"]["persistent_state"], json!(false));
+    let tools = report["tools"].as_array().unwrap();
+    assert!(tools.contains(&json!("codex_mcp_version")) && !tools.contains(&json!("codex_usage")), "{report}");
+    assert_eq!(report["workspaces"], json!(["app"]));
+    assert_eq!(report["limits"]["daily_budget_usd"], json!(5.0));
+    assert_eq!(report["codex"]["version"], json!("codex-cli 0.0.0-fake"));
+    assert_eq!(report["codex"]["capabilities"]["resume"], json!(true));
+}
+
+#[test]
+fn test_idempotency_key_replays_completed_run() {
+    let mut harness = Harness::start(
+        "idempotency",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Done once."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+        ]),
+    );
+    let workspace = harness.workspace();
+    let mut call = |name: &str, arguments: Value| -> Value {
+        let response = harness.request("tools/call", json!({"name": name, "arguments": arguments}));
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+
+    let request = json!({"PROMPT": "fix it", "cd": workspace, "idempotency_key": "retry-1"});
+    let first = call("codex", request.clone());
+    assert_eq!(first["success"], json!(true), "{first}");
+    assert!(first.get("idempotent_replay").is_none());
+    assert_eq!(first["prompt_fingerprint"].as_str().unwrap().len(), 16);
+
+    let replay = call("codex", request);
+    assert_eq!(replay["idempotent_replay"], json!(true));
+    assert_eq!(replay["agent_messages"], json!("Done once."));
+    assert_eq!(replay["result_fingerprint"], first["result_fingerprint"]);
+
+    let reused = call("codex", json!({"PROMPT": "other", "cd": workspace, "idempotency_key": "retry-1"}));
+    assert_eq!(reused["error_code"], json!("idempotency_key_reused"));
+
+    assert_eq!(call("codex_usage", json!({}))["total"]["runs"], json!(1));
+}
+
+#[test]
+fn test_exec_runs_stdin_prompts_without_mcp() {
+    let dir = std::env::temp_dir().join(format!("codex-mcp-it-exec-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let events = script(&[
+        json!({"type": "thread.started", "thread_id": THREAD_ID}),
+        json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Ran."}}),
+        json!({"type": "turn.completed", "usage": {"input_tokens": 3, "output_tokens": 1}}),
+    ]);
+    std::fs::write(dir.join("script.jsonl"), events).unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        format!("codex_path = {}\n", Value::from(env!("CARGO_BIN_EXE_fake-codex"))),
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codex-mcp"))
+        .args(["exec", "--same-session", "--cd"])
+        .arg(&dir)
+        .env("CODEX_MCP_CONFIG", dir.join("config.toml"))
+        .env("FAKE_CODEX_SCRIPT", dir.join("script.jsonl"))
+        .env("FAKE_CODEX_ARGS_OUT", dir.join("args.json"))
+        .env("RUST_LOG", "warn")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn codex-mcp exec");
+    child.stdin.take().unwrap().write_all(b"first prompt\n\nsecond prompt\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let results: Vec<Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1]["agent_messages"], json!("Ran."));
+    let args: Vec<String> = serde_json::from_str(&std::fs::read_to_string(dir.join("args.json")).unwrap()).unwrap();
+    assert!(args.contains(&THREAD_ID.to_string()), "second prompt resumes the first session: {args:?}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_daily_budget_stops_and_then_refuses_runs() {
+    let mut harness = Harness::start_with_config(
+        "budget",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Pricey."}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 10, "output_tokens": 10}}),
+        ]),
+        "daily_budget_usd = 0.00001\n[model_prices.default]\ninput = 1.0\noutput = 1.0\n",
+    );
+
+    let first = harness.call_codex(json!({"PROMPT": "spend"}));
+    assert_eq!(first["success"], json!(false), "{first}");
+    assert_eq!(first["error_code"], json!("budget_exceeded"));
+    assert!((first["cost_usd"].as_f64().unwrap() - 0.00002).abs() < 1e-12);
+
+    let second = harness.call_codex(json!({"PROMPT": "spend more"}));
+    assert_eq!(second["error_code"], json!("budget_exceeded"));
+    assert!(second.get("SESSION_ID").is_none(), "refused before codex started: {second}");
+}
+
+#[test]
+fn test_rate_limit_holds_later_runs() {
+    let mut harness = Harness::start_with_config(
+        "ratelimit",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "turn.failed", "error": {"message": "Rate limit reached for gpt-5. Please try again in 20s."}}),
+            json!({"fake": {"exit": 1}}),
+        ]),
+        "rate_limit_max_defer_secs = 0",
+    );
+
+    let first = harness.call_codex(json!({"PROMPT": "burst"}));
+    assert_eq!(first["error_code"], json!("rate_limited"), "{first}");
+    assert_eq!(first["retry_after_secs"], json!(20));
+
+    let second = harness.call_codex(json!({"PROMPT": "burst again"}));
+    assert_eq!(second["error_code"], json!("rate_limited"));
+    assert!(second.get("SESSION_ID").is_none(), "held before codex started: {second}");
+
+    let response = harness.request("tools/call", json!({"name": "codex_usage", "arguments": {}}));
+    let report: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert!(report["throttle"]["remaining_secs"].as_u64().unwrap() <= 20);
+    assert_eq!(report["total"]["runs"], json!(1));
+}
+
+#[test]
+fn test_self_test_gates_startup() {
+    let mut harness = Harness::start_with_config(
+        "selftest-ok",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"type": "agent_message", "text": "OK"}}),
+        ]),
+        "self_test = true",
+    );
+    let args = harness.codex_args();
+    assert!(args.windows(2).any(|w| w == ["--sandbox", "read-only"]), "{args:?}");
+    assert!(args.contains(&"--skip-git-repo-check".to_string()), "{args:?}");
+    let response = harness.request("tools/list", json!({}));
+    assert!(response["result"]["tools"].is_array(), "{response}");
+    drop(harness);
+
+    let dir = Harness::dir("selftest-fail");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("script.jsonl"),
+        script(&[
+            json!({"fake": {"stderr": "Error: not logged in, run `codex login`"}}),
+            json!({"fake": {"exit": 1}}),
+        ]),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        format!("codex_path = {}\n", Value::from(env!("CARGO_BIN_EXE_fake-codex"))),
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_codex-mcp"))
+        .arg("--self-test")
+        .env("CODEX_MCP_CONFIG", dir.join("config.toml"))
+        .env("FAKE_CODEX_SCRIPT", dir.join("script.jsonl"))
+        .env("RUST_LOG", "error")
+        .stdin(Stdio::null())
+        .output()
+        .expect("run codex-mcp");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("codex self-test failed"), "{stderr}");
+    assert!(stderr.contains("exit: exited with code 1"), "{stderr}");
+    assert!(stderr.contains("not logged in"), "{stderr}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_client_disconnect_stops_runs_and_exits() {
+    let mut harness = Harness::start_with_config(
+        "disconnect",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"fake": {"sleep_ms": 60_000}}),
+        ]),
+        "state_dir = \"{dir}/state\"",
+    );
+    let workspace = harness.workspace();
+    let response = harness.request(
+        "tools/call",
+        json!({"name": "codex_start", "arguments": {"PROMPT": "slow", "cd": workspace}}),
+    );
+    let started: Value = serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    let run_id = started["run_id"].as_str().unwrap().to_string();
+
+    let pids = harness.dir.join("state/pids");
+    let pid = loop {
+        let entry = std::fs::read_dir(&pids).ok().and_then(|mut entries| entries.next());
+        if let Some(entry) = entry {
+            break entry.unwrap().path().file_stem().unwrap().to_string_lossy().into_owned();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+
+    // The client goes away.
+    harness.stdin = None;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = harness.child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(std::time::Instant::now() < deadline, "server kept running after stdin closed");
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+    assert!(status.success());
+    assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists(), "codex outlived the server");
+
+    // The background run is left for the next start to report or resume.
+    let job: Value = serde_json::from_str(
+        &std::fs::read_to_string(harness.dir.join(format!("state/jobs/{run_id}.json"))).unwrap(),
+    )
+    .unwrap();
+    assert!(job.get("result").is_none_or(Value::is_null), "{job}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scrubbed_child_env_keeps_only_allowlisted_variables() {
+    let mut harness = Harness::start_with_config(
+        "scrub-env",
+        &script(&[
+            json!({"type": "thread.started", "thread_id": THREAD_ID}),
+            json!({"type": "item.completed", "item": {"type": "agent_message", "text": "Done."}}),
+        ]),
+        "scrub_child_env = true\nchild_env_allowlist = [\"FAKE_CODEX_*\"]\nchild_path = \"/usr/bin:/bin\"",
+    );
+    let result = harness.call_codex(json!({"PROMPT": "do the thing"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+
+    let env: Value = serde_json::from_str(&std::fs::read_to_string(harness.dir.join("env.json")).unwrap()).unwrap();
+    // Set for the server, but not allowlisted.
+    assert!(env.get("RUST_LOG").is_none(), "{env}");
+    assert!(env.get("CODEX_MCP_CONFIG").is_none(), "{env}");
+    assert!(env.get("FAKE_CODEX_SCRIPT").is_some());
+    let fake_dir = std::path::Path::new(env!("CARGO_BIN_EXE_fake-codex")).parent().unwrap();
+    assert_eq!(env["PATH"], json!(format!("{}:/usr/bin:/bin", fake_dir.display())));
+}
+
+/// Script of a run that asks to run `cargo publish` and waits for the answer.
+fn approval_script() -> String {
+    script(&[
+        json!({"type": "thread.started", "thread_id": THREAD_ID}),
+        json!({"type": "exec_approval_request", "call_id": "call_1", "command": ["cargo", "publish"]}),
+        json!({"fake": {"read_stdin": true}}),
+        json!({"type": "item.completed", "item": {"id": "item_0", "type": "agent_message", "text": "Done."}}),
+        json!({"type": "turn.completed", "usage": {"input_tokens": 1, "output_tokens": 1}}),
+    ])
+}
+
+/// The answers the fake codex read from stdin.
+fn approval_answers(harness: &Harness) -> Vec<Value> {
+    let text = std::fs::read_to_string(harness.dir.join("stdin.jsonl")).unwrap();
+    text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn test_approval_bridge_relays_the_client_decision() {
+    let mut harness = Harness::start_with_client(
+        "approval-bridge",
+        &approval_script(),
+        "approval_bridge = true",
+        json!({"elicitation": {}}),
+    );
+    harness.elicitation_reply = Some(json!({"action": "accept", "content": {"approve": true}}));
+
+    let result = harness.call_codex(json!({"PROMPT": "release it"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(result["agent_messages"], json!("Done."));
+    assert_eq!(
+        result["approvals"],
+        json!([{"action": "run `cargo publish`", "approved": true, "decided_by": "client"}])
+    );
+    assert_eq!(result["effective_options"]["approval_policy"], json!("on-request"));
+    assert!(harness.codex_args().contains(&r#"approval_policy="on-request""#.to_string()));
+
+    assert_eq!(harness.elicitations.len(), 1);
+    assert!(harness.elicitations[0]["message"].as_str().unwrap().contains("cargo publish"));
+    let answers = approval_answers(&harness);
+    assert_eq!(answers[0]["op"], json!({"type": "exec_approval", "id": "call_1", "decision": "approved"}));
+}
+
+#[test]
+fn test_approval_bridge_denies_unanswered_requests() {
+    let mut harness = Harness::start_with_client(
+        "approval-timeout",
+        &approval_script(),
+        "approval_bridge = true\napproval_timeout_secs = 1",
+        json!({"elicitation": {}}),
+    );
+
+    let result = harness.call_codex(json!({"PROMPT": "release it"}));
+    assert_eq!(result["success"], json!(true), "{result}");
+    assert_eq!(
+        result["approvals"],
+        json!([{"action": "run `cargo publish`", "approved": false, "decided_by": "timeout"}])
+    );
+    assert_eq!(harness.elicitations.len(), 1);
+    assert_eq!(approval_answers(&harness)[0]["op"]["decision"], json!("denied"));
+
+    // A client that cannot be asked is denied without waiting.
+    let mut harness = Harness::start_with_config("approval-unsupported", &approval_script(), "approval_bridge = true");
+    let result = harness.call_codex(json!({"PROMPT": "release it"}));
+    assert_eq!(result["approvals"][0]["decided_by"], json!("unavailable"), "{result}");
+    assert!(harness.elicitations.is_empty());
+    assert_eq!(approval_answers(&harness)[0]["op"]["decision"], json!("denied"));
+}